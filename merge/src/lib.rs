@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt};
 
 pub trait Merge {
     /// Merge another instance into this one.
@@ -25,9 +25,47 @@ impl<K: std::hash::Hash + Ord, V> Merge for BTreeMap<K, V> {
     }
 }
 
+/// Two layers setting a protected field to different values
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub field: String,
+    pub base: String,
+    pub other: String,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cannot be changed from {} to {}",
+            self.field, self.base, self.other
+        )
+    }
+}
+
+impl std::error::Error for MergeConflict {}
+
+/// Merge strategy for protected scalar fields
+///
+/// Like `Option::merge`, but a later layer may only repeat a value set by an earlier one.
+pub fn merge_protected<T: PartialEq + ToString>(
+    field: &str,
+    base: Option<T>,
+    other: Option<T>,
+) -> Result<Option<T>, MergeConflict> {
+    match (base, other) {
+        (Some(b), Some(o)) if b != o => Err(MergeConflict {
+            field: field.to_string(),
+            base: b.to_string(),
+            other: o.to_string(),
+        }),
+        (base, other) => Ok(other.or(base)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::Merge;
+    use crate::{merge_protected, Merge};
     use std::collections::BTreeMap;
 
     #[test]
@@ -59,4 +97,13 @@ mod tests {
         expected.insert("c", "b-value");
         assert_eq!(merged, expected);
     }
+
+    #[test]
+    fn protected() {
+        assert_eq!(merge_protected("ns", Some(1), Some(1)), Ok(Some(1)));
+        assert_eq!(merge_protected("ns", Some(1), None), Ok(Some(1)));
+        assert_eq!(merge_protected("ns", None, Some(2)), Ok(Some(2)));
+        let err = merge_protected("ns", Some(1), Some(2)).unwrap_err();
+        assert_eq!(err.to_string(), "ns cannot be changed from 1 to 2");
+    }
 }
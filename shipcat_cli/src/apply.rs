@@ -2,17 +2,19 @@ use std::path::Path;
 use tokio::fs;
 
 use crate::{
-    diff, helm,
-    kubeapi::ShipKube,
-    kubectl, track,
+    diff, helm, kong,
+    kubeapi::{KubeClient, ShipKube},
+    kubectl,
+    redact::Redactor,
+    track::{self, RolloutWait},
     webhooks::{self, UpgradeState},
 };
 use serde_json::json;
 
 use shipcat_definitions::{
-    status::{make_date, Condition},
+    status::{make_date, ApplyReason, Condition, RolloutRecord},
     structs::{Metadata, NotificationMode},
-    Config, Manifest, PrimaryWorkload, ReconciliationMode, Region,
+    Config, Environment, Manifest, PrimaryWorkload, ReconciliationMode, Region,
 };
 
 use super::{ErrorKind, Result, ResultExt};
@@ -62,6 +64,40 @@ impl UpgradeInfo {
     }
 }
 
+/// Guard against accidental applies to prod regions
+///
+/// Prod applies need `confirmed` (from `--yes`), or the service name typed on `input`.
+pub fn confirm_prod_apply<R: std::io::BufRead>(
+    svc: &str,
+    version: Option<&str>,
+    (region, env): (&str, &Environment),
+    confirmed: bool,
+    input: &mut R,
+) -> Result<()> {
+    if env != &Environment::Prod {
+        return Ok(());
+    }
+    let ver = version.unwrap_or("the manifest version");
+    if confirmed {
+        warn!("Applying {} at {} to prod region {}", svc, ver, region);
+        return Ok(());
+    }
+    eprintln!(
+        "Applying {} at {} to prod region {}. Type the service name to confirm:",
+        svc, ver, region
+    );
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if answer.trim() != svc {
+        bail!(
+            "Refusing to apply {} to prod region {} without confirmation",
+            svc,
+            region
+        );
+    }
+    Ok(())
+}
+
 /// shipcat apply
 ///
 /// This is the main entrypoint for cli upgrades of a service in a region.
@@ -82,17 +118,94 @@ impl UpgradeInfo {
 /// It is also entirely responsible for sending webhooks on errors / successes.
 /// As such, it's entirely responsible for not propagating random errors here with `?`
 /// Every error cases is something that might need to be notified.
+#[allow(clippy::too_many_arguments)]
 pub async fn apply(
     svc: String,
     force: bool,
     region: &Region,
     conf: &Config,
-    wait: bool,
+    kube: &KubeClient,
+    wait: RolloutWait,
     passed_version: Option<String>,
+    apply_reason: Option<String>,
 ) -> Result<Option<UpgradeInfo>> {
     match region.reconciliationMode {
-        ReconciliationMode::CrdOwned => apply_kubectl(&svc, force, region, conf, wait, passed_version).await,
+        ReconciliationMode::CrdOwned => {
+            apply_kubectl(
+                &svc,
+                force,
+                region,
+                conf,
+                kube,
+                wait,
+                passed_version,
+                apply_reason,
+            )
+            .await
+        }
+    }
+}
+
+/// Build a manifest at the version passed on the cli, or at its `pinned` version
+///
+/// A version is set EITHER via `-t SOMEVER` on CLI, or pinned in manifest.
+/// Both are resolved by the manifest build, so they compare after any region suffix.
+async fn load_versioned(
+    svc: &str,
+    conf: &Config,
+    region: &Region,
+    pinned: Option<String>,
+    passed_version: Option<String>,
+) -> Result<Manifest> {
+    let mf =
+        shipcat_filebacked::load_manifest_with_version(svc, conf, region, passed_version.as_deref()).await?;
+    if passed_version.is_some() && pinned.is_some() && mf.version != pinned {
+        error!("Overriding a pinned version will be undone at next reconcile");
+        bail!(
+            "Cannot override version for '{}' because it is pinned in manifests",
+            svc
+        );
     }
+    Ok(mf)
+}
+
+/// Verify a resolved version against the region's versioning scheme and tag policy
+pub fn verify_version(region: &Region, version: &str) -> Result<()> {
+    region.versioningScheme.verify(version)?;
+    region.verify_tag_policy(version)?;
+    Ok(())
+}
+
+/// Print what an apply would change in the shipcatmanifest crd without applying it
+///
+/// Uses the same version resolution as apply; nothing is written to the cluster.
+pub async fn dry_run(
+    svc: &str,
+    region: &Region,
+    conf: &Config,
+    passed_version: Option<String>,
+) -> Result<()> {
+    let pinned = shipcat_filebacked::load_metadata(svc, conf, region)
+        .await?
+        .version;
+    let mfbase = load_versioned(svc, conf, region, pinned, passed_version).await?;
+    let kube = KubeClient::new(region).await?;
+    let s = ShipKube::new(&mfbase, &kube);
+    let version = match mfbase.version.clone() {
+        Some(v) => Some(v),
+        None => s.get_minimal().await.ok().map(|o| o.spec.version),
+    };
+    let mf = match version {
+        Some(v) => mfbase.version(v),
+        None => mfbase,
+    };
+    let out = s.diff(mf).await?;
+    if out.is_empty() {
+        info!("{} up to date (no changes)", svc);
+    } else {
+        print!("{}", out);
+    }
+    Ok(())
 }
 
 /// Reason for an apply being allowed through
@@ -120,46 +233,77 @@ impl ToString for UpgradeReason {
     }
 }
 
+/// Regions a service is enabled in, with `!region` negations resolved
+///
+/// The config loaded for an apply only knows its own region, so this reads the full config,
+/// falling back to the loaded one when that is unavailable (e.g. when reconciling from a crd).
+async fn enabled_regions(svc: &str, region: &Region, conf: &Config) -> Result<Vec<String>> {
+    let simple = match Config::read().await {
+        Ok(full) => shipcat_filebacked::load_metadata(svc, &full, region).await?,
+        Err(e) => {
+            debug!("Resolving enabled regions from the loaded config: {}", e);
+            shipcat_filebacked::load_metadata(svc, conf, region).await?
+        }
+    };
+    Ok(if simple.base.disabled { vec![] } else { simple.base.regions })
+}
+
+/// Refuse to apply a service to a region it is not enabled in, unless forced
+///
+/// Applying there would leave an orphaned shipcatmanifest that reconciles never clean up.
+/// `regions` are the resolved regions the service is enabled in.
+fn verify_region_enabled(svc: &str, region: &str, regions: &[String], force: bool) -> Result<()> {
+    let allowed = if regions.is_empty() {
+        "none".to_string()
+    } else {
+        regions.join(", ")
+    };
+    if force {
+        warn!(
+            "Forcing apply of '{}' to {} (enabled regions: {})",
+            svc, region, allowed
+        );
+        return Ok(());
+    }
+    bail!(
+        "Cannot apply '{}' to {} as it is not enabled there (enabled regions: {}) - use --force to override",
+        svc,
+        region,
+        allowed
+    );
+}
+
 /// First version of apply that does not use tiller
 ///
 /// This writes events to uses the shipcatmanifest crd
-#[allow(clippy::cognitive_complexity)] // TODO: refactor this!
+#[allow(clippy::cognitive_complexity, clippy::too_many_arguments)] // TODO: refactor this!
 async fn apply_kubectl(
     svc: &str,
     force: bool,
     region: &Region,
     conf: &Config,
-    wait: bool,
+    kube: &KubeClient,
+    wait: RolloutWait,
     passed_version: Option<String>,
+    apply_reason: Option<String>,
 ) -> Result<Option<UpgradeInfo>> {
     if let Err(e) = webhooks::ensure_requirements(&region) {
         warn!("Could not ensure webhook requirements: {}", e);
     }
-    let mfbase = shipcat_filebacked::load_manifest(&svc, &conf, &region).await?;
-
-    // A version is set EITHER via `-t SOMEVER` on CLI, or pinned in manifest
-    if passed_version.is_some() && mfbase.version.is_some() && mfbase.version != passed_version {
-        error!("Overriding a pinned version will be undone at next reconcile");
-        bail!(
-            "Cannot override version for '{}' because it is pinned in manifests",
-            svc
-        );
-    }
-    let explicit_version = mfbase.version.clone().or(passed_version);
-
-    if !mfbase.regions.contains(&region.name) {
-        bail!(
-            "Cannot deploy '{}' to a region it's not configured for in its manifest",
-            svc
-        );
+    let simple = shipcat_filebacked::load_metadata(&svc, &conf, &region).await?;
+    if !simple.enabled {
+        let regions = enabled_regions(svc, region, conf).await?;
+        verify_region_enabled(svc, &region.name, &regions, force)?;
     }
+    let mfbase = load_versioned(&svc, &conf, &region, simple.version, passed_version).await?;
+    let explicit_version = mfbase.version.clone();
 
     // Interact with the kube api to get the shipcatmanifest crd and its .status
     // This lets us work out:
     // - if the service has been installed before (negates the need for a diff)
     // - if we need to apply a new crd (so we have an atomic change)
     // - if we need to interact with secret-manager TODO: do
-    let s = ShipKube::new(&mfbase).await?;
+    let s = ShipKube::new(&mfbase, kube);
 
     // Next large batch is working out the reason for the upgrade (if any)
     let mut reason = None;
@@ -200,11 +344,11 @@ async fn apply_kubectl(
     let can_diff = crd.is_some();
     debug!("using {}={}", svc, actual_version);
     // no shoehorning in illegal versions in the crd!
-    region.versioningScheme.verify(&actual_version)?;
+    verify_version(&region, &actual_version)?;
 
     // Complete and apply the CRD
     let mfcrd = mfbase.version(actual_version.clone());
-    let crd_changed = s.apply(mfcrd.clone()).await?;
+    let crd_changed = s.apply(mfcrd.clone(), region.kubectlCrdApply).await?;
     // Cheap reconcile ends here if !changed && !force
     if crd_changed {
         reason = reason.or(Some(UpgradeReason::ManifestChange));
@@ -230,6 +374,8 @@ async fn apply_kubectl(
             return Err(e.into());
         }
     };
+    // Errors from here on can echo resolved secrets; keep them out of .status
+    let redactor = Redactor::for_manifest(&mf);
     // Should have a UID for ownerReferences now
     mf.uid = if let Some(o) = crd {
         o.metadata.uid
@@ -251,10 +397,10 @@ async fn apply_kubectl(
     // Create completed kubernetes yaml (via shipcat values | helm template)
     let tfile = format!("{}.kube.gen.yml", svc);
     let tpth = Path::new(".").join(tfile.clone());
-    if let Err(e) = helm::template(&mf, Some(tpth)).await {
+    if let Err(e) = helm::template(&mf, Some(tpth), true).await {
         // Errors here are obscure, and should not happen, but pass them up anyway
         webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-        s.update_generate_false("ResolveFailure", e.description().to_string())
+        s.update_generate_false("ResolveFailure", redactor.redact(e.description()))
             .await?;
         return Err(e);
     }
@@ -281,7 +427,7 @@ async fn apply_kubectl(
                 if !force && reason.is_none() {
                     // pass on a diff failure
                     webhooks::apply_event(UpgradeState::Cancelled, &ui, &region, &conf).await;
-                    s.update_generate_false("DiffFailure", e.description().to_string())
+                    s.update_generate_false("DiffFailure", redactor.redact(e.description()))
                         .await?;
                     return Ok(None); // but ultimately ignore this in fast reconciles
                 }
@@ -291,7 +437,12 @@ async fn apply_kubectl(
     }
 
     // We cannot be here without a reason now, although you have to convince yourself.
-    let ureason = reason.expect("cannot apply without a reason");
+    let ureason = reason.expect("cannot apply without a reason").to_string();
+    // Stored as lastApplyReason, along with any reference given by the applier
+    let ureason = match apply_reason {
+        Some(r) => format!("{}: {}", ureason, r),
+        None => ureason,
+    };
     webhooks::apply_event(UpgradeState::Started, &ui, &region, &conf).await;
     s.update_generate_true().await?; // if this fails, stop, want .status to be correct
 
@@ -299,40 +450,39 @@ async fn apply_kubectl(
         Err(e) => {
             error!("{} from {}", e, ui.name);
             webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-            let reason = e.description().to_string();
-            s.update_apply_false(ureason.to_string(), "ApplyFailure", reason)
-                .await?; // TODO: chain
+            let reason = redactor.redact(e.description());
+            s.update_apply_false(ureason, "ApplyFailure", reason).await?; // TODO: chain
             return Err(e);
         }
         Ok(_) => {
-            let _ = s.update_apply_true(ureason.to_string()).await;
-            if !wait {
-                info!("successfully applied {} (without waiting)", ui.name);
-            } else {
-                match track::workload_rollout(&mf, &s).await {
+            let _ = s.update_apply_true(ureason).await;
+            if let Some(time) = wait.timeout(&mf) {
+                match track::workload_rollout(&mf, &s, time).await {
                     Ok(true) => {
                         info!("successfully rolled out {}", &ui.name);
                         webhooks::apply_event(UpgradeState::Completed, &ui, &region, &conf).await;
                         s.update_rollout_true(&actual_version).await?;
                     }
                     Ok(false) => {
-                        let time = mf.estimate_wait_time();
                         let reason = format!("timed out waiting {}s for rollout", time);
                         //let _ = kubectl::debug_rollout_status(&mf).await;
                         let _ = track::debug(&mf, &s).await;
                         // TODO: collect these for .status call ^?
                         warn!("failed to roll out {}", &ui.name);
                         webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-                        s.update_rollout_false("Timeout", reason).await?; // TODO: chain
+                        s.update_rollout_false(&actual_version, "Timeout", reason).await?; // TODO: chain
                         return Err(ErrorKind::UpgradeTimeout(mf.name.clone(), time).into());
                     }
                     Err(e) => {
                         webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-                        s.update_rollout_false("RolloutTrackFailure", e.description().to_string())
+                        let err = redactor.redact(e.description());
+                        s.update_rollout_false(&actual_version, "RolloutTrackFailure", err)
                             .await?; // TODO: chain
                         return Err(e);
                     }
                 }
+            } else {
+                info!("successfully applied {} (without waiting)", ui.name);
             }
         }
     };
@@ -401,7 +551,7 @@ pub async fn diff_kubectl(mf: &Manifest, tfile: &str) -> Result<Option<String>>
 /// Restart the workloads associated with a shipcatmanifest
 ///
 /// Optionally wait for the main resource
-pub async fn restart(mf: &Manifest, wait: bool) -> Result<()> {
+pub async fn restart(mf: &Manifest, kube: &KubeClient, wait: RolloutWait) -> Result<()> {
     for w in &mf.workers {
         let r = Restartable {
             name: w.container.name.clone(),
@@ -416,21 +566,23 @@ pub async fn restart(mf: &Manifest, wait: bool) -> Result<()> {
         workload: mf.workload.clone(),
     };
     trigger_rollout_restart(main).await?;
-    if !wait {
-        info!(
-            "successfully triggered a restart of {}/{}",
-            mf.workload.to_string(),
-            mf.name
-        );
-        return Ok(());
-    }
-    let sk = ShipKube::new(&mf).await?;
+    let time = match wait.timeout(mf) {
+        Some(t) => t,
+        None => {
+            info!(
+                "successfully triggered a restart of {}/{}",
+                mf.workload.to_string(),
+                mf.name
+            );
+            return Ok(());
+        }
+    };
+    let sk = ShipKube::new(&mf, kube);
     // wait for primary if we are waiting
-    if track::workload_rollout(&mf, &sk).await? {
+    if track::workload_rollout(&mf, &sk, time).await? {
         info!("successfully restarted {}/{}", mf.workload.to_string(), &mf.name);
         Ok(())
     } else {
-        let time = mf.estimate_wait_time();
         let reason = format!("timed out waiting {}s for rollout to restart", time);
         //let _ = kubectl::debug_rollout_status(&mf).await;
         let _ = track::debug(&mf, &sk).await;
@@ -458,20 +610,106 @@ async fn trigger_rollout_restart(r: Restartable) -> Result<()> {
         .chain_err(|| ErrorKind::KubectlApplyFailure(r.name))
 }
 
+/// Finalizer on a shipcatmanifest guarding the cleanup of its rendered resources
+pub const CLEANUP_FINALIZER: &str = "shipcat.babylontech.co.uk/cleanup";
+
+/// Individual steps of a service deletion
+#[derive(Debug, PartialEq)]
+enum DeleteStep {
+    AddFinalizer,
+    DeleteManifest,
+    DeleteResources,
+    DeleteKongApis,
+    RemoveFinalizer,
+}
+
+/// Order of operations for deleting a service
+///
+/// With cleanup, the finalizer keeps the crd around until the rendered resources
+/// and the service's kong apis are gone.
+fn delete_plan(cleanup: bool) -> Vec<DeleteStep> {
+    if cleanup {
+        vec![
+            DeleteStep::AddFinalizer,
+            DeleteStep::DeleteManifest,
+            DeleteStep::DeleteResources,
+            DeleteStep::DeleteKongApis,
+            DeleteStep::RemoveFinalizer,
+        ]
+    } else {
+        vec![DeleteStep::DeleteManifest]
+    }
+}
+
+async fn delete_resources(svc: &str, ns: &str) -> Result<()> {
+    let delvec = vec![
+        "delete".into(),
+        format!("-n={}", ns),
+        "all,configmaps,secrets".into(),
+        format!("-l=app.kubernetes.io/name={}", svc),
+        "--wait".into(),
+    ];
+    info!("kubectl {}", delvec.join(" "));
+    kubectl::kexec(delvec)
+        .await
+        .chain_err(|| ErrorKind::KubectlApiFailure("delete".into(), svc.into()))
+}
+
+async fn delete_kong_apis(reg: &Region, apis: &[String]) -> Result<()> {
+    match &reg.kong {
+        Some(k) if !apis.is_empty() => kong::delete_apis(&k.config_url, apis).await,
+        _ => Ok(()),
+    }
+}
+
+async fn delete_with_plan(s: &ShipKube, svc: &str, reg: &Region, kong_apis: &[String]) -> Result<()> {
+    for step in delete_plan(reg.cleanupOnDelete) {
+        debug!("Delete step {:?} for {}", step, svc);
+        match step {
+            DeleteStep::AddFinalizer => s.add_finalizer(CLEANUP_FINALIZER).await?,
+            DeleteStep::DeleteManifest => s.delete().await?,
+            DeleteStep::DeleteResources => delete_resources(svc, &reg.namespace).await?,
+            DeleteStep::DeleteKongApis => delete_kong_apis(reg, kong_apis).await?,
+            DeleteStep::RemoveFinalizer => s.remove_finalizer(CLEANUP_FINALIZER).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Version to roll a service back to
+///
+/// Uses the last successful rollout recorded in the shipcatmanifest status,
+/// and refuses to guess when none has been recorded.
+pub async fn rollback_version(svc: &str, reg: &Region, kube: &KubeClient) -> Result<String> {
+    let s = ShipKube::new_within(svc, &reg.namespace, kube);
+    match s.last_successful_version().await? {
+        Some(v) => Ok(v),
+        None => bail!(
+            "{} has no successful rollout recorded in {} - refusing to roll back",
+            svc,
+            reg.name
+        ),
+    }
+}
+
 /// Uninstall a service
 ///
 /// Not meant to be called if the manifest is still installed in the region
 /// shipcat::cluster module is responsible for calling this,
 /// when (and only when) a service disappears from disk.
-pub async fn delete(svc: &str, reg: &Region, conf: &Config) -> Result<()> {
-    let s = ShipKube::new_within(&svc, &reg.namespace).await?;
+///
+/// If the region sets `cleanupOnDelete`, the rendered resources are torn down
+/// before the finalizer is released and the crd disappears.
+pub async fn delete(svc: &str, reg: &Region, conf: &Config, kube: &KubeClient) -> Result<()> {
+    let s = ShipKube::new_within(&svc, &reg.namespace, kube);
     match s.get().await {
         // audit all events if it's possible to deserialize current crd
         Ok(mfk) => {
             let info = UpgradeInfo::new(&mfk.spec);
+            let kong_apis: Vec<String> = mfk.spec.kongApis.iter().map(|k| k.name.clone()).collect();
             // We notify before we start, because this is potentially a "panic" type notification.
             webhooks::delete_event(&UpgradeState::Started, &info, &reg, &conf).await;
-            match s.delete().await {
+            match delete_with_plan(&s, svc, reg, &kong_apis).await {
                 Ok(_) => {
                     // NB: we say completed when the api is "done"
                     // This might still trigger finalizers ATM...
@@ -488,7 +726,8 @@ pub async fn delete(svc: &str, reg: &Region, conf: &Config) -> Result<()> {
         Err(e) => {
             warn!("Unable to notify about service deletion: {}", e);
             // The following Result is more important
-            s.delete()
+            // NB: kong apis are not known without the spec
+            delete_with_plan(&s, svc, reg, &[])
                 .await
                 .chain_err(|| ErrorKind::KubectlApiFailure("delete".into(), svc.into()))
         }
@@ -557,10 +796,23 @@ impl ShipKube {
         self.patch(&data).await
     }
 
+    /// Apply reasons including a new entry (merge patches replace the whole list)
+    async fn apply_reasons_with(&self, reason: &str, timestamp: &str) -> Result<Vec<ApplyReason>> {
+        let status = self.get_minimal().await?.status.unwrap_or_default();
+        let mut summary = status.summary.unwrap_or_default();
+        summary.push_apply_reason(ApplyReason {
+            reason: reason.to_string(),
+            timestamp: timestamp.to_string(),
+            applier: Some(self.applier.clone()),
+        });
+        Ok(summary.apply_reasons)
+    }
+
     pub async fn update_apply_true(&self, ureason: String) -> Result<()> {
         debug!("Setting applied true");
         let now = make_date();
         let cond = Condition::ok(&self.applier);
+        let reasons = self.apply_reasons_with(&ureason, &now).await?;
         let data = json!({
             "status": {
                 "conditions": {
@@ -570,6 +822,7 @@ impl ShipKube {
                     "lastApply": now,
                     "lastSuccessfulApply": now,
                     "lastApplyReason": ureason,
+                    "applyReasons": reasons,
                     "lastAction": "Apply",
                 }
             }
@@ -581,6 +834,7 @@ impl ShipKube {
         debug!("Setting applied false");
         let now = make_date();
         let cond = Condition::bad(&self.applier, err, reason.clone());
+        let reasons = self.apply_reasons_with(&ureason, &now).await?;
         let data = json!({
             "status": {
                 "conditions": {
@@ -590,6 +844,7 @@ impl ShipKube {
                     "lastApply": now,
                     "lastFailureReason": reason,
                     "lastApplyReason": ureason,
+                    "applyReasons": reasons,
                     "lastAction": "Apply",
                 }
             }
@@ -597,15 +852,29 @@ impl ShipKube {
         self.patch(&data).await
     }
 
-    pub async fn update_rollout_false(&self, err: &str, reason: String) -> Result<()> {
+    /// Rollout history including a new record (merge patches replace the whole list)
+    async fn history_with(&self, version: &str, success: bool) -> Result<Vec<RolloutRecord>> {
+        let mut status = self.get_minimal().await?.status.unwrap_or_default();
+        status.push_history(RolloutRecord {
+            version: version.to_string(),
+            timestamp: make_date(),
+            success,
+            applier: Some(self.applier.clone()),
+        });
+        Ok(status.history)
+    }
+
+    pub async fn update_rollout_false(&self, version: &str, err: &str, reason: String) -> Result<()> {
         debug!("Setting rolledout false");
         let cond = Condition::bad(&self.applier, err, reason.clone());
         let now = make_date();
+        let history = self.history_with(version, false).await?;
         let data = json!({
             "status": {
                 "conditions": {
                     "rolledout": cond
                 },
+                "history": history,
                 "summary": {
                     "lastRollout": now,
                     "lastFailureReason": reason,
@@ -620,11 +889,13 @@ impl ShipKube {
         debug!("Setting rolledout true");
         let now = make_date();
         let cond = Condition::ok(&self.applier);
+        let history = self.history_with(version, true).await?;
         let data = json!({
             "status": {
                 "conditions": {
                     "rolledout": cond
                 },
+                "history": history,
                 "summary": {
                     "lastRollout": now,
                     "lastSuccessfulRollout": now,
@@ -637,3 +908,47 @@ impl ShipKube {
         self.patch(&data).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{confirm_prod_apply, delete_plan, verify_region_enabled, DeleteStep};
+    use shipcat_definitions::Environment;
+
+    #[test]
+    fn apply_only_to_enabled_regions() {
+        let regions = vec!["dev-uk".to_string(), "staging-uk".to_string()];
+        let err = verify_region_enabled("fake-ask", "prod-uk", &regions, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot apply 'fake-ask' to prod-uk as it is not enabled there (enabled regions: dev-uk, staging-uk) - use --force to override"
+        );
+        assert!(verify_region_enabled("fake-ask", "prod-uk", &regions, true).is_ok());
+        let err = verify_region_enabled("fake-ask", "prod-uk", &[], false).unwrap_err();
+        assert!(err.to_string().contains("(enabled regions: none)"));
+    }
+
+    #[test]
+    fn prod_apply_needs_confirmation() {
+        let prod = ("prod-uk", &Environment::Prod);
+        let mut empty = std::io::empty();
+        assert!(confirm_prod_apply("fake-ask", Some("1.0.0"), prod, false, &mut empty).is_err());
+        assert!(confirm_prod_apply("fake-ask", Some("1.0.0"), prod, true, &mut empty).is_ok());
+        let mut typed = "fake-ask\n".as_bytes();
+        assert!(confirm_prod_apply("fake-ask", None, prod, false, &mut typed).is_ok());
+
+        let dev = ("dev-uk", &Environment::Dev);
+        assert!(confirm_prod_apply("fake-ask", None, dev, false, &mut empty).is_ok());
+    }
+
+    #[test]
+    fn delete_plan_finalizer_order() {
+        assert_eq!(delete_plan(false), vec![DeleteStep::DeleteManifest]);
+        assert_eq!(delete_plan(true), vec![
+            DeleteStep::AddFinalizer,
+            DeleteStep::DeleteManifest,
+            DeleteStep::DeleteResources,
+            DeleteStep::DeleteKongApis,
+            DeleteStep::RemoveFinalizer,
+        ]);
+    }
+}
@@ -2,8 +2,39 @@ use super::{Config, Region, Result};
 use crate::kubectl;
 use std::process::Command;
 
+/// State of a teleport session according to `tsh status`
+#[derive(Debug, PartialEq)]
+enum Session {
+    /// Missing or expired, needs a new login
+    Expired,
+    /// Active, with the remaining validity when tsh reports it
+    Active(Option<String>),
+}
+
+/// Parse the Valid until line of `tsh status`
+///
+/// Looks like `Valid until: 2020-11-25 21:35:24 +0000 GMT [valid for 2h13m0s]`.
+fn parse_valid_until(valid_ln: &str) -> Session {
+    if valid_ln.contains("EXPIRED") {
+        return Session::Expired;
+    }
+    let remaining = valid_ln
+        .split("[valid for ")
+        .nth(1)
+        .and_then(|r| r.split(']').next())
+        .map(|r| {
+            // drop zero seconds from whole minutes, e.g. 2h13m0s
+            if r.ends_with("m0s") {
+                r[..r.len() - 2].to_string()
+            } else {
+                r.to_string()
+            }
+        });
+    Session::Active(remaining)
+}
+
 /// Check if teleport expired
-fn need_teleport_login(url: &str) -> Result<bool> {
+fn need_teleport_login(url: &str) -> Result<Session> {
     let args = vec!["status".to_string()]; // tsh status doesn't seem to have a nice filtering or yaml output :(
                                            // https://github.com/gravitational/teleport/issues/2869
     let s = Command::new("tsh").args(&args).output()?;
@@ -13,10 +44,10 @@ fn need_teleport_login(url: &str) -> Result<bool> {
     if let Some(idx) = lines.iter().position(|l| l.contains(url)) {
         let valid_ln = lines[idx + 5]; // idx+5 is Valid until line
         debug!("Checking Valid line {}", valid_ln);
-        Ok(valid_ln.contains("EXPIRED"))
+        Ok(parse_valid_until(valid_ln))
     } else {
         debug!("No {} found in tsh status", url);
-        Ok(true)
+        Ok(Session::Expired)
     }
 }
 
@@ -42,7 +73,7 @@ pub async fn login(conf: &Config, region: &Region, force: bool) -> Result<()> {
     if let Some(cluster) = conf.find_owning_cluster(&region) {
         if let Some(teleport) = &cluster.teleport {
             ensure_teleport()?;
-            let needs_login = need_teleport_login(&teleport)?;
+            let session = need_teleport_login(&teleport)?;
             if force {
                 let tsh_state_file = dirs::home_dir()
                     .expect("need a homedir")
@@ -51,13 +82,16 @@ pub async fn login(conf: &Config, region: &Region, force: bool) -> Result<()> {
                 debug!("Removing {}", tsh_state_file.display());
                 let _ = std::fs::remove_file(tsh_state_file); // don't care if the file is missing
             }
-            if needs_login || force {
-                let tsh_args = vec![
+            if session == Session::Expired || force {
+                let mut tsh_args = vec![
                     "login".into(),
-                    // NB: using default TTL here because there might be a hard limit
                     format!("--proxy={url}:443", url = &teleport),
                     "--auth=github".into(),
                 ];
+                // NB: tsh default TTL unless configured because there might be a hard limit
+                if let Some(ttl) = cluster.teleport_ttl {
+                    tsh_args.push(format!("--ttl={}", ttl));
+                }
                 info!("tsh {}", tsh_args.join(" "));
                 let s = Command::new("tsh").args(&tsh_args).output()?;
                 let out = String::from_utf8_lossy(&s.stdout);
@@ -68,6 +102,8 @@ pub async fn login(conf: &Config, region: &Region, force: bool) -> Result<()> {
                 if !s.status.success() {
                     bail!("tsh login: {}", err);
                 }
+            } else if let Session::Active(Some(remaining)) = session {
+                info!("Reusing active session for {}, valid for {}", teleport, remaining);
             } else {
                 info!("Reusing active session for {}", teleport);
             }
@@ -97,3 +133,18 @@ pub async fn login(conf: &Config, region: &Region, force: bool) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_valid_until, Session};
+
+    #[test]
+    fn parses_remaining_validity() {
+        let ln = "  Valid until:        2020-11-25 21:35:24 +0000 GMT [valid for 2h13m0s]";
+        assert_eq!(parse_valid_until(ln), Session::Active(Some("2h13m".into())));
+        let ln = "  Valid until:        2020-11-25 21:35:24 +0000 GMT [EXPIRED]";
+        assert_eq!(parse_valid_until(ln), Session::Expired);
+        let ln = "  Valid until:        2020-11-25 21:35:24 +0000 GMT";
+        assert_eq!(parse_valid_until(ln), Session::Active(None));
+    }
+}
@@ -1,11 +1,13 @@
 use futures::stream::{self, StreamExt};
 use shipcat_definitions::{BaseManifest, Config, Region, ShipcatConfig};
 use shipcat_filebacked::SimpleManifest;
+use std::path::Path;
 
 use super::{kubectl, Error, ErrorKind, Result};
 use crate::{
-    apply, diff, helm,
-    kubeapi::ShipKube,
+    apply, diff, graph, helm,
+    kubeapi::{self, KubeClient, ShipKube},
+    track::RolloutWait,
     webhooks::{self, UpgradeState},
 };
 
@@ -13,13 +15,13 @@ struct DiffResult {
     name: String,
     diff: Option<String>,
 }
-async fn diff_summary(svc: String, conf: &Config, reg: &Region) -> Result<DiffResult> {
+async fn diff_summary(svc: String, conf: &Config, reg: &Region, kube: &KubeClient) -> Result<DiffResult> {
     let mut mf = shipcat_filebacked::load_manifest(&svc, &conf, &reg)
         .await?
         .complete(&reg)
         .await?;
     // complete with version and uid from crd
-    let s = ShipKube::new(&mf).await?;
+    let s = ShipKube::new(&mf, kube);
     let crd = s.get().await?;
     mf.version = mf.version.or(crd.spec.version);
     mf.uid = crd.metadata.uid;
@@ -46,9 +48,10 @@ async fn diff_summary(svc: String, conf: &Config, reg: &Region) -> Result<DiffRe
 pub async fn mass_diff(conf: &Config, reg: &Region) -> Result<()> {
     let svcs = shipcat_filebacked::available(conf, reg).await?;
     assert!(conf.has_secrets());
+    let kube = &KubeClient::new(reg).await?;
 
     let mut buffered = stream::iter(svcs)
-        .map(move |mf| diff_summary(mf.base.name, &conf, &reg))
+        .map(move |mf| diff_summary(mf.base.name, &conf, &reg, kube))
         .buffer_unordered(10);
 
     let mut errs = vec![];
@@ -96,7 +99,7 @@ async fn check_summary(svc: String, skipped: &[String], conf: &Config, reg: &Reg
     mf.uid = Some("FAKE-GUID".to_string());
 
     info!("verifying template for {}", mf.name);
-    let tpl = helm::template(&mf, None).await?;
+    let tpl = helm::template(&mf, None, true).await?;
     helm::template_check(&mf, reg, skipped, &tpl)?;
     Ok(mf.name)
 }
@@ -132,6 +135,115 @@ pub async fn mass_template_verify(conf: &Config, reg: &Region, skipped: &[String
     Ok(())
 }
 
+async fn template_summary(svc: String, conf: &Config, reg: &Region) -> Result<(String, String)> {
+    let mut mf = shipcat_filebacked::load_manifest(&svc, &conf, &reg)
+        .await?
+        .stub(&reg)
+        .await?;
+    mf.version = mf.version.or(Some("latest".to_string()));
+    mf.uid = Some("FAKE-GUID".to_string());
+
+    info!("templating {}", mf.name);
+    let tpl = helm::template(&mf, None, true).await?;
+    Ok((mf.name, tpl))
+}
+
+/// Join rendered templates into one multi-document yaml stream
+///
+/// Services are ordered by name so the output is stable.
+pub fn join_documents(mut rendered: Vec<(String, String)>) -> String {
+    rendered.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut out = String::new();
+    for (_, tpl) in rendered {
+        let doc = tpl.trim().trim_start_matches("---").trim_start();
+        out.push_str("---\n");
+        out.push_str(doc);
+        out.push('\n');
+    }
+    out
+}
+
+/// Marker file identifying a directory written by `cluster template`
+const OUTPUT_MARKER: &str = ".shipcat-output";
+
+/// How `cluster template` treats an existing output directory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputMode {
+    /// Wipe the directory first (refused for non-shipcat directories unless forced)
+    Replace { force: bool },
+    /// Only replace the subdirectories of the rendered services
+    Append,
+}
+
+/// Write each rendered service into `{dir}/{service}/template.yml`
+pub fn write_output(dir: &Path, rendered: Vec<(String, String)>, mode: OutputMode) -> Result<()> {
+    use std::fs;
+    if let OutputMode::Replace { force } = mode {
+        if dir.exists() {
+            let empty = fs::read_dir(dir)?.next().is_none();
+            if !empty && !dir.join(OUTPUT_MARKER).is_file() && !force {
+                bail!(
+                    "Refusing to wipe {}: it does not look like a shipcat output directory (use --force)",
+                    dir.display()
+                );
+            }
+            fs::remove_dir_all(dir)?;
+        }
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(OUTPUT_MARKER), "")?;
+    for (name, tpl) in rendered {
+        let svcdir = dir.join(&name);
+        if svcdir.exists() {
+            fs::remove_dir_all(&svcdir)?;
+        }
+        fs::create_dir_all(&svcdir)?;
+        let pth = svcdir.join("template.yml");
+        debug!("Writing template for {} to {}", name, pth.display());
+        fs::write(&pth, tpl)?;
+    }
+    Ok(())
+}
+
+/// Render all services in a region
+///
+/// Prints a single multi-document yaml stream, or writes one directory per service
+/// into `output_dir` when given.
+pub async fn mass_template(
+    conf: &Config,
+    reg: &Region,
+    output_dir: Option<(&Path, OutputMode)>,
+    n_workers: usize,
+) -> Result<()> {
+    let svcs = shipcat_filebacked::available(conf, reg).await?;
+
+    let mut buffered = stream::iter(svcs)
+        .map(move |mf| template_summary(mf.base.name, &conf, &reg))
+        .buffer_unordered(n_workers);
+
+    let (mut errs, mut rendered): (Vec<Error>, Vec<_>) = (vec![], vec![]);
+    while let Some(r) = buffered.next().await {
+        match r {
+            Ok(t) => rendered.push(t),
+            Err(e) => errs.push(e),
+        }
+    }
+    if !errs.is_empty() {
+        for e in &errs {
+            error!("{}", e);
+            debug!("{:?}", e);
+        }
+        bail!("Failed to template {} manifests", errs.len());
+    }
+
+    if let Some((dir, mode)) = output_dir {
+        write_output(dir, rendered, mode)?;
+    } else {
+        print!("{}", join_documents(rendered));
+    }
+    Ok(())
+}
+
 /// Apply CRDs in all region
 pub async fn crd_install(reg: &Region) -> Result<()> {
     use shipcat_definitions::gen_all_crds;
@@ -175,6 +287,7 @@ async fn crd_reconcile(
         .unwrap()
         .clone();
 
+    let kube = KubeClient::new(&region_sec).await?;
     webhooks::reconcile_event(UpgradeState::Pending, &region_sec).await;
     // Always reconcile the CRDs (definitions themselves) first
     crd_install(&region_base).await?;
@@ -197,7 +310,7 @@ async fn crd_reconcile(
     }
     for svc in excess {
         // NB: doing deletion sequentially...
-        apply::delete(&svc, &region_sec, &config_sec).await?;
+        apply::delete(&svc, &region_sec, &config_sec, &kube).await?;
     }
 
     info!(
@@ -209,14 +322,24 @@ async fn crd_reconcile(
     webhooks::reconcile_event(UpgradeState::Started, &region_sec).await;
     // then parallel apply the remaining ones
     let force = std::env::var("SHIPCAT_MASS_RECONCILE").unwrap_or("0".into()) == "1";
-    let wait_for_rollout = true;
+    let wait_for_rollout = RolloutWait::Estimated;
 
     let conf = config_sec.clone();
     let reg = region_sec.clone();
+    let kube = &kube;
     let mut buffered = stream::iter(svcs)
         .map(|mf| {
             debug!("Running CRD reconcile for {:?}", mf.base.name);
-            apply::apply(mf.base.name, force, &reg, &conf, wait_for_rollout, None)
+            apply::apply(
+                mf.base.name,
+                force,
+                &reg,
+                &conf,
+                kube,
+                wait_for_rollout,
+                None,
+                None,
+            )
         })
         .buffer_unordered(n_workers);
 
@@ -251,6 +374,78 @@ async fn crd_reconcile(
     Ok(())
 }
 
+/// Find shipcatmanifests that no longer have a corresponding service in git
+///
+/// Returns a sorted list of the names found in the cluster but not in `svcs`.
+pub fn find_orphans(found: &[String], svcs: &[String]) -> Vec<String> {
+    use std::collections::BTreeSet;
+    let requested: BTreeSet<_> = svcs.iter().collect();
+    found
+        .iter()
+        .filter(|n| !requested.contains(n))
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// List the shipcatmanifests in a namespace that are not backed by one of `svcs`
+pub async fn list_orphans(kube: &KubeClient, ns: &str, svcs: &[String]) -> Result<Vec<String>> {
+    let found = kubeapi::list_manifest_names(kube, ns).await?;
+    debug!("Found manifests: {:?}", found);
+    Ok(find_orphans(&found, svcs))
+}
+
+/// Remove shipcatmanifests in a region that are not backed by an available service
+///
+/// Reports the orphans by default. Only deletes them when `prune` is set.
+pub async fn prune_orphans(conf: &Config, reg: &Region, prune: bool) -> Result<Vec<String>> {
+    let svcs = shipcat_filebacked::available(conf, reg).await?;
+    let svc_names = svcs.into_iter().map(|x| x.base.name).collect::<Vec<_>>();
+    let kube = KubeClient::new(reg).await?;
+    let orphans = list_orphans(&kube, &reg.namespace, &svc_names).await?;
+    if orphans.is_empty() {
+        info!("No orphaned manifests found in {}", reg.name);
+        return Ok(orphans);
+    }
+    if !prune {
+        for svc in &orphans {
+            info!("Would remove orphaned manifest {} (pass --prune to delete)", svc);
+        }
+        return Ok(orphans);
+    }
+    for svc in &orphans {
+        info!("Removing orphaned manifest {}", svc);
+        apply::delete(svc, reg, conf, &kube).await?;
+    }
+    Ok(orphans)
+}
+
+/// Delete every service in a region in reverse dependency order
+///
+/// Dependents are removed before the services they depend on.
+/// Only reports the planned order unless `confirmed` is set.
+pub async fn teardown(conf: &Config, reg: &Region, confirmed: bool) -> Result<Vec<String>> {
+    let svcs = shipcat_filebacked::available(conf, reg).await?;
+    let svc_names = svcs.into_iter().map(|x| x.base.name).collect::<Vec<_>>();
+    let depgraph = graph::build_full(conf, reg).await?;
+    let order = graph::teardown_order(&depgraph)?
+        .into_iter()
+        .filter(|s| svc_names.contains(s))
+        .collect::<Vec<_>>();
+    info!("Teardown order for {}: {}", reg.name, order.join(", "));
+    if !confirmed {
+        warn!("Not deleting anything in {} without --yes", reg.name);
+        return Ok(order);
+    }
+    let kube = KubeClient::new(reg).await?;
+    for svc in &order {
+        info!("Tearing down {}", svc);
+        apply::delete(svc, reg, conf, &kube).await?;
+    }
+    Ok(order)
+}
+
 /// Apply all vault policies in a region
 ///
 /// Generates and writes policies direct to vault using their github team name as auth mappers.
@@ -370,3 +565,96 @@ async fn vault_reconcile_worker(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{find_orphans, join_documents, list_orphans, write_output, OutputMode};
+    use crate::kubeapi::KubeClient;
+    use kube::{client::APIClient, config::Configuration};
+    use std::fs;
+
+    #[test]
+    fn multi_document_stream_sorted() {
+        let rendered = vec![
+            (
+                "fake-storage".to_string(),
+                "---\nkind: Deployment\nname: fake-storage\n".to_string(),
+            ),
+            (
+                "fake-ask".to_string(),
+                "kind: Deployment\nname: fake-ask".to_string(),
+            ),
+        ];
+        let out = join_documents(rendered);
+        let docs = out.split("---\n").filter(|d| !d.is_empty()).collect::<Vec<_>>();
+        assert_eq!(docs, vec![
+            "kind: Deployment\nname: fake-ask\n",
+            "kind: Deployment\nname: fake-storage\n"
+        ]);
+    }
+
+    #[test]
+    fn output_dir_append_and_safety() {
+        let dir = std::env::temp_dir().join(format!("shipcat-output-{}", uuid::Uuid::new_v4()));
+        let tpl = |name: &str| vec![(name.to_string(), format!("name: {}\n", name))];
+        write_output(&dir, tpl("fake-ask"), OutputMode::Append).unwrap();
+        write_output(&dir, tpl("fake-storage"), OutputMode::Append).unwrap();
+        assert!(dir.join("fake-ask").join("template.yml").is_file());
+        assert!(dir.join("fake-storage").join("template.yml").is_file());
+
+        // a shipcat output dir can be replaced
+        write_output(&dir, tpl("fake-ask"), OutputMode::Replace { force: false }).unwrap();
+        assert!(!dir.join("fake-storage").exists());
+
+        // other directories are only wiped when forced
+        let other = dir.join("fake-ask");
+        let err = write_output(&other, tpl("x"), OutputMode::Replace { force: false }).unwrap_err();
+        assert!(err.to_string().starts_with("Refusing to wipe"));
+        assert!(other.join("template.yml").is_file());
+        write_output(&other, tpl("x"), OutputMode::Replace { force: true }).unwrap();
+        assert!(!other.join("template.yml").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn orphans_without_git_service() {
+        let found = vec![
+            "fake-ask".to_string(),
+            "deleted-svc".into(),
+            "fake-storage".into(),
+        ];
+        let svcs = vec!["fake-ask".to_string(), "fake-storage".into(), "new-svc".into()];
+        assert_eq!(find_orphans(&found, &svcs), vec!["deleted-svc".to_string()]);
+        assert!(find_orphans(&[], &svcs).is_empty());
+    }
+
+    #[tokio::test]
+    async fn orphans_listed_from_the_cluster() {
+        let item = |name: &str| {
+            serde_json::json!({
+                "apiVersion": "babylontech.co.uk/v1",
+                "kind": "ShipcatManifest",
+                "metadata": { "name": name },
+                "spec": { "name": name, "version": "1.0.0" },
+            })
+        };
+        let list = serde_json::json!({
+            "metadata": {},
+            "items": [item("fake-ask"), item("deleted-svc")],
+        });
+        let mocked = mockito::mock(
+            "GET",
+            mockito::Matcher::Regex("^/apis/babylontech.co.uk/v1/namespaces/orphans/shipcatmanifests".into()),
+        )
+        .with_header("content-type", "application/json")
+        .with_body(list.to_string())
+        .create();
+
+        let config = Configuration::new(mockito::server_url(), reqwest::Client::new());
+        let kube = KubeClient::with_client(APIClient::new(config), 2);
+        let svcs = vec!["fake-ask".to_string(), "fake-storage".into()];
+        let orphans = list_orphans(&kube, "orphans", &svcs).await.unwrap();
+        mocked.assert();
+        assert_eq!(orphans, vec!["deleted-svc".to_string()]);
+    }
+}
@@ -0,0 +1,103 @@
+use clap::{App, Shell};
+use std::io::Write;
+
+use super::Result;
+
+/// Bash hook completing regions and service names dynamically
+///
+/// Wraps the generated `_shipcat` and swaps its argument placeholders for real values.
+const BASH_HOOK: &str = r#"
+_shipcat_dynamic() {
+    local cur prev i
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ "${prev}" == "-r" || "${prev}" == "--region" ]]; then
+        COMPREPLY=( $(compgen -W "$(shipcat __complete-regions 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _shipcat "$@"
+    if [[ ${COMP_CWORD} -gt 1 && "${cur}" != -* ]]; then
+        local static=()
+        for i in "${COMPREPLY[@]}"; do
+            [[ "${i}" == -* || "${i}" == \<* ]] || static+=( "${i}" )
+        done
+        COMPREPLY=( "${static[@]}" $(compgen -W "$(shipcat __complete-services 2>/dev/null)" -- "${cur}") )
+    fi
+}
+complete -F _shipcat_dynamic -o bashdefault -o default shipcat
+"#;
+
+/// Zsh hook completing regions and service names dynamically
+const ZSH_HOOK: &str = r#"
+_shipcat_dynamic() {
+    if [[ ${words[CURRENT-1]} == (-r|--region) ]]; then
+        compadd -- ${(f)"$(shipcat __complete-regions 2>/dev/null)"}
+        return
+    fi
+    _shipcat "$@"
+    if (( CURRENT > 2 )) && [[ ${words[CURRENT]} != -* ]]; then
+        compadd -- ${(f)"$(shipcat __complete-services 2>/dev/null)"}
+    fi
+}
+compdef _shipcat_dynamic shipcat
+"#;
+
+/// Fish completions for regions and service names
+const FISH_HOOK: &str = r#"
+complete -c shipcat -s r -l region -x -a "(shipcat __complete-regions 2>/dev/null)"
+complete -c shipcat -n "not __fish_use_subcommand" -f -a "(shipcat __complete-services 2>/dev/null)"
+"#;
+
+/// Write the completion script for a shell
+///
+/// The script generated from the arg parser only knows static subcommands and flags,
+/// so bash, zsh and fish get a hook appended that asks shipcat for services and regions.
+pub fn generate<W: Write>(mut app: App<'_, '_>, shell: Shell, out: &mut W) -> Result<()> {
+    let mut buf = vec![];
+    app.gen_completions_to("shipcat", shell, &mut buf);
+    let script = String::from_utf8_lossy(&buf).into_owned();
+    let (script, hook) = match shell {
+        Shell::Bash => (script.as_str(), BASH_HOOK),
+        // the generated script calls itself for autoloading, which fails when sourced
+        Shell::Zsh => (
+            script.trim_end().trim_end_matches("_shipcat \"$@\"").trim_end(),
+            ZSH_HOOK,
+        ),
+        Shell::Fish => (script.as_str(), FISH_HOOK),
+        _ => (script.as_str(), ""),
+    };
+    writeln!(out, "{}", script.trim_end())?;
+    write!(out, "{}", hook)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use crate::Result;
+    use clap::{App, Arg, Shell, SubCommand};
+
+    fn app() -> App<'static, 'static> {
+        App::new("shipcat")
+            .arg(Arg::with_name("region").long("region").takes_value(true))
+            .subcommand(SubCommand::with_name("status").arg(Arg::with_name("service").required(true)))
+    }
+
+    #[test]
+    fn completions_hook_dynamic_values() -> Result<()> {
+        for shell in &[Shell::Bash, Shell::Zsh] {
+            let mut out = vec![];
+            generate(app(), *shell, &mut out)?;
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("_shipcat()"));
+            assert!(script.contains("shipcat __complete-services"));
+            assert!(script.contains("shipcat __complete-regions"));
+        }
+        let mut out = vec![];
+        generate(app(), Shell::Zsh, &mut out)?;
+        let script = String::from_utf8(out).unwrap();
+        assert!(!script.contains("\n_shipcat \"$@\""));
+        assert!(script.trim_end().ends_with("compdef _shipcat_dynamic shipcat"));
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use super::{Config, ConfigState, Manifest, Region, Result};
-use crate::{git, helm, kubectl};
+use crate::{git, helm, kubectl, redact::Redactor};
 use regex::Regex;
+use serde_json::Value;
 use shipcat_definitions::ShipcatManifest;
-use std::process::Command;
+use std::{collections::VecDeque, fmt, process::Command};
 
 /// YAML serialisation of a manifest.
 ///
@@ -13,7 +14,7 @@ async fn as_yaml(svc: &str, conf: &Config, region: &Region) -> Result<String> {
     let mf = shipcat_filebacked::load_manifest(&svc, conf, region).await?;
     if let Ok(m) = mf.verify_region() {
         let yaml = serde_yaml::to_string(&m)?;
-        Ok(yaml)
+        Ok(Redactor::for_manifest(&m).redact(&yaml))
     } else {
         Ok("".to_string())
     }
@@ -25,7 +26,7 @@ async fn as_yaml(svc: &str, conf: &Config, region: &Region) -> Result<String> {
 /// then goes back to previous branch and pops the stash.
 ///
 /// Because this does fiddle with git state while running it is not the default implementation.
-pub async fn values_vs_git(svc: &str, conf: &Config, region: &Region) -> Result<bool> {
+pub async fn values_vs_git(svc: &str, conf: &Config, region: &Region, context: usize) -> Result<bool> {
     let after = as_yaml(&svc, conf, region).await?;
 
     // move git to get before state:
@@ -48,7 +49,7 @@ pub async fn values_vs_git(svc: &str, conf: &Config, region: &Region) -> Result<
     git::checkout("-")?;
 
     // display diff
-    shell_diff(&before, &after, "before", "after")
+    shell_diff(&before, &after, "before", "after", context)
 }
 
 /// Fast local compare of shipcat template for two regions
@@ -57,6 +58,7 @@ pub async fn values_vs_region(
     conf: &Config,
     region: &Region,
     ref_region: &Region,
+    context: usize,
 ) -> Result<bool> {
     let before_region = format!("{}.{}", svc, ref_region.name);
     let before_values = as_yaml(svc, conf, ref_region).await?;
@@ -65,20 +67,26 @@ pub async fn values_vs_region(
     let after_values = as_yaml(svc, conf, region).await?;
 
     // display diff
-    shell_diff(&before_values, &after_values, &before_region, &after_region)
+    shell_diff(
+        &before_values,
+        &after_values,
+        &before_region,
+        &after_region,
+        context,
+    )
 }
 
 /// Fast local git compare of shipcat template
 ///
 /// Because this uses the template in master against local state,
 /// we don't resolve secrets for this (would compare equal values anyway).
-pub async fn template_vs_git(svc: &str, conf: &Config, region: &Region) -> Result<bool> {
+pub async fn template_vs_git(svc: &str, conf: &Config, region: &Region, context: usize) -> Result<bool> {
     let afterpth = Path::new(".").join("after.shipcat.gen.yml");
     let mf_after = shipcat_filebacked::load_manifest(svc, conf, region)
         .await?
         .stub(region)
         .await?;
-    let _after = helm::template(&mf_after, Some(afterpth.clone())).await?;
+    let _after = helm::template(&mf_after, Some(afterpth.clone()), true).await?;
 
     // move git to get before state:
     let merge_base = git::merge_base()?;
@@ -97,7 +105,7 @@ pub async fn template_vs_git(svc: &str, conf: &Config, region: &Region) -> Resul
         .await?
         .stub(region)
         .await?;
-    let _before = helm::template(&mf_before, Some(beforepth.clone())).await?;
+    let _before = helm::template(&mf_before, Some(beforepth.clone()), true).await?;
 
     // move git back
     if needs_stash {
@@ -107,13 +115,16 @@ pub async fn template_vs_git(svc: &str, conf: &Config, region: &Region) -> Resul
 
     // display diff
     // doesn't reuse shell_diff because we already have files from direct::template
-    let args = ["-u", "before.shipcat.gen.yml", "after.shipcat.gen.yml"];
+    let unified = format!("-U{}", context);
+    let args = [&unified, "before.shipcat.gen.yml", "after.shipcat.gen.yml"];
     debug!("diff {}", args.join(" "));
-    let s = Command::new("diff").args(&args).status()?;
+    let s = Command::new("diff").args(&args).output()?;
     // cleanup
     fs::remove_file(beforepth)?;
     fs::remove_file(afterpth)?;
-    Ok(s.success())
+    let redactor = Redactor::for_manifests(&[&mf_before, &mf_after]);
+    print!("{}", redactor.redact(&String::from_utf8_lossy(&s.stdout)));
+    Ok(s.status.success())
 }
 
 use std::{
@@ -126,9 +137,10 @@ use std::{
 ///
 /// Generate crd as we write it and pipe it to `kubectl diff -`
 /// Only works on clusters with kubectl 1.13 on the server side, so not available everywhere
-pub async fn values_vs_kubectl(svc: &str, conf: &Config, region: &Region) -> Result<bool> {
+pub async fn values_vs_kubectl(svc: &str, conf: &Config, region: &Region, context: usize) -> Result<bool> {
     // Generate crd in a temp file:
     let mf = shipcat_filebacked::load_manifest(svc, conf, region).await?;
+    let redactor = Redactor::for_manifest(&mf);
     let crd = ShipcatManifest::from(mf);
     let encoded = serde_yaml::to_string(&crd)?;
     let cfile = format!("{}.shipcat.crd.gen.yml", svc);
@@ -138,7 +150,7 @@ pub async fn values_vs_kubectl(svc: &str, conf: &Config, region: &Region) -> Res
     writeln!(f, "{}", encoded)?;
     // shell out to kubectl:
     let (out, _err, success) = kubectl::diff(pth.clone(), &region.namespace).await?;
-    println!("{}", out);
+    println!("{}", redactor.redact(&limit_context(&out, context)));
     // cleanup:
     fs::remove_file(pth)?;
     Ok(success)
@@ -153,7 +165,7 @@ pub async fn template_vs_kubectl(mf: &Manifest) -> Result<Option<String>> {
     let tfile = format!("{}.shipcat.tpl.gen.yml", mf.name);
     let pth = Path::new(".").join(tfile);
 
-    let _tpl = helm::template(&mf, Some(pth.clone())).await?;
+    let _tpl = helm::template(&mf, Some(pth.clone()), true).await?;
 
     let (out, err, success) = kubectl::diff(pth.clone(), &mf.namespace).await?;
     // cleanup:
@@ -170,7 +182,28 @@ pub async fn template_vs_kubectl(mf: &Manifest) -> Result<Option<String>> {
 
 // Compare using diff(1)
 // difference libraries all seemed to be lacking somewhat
-fn shell_diff(before: &str, after: &str, before_name: &str, after_name: &str) -> Result<bool> {
+fn shell_diff(
+    before: &str,
+    after: &str,
+    before_name: &str,
+    after_name: &str,
+    context: usize,
+) -> Result<bool> {
+    let (out, same) = unified_diff(before, after, before_name, after_name, context)?;
+    print!("{}", out);
+    Ok(same)
+}
+
+/// Unified diff of two strings via diff(1)
+///
+/// Returns the diff output along with whether the inputs were identical.
+pub fn unified_diff(
+    before: &str,
+    after: &str,
+    before_name: &str,
+    after_name: &str,
+    context: usize,
+) -> Result<(String, bool)> {
     let beforefilename = format!("{}.shipcat.gen.yml", before_name);
     let beforepth = Path::new(".").join(&beforefilename);
     debug!("Writing before to {}", beforepth.display());
@@ -183,14 +216,193 @@ fn shell_diff(before: &str, after: &str, before_name: &str, after_name: &str) ->
     let mut f = File::create(&afterpth)?;
     writeln!(f, "{}", after)?;
 
-    let args = ["-u", &beforefilename, &afterfilename];
+    let unified = format!("-U{}", context);
+    let args = [unified.as_str(), beforefilename.as_str(), afterfilename.as_str()];
     debug!("diff {}", args.join(" "));
-    let s = Command::new("diff").args(&args).status()?;
+    let s = Command::new("diff").args(&args).output()?;
     // cleanup
     fs::remove_file(beforepth)?;
     fs::remove_file(afterpth)?;
 
-    Ok(s.success())
+    let out = String::from_utf8_lossy(&s.stdout).to_string();
+    Ok((out, s.status.success()))
+}
+
+/// Default number of unchanged lines shown around a change
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Limit the unchanged lines surrounding changes in a unified diff
+///
+/// Equivalent to `diff -U context` for diffs we do not generate ourselves (e.g. kubectl diff).
+/// Hunks are split where changes end up further apart than twice the context,
+/// and their headers are recomputed for the lines kept. Lines outside hunks are kept as is.
+pub fn limit_context(diff: &str, context: usize) -> String {
+    let hunk_header = Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@(.*)$").unwrap();
+    let mut res = vec![];
+    let mut hunk: Option<HunkLimiter<'_>> = None;
+    for l in diff.lines() {
+        if let Some(h) = hunk.as_mut() {
+            // a "no newline" marker belongs to the line before it
+            if !h.is_done() || l.starts_with('\\') {
+                h.push(l);
+                continue;
+            }
+        }
+        if let Some(h) = hunk.take() {
+            h.finish(&mut res);
+        }
+        match hunk_header.captures(l) {
+            Some(cap) => {
+                let num = |i: usize| cap.get(i).map_or(1, |m| m.as_str().parse().unwrap_or(0));
+                let section = cap.get(5).map_or("", |m| m.as_str());
+                let (old, new) = ((num(1), num(2)), (num(3), num(4)));
+                hunk = Some(HunkLimiter::new(old, new, section, context));
+            }
+            None => res.push(l.to_string()),
+        }
+    }
+    if let Some(h) = hunk {
+        h.finish(&mut res);
+    }
+    res.join("\n")
+}
+
+/// Lines kept from a hunk, with the (start, length) of the ranges they cover
+struct KeptLines<'a> {
+    old: (usize, usize),
+    new: (usize, usize),
+    lines: Vec<&'a str>,
+}
+
+impl<'a> KeptLines<'a> {
+    fn push(&mut self, l: &'a str, old: usize, new: usize) {
+        self.lines.push(l);
+        self.old.1 += old;
+        self.new.1 += new;
+    }
+
+    fn header(&self, section: &str) -> String {
+        // an empty range starts at the line before it
+        let range = |(start, len): (usize, usize)| match len {
+            0 => format!("{},0", start.saturating_sub(1)),
+            1 => start.to_string(),
+            _ => format!("{},{}", start, len),
+        };
+        format!("@@ -{} +{} @@{}", range(self.old), range(self.new), section)
+    }
+}
+
+/// Streams the body of one hunk, keeping `context` unchanged lines around its changes
+struct HunkLimiter<'a> {
+    context: usize,
+    section: &'a str,
+    /// Line numbers of the next line on each side
+    pos: (usize, usize),
+    /// Lines of the hunk still to come on each side
+    left: (usize, usize),
+    kept: Option<KeptLines<'a>>,
+    /// Unchanged lines kept after the last change
+    trailing: usize,
+    /// Unchanged lines dropped since then, the last `context` of which are pending
+    gap: usize,
+    pending: VecDeque<((usize, usize), &'a str)>,
+    last_kept: bool,
+    out: Vec<String>,
+}
+
+impl<'a> HunkLimiter<'a> {
+    fn new(old: (usize, usize), new: (usize, usize), section: &'a str, context: usize) -> Self {
+        // empty ranges are numbered from the line before
+        let first = |(start, len): (usize, usize)| if len == 0 { start + 1 } else { start };
+        HunkLimiter {
+            context,
+            section,
+            pos: (first(old), first(new)),
+            left: (old.1, new.1),
+            kept: None,
+            trailing: 0,
+            gap: 0,
+            pending: VecDeque::new(),
+            last_kept: false,
+            out: vec![],
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.left == (0, 0)
+    }
+
+    fn push(&mut self, l: &'a str) {
+        match l.chars().next() {
+            Some('-') => self.change(l, 1, 0),
+            Some('+') => self.change(l, 0, 1),
+            Some('\\') if self.last_kept => {
+                if let Some(k) = self.kept.as_mut() {
+                    k.push(l, 0, 0);
+                }
+            }
+            Some('\\') => {}
+            _ => self.unchanged(l),
+        }
+    }
+
+    fn advance(&mut self, old: usize, new: usize) {
+        self.pos = (self.pos.0 + old, self.pos.1 + new);
+        self.left = (self.left.0.saturating_sub(old), self.left.1.saturating_sub(new));
+    }
+
+    fn unchanged(&mut self, l: &'a str) {
+        let pos = self.pos;
+        self.advance(1, 1);
+        match self.kept.as_mut() {
+            Some(k) if self.trailing < self.context => {
+                k.push(l, 1, 1);
+                self.trailing += 1;
+                self.last_kept = true;
+            }
+            _ => {
+                self.gap += 1;
+                self.pending.push_back((pos, l));
+                if self.pending.len() > self.context {
+                    self.pending.pop_front();
+                }
+                self.last_kept = false;
+            }
+        }
+    }
+
+    fn change(&mut self, l: &'a str, old: usize, new: usize) {
+        // too far from the previous change to share its header
+        if self.gap > self.context {
+            self.flush();
+        }
+        let start = self.pending.front().map_or(self.pos, |(pos, _)| *pos);
+        let kept = self.kept.get_or_insert_with(|| KeptLines {
+            old: (start.0, 0),
+            new: (start.1, 0),
+            lines: vec![],
+        });
+        for (_, p) in self.pending.drain(..) {
+            kept.push(p, 1, 1);
+        }
+        kept.push(l, old, new);
+        self.advance(old, new);
+        self.trailing = 0;
+        self.gap = 0;
+        self.last_kept = true;
+    }
+
+    fn flush(&mut self) {
+        if let Some(k) = self.kept.take() {
+            self.out.push(k.header(self.section));
+            self.out.extend(k.lines.into_iter().map(String::from));
+        }
+    }
+
+    fn finish(mut self, res: &mut Vec<String>) {
+        self.flush();
+        res.append(&mut self.out);
+    }
 }
 
 /// Minify diff output from kubectl diff
@@ -251,23 +463,113 @@ pub fn infer_version_change(diff: &str) -> Option<(String, String)> {
     None
 }
 
-/// Obfuscate a set of secrets from an input string
-pub fn obfuscate_secrets(input: String, secrets: Vec<String>) -> String {
-    let mut out = input;
-    for s in secrets {
-        // If your secret is less than 8 characters, we won't obfuscate it
-        // Mostly for fear of clashing with other parts of the output,
-        // but also because it's an insecure secret anyway
-        if s.len() >= 8 {
-            out = out.replace(&s, "************");
+/// Fields that always differ between regions, so are not worth comparing
+const REGION_INTRINSIC_FIELDS: &[&str] = &["region", "environment", "namespace", "uid", "regions"];
+
+/// A single field that differs between two manifests
+#[derive(Debug, PartialEq)]
+pub struct FieldChange {
+    /// Dotted path to the field, e.g. `resources.limits.cpu`
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<Value>| v.as_ref().map_or("<unset>".into(), |v| v.to_string());
+        let (before, after) = (show(&self.before), show(&self.after));
+        write!(f, "{}: {} -> {}", self.path, before, after)
+    }
+}
+
+/// Recursively collect differing leaves of two serialized manifests
+///
+/// Objects are compared key by key, anything else (including lists) as a whole.
+fn collect_changes(path: &str, before: Option<&Value>, after: Option<&Value>, out: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (Some(Value::Object(b)), Some(Value::Object(a))) => {
+            let mut keys = b.keys().chain(a.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for k in keys {
+                let child = if path.is_empty() {
+                    k.to_string()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                collect_changes(&child, b.get(k), a.get(k), out);
+            }
+        }
+        (b, a) if b != a => out.push(FieldChange {
+            path: path.to_string(),
+            before: b.cloned(),
+            after: a.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Field level differences between the same service built for two regions
+///
+/// Region intrinsic fields like `namespace` and `region` are ignored.
+pub fn field_diff(before: &Manifest, after: &Manifest) -> Result<Vec<FieldChange>> {
+    let mut values = vec![serde_json::to_value(before)?, serde_json::to_value(after)?];
+    for v in &mut values {
+        if let Value::Object(map) = v {
+            for f in REGION_INTRINSIC_FIELDS {
+                map.remove(*f);
+            }
         }
     }
-    out
+    let mut changes = vec![];
+    collect_changes("", Some(&values[0]), Some(&values[1]), &mut changes);
+    Ok(changes)
+}
+
+/// Print the field level differences of a service between two regions
+///
+/// Returns whether the service is built identically in both.
+pub async fn manifest_vs_region(svc: &str, region: &str, ref_region: &str) -> Result<bool> {
+    let mut mfs = vec![];
+    for r in &[region, ref_region] {
+        let (conf, reg) = Config::new(ConfigState::Base, r).await?;
+        mfs.push(shipcat_filebacked::load_manifest(svc, &conf, &reg).await?);
+    }
+    let changes = field_diff(&mfs[0], &mfs[1])?;
+    for c in &changes {
+        println!("{}", c);
+    }
+    Ok(changes.is_empty())
+}
+
+/// Obfuscate a set of secrets from an input string
+pub fn obfuscate_secrets(input: String, secrets: Vec<String>) -> String {
+    Redactor::new(secrets).redact(&input)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{infer_version_change, is_version_only, minify};
+    use super::{field_diff, infer_version_change, is_version_only, limit_context, minify};
+    use crate::Manifest;
+
+    #[test]
+    fn field_diff_ignores_region_fields() {
+        let dev = Manifest::test("fake-ask");
+        let mut prod = Manifest::test("fake-ask");
+        prod.region = "prod-uk".into();
+        prod.namespace = "prod".into();
+        assert_eq!(field_diff(&dev, &prod).unwrap(), vec![]);
+
+        prod.replicaCount = Some(3);
+        prod.version = Some("1.1.0".into());
+        let changes = field_diff(&dev, &prod).unwrap();
+        let shown = changes.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(shown, vec![
+            "replicaCount: null -> 3",
+            "version: \"1.0.0\" -> \"1.1.0\""
+        ]);
+    }
 
     #[test]
     fn version_change_test() {
@@ -372,6 +674,97 @@ mod tests {
         );
     }
 
+    #[test]
+    fn diff_context_zero() {
+        let input = "--- /tmp/LIVE-A9/v1.ConfigMap.dev.raftcat
++++ /tmp/MERGED-B0/v1.ConfigMap.dev.raftcat
+@@ -1,4 +1,4 @@
+ data:
+   a: 1
+-  b: 2
++  b: 3
+   c: 4";
+        assert_eq!(
+            limit_context(input, 0),
+            "--- /tmp/LIVE-A9/v1.ConfigMap.dev.raftcat
++++ /tmp/MERGED-B0/v1.ConfigMap.dev.raftcat
+@@ -3 +3 @@
+-  b: 2
++  b: 3"
+        );
+        assert_eq!(limit_context(input, 3), input);
+    }
+
+    #[test]
+    fn diff_context_splits_hunks() {
+        let input = "--- live
++++ merged
+@@ -1,10 +1,10 @@ kind: ConfigMap
+ l1
+-l2
++L2
+ l3
+ l4
+ l5
+ l6
+ l7
+ l8
+-l9
++L9
+ l10";
+        assert_eq!(
+            limit_context(input, 1),
+            "--- live
++++ merged
+@@ -1,3 +1,3 @@ kind: ConfigMap
+ l1
+-l2
++L2
+ l3
+@@ -8,3 +8,3 @@ kind: ConfigMap
+ l8
+-l9
++L9
+ l10"
+        );
+        // six unchanged lines between the changes are shared context
+        assert_eq!(limit_context(input, 3), input);
+    }
+
+    #[test]
+    fn diff_context_body_lines_like_headers() {
+        // removing a yaml document separator, adding a line starting with ++, then a new file
+        let input = "diff -u -N /tmp/LIVE-A9/v1.ConfigMap /tmp/MERGED-B0/v1.ConfigMap
+--- /tmp/LIVE-A9/v1.ConfigMap
++++ /tmp/MERGED-B0/v1.ConfigMap
+@@ -1,5 +1,5 @@
+ a: 1
+ b: 2
+ c: 3
+ d: 4
+----
++++x
+diff -u -N /tmp/LIVE-A9/v1.Service /tmp/MERGED-B0/v1.Service
+--- /tmp/LIVE-A9/v1.Service
++++ /tmp/MERGED-B0/v1.Service
+@@ -0,0 +1 @@
++e: 5";
+        assert_eq!(
+            limit_context(input, 0),
+            "diff -u -N /tmp/LIVE-A9/v1.ConfigMap /tmp/MERGED-B0/v1.ConfigMap
+--- /tmp/LIVE-A9/v1.ConfigMap
++++ /tmp/MERGED-B0/v1.ConfigMap
+@@ -5 +5 @@
+----
++++x
+diff -u -N /tmp/LIVE-A9/v1.Service /tmp/MERGED-B0/v1.Service
+--- /tmp/LIVE-A9/v1.Service
++++ /tmp/MERGED-B0/v1.Service
+@@ -0,0 +1 @@
++e: 5"
+        );
+    }
+
     #[test]
     fn kubectl_diff_version_only() {
         let min_input = "extensions.v1beta1.Deployment.dev has changed:
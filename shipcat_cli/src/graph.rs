@@ -1,8 +1,11 @@
 use petgraph::{
-    dot,
+    algo::toposort,
     graph::{DiGraph, NodeIndex},
 };
-use std::fmt::{self, Debug};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Debug},
+};
 
 use super::{
     structs::{Dependency, DependencyProtocol},
@@ -13,12 +16,16 @@ use super::{
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ManifestNode {
     pub name: String,
+    /// Owning team from `metadata.team`
+    #[serde(default)]
+    pub team: String,
     // pub image: String,
 }
 impl ManifestNode {
     fn new(mf: &Manifest) -> Self {
         ManifestNode {
             name: mf.name.clone(),
+            team: mf.metadata.as_ref().map(|md| md.team.clone()).unwrap_or_default(),
             /* image would be nice, but requires env override atm - should be global
              * image: format!("{}", mf.image.clone().unwrap()), */
         }
@@ -54,9 +61,72 @@ impl DepEdge {
 ///
 /// This is fully serializable because it is created with `petgraph` using the serde
 /// featurset. We use that to serialize the graph as yaml.
-/// We can also convert this to `graphviz` format via some of the `petgraph` helpers.
+/// We can also render it in `graphviz` dot format via `to_dot`.
 pub type CatGraph = DiGraph<ManifestNode, DepEdge>;
 
+/// Output formats for `shipcat graph`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphFormat {
+    Yaml,
+    Json,
+    /// Graphviz dot with nodes colored by team
+    Dot,
+}
+
+/// Fill colors for teams in dot output, cycled when there are more teams
+const TEAM_COLORS: &[&str] = &[
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5", "#d9d9d9",
+    "#bc80bd",
+];
+
+/// Render a graph as graphviz dot, coloring nodes by team
+pub fn to_dot(graph: &CatGraph) -> String {
+    let teams: BTreeSet<&str> = graph.node_indices().map(|i| graph[i].team.as_str()).collect();
+    let mut out = String::from("digraph {\n");
+    for idx in graph.node_indices() {
+        let n = &graph[idx];
+        let color = teams.iter().position(|t| *t == n.team).unwrap_or_default();
+        out.push_str(&format!(
+            "    {} [ label = \"{}\" tooltip = \"{}\" style = filled fillcolor = \"{}\" ]\n",
+            idx.index(),
+            n.name,
+            n.team,
+            TEAM_COLORS[color % TEAM_COLORS.len()]
+        ));
+    }
+    for e in graph.edge_indices() {
+        if let Some((a, b)) = graph.edge_endpoints(e) {
+            out.push_str(&format!("    {} -> {}\n", a.index(), b.index()));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Render a graph in the requested format
+pub fn render(graph: &CatGraph, format: GraphFormat) -> Result<String> {
+    Ok(match format {
+        GraphFormat::Yaml => serde_yaml::to_string(graph)?,
+        GraphFormat::Json => serde_json::to_string_pretty(graph)?,
+        GraphFormat::Dot => to_dot(graph),
+    })
+}
+
+/// A service on a dependency cycle, if the graph has one
+pub fn find_cycle(graph: &CatGraph) -> Option<String> {
+    toposort(graph, None)
+        .err()
+        .map(|c| graph[c.node_id()].name.clone())
+}
+
+fn print_graph(graph: &CatGraph, format: GraphFormat) -> Result<()> {
+    if let Some(svc) = find_cycle(graph) {
+        warn!("Dependency graph has a cycle involving {}", svc);
+    }
+    println!("{}", render(graph, format)?);
+    Ok(())
+}
+
 /// Helper function that should be an impl on CatGraph
 /// Left public for tests
 pub fn nodeidx_from_name(name: &str, graph: &CatGraph) -> Option<NodeIndex> {
@@ -103,7 +173,7 @@ fn recurse_manifest(
 }
 
 /// Generate dependency graph from an entry point via recursion
-pub async fn generate(service: &str, conf: &Config, reg: &Region, dot: bool) -> Result<CatGraph> {
+pub async fn generate(service: &str, conf: &Config, reg: &Region, format: GraphFormat) -> Result<CatGraph> {
     let base = shipcat_filebacked::load_manifest(service, conf, reg).await?;
 
     let mut graph: CatGraph = DiGraph::<_, _>::new();
@@ -111,30 +181,22 @@ pub async fn generate(service: &str, conf: &Config, reg: &Region, dot: bool) ->
     let baseidx = graph.add_node(node);
 
     recurse_manifest(baseidx, &base, conf, reg, &mut graph)?;
-
-    let out = if dot {
-        format!("{:?}", dot::Dot::with_config(&graph, &[dot::Config::EdgeNoLabel]))
-    } else {
-        serde_yaml::to_string(&graph)?
-    };
-    println!("{}", out);
+    print_graph(&graph, format)?;
     Ok(graph)
 }
 
-/// Generate dependency graph from services directory
-///
-/// This is a better solution even if we wanted the result centered around
-/// one or more services as we could also show grahps reaching into the ecosystem.
-///
-/// But it would require: TODO: optionally filter edges around node(s)
-pub async fn full(dot: bool, conf: &Config, reg: &Region) -> Result<CatGraph> {
+/// Build the dependency graph for all available services in a region
+pub async fn build_full(conf: &Config, reg: &Region) -> Result<CatGraph> {
     let mut graph: CatGraph = DiGraph::<_, _>::new();
     for svc in shipcat_filebacked::available(conf, reg).await? {
         debug!("Scanning service {:?}", svc);
 
         let mf = shipcat_filebacked::load_manifest(&svc.base.name, conf, reg).await?;
-        let node = ManifestNode::new(&mf);
-        let idx = graph.add_node(node);
+        let idx = if let Some(id) = nodeidx_from_name(&mf.name, &graph) {
+            id // already added as a dependency of an earlier service
+        } else {
+            graph.add_node(ManifestNode::new(&mf))
+        };
 
         for dep in &mf.dependencies {
             let subidx = if let Some(id) = nodeidx_from_name(&dep.name, &graph) {
@@ -149,16 +211,34 @@ pub async fn full(dot: bool, conf: &Config, reg: &Region) -> Result<CatGraph> {
             graph.update_edge(idx, subidx, DepEdge::new(&dep));
         }
     }
+    Ok(graph)
+}
 
-    let out = if dot {
-        format!("{:?}", dot::Dot::with_config(&graph, &[dot::Config::EdgeNoLabel]))
-    } else {
-        serde_yaml::to_string(&graph)?
-    };
-    println!("{}", out);
+/// Generate dependency graph from services directory
+///
+/// This is a better solution even if we wanted the result centered around
+/// one or more services as we could also show grahps reaching into the ecosystem.
+///
+/// But it would require: TODO: optionally filter edges around node(s)
+pub async fn full(format: GraphFormat, conf: &Config, reg: &Region) -> Result<CatGraph> {
+    let graph = build_full(conf, reg).await?;
+    print_graph(&graph, format)?;
     Ok(graph)
 }
 
+/// Order in which services can be safely removed
+///
+/// Dependents come before their dependencies. Fails on dependency cycles.
+pub fn teardown_order(graph: &CatGraph) -> Result<Vec<String>> {
+    match toposort(graph, None) {
+        Ok(idxs) => Ok(idxs.into_iter().map(|i| graph[i].name.clone()).collect()),
+        Err(cycle) => bail!(
+            "Cannot order teardown: dependency cycle involving {}",
+            graph[cycle.node_id()].name
+        ),
+    }
+}
+
 /// Generate first level reverse dependencies for a service
 pub async fn reverse(service: &str, conf: &Config, reg: &Region) -> Result<Vec<String>> {
     let mut res = vec![];
@@ -67,10 +67,17 @@ pub async fn clone_chart(repo_url: &str) -> Result<(String, String, bool)> {
 
 /// Create helm values file for a service
 ///
-/// Requires a completed manifest (with inlined configs)
-pub async fn values(mf: &Manifest, output: &str) -> Result<()> {
+/// Requires a completed manifest (with inlined configs).
+/// An identical existing file is left untouched, and false is returned.
+pub async fn values(mf: &Manifest, output: &str) -> Result<bool> {
     let encoded = serde_yaml::to_string(&mf)?;
     let pth = Path::new(".").join(output);
+    if let Ok(existing) = fs::read(&pth).await {
+        if existing == encoded.as_bytes() {
+            debug!("Helm values for {} in {} are unchanged", mf.name, pth.display());
+            return Ok(false);
+        }
+    }
     debug!("Writing helm values for {} to {}", mf.name, pth.display());
     let mut f = File::create(&pth).await?;
     f.write_all(&encoded.as_bytes()).await?;
@@ -81,16 +88,14 @@ pub async fn values(mf: &Manifest, output: &str) -> Result<()> {
         pth.display(),
         encoded
     );
-    Ok(())
+    Ok(true)
 }
 
 /// Analogue of helm template
 ///
-/// Generates helm values to disk, then passes it to helm template
-pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String> {
-    let hfile = format!("{}.helm.gen.yml", mf.name);
-    values(&mf, &hfile).await?;
-
+/// Generates helm values to disk, then passes it to helm template.
+/// With `validate`, every rendered document must also parse as yaml.
+pub async fn template(mf: &Manifest, output: Option<PathBuf>, validate: bool) -> Result<String> {
     let chart = mf.chart.clone().unwrap();
     if chart.starts_with("git@") {
         let (_tpl, tplerr, success) = clone_chart(&chart).await?;
@@ -99,6 +104,14 @@ pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String>
             bail!("helm failed to fetch template");
         }
     }
+    // fail early rather than half way through a helm template
+    let tpldir = Path::new(".").join("charts").join(&chart).join("templates");
+    if !tpldir.is_dir() {
+        bail!("Chart {} not found: missing {}", chart, tpldir.display());
+    }
+
+    let hfile = format!("{}.helm.gen.yml", mf.name);
+    values(&mf, &hfile).await?;
     // helm template with correct params
     let tplvec = vec![
         "template".into(),
@@ -112,6 +125,9 @@ pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String>
         warn!("{} stderr: {}", tplvec.join(" "), tplerr);
         bail!("helm template failed");
     }
+    if validate {
+        validate_yaml(&mf.name, &tpl)?;
+    }
     if let Some(o) = &output {
         let pth = Path::new(".").join(o);
         debug!("Writing helm template for {} to {}", mf.name, pth.display());
@@ -131,6 +147,43 @@ pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String>
     Ok(tpl)
 }
 
+/// Ensure every document in a rendered template parses as yaml
+///
+/// Catches charts that render valid text but broken yaml (e.g. unindented multiline values).
+pub fn validate_yaml(svc: &str, tpl: &str) -> Result<()> {
+    // documents along with the line number they start on
+    let mut docs = vec![(1, vec![])];
+    for (i, l) in tpl.lines().enumerate() {
+        if l.trim_end() == "---" {
+            docs.push((i + 2, vec![]));
+        } else if let Some((_, doc)) = docs.last_mut() {
+            doc.push(l);
+        }
+    }
+    for (start, doc) in docs {
+        let mut trimmed = doc.iter().map(|l| l.trim()).filter(|l| !l.is_empty());
+        if trimmed.all(|l| l.starts_with('#')) {
+            continue; // empty or comment only documents
+        }
+        if let Err(e) = serde_yaml::from_str::<serde_yaml::Value>(&doc.join("\n")) {
+            let context = match e.location() {
+                Some(loc) if loc.line() > 0 => doc
+                    .get(loc.line() - 1)
+                    .map(|l| format!("\n{:>4} | {}", start + loc.line() - 1, l))
+                    .unwrap_or_default(),
+                _ => "".into(),
+            };
+            bail!(
+                "Rendered template for {} is not valid yaml: {}{}",
+                svc,
+                e,
+                context
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Helper to validate the assumption of the charts
 ///
 /// This is an addon to checks done through `kubeval`.
@@ -0,0 +1,152 @@
+use std::{
+    fmt,
+    net::{IpAddr, ToSocketAddrs},
+};
+
+use super::{Manifest, Region, Result};
+
+/// DNS lookups of hostnames
+pub trait Resolver {
+    fn resolve(&self, host: &str) -> Result<Vec<IpAddr>>;
+}
+
+/// Resolver using the system's DNS configuration
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.parse() {
+            return Ok(vec![ip]);
+        }
+        Ok((host, 443).to_socket_addrs()?.map(|a| a.ip()).collect())
+    }
+}
+
+/// A service host with broken DNS
+#[derive(Debug, PartialEq)]
+pub enum HostProblem {
+    Unresolvable { host: String, error: String },
+    UnexpectedTarget { host: String, addrs: Vec<IpAddr> },
+}
+
+impl fmt::Display for HostProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostProblem::Unresolvable { host, error } => write!(f, "{} does not resolve: {}", host, error),
+            HostProblem::UnexpectedTarget { host, addrs } => {
+                let addrs = addrs.iter().map(IpAddr::to_string).collect::<Vec<_>>();
+                write!(f, "{} resolves to {} - not the ingress", host, addrs.join(", "))
+            }
+        }
+    }
+}
+
+/// All external hosts of a service (from kong and gate)
+pub fn service_hosts(mf: &Manifest) -> Vec<String> {
+    let mut hosts: Vec<String> = mf.kongApis.iter().flat_map(|k| k.hosts.clone()).collect();
+    if let Some(g) = &mf.gate {
+        hosts.extend(g.hosts.clone());
+    }
+    hosts.sort();
+    hosts.dedup();
+    hosts
+}
+
+/// Resolve hosts and report the ones that do not resolve (or miss the expected ingress)
+///
+/// Any address in `expected` is accepted; when empty only resolvability is checked.
+pub fn check_hosts(hosts: &[String], expected: &[IpAddr], resolver: &impl Resolver) -> Vec<HostProblem> {
+    let mut problems = vec![];
+    for h in hosts {
+        match resolver.resolve(h) {
+            Err(e) => problems.push(HostProblem::Unresolvable {
+                host: h.clone(),
+                error: e.to_string(),
+            }),
+            Ok(addrs) if addrs.is_empty() => problems.push(HostProblem::Unresolvable {
+                host: h.clone(),
+                error: "no addresses".into(),
+            }),
+            Ok(addrs) => {
+                if !expected.is_empty() && !addrs.iter().any(|a| expected.contains(a)) {
+                    problems.push(HostProblem::UnexpectedTarget {
+                        host: h.clone(),
+                        addrs,
+                    })
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Entry point for `shipcat validate-ingress`
+///
+/// Opt-in preflight as it needs network access for the DNS lookups.
+pub fn validate_ingress(mf: &Manifest, reg: &Region) -> Result<()> {
+    let resolver = SystemResolver;
+    let hosts = service_hosts(mf);
+    if hosts.is_empty() {
+        info!("{} has no ingress hosts in {}", mf.name, reg.name);
+        return Ok(());
+    }
+    let mut expected = vec![];
+    for addr in reg.kong.iter().flat_map(|k| &k.ingress_addresses) {
+        expected.extend(resolver.resolve(addr)?);
+    }
+    let problems = check_hosts(&hosts, &expected, &resolver);
+    for p in &problems {
+        warn!("{}: {}", mf.name, p);
+    }
+    if !problems.is_empty() {
+        bail!("{} has {} hosts with broken dns", mf.name, problems.len());
+    }
+    info!("{} hosts resolve in {}", mf.name, reg.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_hosts, HostProblem, Resolver};
+    use crate::Result;
+    use std::{collections::BTreeMap, net::IpAddr};
+
+    struct FakeResolver(BTreeMap<&'static str, &'static str>);
+    impl Resolver for FakeResolver {
+        fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+            match self.0.get(host) {
+                Some(ip) => Ok(vec![ip.parse().unwrap()]),
+                None => bail!("NXDOMAIN"),
+            }
+        }
+    }
+
+    #[test]
+    fn unresolvable_host_is_reported() {
+        let mut records = BTreeMap::new();
+        records.insert("fake-ask.dev.something.domain.com", "10.0.0.1");
+        records.insert("fake.example.com", "192.168.1.1");
+        let resolver = FakeResolver(records);
+        let hosts = vec![
+            "fake-ask.dev.something.domain.com".to_string(),
+            "fake.example.com".to_string(),
+            "missing.example.com".to_string(),
+        ];
+        let problems = check_hosts(&hosts, &[], &resolver);
+        assert_eq!(
+            problems,
+            vec![HostProblem::Unresolvable {
+                host: "missing.example.com".into(),
+                error: "NXDOMAIN".into(),
+            }]
+        );
+
+        let ingress: IpAddr = "10.0.0.1".parse().unwrap();
+        let problems = check_hosts(&hosts, &[ingress], &resolver);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(
+            problems[0].to_string(),
+            "fake.example.com resolves to 192.168.1.1 - not the ingress"
+        );
+    }
+}
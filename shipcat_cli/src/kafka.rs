@@ -0,0 +1,163 @@
+use std::{collections::BTreeMap, fmt, process::Command};
+
+use super::{Manifest, Result};
+
+/// Admin access to a kafka cluster
+pub trait TopicAdmin {
+    /// Partition counts of every topic in the cluster
+    fn topic_partitions(&self) -> Result<BTreeMap<String, i32>>;
+}
+
+/// Admin client shelling out to the `kafka-topics` cli
+pub struct KafkaTopicsCli {
+    pub brokers: Vec<String>,
+}
+
+impl TopicAdmin for KafkaTopicsCli {
+    fn topic_partitions(&self) -> Result<BTreeMap<String, i32>> {
+        let servers = self.brokers.join(",");
+        let args = vec!["--bootstrap-server", &servers, "--describe"];
+        debug!("kafka-topics {}", args.join(" "));
+        let s = Command::new("kafka-topics").args(&args).output()?;
+        if !s.status.success() {
+            let err = String::from_utf8_lossy(&s.stderr);
+            bail!("Subprocess failure from kafka-topics: {}", err.trim())
+        }
+        Ok(parse_describe(&String::from_utf8_lossy(&s.stdout)))
+    }
+}
+
+/// Parse the topic summary lines of `kafka-topics --describe`
+///
+/// Summary lines look like `Topic: foo\tPartitionCount: 3\tReplicationFactor: 1`,
+/// and are followed by one line per partition, which are ignored.
+fn parse_describe(output: &str) -> BTreeMap<String, i32> {
+    let mut topics = BTreeMap::new();
+    for line in output.lines() {
+        let fields: BTreeMap<&str, &str> = line
+            .split('\t')
+            .filter_map(|f| {
+                let mut kv = f.splitn(2, ':');
+                Some((kv.next()?.trim(), kv.next()?.trim()))
+            })
+            .collect();
+        if let (Some(name), Some(count)) = (fields.get("Topic"), fields.get("PartitionCount")) {
+            if let Ok(n) = count.parse() {
+                topics.insert(name.to_string(), n);
+            }
+        }
+    }
+    topics
+}
+
+/// A declared topic that does not match the cluster
+#[derive(Debug, PartialEq)]
+pub enum TopicProblem {
+    Missing(String),
+    PartitionMismatch {
+        name: String,
+        expected: i32,
+        actual: i32,
+    },
+}
+
+impl fmt::Display for TopicProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopicProblem::Missing(name) => write!(f, "topic {} does not exist", name),
+            TopicProblem::PartitionMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "topic {} has {} partitions (expected {})",
+                name, actual, expected
+            ),
+        }
+    }
+}
+
+/// Compare the topics declared in a manifest against the cluster
+///
+/// Partition counts are only compared when `check_partitions` is set.
+pub fn check_topics(
+    mf: &Manifest,
+    admin: &impl TopicAdmin,
+    check_partitions: bool,
+) -> Result<Vec<TopicProblem>> {
+    let declared = match &mf.kafkaResources {
+        Some(kr) => &kr.topics,
+        None => return Ok(vec![]),
+    };
+    let existing = admin.topic_partitions()?;
+    let mut problems = vec![];
+    for t in declared {
+        match existing.get(&t.name) {
+            None => problems.push(TopicProblem::Missing(t.name.clone())),
+            Some(&actual) if check_partitions && t.partitions > 0 && actual != t.partitions => {
+                problems.push(TopicProblem::PartitionMismatch {
+                    name: t.name.clone(),
+                    expected: t.partitions,
+                    actual,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(problems)
+}
+
+/// Entry point for `shipcat validate-kafka`
+///
+/// Brokers come from the resolved kafka config of the manifest.
+pub fn validate_kafka(mf: &Manifest, check_partitions: bool) -> Result<()> {
+    let brokers = mf.kafka.as_ref().map(|k| k.brokers.clone()).unwrap_or_default();
+    if brokers.is_empty() {
+        bail!("{} has no kafka brokers configured in {}", mf.name, mf.region);
+    }
+    let problems = check_topics(mf, &KafkaTopicsCli { brokers }, check_partitions)?;
+    for p in &problems {
+        warn!("{}: {}", mf.name, p);
+    }
+    if !problems.is_empty() {
+        bail!("{} has {} kafka topic problems", mf.name, problems.len());
+    }
+    info!("{} kafka topics exist in {}", mf.name, mf.region);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_topics, parse_describe, TopicAdmin, TopicProblem};
+    use crate::{structs::KafkaResources, Manifest, Result};
+    use std::collections::BTreeMap;
+
+    struct MockAdmin(BTreeMap<String, i32>);
+    impl TopicAdmin for MockAdmin {
+        fn topic_partitions(&self) -> Result<BTreeMap<String, i32>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn missing_topic_is_reported() -> Result<()> {
+        let mut mf = Manifest::default();
+        mf.kafkaResources = Some(serde_yaml::from_str::<KafkaResources>(
+            "topics:\n- name: orders\n  partitions: 3\n- name: payments\n  partitions: 1\n",
+        )?);
+        let admin = MockAdmin(parse_describe(
+            "Topic: orders\tPartitionCount: 6\tReplicationFactor: 3\tConfigs:\n\tTopic: orders\tPartition: 0\tLeader: 1\n",
+        ));
+        let problems = check_topics(&mf, &admin, false)?;
+        assert_eq!(problems, vec![TopicProblem::Missing("payments".into())]);
+
+        let problems = check_topics(&mf, &admin, true)?;
+        assert_eq!(problems.len(), 2);
+        assert_eq!(
+            problems[0].to_string(),
+            "topic orders has 6 partitions (expected 3)"
+        );
+        Ok(())
+    }
+}
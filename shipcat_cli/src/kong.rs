@@ -1,14 +1,17 @@
+use serde_json::{json, Value};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     io::{self, Write},
 };
 
 use super::{
     structs::{
-        kongfig::{kongfig_apis, kongfig_consumers, Api, Certificate, Consumer, Plugin, Upstream},
+        kongfig::{
+            kongfig_apis, kongfig_consumers, kongfig_upstreams, Api, Certificate, Consumer, Plugin, Upstream,
+        },
         Kong,
     },
-    Config, KongConfig, Region, Result,
+    Config, ErrorKind, KongConfig, Region, Result, ResultExt,
 };
 
 /// KongOutput matches the format expected by the Kong Configurator script
@@ -32,13 +35,14 @@ pub struct KongfigOutput {
 
 impl KongfigOutput {
     pub fn new(data: KongOutput, region: &Region) -> Self {
+        let upstreams = kongfig_upstreams(&data.apis);
         KongfigOutput {
             host: data.kong.clone().config_url,
             headers: vec![],
             apis: kongfig_apis(data.apis, data.kong.clone(), region),
             consumers: kongfig_consumers(data.kong),
             plugins: vec![],
-            upstreams: vec![],
+            upstreams,
             certificates: vec![],
         }
     }
@@ -75,7 +79,7 @@ pub async fn generate_kong_output(conf: &Config, region: &Region) -> Result<Kong
         // Generate list of APIs to feed to Kong
         for mf in shipcat_filebacked::available(conf, region).await? {
             debug!("Scanning service {:?}", mf);
-            for k in mf.kong_apis {
+            for k in mf.kong_apis.into_iter().flat_map(Kong::split_host_routes) {
                 if let Some(clash) = apis.insert(k.name.clone(), k) {
                     bail!("A Kong API named {:?} is already defined", clash.name);
                 }
@@ -131,3 +135,281 @@ pub fn config_url(region: &Region) -> Result<()> {
     }
     Ok(())
 }
+
+/// The parts of a Kong config that `kong diff` compares
+#[derive(Default, Debug)]
+pub struct KongState {
+    /// Attributes of every api by name
+    pub apis: BTreeMap<String, Value>,
+    /// Attributes (`enabled` and `config`) of every api plugin by api and plugin name
+    pub plugins: BTreeMap<(String, String), Value>,
+    /// Usernames of all consumers
+    pub consumers: BTreeSet<String>,
+}
+
+impl KongState {
+    /// The desired state, from the kongfig serialization
+    pub fn desired(output: &KongfigOutput) -> Result<Self> {
+        let mut state = KongState::default();
+        for api in &output.apis {
+            state
+                .apis
+                .insert(api.name.clone(), serde_json::to_value(&api.attributes)?);
+            for plugin in serde_json::to_value(&api.plugins)?
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+            {
+                // removed plugins are expected to be absent
+                if plugin["ensure"] == "present" {
+                    let name = plugin["name"].as_str().unwrap_or_default().to_string();
+                    state
+                        .plugins
+                        .insert((api.name.clone(), name), plugin["attributes"].clone());
+                }
+            }
+        }
+        state.consumers = output.consumers.iter().map(|c| c.username.clone()).collect();
+        Ok(state)
+    }
+
+    /// The live state, from responses of the admin api `/apis`, `/plugins` and `/consumers`
+    pub fn live(apis: Vec<Value>, plugins: Vec<Value>, consumers: Vec<Value>) -> Self {
+        let mut state = KongState::default();
+        let mut names = BTreeMap::new();
+        for api in apis {
+            let name = api["name"].as_str().unwrap_or_default().to_string();
+            if let Some(id) = api["id"].as_str() {
+                names.insert(id.to_string(), name.clone());
+            }
+            state.apis.insert(name, api);
+        }
+        for p in plugins {
+            // global plugins are not managed per api
+            if let Some(api) = p["api_id"].as_str().and_then(|id| names.get(id)) {
+                let name = p["name"].as_str().unwrap_or_default().to_string();
+                let attributes = json!({ "enabled": p["enabled"], "config": p["config"] });
+                state.plugins.insert((api.clone(), name), attributes);
+            }
+        }
+        for c in consumers {
+            if let Some(u) = c["username"].as_str() {
+                state.consumers.insert(u.to_string());
+            }
+        }
+        state
+    }
+}
+
+/// Describe what applying `desired` would change in `live`, one line per change
+///
+/// Only values shipcat sets are compared, so fields kong adds (like ids) are ignored.
+pub fn diff_state(desired: &KongState, live: &KongState) -> Vec<String> {
+    let mut lines = diff_entries("api", &desired.apis, &live.apis, |n| n.clone());
+    lines.extend(diff_entries(
+        "plugin",
+        &desired.plugins,
+        &live.plugins,
+        |(api, plugin)| format!("{}/{}", api, plugin),
+    ));
+    for c in desired.consumers.difference(&live.consumers) {
+        lines.push(format!("+ consumer {}", c));
+    }
+    for c in live.consumers.difference(&desired.consumers) {
+        lines.push(format!("- consumer {}", c));
+    }
+    lines
+}
+
+fn diff_entries<K: Ord>(
+    kind: &str,
+    desired: &BTreeMap<K, Value>,
+    live: &BTreeMap<K, Value>,
+    name: impl Fn(&K) -> String,
+) -> Vec<String> {
+    let mut lines = vec![];
+    for (k, d) in desired {
+        match live.get(k) {
+            None => lines.push(format!("+ {} {}", kind, name(k))),
+            Some(l) => {
+                let mut changed = vec![];
+                changed_fields(d, l, "", &mut changed);
+                if !changed.is_empty() {
+                    lines.push(format!("~ {} {}: {}", kind, name(k), changed.join(", ")));
+                }
+            }
+        }
+    }
+    for k in live.keys().filter(|k| !desired.contains_key(k)) {
+        lines.push(format!("- {} {}", kind, name(k)));
+    }
+    lines
+}
+
+/// Collect paths of desired values that differ from, or are missing in, the live value
+fn changed_fields(desired: &Value, live: &Value, path: &str, out: &mut Vec<String>) {
+    match (desired, live) {
+        (Value::Object(d), Value::Object(l)) => {
+            for (k, v) in d {
+                let p = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                match l.get(k) {
+                    Some(lv) => changed_fields(v, lv, &p, out),
+                    None if v.is_null() => {}
+                    None => out.push(p),
+                }
+            }
+        }
+        // kong encodes empty arrays as empty objects
+        (Value::Array(d), Value::Object(l)) if d.is_empty() && l.is_empty() => {}
+        (d, l) if d != l => out.push(path.to_string()),
+        _ => {}
+    }
+}
+
+/// Every entry of a paginated kong admin api listing
+async fn fetch_all(
+    client: &reqwest::Client,
+    base: &str,
+    path: &str,
+    auth: Option<&(String, String)>,
+) -> Result<Vec<Value>> {
+    let mut entries = vec![];
+    let mut next = Some(format!("{}{}", base.trim_end_matches('/'), path));
+    while let Some(u) = next {
+        let url = reqwest::Url::parse(&u)?;
+        let mut req = client.get(url.clone());
+        if let Some((name, value)) = auth {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        let res = req.send().await.chain_err(|| ErrorKind::Url(url.clone()))?;
+        let page: Value = res
+            .error_for_status()
+            .chain_err(|| ErrorKind::Url(url.clone()))?
+            .json()
+            .await?;
+        entries.extend(page["data"].as_array().cloned().unwrap_or_default());
+        next = page["next"].as_str().map(String::from);
+    }
+    Ok(entries)
+}
+
+/// Remove apis from a live Kong admin api
+///
+/// Apis that are already gone are skipped.
+pub async fn delete_apis(base: &str, names: &[String]) -> Result<()> {
+    let client = reqwest::Client::new();
+    for name in names {
+        let url = reqwest::Url::parse(&format!("{}/apis/{}", base.trim_end_matches('/'), name))?;
+        info!("Deleting kong api {}", name);
+        let res = client.delete(url.clone()).send().await.chain_err(|| ErrorKind::Url(url.clone()))?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            debug!("Kong api {} already removed", name);
+            continue;
+        }
+        res.error_for_status().chain_err(|| ErrorKind::Url(url.clone()))?;
+    }
+    Ok(())
+}
+
+/// Split a `Name: value` header
+fn parse_header(raw: &str) -> Result<(String, String)> {
+    match raw.find(':') {
+        Some(i) if i > 0 => Ok((raw[..i].trim().to_string(), raw[i + 1..].trim().to_string())),
+        _ => bail!("Auth header {} is not of the form 'Name: value'", raw),
+    }
+}
+
+/// Print what applying the generated config would change in a live Kong
+///
+/// The admin api defaults to the region's `config_url`.
+pub async fn diff(
+    conf: &Config,
+    region: &Region,
+    url: Option<&str>,
+    auth_header: Option<&str>,
+) -> Result<()> {
+    let data = generate_kong_output(conf, region).await?;
+    let desired = KongState::desired(&KongfigOutput::new(data, region))?;
+
+    let base = match (url, &region.kong) {
+        (Some(u), _) => u.to_string(),
+        (None, Some(k)) => k.config_url.clone(),
+        (None, None) => bail!("No kong specified in {} region", region.name),
+    };
+    let auth = auth_header.map(parse_header).transpose()?;
+    let client = reqwest::Client::new();
+    let apis = fetch_all(&client, &base, "/apis", auth.as_ref()).await?;
+    let plugins = fetch_all(&client, &base, "/plugins", auth.as_ref()).await?;
+    let consumers = fetch_all(&client, &base, "/consumers", auth.as_ref()).await?;
+    let live = KongState::live(apis, plugins, consumers);
+
+    let lines = diff_state(&desired, &live);
+    if lines.is_empty() {
+        info!("Kong in {} is up to date", region.name);
+    }
+    for l in lines {
+        println!("{}", l);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_state, parse_header, KongState};
+    use serde_json::json;
+
+    #[test]
+    fn kong_state_diff() {
+        let mut desired = KongState::default();
+        desired.apis.insert(
+            "fake-ask".into(),
+            json!({ "hosts": ["fake.example.com"], "uris": [], "strip_uri": false }),
+        );
+        desired.apis.insert("fake-storage".into(), json!({ "hosts": [] }));
+        desired.plugins.insert(
+            ("fake-ask".into(), "cors".into()),
+            json!({ "enabled": true, "config": { "max_age": 3600 } }),
+        );
+        desired.consumers.insert("my-idp".into());
+
+        let live = KongState::live(
+            vec![
+                json!({ "id": "1", "name": "fake-ask", "hosts": ["old.example.com"], "uris": {}, "strip_uri": false }),
+                json!({ "id": "2", "name": "legacy", "hosts": ["legacy.example.com"] }),
+            ],
+            vec![
+                json!({ "api_id": "1", "name": "cors", "enabled": true, "config": { "max_age": 60 } }),
+                json!({ "api_id": "1", "name": "jwt", "enabled": true, "config": {} }),
+                json!({ "name": "tcp-log", "enabled": true, "config": {} }),
+            ],
+            vec![
+                json!({ "username": "my-idp" }),
+                json!({ "username": "anonymous" }),
+            ],
+        );
+
+        assert_eq!(
+            diff_state(&desired, &live),
+            vec![
+                "~ api fake-ask: hosts",
+                "+ api fake-storage",
+                "- api legacy",
+                "~ plugin fake-ask/cors: config.max_age",
+                "- plugin fake-ask/jwt",
+                "- consumer anonymous",
+            ]
+        );
+    }
+
+    #[test]
+    fn auth_headers() {
+        let (name, value) = parse_header("Kong-Admin-Token: secret: value").unwrap();
+        assert_eq!(name, "Kong-Admin-Token");
+        assert_eq!(value, "secret: value");
+        assert!(parse_header("no-separator").is_err());
+    }
+}
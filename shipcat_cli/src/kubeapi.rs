@@ -1,16 +1,24 @@
-use crate::{ErrorKind, Manifest, Result};
-use k8s_openapi::api::{
-    apps::v1::{Deployment, ReplicaSet, StatefulSet},
-    core::v1::Pod,
+use crate::{diff, ErrorKind, Manifest, Result};
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, ReplicaSet, StatefulSet},
+        core::v1::Pod,
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
 };
 use kube::{
-    api::{Api, DeleteParams, ListParams, LogParams, Object, ObjectList, PatchParams, Resource},
+    api::{
+        Api, DeleteParams, ListParams, LogParams, Object, ObjectList, PatchParams, PatchStrategy, Resource,
+    },
     client::APIClient,
 };
 use shipcat_definitions::{
     manifest::ShipcatManifest,
     status::{Applier, ManifestStatus},
+    Region,
 };
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
 
 /// Client creator
 ///
@@ -25,6 +33,154 @@ async fn make_client() -> Result<APIClient> {
     };
     Ok(kube::client::APIClient::new(config))
 }
+/// Field manager owning the shipcatmanifest fields shipcat applies
+const FIELD_MANAGER: &str = "shipcat";
+/// Default maximum of concurrent kube api calls from one shipcat process
+pub const DEFAULT_KUBE_API_CONCURRENCY: usize = 8;
+/// Attempts made at a throttled (429) kube api call before giving up
+const THROTTLE_ATTEMPTS: u32 = 5;
+/// Default retries of a kube api call failing transiently
+pub const DEFAULT_KUBE_API_RETRIES: u32 = 3;
+/// Default delay before the first retry of a transiently failing kube api call
+pub const DEFAULT_KUBE_API_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Retries for kube api calls failing with server (5xx) or connection errors
+///
+/// Delays double after every retry. Client errors (4xx) are never retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first failed attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: DEFAULT_KUBE_API_RETRIES,
+            base_delay: DEFAULT_KUBE_API_RETRY_DELAY,
+        }
+    }
+}
+
+/// Whether a failed kube api call might succeed when retried
+fn is_transient(e: &kube::Error) -> bool {
+    match e {
+        kube::Error::Api(ae) => ae.code >= 500,
+        kube::Error::ReqwestError(re) => re.is_timeout() || is_connection_failure(re),
+        _ => false,
+    }
+}
+
+/// Whether a request failed because its connection was refused or dropped
+fn is_connection_failure(e: &reqwest::Error) -> bool {
+    use std::{error::Error, io::ErrorKind as IoKind};
+    let mut source = e.source();
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            match io.kind() {
+                IoKind::ConnectionRefused | IoKind::ConnectionReset | IoKind::ConnectionAborted => {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Kube api client shared by every call shipcat makes against one region
+///
+/// Concurrent calls share a budget of permits and queue in order for a free one.
+#[derive(Clone)]
+pub struct KubeClient {
+    client: APIClient,
+    limiter: Arc<Semaphore>,
+}
+
+impl KubeClient {
+    /// Client for the current kube context, budgeted by the region's `kubeApiConcurrency`
+    pub async fn new(reg: &Region) -> Result<Self> {
+        let client = make_client().await?;
+        let concurrency = reg.kubeApiConcurrency.unwrap_or(DEFAULT_KUBE_API_CONCURRENCY);
+        Ok(Self::with_client(client, concurrency))
+    }
+
+    /// Budget calls through an existing client (values below 1 are treated as 1)
+    pub fn with_client(client: APIClient, concurrency: usize) -> Self {
+        KubeClient {
+            client,
+            limiter: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    /// Run a kube api call within the budget
+    ///
+    /// Throttled calls give up their permit and are retried with exponential backoff.
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<T, kube::Error>>,
+    {
+        self.call_unconverted(&f)
+            .await
+            .map_err(|e| ErrorKind::KubeError(e).into())
+    }
+
+    /// Run a kube api call within the budget, retrying transient failures
+    ///
+    /// Errors are only converted once the retries are exhausted.
+    pub async fn call_retrying<T, F, Fut>(&self, policy: &RetryPolicy, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<T, kube::Error>>,
+    {
+        let mut delay = policy.base_delay;
+        let mut retries = 0;
+        loop {
+            match self.call_unconverted(&f).await {
+                Err(e) if is_transient(&e) && retries < policy.max_retries => {
+                    warn!(
+                        "kube api call failed ({}), retrying in {}ms",
+                        e,
+                        delay.as_millis()
+                    );
+                    tokio::time::delay_for(delay).await;
+                    delay *= 2;
+                    retries += 1;
+                }
+                res => return res.map_err(|e| ErrorKind::KubeError(e).into()),
+            }
+        }
+    }
+
+    async fn call_unconverted<T, F, Fut>(&self, f: &F) -> std::result::Result<T, kube::Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<T, kube::Error>>,
+    {
+        let mut backoff = Duration::from_millis(250);
+        let mut attempt = 1;
+        loop {
+            let res = {
+                let _permit = self.limiter.acquire().await;
+                f().await
+            };
+            match res {
+                Err(kube::Error::Api(ae)) if ae.code == 429 && attempt < THROTTLE_ATTEMPTS => {
+                    debug!("kube api throttled, retrying in {}ms", backoff.as_millis());
+                    tokio::time::delay_for(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MinimalManifest {
     pub name: String,
@@ -35,74 +191,172 @@ type MinimalMfCrd = Object<MinimalManifest, ManifestStatus>;
 /// Interface for dealing with kubernetes shipcatmanifests
 pub struct ShipKube {
     mfs: Resource,
-    client: APIClient,
+    kube: KubeClient,
     pub(crate) applier: Applier,
     api: Api<ShipcatManifest>,
     name: String,
     namespace: String,
+    retry: RetryPolicy,
 }
 
 /// Entry points for shipcat::apply, and shipcat::status
 impl ShipKube {
-    pub async fn new_within(svc: &str, ns: &str) -> Result<Self> {
-        // hide the client in here -> Api resource for now (not needed elsewhere)
-        let client = make_client().await?;
+    pub fn new_within(svc: &str, ns: &str, kube: &KubeClient) -> Self {
         let mfs = Resource::namespaced::<ShipcatManifest>(ns);
-        let api = Api::namespaced(client.clone(), ns);
+        let api = Api::namespaced(kube.client.clone(), ns);
 
-        Ok(Self {
+        Self {
             name: svc.to_string(),
             namespace: ns.to_string(),
             applier: Applier::infer(),
             api,
-            client,
+            kube: kube.clone(),
             mfs,
-        })
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Override how transiently failing CRD calls are retried
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
     }
 
-    pub async fn new(mf: &Manifest) -> Result<Self> {
-        Self::new_within(&mf.name, &mf.namespace).await
+    pub fn new(mf: &Manifest, kube: &KubeClient) -> Self {
+        Self::new_within(&mf.name, &mf.namespace, kube)
     }
 
     /// Apply a Manifest (e.g. it's CRD wrapper)
-    pub async fn apply(&self, mf: Manifest) -> Result<bool> {
+    ///
+    /// Uses server-side apply, or `kubectl apply` for clusters too old for it.
+    /// Returns whether the CRD was created or changed; an unchanged spec is not sent on either path.
+    pub async fn apply(&self, mf: Manifest, kubectl_apply: bool) -> Result<bool> {
         assert!(mf.version.is_some()); // ensure crd is in right state w/o secrets
         assert!(mf.is_base());
         // Wrap in the Crd Struct:
         let svc = mf.name.clone();
         let ns = mf.namespace.clone();
         let mfcrd = ShipcatManifest::new(&svc, mf);
-        // TODO: use server side apply in 1.15
-        // for now, shell out to kubectl
-        use crate::kubectl;
-        kubectl::apply_resource(&svc, mfcrd, &ns).await
+        // a live crd we cannot read (e.g. from an older schema) counts as changed
+        if let Some(live) = self.find().await.ok().flatten() {
+            if spec_unchanged(&mfcrd.spec, &live.spec) {
+                debug!("{} crd spec matches the live crd", svc);
+                return Ok(false);
+            }
+        }
+        if kubectl_apply {
+            use crate::kubectl;
+            return kubectl::apply_resource(&svc, mfcrd, &ns).await;
+        }
+        let pp = PatchParams {
+            patch_strategy: PatchStrategy::Apply,
+            field_manager: Some(FIELD_MANAGER.into()),
+            force: true,
+            ..Default::default()
+        };
+        let (pp, body) = (&pp, &serde_json::to_vec(&mfcrd)?);
+        self.kube
+            .call_retrying(&self.retry, || async move {
+                let req = self.mfs.patch(&self.name, pp, body.clone())?;
+                self.kube.client.request::<MinimalMfCrd>(req).await
+            })
+            .await?;
+        Ok(true)
+    }
+
+    /// Unified diff of the live CRD spec against a Manifest
+    ///
+    /// A CRD that does not exist yet shows the whole spec as an addition.
+    pub async fn diff(&self, mf: Manifest) -> Result<String> {
+        let before = match self.find().await? {
+            Some(crd) => serde_yaml::to_string(&crd.spec)?,
+            None => "".into(),
+        };
+        let svc = mf.name.clone();
+        let mfcrd = ShipcatManifest::new(&svc, mf);
+        let after = serde_yaml::to_string(&mfcrd.spec)?;
+        let before_name = format!("{}.live", self.name);
+        let after_name = format!("{}.local", self.name);
+        let (out, _) = diff::unified_diff(&before, &after, &before_name, &after_name, diff::DEFAULT_CONTEXT)?;
+        Ok(out)
     }
 
     /// Full CRD fetcher
     pub async fn get(&self) -> Result<ShipcatManifest> {
-        let o = self.api.get(&self.name).await.map_err(ErrorKind::KubeError)?;
+        let o = self.kube
+            .call_retrying(&self.retry, || self.api.get(&self.name))
+            .await?;
         Ok(o)
     }
 
+    /// Full CRD fetcher that treats a missing CRD as None
+    pub async fn find(&self) -> Result<Option<ShipcatManifest>> {
+        match self.get().await {
+            Ok(crd) => Ok(Some(crd)),
+            Err(e) => match e.kind() {
+                ErrorKind::KubeError(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Last successfully rolled out version from the CRD status
+    pub async fn last_successful_version(&self) -> Result<Option<String>> {
+        let status = self.get().await?.status;
+        Ok(status.and_then(|s| s.last_successful_version().map(String::from)))
+    }
+
     /// Minimal CRD fetcher (for upgrades)
     pub async fn get_minimal(&self) -> Result<MinimalMfCrd> {
-        let req = self.mfs.get(&self.name).map_err(ErrorKind::KubeError)?;
-        let o = self
-            .client
-            .request::<MinimalMfCrd>(req)
-            .await
-            .map_err(ErrorKind::KubeError)?;
+        let o = self.kube
+            .call_retrying(&self.retry, || async move {
+                let req = self.mfs.get(&self.name)?;
+                self.kube.client.request::<MinimalMfCrd>(req).await
+            })
+            .await?;
         Ok(o)
     }
 
     /// Minimal CRD deleter
     pub async fn delete(&self) -> Result<()> {
-        let dp = DeleteParams::default();
-        let req = self.mfs.delete(&self.name, &dp).map_err(ErrorKind::KubeError)?;
-        self.client
-            .request_status::<MinimalManifest>(req)
-            .await
-            .map_err(ErrorKind::KubeError)?;
+        let dp = &DeleteParams::default();
+        self.kube
+            .call_retrying(&self.retry, || async move {
+                let req = self.mfs.delete(&self.name, dp)?;
+                self.kube.client.request_status::<MinimalManifest>(req).await
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Add a finalizer to the CRD, keeping any set by other controllers
+    pub async fn add_finalizer(&self, finalizer: &str) -> Result<()> {
+        self.patch_finalizer(finalizer, true).await
+    }
+
+    /// Remove a finalizer from the CRD, keeping any set by other controllers
+    pub async fn remove_finalizer(&self, finalizer: &str) -> Result<()> {
+        self.patch_finalizer(finalizer, false).await
+    }
+
+    // helper to send a json patch for one finalizer against the live list
+    async fn patch_finalizer(&self, finalizer: &str, present: bool) -> Result<()> {
+        let meta = self.get_minimal().await?.metadata;
+        let data = match finalizer_patch(&meta, finalizer, present) {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        let pp = PatchParams {
+            patch_strategy: PatchStrategy::JSON,
+            ..Default::default()
+        };
+        let (pp, body) = (&pp, &serde_json::to_vec(&data)?);
+        self.kube
+            .call(|| async move {
+                let req = self.mfs.patch(&self.name, pp, body.clone())?;
+                self.kube.client.request::<MinimalMfCrd>(req).await
+            })
+            .await?;
         Ok(())
     }
 
@@ -112,82 +366,80 @@ impl ShipKube {
         // Run this patch with a smaller deserialization surface via kube::Resource
         // kube::Api would force ShipcatManifest fully valid here
         // and this would prevent status updates during schema changes.
-        let req = self
-            .mfs
-            .patch_status(&self.name, &pp, serde_json::to_vec(data)?)
-            .map_err(ErrorKind::KubeError)?;
-        let o = self
-            .client
-            .request::<MinimalMfCrd>(req) // <- difference from using Api::patch_status
-            .await
-            .map_err(ErrorKind::KubeError)?;
+        let (pp, body) = (&pp, &serde_json::to_vec(data)?);
+        let o = self.kube
+            .call_retrying(&self.retry, || async move {
+                let req = self.mfs.patch_status(&self.name, pp, body.clone())?;
+                self.kube.client.request::<MinimalMfCrd>(req).await // <- difference from using Api::patch_status
+            })
+            .await?;
         debug!("Patched status: {:?}", o.status);
         Ok(())
     }
 
     // helper to get pod data
     pub async fn get_pods(&self) -> Result<ObjectList<Pod>> {
-        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let api: Api<Pod> = Api::namespaced(self.kube.client.clone(), &self.namespace);
         let lp = ListParams {
             label_selector: Some(format!("app={}", self.name)),
             ..Default::default()
         };
-        let pods = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        let pods = self.kube.call(|| api.list(&lp)).await?;
         Ok(pods)
     }
 
     // helper to get pods by pod hash
     pub async fn get_pods_by_template_hash(&self, hash: &str) -> Result<ObjectList<Pod>> {
-        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let api: Api<Pod> = Api::namespaced(self.kube.client.clone(), &self.namespace);
         let lp = ListParams {
             label_selector: Some(format!("app={},pod-template-hash={}", self.name, hash)),
             ..Default::default()
         };
-        let pods = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        let pods = self.kube.call(|| api.list(&lp)).await?;
         Ok(pods)
     }
 
     // helper to get pod logs
     pub async fn get_pod_logs(&self, podname: &str) -> Result<String> {
-        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let api: Api<Pod> = Api::namespaced(self.kube.client.clone(), &self.namespace);
         let lp = LogParams {
             tail_lines: Some(30),
             container: Some(self.name.to_string()),
             ..Default::default()
         };
-        let logs = api.logs(podname, &lp).await.map_err(ErrorKind::KubeError)?;
+        let logs = self.kube.call(|| api.logs(podname, &lp)).await?;
         Ok(logs)
     }
 
     // helper to get rs data
     pub async fn get_rs(&self) -> Result<ObjectList<ReplicaSet>> {
-        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), &self.namespace);
+        let api: Api<ReplicaSet> = Api::namespaced(self.kube.client.clone(), &self.namespace);
         let lp = ListParams {
             label_selector: Some(format!("app={}", self.name)),
             ..Default::default()
         };
-        let rs = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        let rs = self.kube.call(|| api.list(&lp)).await?;
         Ok(rs)
     }
 
     // helper to get rs by template hash
     pub async fn get_rs_by_template_hash(&self, hash: &str) -> Result<Option<ReplicaSet>> {
-        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), &self.namespace);
+        let api: Api<ReplicaSet> = Api::namespaced(self.kube.client.clone(), &self.namespace);
         let lp = ListParams {
             label_selector: Some(format!("app={},pod-template-hash={}", self.name, hash)),
             ..Default::default()
         };
-        let rs = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        let rs = self.kube.call(|| api.list(&lp)).await?;
         Ok(rs.items.first().map(Clone::clone))
     }
 
     // helper to get rs from deployment
     pub async fn get_rs_from_deploy(&self) -> Result<Option<ReplicaSet>> {
-        let deps: Api<Deployment> = Api::namespaced(self.client.clone(), &self.namespace);
-        let replicasets: Api<ReplicaSet> = Api::namespaced(self.client.clone(), &self.namespace);
+        let deps: Api<Deployment> = Api::namespaced(self.kube.client.clone(), &self.namespace);
+        let replicasets: Api<ReplicaSet> = Api::namespaced(self.kube.client.clone(), &self.namespace);
 
         // Get owning deployment and its revision annotation
-        let dep = deps.get(&self.name).await.map_err(ErrorKind::KubeError)?;
+        let dep = self.kube.call(|| deps.get(&self.name)).await?;
         let mut rev = None;
         if let Some(meta) = dep.metadata {
             if let Some(annot) = meta.annotations {
@@ -205,7 +457,7 @@ impl ShipKube {
                 label_selector: Some(format!("app={}", self.name)),
                 ..Default::default()
             };
-            let rs = replicasets.list(&lp).await.map_err(ErrorKind::KubeError)?;
+            let rs = self.kube.call(|| replicasets.list(&lp)).await?;
 
             // Rely on kubernetes' annotation conventions
             let matching = rs
@@ -236,15 +488,276 @@ impl ShipKube {
 
     // helper to get deployment data
     pub async fn get_deploy(&self) -> Result<Deployment> {
-        let api: Api<Deployment> = Api::namespaced(self.client.clone(), &self.namespace);
-        let deps = api.get(&self.name).await.map_err(ErrorKind::KubeError)?;
+        let api: Api<Deployment> = Api::namespaced(self.kube.client.clone(), &self.namespace);
+        let deps = self.kube.call(|| api.get(&self.name)).await?;
         Ok(deps)
     }
 
     // helper to get statefulset data
     pub async fn get_statefulset(&self) -> Result<StatefulSet> {
-        let api: Api<StatefulSet> = Api::namespaced(self.client.clone(), &self.namespace);
-        let ssets = api.get(&self.name).await.map_err(ErrorKind::KubeError)?;
+        let api: Api<StatefulSet> = Api::namespaced(self.kube.client.clone(), &self.namespace);
+        let ssets = self.kube.call(|| api.get(&self.name)).await?;
         Ok(ssets)
     }
 }
+
+/// List the names of all shipcatmanifests in a namespace
+///
+/// Uses the minimal manifest type so that crds from older schemas still list.
+pub async fn list_manifest_names(kube: &KubeClient, ns: &str) -> Result<Vec<String>> {
+    let mfs = &Resource::namespaced::<ShipcatManifest>(ns);
+    let client = &kube.client;
+    let res = kube
+        .call(|| async move {
+            let req = mfs.list(&ListParams::default())?;
+            client.request::<ObjectList<MinimalMfCrd>>(req).await
+        })
+        .await?;
+    Ok(res.items.into_iter().filter_map(|o| o.metadata.name).collect())
+}
+
+/// JSON patch adding or removing one finalizer, if the list needs to change
+///
+/// The patch only applies to the resourceVersion it was computed from,
+/// so concurrent changes to the finalizers fail it rather than being overwritten.
+fn finalizer_patch(meta: &ObjectMeta, finalizer: &str, present: bool) -> Option<serde_json::Value> {
+    use serde_json::json;
+    let precondition = json!({
+        "op": "test",
+        "path": "/metadata/resourceVersion",
+        "value": meta.resource_version,
+    });
+    let finalizers = meta.finalizers.as_ref();
+    let change = match finalizers.and_then(|fs| fs.iter().position(|f| f == finalizer)) {
+        None if present && finalizers.is_none() => json!({
+            "op": "add",
+            "path": "/metadata/finalizers",
+            "value": [finalizer],
+        }),
+        None if present => json!({
+            "op": "add",
+            "path": "/metadata/finalizers/-",
+            "value": finalizer,
+        }),
+        Some(i) if !present => json!({
+            "op": "remove",
+            "path": format!("/metadata/finalizers/{}", i),
+        }),
+        _ => return None,
+    };
+    Some(json!([precondition, change]))
+}
+
+/// Whether a built manifest is identical to the spec of the live crd
+///
+/// Ignores secrets and the region intrinsic outputs that never deserialize from the crd.
+fn spec_unchanged(local: &Manifest, live: &Manifest) -> bool {
+    let strip = |mf: &Manifest| -> Option<serde_json::Value> {
+        let mut v = serde_json::to_value(mf).ok()?;
+        let obj = v.as_object_mut()?;
+        for k in &["secrets", "uid", "region", "environment", "namespace"] {
+            obj.remove(*k);
+        }
+        Some(v)
+    };
+    match (strip(local), strip(live)) {
+        (Some(l), Some(r)) => l == r,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{finalizer_patch, spec_unchanged, KubeClient, RetryPolicy, ShipKube, ShipcatManifest};
+    use crate::Manifest;
+    use futures::future::join_all;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use kube::{client::APIClient, config::Configuration};
+    use serde_json::json;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    /// A client for a fake api server, with the given concurrency budget
+    fn fake_client(concurrency: usize) -> KubeClient {
+        let config = Configuration::new("http://127.0.0.1:1".into(), reqwest::Client::new());
+        KubeClient::with_client(APIClient::new(config), concurrency)
+    }
+
+    #[tokio::test]
+    async fn limiter_bounds_concurrent_calls() {
+        let kube = fake_client(2);
+        let (in_flight, peak) = (&AtomicUsize::new(0), &AtomicUsize::new(0));
+        // fake client call that records how many calls overlap
+        let call = || async move {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, kube::Error>(now)
+        };
+        let res = join_all((0..6).map(|_| kube.call(call))).await;
+        assert!(res.iter().all(|r| r.is_ok()));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(kube.limiter.available_permits(), 2);
+
+        // clones share the budget
+        let other = kube.clone();
+        let _permit = other.limiter.acquire().await;
+        assert_eq!(kube.limiter.available_permits(), 1);
+    }
+
+    fn api_error(code: u16) -> kube::Error {
+        kube::Error::Api(kube::ErrorResponse {
+            status: "Failure".into(),
+            message: "fake".into(),
+            reason: "fake".into(),
+            code,
+        })
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let kube = fake_client(2);
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+        };
+        // fails with the given codes in order, then succeeds
+        let flaky = |codes: &'static [u16], calls: &'static AtomicUsize| {
+            move || async move {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                match codes.get(n) {
+                    Some(code) => Err(api_error(*code)),
+                    None => Ok(n),
+                }
+            }
+        };
+
+        static RECOVERS: AtomicUsize = AtomicUsize::new(0);
+        let res = kube.call_retrying(&policy, flaky(&[503, 500], &RECOVERS)).await;
+        assert_eq!(res.unwrap(), 2);
+
+        static EXHAUSTS: AtomicUsize = AtomicUsize::new(0);
+        let res = kube
+            .call_retrying(&policy, flaky(&[502, 502, 502], &EXHAUSTS))
+            .await;
+        assert!(res.unwrap_err().to_string().starts_with("kube api: "));
+        assert_eq!(EXHAUSTS.load(Ordering::SeqCst), 3);
+
+        static CLIENT_ERROR: AtomicUsize = AtomicUsize::new(0);
+        let res = kube.call_retrying(&policy, flaky(&[404], &CLIENT_ERROR)).await;
+        match res.unwrap_err().kind() {
+            crate::ErrorKind::KubeError(kube::Error::Api(ae)) => assert_eq!(ae.code, 404),
+            e => panic!("unexpected error {}", e),
+        }
+        assert_eq!(CLIENT_ERROR.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn spec_comparison_ignores_secrets() {
+        let live = Manifest::test("fake-ask");
+        let mut local = Manifest::test("fake-ask");
+        local.secrets.insert("SECRET".into(), "hunter2hunter2".into());
+        assert!(spec_unchanged(&local, &live)); // secrets ignored
+        local.version = Some("1.0.1".into());
+        assert!(!spec_unchanged(&local, &live));
+    }
+
+    #[tokio::test]
+    async fn unchanged_spec_skips_apply() {
+        use mockito::{mock, Matcher};
+        let mf = Manifest::test("fake-ask");
+        let crd = serde_json::to_value(ShipcatManifest::new("fake-ask", mf.clone())).unwrap();
+        let path = "/apis/babylontech.co.uk/v1/namespaces/unchanged/shipcatmanifests/fake-ask";
+        let live = mock("GET", Matcher::Regex(format!("^{}", path)))
+            .with_header("content-type", "application/json")
+            .with_body(crd.to_string())
+            .create();
+        // neither a server-side apply nor a status patch
+        let patched = mock("PATCH", Matcher::Regex(format!("^{}", path)))
+            .expect(0)
+            .create();
+
+        let config = Configuration::new(mockito::server_url(), reqwest::Client::new());
+        let kube = KubeClient::with_client(APIClient::new(config), 2);
+        let s = ShipKube::new_within("fake-ask", "unchanged", &kube);
+        assert!(!s.apply(mf.clone(), false).await.unwrap());
+        // kubectl apply is skipped too
+        assert!(!s.apply(mf, true).await.unwrap());
+        live.expect(2).assert();
+        patched.assert();
+    }
+
+    const FINALIZER: &str = "shipcat.babylontech.co.uk/cleanup";
+
+    #[test]
+    fn finalizer_patch_keeps_other_finalizers() {
+        let meta = |finalizers: Option<Vec<&str>>| ObjectMeta {
+            resource_version: Some("42".into()),
+            finalizers: finalizers.map(|fs| fs.into_iter().map(String::from).collect()),
+            ..Default::default()
+        };
+        let precondition = json!({ "op": "test", "path": "/metadata/resourceVersion", "value": "42" });
+
+        let add = finalizer_patch(&meta(Some(vec!["other/finalizer"])), FINALIZER, true);
+        assert_eq!(
+            add.unwrap(),
+            json!([precondition, { "op": "add", "path": "/metadata/finalizers/-", "value": FINALIZER }])
+        );
+        let first = finalizer_patch(&meta(None), FINALIZER, true);
+        assert_eq!(
+            first.unwrap(),
+            json!([precondition, { "op": "add", "path": "/metadata/finalizers", "value": [FINALIZER] }])
+        );
+        let remove = finalizer_patch(&meta(Some(vec!["other/finalizer", FINALIZER])), FINALIZER, false);
+        assert_eq!(
+            remove.unwrap(),
+            json!([precondition, { "op": "remove", "path": "/metadata/finalizers/1" }])
+        );
+
+        // nothing to do
+        assert!(finalizer_patch(&meta(Some(vec![FINALIZER])), FINALIZER, true).is_none());
+        assert!(finalizer_patch(&meta(Some(vec!["other/finalizer"])), FINALIZER, false).is_none());
+        assert!(finalizer_patch(&meta(None), FINALIZER, false).is_none());
+    }
+
+    #[tokio::test]
+    async fn finalizers_patched_against_the_live_list() {
+        use mockito::{mock, Matcher};
+        let crd = json!({
+            "apiVersion": "babylontech.co.uk/v1",
+            "kind": "ShipcatManifest",
+            "metadata": {
+                "name": "fake-ask",
+                "resourceVersion": "42",
+                "finalizers": ["other/finalizer", FINALIZER],
+            },
+            "spec": { "name": "fake-ask", "version": "1.0.0" },
+        });
+        let path = "/apis/babylontech.co.uk/v1/namespaces/finalizers/shipcatmanifests/fake-ask";
+        let live = mock("GET", Matcher::Regex(format!("^{}", path)))
+            .with_header("content-type", "application/json")
+            .with_body(crd.to_string())
+            .create();
+        let patched = mock("PATCH", Matcher::Regex(format!("^{}", path)))
+            .match_header("content-type", "application/json-patch+json")
+            .match_body(Matcher::Json(json!([
+                { "op": "test", "path": "/metadata/resourceVersion", "value": "42" },
+                { "op": "remove", "path": "/metadata/finalizers/1" },
+            ])))
+            .with_header("content-type", "application/json")
+            .with_body(crd.to_string())
+            .create();
+
+        let config = Configuration::new(mockito::server_url(), reqwest::Client::new());
+        let kube = KubeClient::with_client(APIClient::new(config), 2);
+        let s = ShipKube::new_within("fake-ask", "finalizers", &kube);
+        s.remove_finalizer(FINALIZER).await.unwrap();
+        // already present, so only read
+        s.add_finalizer(FINALIZER).await.unwrap();
+        live.expect(2).assert();
+        patched.assert();
+    }
+}
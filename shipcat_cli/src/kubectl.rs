@@ -5,6 +5,8 @@ use kube::{
     config::load_kube_config,
 };
 use serde::Serialize;
+use shipcat_definitions::structs::Rbac;
+use std::future::Future;
 use tokio::process::Command;
 
 use k8s_openapi::api::authorization::v1::{
@@ -16,6 +18,24 @@ struct AccessReviewRequest {
     verb: String,
     resource: String,
     subresource: Option<String>,
+    group: Option<String>,
+    name: Option<String>,
+}
+
+impl AccessReviewRequest {
+    fn describe(&self) -> String {
+        let mut res = self.resource.clone();
+        if let Some(sub) = &self.subresource {
+            res = format!("{}/{}", res, sub);
+        }
+        if let Some(g) = self.group.as_ref().filter(|g| !g.is_empty()) {
+            res = format!("{}.{}", res, g);
+        }
+        if let Some(n) = &self.name {
+            res = format!("{} {}", res, n);
+        }
+        format!("{} {}", self.verb, res)
+    }
 }
 
 pub async fn kexec(args: Vec<String>) -> Result<()> {
@@ -82,8 +102,8 @@ async fn kani(rr: AccessReviewRequest) -> Result<bool> {
             verb: Some(rr.verb),
             resource: Some(rr.resource),
             subresource: rr.subresource,
-            group: None,
-            name: None,
+            group: rr.group,
+            name: rr.name,
             version: None,
         }),
         non_resource_attributes: None,
@@ -194,6 +214,8 @@ pub async fn port_forward(mf: &Manifest) -> Result<()> {
         verb: "create".into(),
         resource: "pods".into(),
         subresource: Some("portforward".into()),
+        group: None,
+        name: None,
     };
 
     if !kani(access_request).await? {
@@ -242,6 +264,70 @@ pub async fn port_forward(mf: &Manifest) -> Result<()> {
     Ok(())
 }
 
+/// Expand rbac rules into individual access reviews
+fn rbac_access_requests(rules: &[Rbac], ns: &str) -> Vec<AccessReviewRequest> {
+    let mut reqs = vec![];
+    for r in rules {
+        let names: Vec<Option<String>> = if r.resourceNames.is_empty() {
+            vec![None]
+        } else {
+            r.resourceNames.iter().cloned().map(Some).collect()
+        };
+        for group in &r.apiGroups {
+            for res in &r.resources {
+                let mut split = res.splitn(2, '/');
+                let resource = split.next().unwrap_or_default().to_string();
+                let subresource = split.next().map(String::from);
+                for verb in &r.verbs {
+                    for name in &names {
+                        reqs.push(AccessReviewRequest {
+                            namespace: ns.to_string(),
+                            verb: verb.clone(),
+                            resource: resource.clone(),
+                            subresource: subresource.clone(),
+                            group: Some(group.clone()),
+                            name: name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    reqs
+}
+
+/// Find the access reviews that the given authorizer denies
+async fn denied_access<F, Fut>(reqs: Vec<AccessReviewRequest>, authorize: F) -> Result<Vec<String>>
+where
+    F: Fn(AccessReviewRequest) -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let mut denied = vec![];
+    for rr in reqs {
+        let desc = rr.describe();
+        if !authorize(rr).await? {
+            denied.push(desc);
+        }
+    }
+    Ok(denied)
+}
+
+/// Check that the current token could grant all rbac rules of a manifest
+///
+/// Kubernetes prevents privilege escalation, so roles can only grant what the applier holds.
+pub async fn verify_rbac(mf: &Manifest) -> Result<()> {
+    let reqs = rbac_access_requests(&mf.rbac, &mf.namespace);
+    let denied = denied_access(reqs, kani).await?;
+    for d in &denied {
+        warn!("{}: cannot {} in {}", mf.name, d, mf.namespace);
+    }
+    if !denied.is_empty() {
+        bail!("{} rbac rules for {} would be denied", denied.len(), mf.name);
+    }
+    info!("All rbac rules for {} are grantable", mf.name);
+    Ok(())
+}
+
 /// Apply the kube object an applyable file
 ///
 /// CRDs itself, Manifest and Config typically.
@@ -361,8 +447,9 @@ pub async fn get_running_version(svc: &str, ns: &str) -> Result<String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{current_context, get_running_version};
+    use super::{current_context, denied_access, get_running_version, rbac_access_requests};
     use dirs;
+    use shipcat_definitions::structs::Rbac;
 
     #[tokio::test]
     async fn validate_ctx() {
@@ -375,6 +462,25 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn rbac_denied_verb_reported() {
+        let rules = vec![Rbac {
+            apiGroups: vec!["".into()],
+            resources: vec!["pods".into(), "pods/log".into()],
+            resourceNames: vec![],
+            verbs: vec!["get".into(), "delete".into()],
+        }];
+        let reqs = rbac_access_requests(&rules, "dev");
+        assert_eq!(reqs.len(), 4);
+        let denied = denied_access(reqs, |rr| async move { Ok(rr.verb != "delete") })
+            .await
+            .unwrap();
+        assert_eq!(
+            denied,
+            vec!["delete pods".to_string(), "delete pods/log".to_string()]
+        );
+    }
+
     #[tokio::test]
     #[ignore]
     async fn check_get_version() {
@@ -0,0 +1,109 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use super::{Config, Region, Result};
+use shipcat_definitions::Vault;
+
+/// Outcome of fetching a single secret
+///
+/// The secret value itself is never kept.
+#[derive(Debug)]
+pub struct SecretFetch {
+    pub key: String,
+    pub path: String,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+}
+
+/// Fetch every secret path in turn, timing each fetch
+pub async fn time_fetches<F, Fut>(paths: BTreeMap<String, String>, fetch: F) -> Vec<SecretFetch>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut fetches = vec![];
+    for (key, path) in paths {
+        let start = Instant::now();
+        let res = fetch(path.clone()).await;
+        fetches.push(SecretFetch {
+            key,
+            path,
+            elapsed: start.elapsed(),
+            error: res.err().map(|e| e.to_string()),
+        });
+    }
+    fetches
+}
+
+/// Entry point for `shipcat secret latency`
+///
+/// Resolves all vault secrets of a service without applying anything,
+/// and prints the fetch latency of each, slowest first.
+pub async fn report(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let paths = mf.vault_secret_paths(&reg.vault);
+    if paths.is_empty() {
+        info!("{} has no vault secrets in {}", svc, reg.name);
+        return Ok(());
+    }
+    let client = Vault::regional(&reg.vault)?;
+    let client = &client;
+    let mut fetches = time_fetches(paths, |pth| async move { Ok(client.read(&pth).await?) }).await;
+    fetches.sort_by(|a, b| b.elapsed.cmp(&a.elapsed));
+
+    for f in &fetches {
+        let status = f.error.as_deref().unwrap_or("ok");
+        println!(
+            "{:>6}ms {} ({}): {}",
+            f.elapsed.as_millis(),
+            f.key,
+            f.path,
+            status
+        );
+    }
+    let failed = fetches.iter().filter(|f| f.error.is_some()).count();
+    if failed > 0 {
+        bail!(
+            "{} of {} secrets failed to resolve for {}",
+            failed,
+            fetches.len(),
+            svc
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::time_fetches;
+    use crate::Result;
+    use std::{collections::BTreeMap, time::Duration};
+
+    #[tokio::test]
+    async fn latency_reflects_backend_delay() {
+        let mut paths = BTreeMap::new();
+        paths.insert("FAST".to_string(), "dev-uk/fake-ask/FAST".to_string());
+        paths.insert("SLOW".to_string(), "dev-uk/fake-ask/SLOW".to_string());
+        paths.insert("GONE".to_string(), "dev-uk/fake-ask/GONE".to_string());
+        let fetches = time_fetches(paths, |pth| async move {
+            if pth.ends_with("SLOW") {
+                tokio::time::delay_for(Duration::from_millis(100)).await;
+            }
+            if pth.ends_with("GONE") {
+                bail!("secret/{} not found", pth);
+            }
+            Result::Ok("hunter2".to_string())
+        })
+        .await;
+
+        let keys = fetches.iter().map(|f| f.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["FAST", "GONE", "SLOW"]);
+        assert!(fetches[2].elapsed >= Duration::from_millis(100));
+        assert!(fetches[0].elapsed < fetches[2].elapsed);
+        assert!(fetches[0].error.is_none());
+        assert!(fetches[1].error.as_ref().unwrap().contains("not found"));
+    }
+}
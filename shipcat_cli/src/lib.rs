@@ -97,6 +97,10 @@ pub mod audit;
 pub mod cluster;
 /// Convenience listers
 pub mod list;
+/// Shell completion scripts
+pub mod completions;
+/// Structured log output
+pub mod logging;
 /// A post interface to slack using `slack_hook`
 pub mod slack;
 
@@ -114,10 +118,20 @@ pub mod kubeapi;
 
 /// A newer upgrade tracking interface
 pub mod track;
+pub use track::RolloutWait;
 
 /// Status subcommand
 pub mod status;
 
+/// Image update checks against the registry
+pub mod updates;
+
+/// Kafka topic preflight checks
+pub mod kafka;
+
+/// DNS preflight checks for ingress hosts
+pub mod ingress;
+
 /// Apply logic
 pub mod apply;
 
@@ -156,6 +170,15 @@ pub use webhooks::UpgradeState;
 /// Simple printers
 pub mod show;
 
+/// Secret redaction for output
+pub mod redact;
+
+/// Ad-hoc overrides for local rendering
+pub mod overrides;
+
+/// Secret fetch latency diagnostics
+pub mod latency;
+
 /// Cluster auth
 pub mod auth;
 
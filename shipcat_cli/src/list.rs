@@ -1,5 +1,6 @@
-/// This file contains all the hidden `shipcat list-*` subcommands
+/// This file contains `shipcat list` and all the hidden `shipcat list-*` subcommands
 use super::{Config, Region, Result};
+use shipcat_definitions::BaseManifest;
 
 /// Print the supported regions
 pub fn regions(conf: &Config) -> Result<()> {
@@ -17,6 +18,16 @@ pub fn locations(conf: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Print the service directory names, for shell completion
+///
+/// Does not need a region, nor does it parse any manifests.
+pub fn service_names() -> Result<()> {
+    for svc in shipcat_filebacked::service_names() {
+        println!("{}", svc);
+    }
+    Ok(())
+}
+
 /// Print supported services in a region
 /// TODO: this one needs to do the guess outside in main!
 pub async fn services(conf: &Config, region: &Region) -> Result<()> {
@@ -26,3 +37,171 @@ pub async fn services(conf: &Config, region: &Region) -> Result<()> {
     }
     Ok(())
 }
+
+/// Keys usable in `shipcat list --selector`
+const SELECTOR_KEYS: &[&str] = &[
+    "team", "squad", "tribe", "language", "external", "disabled", "region",
+];
+
+/// A `key=value` or `key!=value` selector on services
+#[derive(Debug, PartialEq)]
+pub struct Selector {
+    key: String,
+    value: String,
+    negated: bool,
+}
+
+impl Selector {
+    /// Parse comma separated selectors, like `team=platform,language!=rust`
+    pub fn parse(raw: &str) -> Result<Vec<Selector>> {
+        let mut res = vec![];
+        for s in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value, negated) = if let Some(i) = s.find("!=") {
+                (&s[..i], &s[i + 2..], true)
+            } else if let Some(i) = s.find('=') {
+                (&s[..i], &s[i + 1..], false)
+            } else {
+                bail!("selector {} must be of the form key=value or key!=value", s);
+            };
+            if !SELECTOR_KEYS.contains(&key) {
+                bail!(
+                    "unknown selector key {} (expected one of {})",
+                    key,
+                    SELECTOR_KEYS.join(", ")
+                );
+            }
+            res.push(Selector {
+                key: key.into(),
+                value: value.into(),
+                negated,
+            });
+        }
+        Ok(res)
+    }
+
+    fn matches(&self, svc: &ServiceListing) -> bool {
+        let found = svc
+            .values(&self.key)
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case(&self.value));
+        found != self.negated
+    }
+}
+
+/// Service information matched against in `shipcat list`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceListing {
+    pub name: String,
+    pub team: String,
+    pub squad: Option<String>,
+    pub tribe: Option<String>,
+    pub language: Option<String>,
+    pub regions: Vec<String>,
+    pub external: bool,
+    pub disabled: bool,
+}
+
+impl From<BaseManifest> for ServiceListing {
+    fn from(base: BaseManifest) -> Self {
+        let language = base
+            .metadata
+            .language
+            .as_ref()
+            .and_then(|l| serde_json::to_value(l).ok())
+            .and_then(|v| v.as_str().map(String::from));
+        ServiceListing {
+            name: base.name,
+            team: base.metadata.team,
+            squad: base.metadata.squad,
+            tribe: base.metadata.tribe,
+            language,
+            regions: base.regions,
+            external: base.external,
+            disabled: base.disabled,
+        }
+    }
+}
+
+impl ServiceListing {
+    fn values(&self, key: &str) -> Vec<String> {
+        match key {
+            "team" => vec![self.team.clone()],
+            "squad" => self.squad.iter().cloned().collect(),
+            "tribe" => self.tribe.iter().cloned().collect(),
+            "language" => self.language.iter().cloned().collect(),
+            "external" => vec![self.external.to_string()],
+            "disabled" => vec![self.disabled.to_string()],
+            "region" => self.regions.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Services matching every selector
+pub fn select(services: Vec<BaseManifest>, selectors: &[Selector]) -> Vec<ServiceListing> {
+    services
+        .into_iter()
+        .map(ServiceListing::from)
+        .filter(|svc| selectors.iter().all(|s| s.matches(svc)))
+        .collect()
+}
+
+/// Print services matching the selectors, one name per line or as json
+///
+/// Only reads `manifest.yml` files; no region overrides are merged.
+pub async fn filtered(conf: &Config, selectors: &[Selector], json: bool) -> Result<()> {
+    let services = select(shipcat_filebacked::all(conf).await?, selectors);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&services)?);
+    } else {
+        for svc in services {
+            println!("{}", svc.name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select, Selector};
+    use shipcat_definitions::{structs::Metadata, BaseManifest};
+
+    fn base(name: &str, team: &str, language: &str, regions: &[&str]) -> BaseManifest {
+        let metadata: Metadata = serde_yaml::from_str(&format!(
+            "repo: https://github.com/babylonhealth/{}\nteam: {}\nlanguage: {}",
+            name, team, language
+        ))
+        .unwrap();
+        BaseManifest {
+            name: name.into(),
+            metadata,
+            regions: regions.iter().map(|r| r.to_string()).collect(),
+            external: false,
+            disabled: false,
+        }
+    }
+
+    #[test]
+    fn selectors_filter_services() {
+        let all = || {
+            vec![
+                base("fake-ask", "platform", "rust", &["dev-uk", "prod-uk"]),
+                base("fake-storage", "platform", "python", &["dev-uk"]),
+                base("webapp", "web", "javascript", &["prod-uk"]),
+            ]
+        };
+        let names = |sel: &str| -> Vec<String> {
+            let selectors = Selector::parse(sel).unwrap();
+            select(all(), &selectors).into_iter().map(|s| s.name).collect()
+        };
+        assert_eq!(names("team=platform"), vec!["fake-ask", "fake-storage"]);
+        assert_eq!(names("team=platform,language!=Rust"), vec!["fake-storage"]);
+        assert_eq!(names("region=prod-uk"), vec!["fake-ask", "webapp"]);
+        assert_eq!(names("external=false,region!=dev-uk"), vec!["webapp"]);
+        assert_eq!(names("").len(), 3);
+
+        assert!(Selector::parse("owner=me").is_err());
+        assert!(Selector::parse("team").is_err());
+    }
+}
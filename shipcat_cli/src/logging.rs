@@ -0,0 +1,92 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{Level, Log, Metadata, Record};
+use serde_json::json;
+use std::io::Write;
+
+use super::Result;
+
+/// Logger writing one json object per line to stderr
+pub struct JsonLogger {
+    level: Level,
+    line_numbers: bool,
+}
+
+impl JsonLogger {
+    /// Verbosity works like the default logger: INFO, then DEBUG and TRACE per `-v`
+    pub fn new(verbosity: u64, line_numbers: bool) -> Self {
+        let level = match verbosity {
+            0 => Level::Info,
+            1 => Level::Debug,
+            _ => Level::Trace,
+        };
+        JsonLogger { level, line_numbers }
+    }
+
+    /// Install as the global logger
+    pub fn init(self) -> Result<()> {
+        let level = self.level;
+        if log::set_logger(Box::leak(Box::new(self))).is_err() {
+            bail!("a logger was already initialised");
+        }
+        log::set_max_level(level.to_level_filter());
+        Ok(())
+    }
+
+    fn format(&self, record: &Record<'_>, now: DateTime<Utc>) -> String {
+        let mut line = json!({
+            "level": record.level().to_string(),
+            "timestamp": now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "module": record.module_path().unwrap_or_default(),
+            "message": record.args().to_string(),
+        });
+        if self.line_numbers {
+            line["line"] = json!(record.line());
+        }
+        line.to_string()
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(std::io::stderr(), "{}", self.format(record, Utc::now()));
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonLogger;
+    use chrono::{TimeZone, Utc};
+    use log::{Level, Log, Record};
+
+    #[test]
+    fn json_log_lines() {
+        let logger = JsonLogger::new(0, true);
+        let record = Record::builder()
+            .args(format_args!("applied {}", "fake-ask"))
+            .level(Level::Info)
+            .module_path(Some("shipcat::apply"))
+            .line(Some(42))
+            .build();
+        let line = logger.format(&record, Utc.ymd(2020, 1, 2).and_hms(3, 4, 5));
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["level"], "INFO");
+        assert_eq!(parsed["timestamp"], "2020-01-02T03:04:05.000Z");
+        assert_eq!(parsed["module"], "shipcat::apply");
+        assert_eq!(parsed["message"], "applied fake-ask");
+        assert_eq!(parsed["line"], 42);
+
+        assert!(logger.enabled(&Record::builder().level(Level::Warn).build().metadata()));
+        assert!(!logger.enabled(&Record::builder().level(Level::Debug).build().metadata()));
+        assert!(JsonLogger::new(2, false).enabled(&Record::builder().level(Level::Trace).build().metadata()));
+    }
+}
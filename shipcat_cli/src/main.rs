@@ -2,7 +2,11 @@
 #[macro_use] extern crate log;
 
 use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
-use shipcat::{kubeapi::ShipKube, *};
+use shipcat::{
+    kubeapi::{KubeClient, ShipKube},
+    redact::Redactor,
+    *,
+};
 use std::{process, str::FromStr};
 
 fn print_error_debug(e: &Error) {
@@ -40,6 +44,13 @@ fn build_cli() -> App<'static, 'static> {
             .long("debug")
             .global(true)
             .help("Adds line numbers to log statements"))
+        .arg(Arg::with_name("log-format")
+            .long("log-format")
+            .takes_value(true)
+            .possible_values(&["text", "json"])
+            .default_value("text")
+            .global(true)
+            .help("Log as human readable text or json lines"))
         .arg(Arg::with_name("strict-version-check")
             .long("strict-version-check")
             .global(true)
@@ -62,7 +73,7 @@ fn build_cli() -> App<'static, 'static> {
             .arg(Arg::with_name("shell")
                 .required(true)
                 .possible_values(&Shell::variants())
-                .help("Shell to generate completions for (zsh or bash)")))
+                .help("Shell to generate completions for (bash, zsh or fish)")))
 
         .subcommand(SubCommand::with_name("shell")
             .about("Shell into pods for a service described in a manifest")
@@ -78,6 +89,50 @@ fn build_cli() -> App<'static, 'static> {
                 .required(true)
                 .help("Service name")))
 
+        .subcommand(SubCommand::with_name("whoami")
+            .about("Show who applies are attributed to, and the resolved context and region"))
+
+        .subcommand(SubCommand::with_name("history")
+            .about("Show the rollout history of a service from its shipcatmanifest status")
+            .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service name")))
+
+        .subcommand(SubCommand::with_name("check-updates")
+            .about("Report services running older versions than the latest image tag"))
+
+        .subcommand(SubCommand::with_name("verify-image")
+            .about("Check the image tag of a service exists in its registry")
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .default_value("10")
+                .help("Seconds to wait for the registry"))
+            .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service name")))
+
+        .subcommand(SubCommand::with_name("validate-kafka")
+            .about("Check that the kafka topics of a service exist in the cluster")
+            .arg(Arg::with_name("partitions")
+                .long("partitions")
+                .help("Also check that partition counts match"))
+            .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service name")))
+
+        .subcommand(SubCommand::with_name("validate-ingress")
+            .about("Check that the kong and gate hosts of a service resolve to the ingress (needs network)")
+            .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service name")))
+
+        .subcommand(SubCommand::with_name("verify-rbac")
+            .about("Check that the current token can grant the rbac rules of a service")
+            .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service name")))
+
         .subcommand(SubCommand::with_name("slack")
             .arg(Arg::with_name("url")
                 .short("u")
@@ -96,16 +151,33 @@ fn build_cli() -> App<'static, 'static> {
 
         .subcommand(SubCommand::with_name("validate")
               .arg(Arg::with_name("services")
-                .required(true)
                 .multiple(true)
-                .help("Service names to validate"))
+                .help("Service names to validate (all services if --region all)"))
               .arg(Arg::with_name("secrets")
                 .short("s")
                 .long("secrets")
                 .help("Verifies secrets exist everywhere"))
+              .arg(Arg::with_name("fix")
+                .long("fix")
+                .help("Apply safe fixes to the manifest.yml of the services first"))
+              .arg(Arg::with_name("changed-fields")
+                .long("changed-fields")
+                .help("Fail on changes to immutable fields of the live shipcatmanifest"))
+              .arg(Arg::with_name("num-jobs")
+                .short("j")
+                .long("num-jobs")
+                .alias("jobs")
+                .takes_value(true)
+                .help("Number of manifests built concurrently"))
               .about("Validate the shipcat manifest"))
 
         .subcommand(SubCommand::with_name("verify")
+            .arg(Arg::with_name("num-jobs")
+                .short("j")
+                .long("num-jobs")
+                .alias("jobs")
+                .takes_value(true)
+                .help("Number of manifests built concurrently"))
             .about("Verify all manifests of a region"))
 
         .subcommand(SubCommand::with_name("secret")
@@ -126,6 +198,19 @@ fn build_cli() -> App<'static, 'static> {
                     .multiple(true)
                     .help("Regions to validate all enabled services for"))
                 .about("Verify existence of secrets for entire regions"))
+            .subcommand(SubCommand::with_name("check")
+                .arg(Arg::with_name("strict")
+                    .long("strict")
+                    .help("Fail when secrets are missing rather than warn"))
+                .arg(Arg::with_name("service")
+                    .required(true)
+                    .help("Service to check secrets for"))
+                .about("Report vault secrets of a service missing in the region's vault"))
+            .subcommand(SubCommand::with_name("latency")
+                .arg(Arg::with_name("service")
+                    .required(true)
+                    .help("Service to resolve secrets for"))
+                .about("Time the vault fetch of every secret of a service without applying"))
             .about("Secret interaction"))
 
         .subcommand(SubCommand::with_name("gdpr")
@@ -170,7 +255,17 @@ fn build_cli() -> App<'static, 'static> {
                 .long("crd")
                 .help("Produce an experimental custom resource values for this kubernetes region"))
             .subcommand(SubCommand::with_name("config-url")
-                .help("Generate Kong config URL")))
+                .help("Generate Kong config URL"))
+            .subcommand(SubCommand::with_name("diff")
+                .about("Diff the generated Kong config against a live Kong admin api")
+                .arg(Arg::with_name("kong-url")
+                    .long("kong-url")
+                    .takes_value(true)
+                    .help("Admin api url to use instead of the region's config_url"))
+                .arg(Arg::with_name("auth-header")
+                    .long("auth-header")
+                    .takes_value(true)
+                    .help("Header authenticating with the admin api as 'Name: value' (or SHIPCAT_KONG_AUTH_HEADER)"))))
         // Statuscake helper
         .subcommand(SubCommand::with_name("statuscake")
             .about("Generate Statuscake config"))
@@ -180,7 +275,13 @@ fn build_cli() -> App<'static, 'static> {
                 .help("Service name to graph around"))
               .arg(Arg::with_name("dot")
                 .long("dot")
+                .conflicts_with("format")
                 .help("Generate dot output for graphviz"))
+              .arg(Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["yaml", "json", "dot"])
+                .help("Output format (dot nodes are colored by team)"))
               .arg(Arg::with_name("reverse")
                 .long("reverse")
                 .help("Generate reverse dependencies for a service"))
@@ -191,6 +292,28 @@ fn build_cli() -> App<'static, 'static> {
             .about("Perform cluster level recovery / reconcilation commands")
             .subcommand(SubCommand::with_name("diff")
                 .about("Diff all services against the a region"))
+            .subcommand(SubCommand::with_name("template")
+                .arg(Arg::with_name("output-dir")
+                    .long("output-dir")
+                    .short("o")
+                    .takes_value(true)
+                    .help("Write one directory per service into this directory instead of stdout"))
+                .arg(Arg::with_name("force")
+                    .long("force")
+                    .requires("output-dir")
+                    .help("Wipe the output directory even if it was not written by shipcat"))
+                .arg(Arg::with_name("append")
+                    .long("append")
+                    .requires("output-dir")
+                    .conflicts_with("force")
+                    .help("Keep the output of other services in the output directory"))
+                .arg(Arg::with_name("num-jobs")
+                    .short("j")
+                    .long("num-jobs")
+                    .alias("jobs")
+                    .takes_value(true)
+                    .help("Number of manifests templated concurrently"))
+                .about("Render all services in a region as one multi-document yaml stream"))
             .subcommand(SubCommand::with_name("check")
                 .arg(Arg::with_name("skip-kinds")
                     .long("skip-kinds")
@@ -214,7 +337,17 @@ fn build_cli() -> App<'static, 'static> {
                     .takes_value(true)
                     .help("Number of worker threads used"))
                 .subcommand(SubCommand::with_name("reconcile")
-                    .about("Reconcile vault policies with manifest state"))))
+                    .about("Reconcile vault policies with manifest state")))
+            .subcommand(SubCommand::with_name("teardown")
+                .arg(Arg::with_name("yes")
+                    .long("yes")
+                    .help("Confirm deletion of every service in the region"))
+                .about("Delete all services in a region in reverse dependency order"))
+            .subcommand(SubCommand::with_name("orphans")
+                .arg(Arg::with_name("prune")
+                    .long("prune")
+                    .help("Delete the orphaned manifests rather than just reporting them"))
+                .about("Find shipcatmanifests in a region without a corresponding service")))
         // all the listers (hidden from cli output)
         .subcommand(SubCommand::with_name("list-regions")
             .setting(AppSettings::Hidden)
@@ -222,15 +355,44 @@ fn build_cli() -> App<'static, 'static> {
         .subcommand(SubCommand::with_name("list-locations")
             .setting(AppSettings::Hidden)
             .about("list supported product locations"))
+        .subcommand(SubCommand::with_name("list")
+            .about("List services matching label style selectors on their manifest.yml")
+            .arg(Arg::with_name("selector")
+                .short("l")
+                .long("selector")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Selector like team=X,language!=rust (keys: team, squad, tribe, language, external, disabled, region)"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .possible_values(&["names", "json"])
+                .default_value("names")
+                .help("Print service names one per line, or json with the matched fields")))
         .subcommand(SubCommand::with_name("list-services")
             .setting(AppSettings::Hidden)
             .about("list supported services for a specified"))
+        .subcommand(SubCommand::with_name("__complete-services")
+            .setting(AppSettings::Hidden)
+            .about("list service directories for shell completion"))
+        .subcommand(SubCommand::with_name("__complete-regions")
+            .setting(AppSettings::Hidden)
+            .about("list regions for shell completion"))
 
         // new service subcommands (absorbing some service manifest responsibility from helm/validate cmds)
         .subcommand(SubCommand::with_name("status")
               .arg(Arg::with_name("service")
                 .required(true)
                 .help("Service to check"))
+              .arg(Arg::with_name("output")
+                .takes_value(true)
+                .default_value("text")
+                .possible_values(&["text", "json"])
+                .long("output")
+                .short("o")
+                .help("Output format to print"))
               .about("Show kubernetes status for all the resources for a service"))
 
         .subcommand(SubCommand::with_name("version")
@@ -245,16 +407,45 @@ fn build_cli() -> App<'static, 'static> {
                 .help("Service to generate crd for"))
               .about("Generate the kube equivalent ShipcatManifest CRD"))
 
+        .subcommand(SubCommand::with_name("manifest")
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to show the manifest for"))
+              .arg(Arg::with_name("output")
+                .takes_value(true)
+                .default_value("yaml")
+                .possible_values(&["yaml", "json"])
+                .long("output")
+                .short("o")
+                .help("Output format to print"))
+              .about("Show the fully merged manifest model before templating (secrets redacted)"))
+
         .subcommand(SubCommand::with_name("values")
               .arg(Arg::with_name("secrets")
                 .short("s")
                 .long("secrets")
                 .help("Use actual secrets from vault"))
+              .arg(Arg::with_name("version")
+                .long("version")
+                .takes_value(true)
+                .help("Image version to render instead of the one in manifests"))
+              .arg(Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Override a manifest field for local rendering (path=value, repeatable)"))
               .arg(Arg::with_name("service")
                 .required(true)
                 .help("Service to generate values for"))
               .about("Generate the completed service manifest that will be passed to the helm chart"))
         .subcommand(SubCommand::with_name("template")
+              .arg(Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Override a manifest field for local rendering (path=value, repeatable)"))
               .arg(Arg::with_name("secrets")
                 .short("s")
                 .long("secrets")
@@ -272,11 +463,28 @@ fn build_cli() -> App<'static, 'static> {
                 .takes_value(true)
                 .requires("check")
                 .help("Kinds to ignore strongest checks for (comma separated)"))
+              .arg(Arg::with_name("summary")
+                .long("summary")
+                .help("Print a human readable summary of the service to stderr"))
+              .arg(Arg::with_name("no-validate-yaml")
+                .long("no-validate-yaml")
+                .help("Skip checking that the rendered template parses as yaml"))
+              .arg(Arg::with_name("file")
+                .long("file")
+                .short("f")
+                .takes_value(true)
+                .conflicts_with_all(&["check", "summary"])
+                .help("Render this template file with the service's template context instead of the chart"))
               .arg(Arg::with_name("tag")
                 .long("tag")
                 .short("t")
                 .takes_value(true)
                 .help("Image version to override (useful when validating)"))
+              .arg(Arg::with_name("version")
+                .long("version")
+                .takes_value(true)
+                .conflicts_with("tag")
+                .help("Image version to render instead of the one in manifests"))
               .arg(Arg::with_name("service")
                 .required(true)
                 .help("Service to generate kube yaml for"))
@@ -290,18 +498,63 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("no-wait")
                     .long("no-wait")
                     .help("Do not wait for service timeout"))
+              .arg(Arg::with_name("timeout")
+                    .long("timeout")
+                    .takes_value(true)
+                    .conflicts_with("no-wait")
+                    .help("Seconds to wait for the rollout (default estimated from the manifest)"))
               .arg(Arg::with_name("force")
                     .long("force")
-                    .help("Apply template even if no changes are detected"))
+                    .help("Apply template even if no changes are detected. Also allows applying to a region the service is not enabled in"))
+              .arg(Arg::with_name("yes")
+                    .long("yes")
+                    .alias("prod")
+                    .help("Skip the confirmation prompt for prod regions"))
+              .arg(Arg::with_name("reason")
+                    .long("reason")
+                    .alias("ticket")
+                    .takes_value(true)
+                    .help("Reason or ticket reference recorded with the apply"))
+              .arg(Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Print the diff against the live shipcatmanifest without applying"))
               .arg(Arg::with_name("service")
                 .required(true)
                 .help("Service to apply"))
             .about("Apply a service's configuration in kubernetes (through helm)"))
 
+        .subcommand(SubCommand::with_name("rollback")
+              .arg(Arg::with_name("no-wait")
+                    .long("no-wait")
+                    .help("Do not wait for service timeout"))
+              .arg(Arg::with_name("timeout")
+                    .long("timeout")
+                    .takes_value(true)
+                    .conflicts_with("no-wait")
+                    .help("Seconds to wait for the rollout (default estimated from the manifest)"))
+              .arg(Arg::with_name("yes")
+                    .long("yes")
+                    .alias("prod")
+                    .help("Skip the confirmation prompt for prod regions"))
+              .arg(Arg::with_name("reason")
+                    .long("reason")
+                    .alias("ticket")
+                    .takes_value(true)
+                    .help("Reason or ticket reference recorded with the rollback"))
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to roll back"))
+            .about("Apply a service again at its last successfully rolled out version"))
+
         .subcommand(SubCommand::with_name("restart")
               .arg(Arg::with_name("no-wait")
                     .long("no-wait")
                     .help("Do not wait for service timeout"))
+              .arg(Arg::with_name("timeout")
+                    .long("timeout")
+                    .takes_value(true)
+                    .conflicts_with("no-wait")
+                    .help("Seconds to wait for the rollout (default estimated from the manifest)"))
               .arg(Arg::with_name("service")
                 .required(true)
                 .help("Service to restart"))
@@ -349,6 +602,11 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("mock")
                 .long("mock")
                 .help("Mock uids and versions rather than fetching from the kubernetes shipcatmanifest"))
+              .arg(Arg::with_name("context")
+                .long("context")
+                .short("U")
+                .takes_value(true)
+                .help("Number of unchanged lines to show around changes"))
               .arg(Arg::with_name("minify")
                 .short("m")
                 .long("minify")
@@ -356,7 +614,7 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("obfuscate")
                 .long("obfuscate")
                 .requires("secrets")
-                .help("Obfuscate secrets in the diff"))
+                .help("Obfuscate secrets in the diff (always done)"))
               .arg(Arg::with_name("secrets")
                 .long("secrets")
                 .short("s")
@@ -365,6 +623,27 @@ fn build_cli() -> App<'static, 'static> {
                 .conflicts_with("crd"))
             .about("Diff a service's yaml output against master or kubernetes"))
 
+        .subcommand(SubCommand::with_name("diff-regions")
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to be compared"))
+              .arg(Arg::with_name("from")
+                .required(true)
+                .help("Region to compare from"))
+              .arg(Arg::with_name("to")
+                .required(true)
+                .help("Region to compare against"))
+            .about("Show the manifest fields of a service that differ between two regions"))
+
+        .subcommand(SubCommand::with_name("schema")
+            .about("Print the JSON Schema of a shipcat file")
+            .arg(Arg::with_name("kind")
+                .required(true)
+                .possible_values(&["manifest"])
+                .help("File to describe")))
+        .subcommand(SubCommand::with_name("manifest-schema-version")
+            .about("Print the manifest schemaVersion this shipcat understands"))
+
         // config
         .subcommand(SubCommand::with_name("config")
             .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -374,7 +653,9 @@ fn build_cli() -> App<'static, 'static> {
             .subcommand(SubCommand::with_name("crd")
                 .about("Show the config in crd form for a region"))
             .subcommand(SubCommand::with_name("verify")
-                .about("Verify the parsed config")))
+                .about("Verify the parsed config"))
+            .subcommand(SubCommand::with_name("check")
+                .about("Check clusters, regions and teams in the config, reporting all problems")))
 
         .subcommand(SubCommand::with_name("login")
             .about("Login to a region (using teleport if possible)")
@@ -435,7 +716,10 @@ async fn main() {
     // completions handling first
     if let Some(a) = args.subcommand_matches("completions") {
         let sh = Shell::from_str(a.value_of("shell").unwrap()).unwrap();
-        build_cli().gen_completions_to("shipcat", sh, &mut std::io::stdout());
+        if let Err(e) = shipcat::completions::generate(build_cli(), sh, &mut std::io::stdout()) {
+            error!("completions error: {}", e);
+            process::exit(1);
+        }
         process::exit(0);
     }
 
@@ -450,15 +734,20 @@ async fn main() {
 
 async fn run(args: &ArgMatches<'static>) -> Result<()> {
     // initialise deps and set log default - always show INFO messages (+1)
-    loggerv::Logger::new()
-        .verbosity(args.occurrences_of("verbose") + 1)
-        .module_path(true) // may need cargo clean's if it fails..
-        .line_numbers(args.is_present("debug"))
-        .output(&log::Level::Info, loggerv::Output::Stderr)
-        .output(&log::Level::Debug, loggerv::Output::Stderr)
-        .output(&log::Level::Trace, loggerv::Output::Stderr)
-        .init()
-        .unwrap();
+    if args.value_of("log-format") == Some("json") {
+        let verbosity = args.occurrences_of("verbose");
+        shipcat::logging::JsonLogger::new(verbosity, args.is_present("debug")).init()?;
+    } else {
+        loggerv::Logger::new()
+            .verbosity(args.occurrences_of("verbose") + 1)
+            .module_path(true) // may need cargo clean's if it fails..
+            .line_numbers(args.is_present("debug"))
+            .output(&log::Level::Info, loggerv::Output::Stderr)
+            .output(&log::Level::Debug, loggerv::Output::Stderr)
+            .output(&log::Level::Trace, loggerv::Output::Stderr)
+            .init()
+            .unwrap();
+    }
     shipcat::init()?;
 
     // Ignore SIGPIPE errors to avoid having to use let _ = write! everywhere
@@ -535,6 +824,35 @@ async fn resolve_config(args: &ArgMatches<'_>, ct: ConfigState) -> Result<(Confi
 
 fn void<T>(_x: T) {} // helper so that dispatch_commands can return Result<()>
 
+/// Parse `--num-jobs`, falling back to the default concurrency
+fn num_jobs(args: &ArgMatches<'_>) -> Result<usize> {
+    match args.value_of("num-jobs") {
+        Some(j) => Ok(j.parse::<usize>()?.max(1)),
+        None => Ok(shipcat::validate::DEFAULT_JOBS),
+    }
+}
+
+/// Parse `--context`, falling back to the default number of diff context lines
+fn diff_context(args: &ArgMatches<'_>) -> Result<usize> {
+    match args.value_of("context") {
+        Some(c) => c
+            .parse::<usize>()
+            .chain_err(|| format!("--context must be a number of lines, got '{}'", c)),
+        None => Ok(shipcat::diff::DEFAULT_CONTEXT),
+    }
+}
+
+/// Parse `--no-wait` and `--timeout` into how long to wait for a rollout
+fn rollout_wait(args: &ArgMatches<'_>) -> Result<RolloutWait> {
+    if args.is_present("no-wait") {
+        return Ok(RolloutWait::Skip);
+    }
+    match args.value_of("timeout") {
+        Some(t) => Ok(RolloutWait::Seconds(t.parse::<u32>()?)),
+        None => Ok(RolloutWait::Estimated),
+    }
+}
+
 /// Dispatch clap arguments to shipcat handlers
 ///
 /// A boring and somewhat error-prone "if-x-then-fnx dance". We are relying on types
@@ -548,9 +866,31 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
     } else if args.subcommand_matches("list-locations").is_some() {
         let rawconf = Config::read().await?;
         return shipcat::list::locations(&rawconf);
+    } else if let Some(a) = args.subcommand_matches("list") {
+        let rawconf = Config::read().await?;
+        let mut selectors = vec![];
+        if let Some(sels) = a.values_of("selector") {
+            for s in sels {
+                selectors.extend(shipcat::list::Selector::parse(s)?);
+            }
+        }
+        let json = a.value_of("output") == Some("json");
+        return shipcat::list::filtered(&rawconf, &selectors, json).await;
     } else if let Some(a) = args.subcommand_matches("list-services") {
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return shipcat::list::services(&conf, &region).await;
+    } else if args.subcommand_matches("__complete-services").is_some() {
+        return shipcat::list::service_names();
+    } else if args.subcommand_matches("__complete-regions").is_some() {
+        let rawconf = Config::read().await?;
+        return shipcat::list::regions(&rawconf);
+    } else if args.subcommand_matches("manifest-schema-version").is_some() {
+        println!("{}", shipcat_filebacked::SCHEMA_VERSION);
+        return Ok(());
+    } else if args.subcommand_matches("schema").is_some() {
+        let schema = shipcat_filebacked::manifest_schema();
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(());
     } else if let Some(a) = args.subcommand_matches("login") {
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return shipcat::auth::login(&conf, &region, a.is_present("force")).await;
@@ -651,6 +991,8 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         };
         if let Some(_) = a.subcommand_matches("verify") {
             return shipcat::validate::config(conf);
+        } else if let Some(_) = a.subcommand_matches("check") {
+            return shipcat::validate::config_check(conf);
         } else if let Some(_) = a.subcommand_matches("show") {
             return shipcat::show::config(conf);
         }
@@ -676,6 +1018,16 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 shipcat::validate::secret_presence_full(&rawconf, regions).await
             };
         }
+        if let Some(b) = a.subcommand_matches("check") {
+            let svc = b.value_of("service").unwrap();
+            let (conf, region) = resolve_config(b, ConfigState::Base).await?;
+            return shipcat::validate::secret_check(svc, &conf, &region, b.is_present("strict")).await;
+        }
+        if let Some(b) = a.subcommand_matches("latency") {
+            let svc = b.value_of("service").unwrap();
+            let (conf, region) = resolve_config(b, ConfigState::Base).await?;
+            return shipcat::latency::report(svc, &conf, &region).await;
+        }
     }
     // ------------------------------------------------------------------------------
     // important dev commands below - they resolve kube context as a fallback
@@ -683,25 +1035,62 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
     else if let Some(a) = args.subcommand_matches("status") {
         let svc = a.value_of("service").map(String::from).unwrap();
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-        return shipcat::status::show(&svc, &conf, &region).await;
+        let json = a.value_of("output") == Some("json");
+        return shipcat::status::show(&svc, &conf, &region, json).await;
+    } else if let Some(a) = args.subcommand_matches("whoami") {
+        let applier = shipcat_definitions::status::Applier::infer();
+        let context = kubectl::current_context().await.ok();
+        let (_conf, region) = resolve_config(a, ConfigState::Base).await?;
+        let out = shipcat::show::whoami(&applier, context.as_deref(), &region.name, &region.namespace);
+        println!("{}", out);
+        return Ok(());
+    } else if let Some(a) = args.subcommand_matches("history") {
+        let svc = a.value_of("service").unwrap();
+        let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+        return shipcat::status::history(svc, &conf, &region).await;
+    } else if let Some(a) = args.subcommand_matches("check-updates") {
+        let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+        return shipcat::updates::check_updates(&conf, &region).await;
+    } else if let Some(a) = args.subcommand_matches("verify-image") {
+        let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+        let service = a.value_of("service").unwrap();
+        let timeout = std::time::Duration::from_secs(a.value_of("timeout").unwrap().parse()?);
+        return shipcat::updates::verify_image(service, &conf, &region, timeout).await;
     } else if let Some(a) = args.subcommand_matches("graph") {
-        let dot = a.is_present("dot");
+        use shipcat::graph::GraphFormat;
+        let format = match a.value_of("format") {
+            Some("json") => GraphFormat::Json,
+            Some("dot") => GraphFormat::Dot,
+            _ if a.is_present("dot") => GraphFormat::Dot,
+            _ => GraphFormat::Yaml,
+        };
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return if let Some(svc) = a.value_of("service") {
             if a.is_present("reverse") {
                 shipcat::graph::reverse(svc, &conf, &region).await.map(void)
             } else {
-                shipcat::graph::generate(svc, &conf, &region, dot).await.map(void)
+                shipcat::graph::generate(svc, &conf, &region, format)
+                    .await
+                    .map(void)
             }
         } else {
-            shipcat::graph::full(dot, &conf, &region).await.map(void)
+            shipcat::graph::full(format, &conf, &region).await.map(void)
         };
     } else if let Some(a) = args.subcommand_matches("validate") {
         let services = a
             .values_of("services")
-            .unwrap()
-            .map(String::from)
-            .collect::<Vec<_>>();
+            .map(|vs| vs.map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if a.is_present("fix") {
+            shipcat::validate::fix(&services, &Config::read().await?)?;
+        }
+        let jobs = num_jobs(a)?;
+        if a.value_of("region") == Some("all") {
+            return shipcat::validate::manifests_in_all_regions(services, jobs).await;
+        }
+        if services.is_empty() {
+            return Err("validate needs at least one service unless using --region all".into());
+        }
         // this only needs a kube context if you don't specify it
         let ss = if a.is_present("secrets") {
             ConfigState::Filtered
@@ -709,13 +1098,19 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             ConfigState::Base
         };
         let (conf, region) = resolve_config(a, ss).await?;
-        return shipcat::validate::manifest(services, &conf, &region, a.is_present("secrets")).await;
+        let secrets = a.is_present("secrets");
+        if a.is_present("changed-fields") {
+            shipcat::validate::manifest(services.clone(), &conf, &region, secrets, jobs).await?;
+            return shipcat::validate::changed_fields(services, &conf, &region).await;
+        }
+        return shipcat::validate::manifest(services, &conf, &region, secrets, jobs).await;
     } else if let Some(a) = args.subcommand_matches("verify") {
+        let jobs = num_jobs(a)?;
         return if a.value_of("region").is_some() {
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-            shipcat::validate::regional_manifests(&conf, &region).await
+            shipcat::validate::regional_manifests(&conf, &region, jobs).await
         } else {
-            shipcat::validate::all_manifests().await
+            shipcat::validate::all_manifests(jobs).await
         };
     } else if let Some(a) = args.subcommand_matches("values") {
         let svc = a.value_of("service").map(String::from).unwrap();
@@ -727,18 +1122,22 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         };
         let (conf, region) = resolve_config(a, ss).await?;
 
+        let pinned = a.value_of("version");
         let mf = if a.is_present("secrets") {
-            shipcat_filebacked::load_manifest(&svc, &conf, &region)
+            shipcat_filebacked::load_manifest_with_version(&svc, &conf, &region, pinned)
                 .await?
                 .complete(&region)
                 .await?
         } else {
-            shipcat_filebacked::load_manifest(&svc, &conf, &region)
+            shipcat_filebacked::load_manifest_with_version(&svc, &conf, &region, pinned)
                 .await?
                 .stub(&region)
                 .await?
         };
-        mf.print()?;
+        let sets = a.values_of("set").map(|v| v.collect()).unwrap_or_else(Vec::new);
+        let mf = shipcat::overrides::apply(mf, &sets)?;
+        let values = serde_yaml::to_string(&mf)?;
+        println!("{}", Redactor::for_manifest(&mf).redact(&values));
         return Ok(());
     } else if let Some(a) = args.subcommand_matches("template") {
         let svc = a.value_of("service").map(String::from).unwrap();
@@ -749,22 +1148,22 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             ConfigState::Base
         };
         let (conf, region) = resolve_config(a, ss).await?;
-        let ver = a.value_of("tag").map(String::from);
-
+        let mf = match a.value_of("version") {
+            Some(pinned) => {
+                shipcat_filebacked::load_manifest_with_version(&svc, &conf, &region, Some(pinned)).await?
+            }
+            None => {
+                let ver = a.value_of("tag");
+                shipcat_filebacked::load_manifest_with_default_version(&svc, &conf, &region, ver).await?
+            }
+        };
         let mut mf = if a.is_present("secrets") {
-            shipcat_filebacked::load_manifest(&svc, &conf, &region)
-                .await?
-                .complete(&region)
-                .await?
+            mf.complete(&region).await?
         } else {
-            shipcat_filebacked::load_manifest(&svc, &conf, &region)
-                .await?
-                .stub(&region)
-                .await?
+            mf.stub(&region).await?
         };
-        mf.version = mf.version.or(ver);
         if a.is_present("current") {
-            let s = ShipKube::new(&mf).await?;
+            let s = ShipKube::new(&mf, &KubeClient::new(&region).await?);
             let crd = s.get().await?;
             mf.version = mf.version.or(crd.spec.version);
             mf.uid = crd.metadata.uid;
@@ -773,7 +1172,18 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             mf.uid = Some("FAKE-GUID".to_string());
             mf.version = mf.version.or(Some("latest".to_string()));
         }
-        let tpl = shipcat::helm::template(&mf, None).await?;
+        let sets = a.values_of("set").map(|v| v.collect()).unwrap_or_else(Vec::new);
+        let mf = shipcat::overrides::apply(mf, &sets)?;
+        if let Some(file) = a.value_of("file") {
+            let out = mf.render_template_file(&region, std::path::Path::new(file)).await?;
+            println!("{}", Redactor::for_manifest(&mf).redact(&out));
+            return Ok(());
+        }
+        let validate = !a.is_present("no-validate-yaml");
+        let tpl = shipcat::helm::template(&mf, None, validate).await?;
+        if a.is_present("summary") {
+            eprintln!("{}", shipcat::show::summary(&mf));
+        }
         if a.is_present("check") {
             let skipped = a
                 .value_of("skip-kinds")
@@ -784,7 +1194,7 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 .collect::<Vec<_>>();
             shipcat::helm::template_check(&mf, &region, &skipped, &tpl)?;
         } else {
-            println!("{}", tpl);
+            println!("{}", Redactor::for_manifest(&mf).redact(&tpl));
         }
         return Ok(());
     } else if let Some(a) = args.subcommand_matches("crd") {
@@ -792,6 +1202,12 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
 
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return shipcat::show::manifest_crd(&svc, &conf, &region).await;
+    } else if let Some(a) = args.subcommand_matches("manifest") {
+        let svc = a.value_of("service").map(String::from).unwrap();
+        let json = a.value_of("output") == Some("json");
+
+        let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+        return shipcat::show::manifest(&svc, &conf, &region, json).await;
     } else if let Some(a) = args.subcommand_matches("env") {
         let svc = a.value_of("service").map(String::from).unwrap();
         let mock = !a.is_present("secrets");
@@ -804,26 +1220,27 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         return shipcat::env::print_bash(&svc, &conf, &region, mock).await;
     } else if let Some(a) = args.subcommand_matches("diff") {
         let svc = a.value_of("service").map(String::from).unwrap();
+        let context = diff_context(a)?;
         let diff_exit = if a.is_present("crd") {
             // NB: no secrets in CRD
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
             if a.is_present("git") {
-                shipcat::diff::values_vs_git(&svc, &conf, &region).await?
+                shipcat::diff::values_vs_git(&svc, &conf, &region, context).await?
             } else {
-                shipcat::diff::values_vs_kubectl(&svc, &conf, &region).await?
+                shipcat::diff::values_vs_kubectl(&svc, &conf, &region, context).await?
             }
         } else if a.is_present("git") {
             // special - serial git diff
             // does not support mocking (but also has no secrets)
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-            shipcat::diff::template_vs_git(&svc, &conf, &region).await?
+            shipcat::diff::template_vs_git(&svc, &conf, &region, context).await?
         } else if a.is_present("with-region") {
             // special - diff between two regions
             // does not support mocking (but also has no secrets)
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
             let with_region = a.value_of("with-region").unwrap();
             let (_ref_conf, ref_region) = Config::new(ConfigState::Base, with_region).await?;
-            shipcat::diff::values_vs_region(&svc, &conf, &region, &ref_region).await?
+            shipcat::diff::values_vs_region(&svc, &conf, &region, &ref_region, context).await?
         } else {
             let ss = if a.is_present("secrets") {
                 ConfigState::Filtered
@@ -831,21 +1248,16 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 ConfigState::Base
             };
             let (conf, region) = resolve_config(a, ss).await?;
+            let ver = a.value_of("tag");
+            let mf =
+                shipcat_filebacked::load_manifest_with_default_version(&svc, &conf, &region, ver).await?;
             let mut mf = if !a.is_present("secrets") {
-                shipcat_filebacked::load_manifest(&svc, &conf, &region)
-                    .await?
-                    .stub(&region)
-                    .await?
+                mf.stub(&region).await?
             } else {
-                shipcat_filebacked::load_manifest(&svc, &conf, &region)
-                    .await?
-                    .complete(&region)
-                    .await?
+                mf.complete(&region).await?
             };
-            let ver = a.value_of("tag").map(String::from);
-            mf.version = mf.version.or(ver);
             if !a.is_present("mock") {
-                let s = ShipKube::new(&mf).await?;
+                let s = ShipKube::new(&mf, &KubeClient::new(&region).await?);
                 let crd = s.get().await?;
                 mf.version = mf.version.or(crd.spec.version);
                 mf.uid = crd.metadata.uid;
@@ -855,12 +1267,13 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 mf.version = mf.version.or(Some("latest".to_string()));
             }
             let diff = shipcat::diff::template_vs_kubectl(&mf).await?;
-            if let Some(mut out) = diff {
-                if a.is_present("obfuscate") {
-                    out = shipcat::diff::obfuscate_secrets(out, mf.get_secrets())
-                };
+            if let Some(out) = diff {
+                // secrets are always redacted (--obfuscate is kept for compatibility)
+                let mut out = Redactor::for_manifest(&mf).redact(&out);
                 if a.is_present("minify") {
                     out = shipcat::diff::minify(&out)
+                } else {
+                    out = shipcat::diff::limit_context(&out, context)
                 };
                 println!("{}", out);
                 false
@@ -869,10 +1282,22 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             }
         };
         process::exit(if diff_exit { 0 } else { 1 });
+    } else if let Some(a) = args.subcommand_matches("diff-regions") {
+        let svc = a.value_of("service").unwrap();
+        let from = a.value_of("from").unwrap();
+        let to = a.value_of("to").unwrap();
+        let same = shipcat::diff::manifest_vs_region(svc, from, to).await?;
+        process::exit(if same { 0 } else { 1 });
     } else if let Some(a) = args.subcommand_matches("kong") {
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return if let Some(_b) = a.subcommand_matches("config-url") {
             shipcat::kong::config_url(&region)
+        } else if let Some(b) = a.subcommand_matches("diff") {
+            let auth = b
+                .value_of("auth-header")
+                .map(String::from)
+                .or_else(|| std::env::var("SHIPCAT_KONG_AUTH_HEADER").ok());
+            shipcat::kong::diff(&conf, &region, b.value_of("kong-url"), auth.as_deref()).await
         } else {
             let mode = if a.is_present("crd") {
                 kong::KongOutputMode::Crd
@@ -891,23 +1316,62 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         let svc = a.value_of("service").map(String::from).unwrap();
         // this absolutely needs secrets..
         let (conf, region) = resolve_config(a, ConfigState::Filtered).await?;
-        let wait = !a.is_present("no-wait");
+        let wait = rollout_wait(a)?;
         let force = a.is_present("force");
         let ver = a.value_of("tag").map(String::from); // needed for some subcommands
+        if a.is_present("dry-run") {
+            return shipcat::apply::dry_run(&svc, &region, &conf, ver).await;
+        }
+        assert!(conf.has_secrets()); // sanity on cluster disruptive commands
+        let stdin = std::io::stdin();
+        shipcat::apply::confirm_prod_apply(
+            &svc,
+            ver.as_deref(),
+            (&region.name, &region.environment),
+            a.is_present("yes"),
+            &mut stdin.lock(),
+        )?;
+        let reason = a.value_of("reason").map(String::from);
+        region.verify_apply_reason(reason.as_deref())?;
+        let kube = KubeClient::new(&region).await?;
+        return shipcat::apply::apply(svc, force, &region, &conf, &kube, wait, ver, reason)
+            .await
+            .map(void);
+    } else if let Some(a) = args.subcommand_matches("rollback") {
+        let svc = a.value_of("service").map(String::from).unwrap();
+        let (conf, region) = resolve_config(a, ConfigState::Filtered).await?;
+        let wait = rollout_wait(a)?;
+        let kube = KubeClient::new(&region).await?;
+        let ver = shipcat::apply::rollback_version(&svc, &region, &kube).await?;
         assert!(conf.has_secrets()); // sanity on cluster disruptive commands
-        return shipcat::apply::apply(svc, force, &region, &conf, wait, ver)
+        let stdin = std::io::stdin();
+        shipcat::apply::confirm_prod_apply(
+            &svc,
+            Some(&ver),
+            (&region.name, &region.environment),
+            a.is_present("yes"),
+            &mut stdin.lock(),
+        )?;
+        let reason = a.value_of("reason").map(String::from);
+        region.verify_apply_reason(reason.as_deref())?;
+        info!("Rolling back {} to {}", svc, ver);
+        return shipcat::apply::apply(svc, false, &region, &conf, &kube, wait, Some(ver), reason)
             .await
             .map(void);
     } else if let Some(a) = args.subcommand_matches("restart") {
         let svc = a.value_of("service").map(String::from).unwrap();
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         let mf = shipcat_filebacked::load_manifest(&svc, &conf, &region).await?;
-        let wait = !a.is_present("no-wait");
-        return shipcat::apply::restart(&mf, wait).await.map(void);
+        let wait = rollout_wait(a)?;
+        let kube = KubeClient::new(&region).await?;
+        return shipcat::apply::restart(&mf, &kube, wait).await.map(void);
     } else if let Some(a) = args.subcommand_matches("delete") {
         let svc = a.value_of("service").map(String::from).unwrap();
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-        return shipcat::apply::delete(&svc, &region, &conf).await.map(void);
+        let kube = KubeClient::new(&region).await?;
+        return shipcat::apply::delete(&svc, &region, &conf, &kube)
+            .await
+            .map(void);
     }
     // 4. cluster level commands
     else if let Some(a) = args.subcommand_matches("cluster") {
@@ -929,6 +1393,18 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             let (conf, region) = resolve_config(args, ConfigState::Filtered).await?;
             return shipcat::cluster::mass_diff(&conf, &region).await;
         }
+        if let Some(b) = a.subcommand_matches("template") {
+            let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+            let mode = if b.is_present("append") {
+                shipcat::cluster::OutputMode::Append
+            } else {
+                shipcat::cluster::OutputMode::Replace {
+                    force: b.is_present("force"),
+                }
+            };
+            let dir = b.value_of("output-dir").map(|d| (std::path::Path::new(d), mode));
+            return shipcat::cluster::mass_template(&conf, &region, dir, num_jobs(b)?).await;
+        }
         if let Some(b) = a.subcommand_matches("check") {
             let (conf, region) = resolve_config(args, ConfigState::Base).await?;
             let skipped = b
@@ -948,6 +1424,18 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 return shipcat::cluster::mass_vault(&conf, &region, jobs).await;
             }
         }
+        if let Some(b) = a.subcommand_matches("teardown") {
+            let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+            return shipcat::cluster::teardown(&conf, &region, b.is_present("yes"))
+                .await
+                .map(void);
+        }
+        if let Some(b) = a.subcommand_matches("orphans") {
+            let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+            return shipcat::cluster::prune_orphans(&conf, &region, b.is_present("prune"))
+                .await
+                .map(void);
+        }
     }
     // ------------------------------------------------------------------------------
     // Dispatch small helpers that does not need secrets
@@ -981,6 +1469,30 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             .stub(&region)
             .await?;
         return shipcat::kubectl::port_forward(&mf).await;
+    } else if let Some(a) = args.subcommand_matches("validate-kafka") {
+        let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+        let service = a.value_of("service").unwrap();
+        let mf = shipcat_filebacked::load_manifest(service, &conf, &region)
+            .await?
+            .stub(&region)
+            .await?;
+        return shipcat::kafka::validate_kafka(&mf, a.is_present("partitions"));
+    } else if let Some(a) = args.subcommand_matches("validate-ingress") {
+        let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+        let service = a.value_of("service").unwrap();
+        let mf = shipcat_filebacked::load_manifest(service, &conf, &region)
+            .await?
+            .stub(&region)
+            .await?;
+        return shipcat::ingress::validate_ingress(&mf, &region);
+    } else if let Some(a) = args.subcommand_matches("verify-rbac") {
+        let (conf, region) = resolve_config(args, ConfigState::Base).await?;
+        let service = a.value_of("service").unwrap();
+        let mf = shipcat_filebacked::load_manifest(service, &conf, &region)
+            .await?
+            .stub(&region)
+            .await?;
+        return shipcat::kubectl::verify_rbac(&mf).await;
     } else if let Some(a) = args.subcommand_matches("debug") {
         let (conf, region) = resolve_config(args, ConfigState::Base).await?;
         let service = a.value_of("service").unwrap();
@@ -988,7 +1500,7 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
             .await?
             .stub(&region)
             .await?;
-        let s = ShipKube::new(&mf).await?;
+        let s = ShipKube::new(&mf, &KubeClient::new(&region).await?);
         return shipcat::track::debug(&mf, &s).await;
     }
     // these could technically forgo the kube dependency..
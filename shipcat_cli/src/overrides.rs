@@ -0,0 +1,95 @@
+use serde_json::Value;
+
+use super::{Manifest, Result, ResultExt};
+
+/// Split a `path=value` override into its dotted path and value
+fn parse_set(set: &str) -> Result<(Vec<&str>, &str)> {
+    let mut kv = set.splitn(2, '=');
+    match (kv.next(), kv.next()) {
+        (Some(path), Some(value)) if !path.is_empty() => Ok((path.split('.').collect(), value)),
+        _ => bail!("--set {} must be of the form path=value", set),
+    }
+}
+
+/// Set a dotted path in a serialized manifest, creating missing parents
+///
+/// The value is parsed as yaml, unless it replaces an existing string.
+fn set_path(tree: &mut Value, path: &[&str], value: &str) -> Result<()> {
+    let mut node = tree;
+    for key in path {
+        if node.is_null() {
+            *node = Value::Object(Default::default());
+        }
+        node = match node {
+            Value::Object(map) => map.entry(key.to_string()).or_insert(Value::Null),
+            _ => bail!("{} is not an object", key),
+        };
+    }
+    *node = if node.is_string() {
+        Value::String(value.into())
+    } else {
+        serde_yaml::from_str(value)?
+    };
+    Ok(())
+}
+
+/// Apply ad-hoc `--set path=value` overrides onto a built manifest
+///
+/// Purely for local rendering: the result is type checked by deserializing it again,
+/// and the output variables that cannot be deserialized are carried over or rederived.
+pub fn apply(mf: Manifest, sets: &[&str]) -> Result<Manifest> {
+    if sets.is_empty() {
+        return Ok(mf);
+    }
+    let mut tree = serde_json::to_value(&mf)?;
+    for s in sets {
+        let (path, value) = parse_set(s)?;
+        set_path(&mut tree, &path, value).chain_err(|| format!("--set {}", s))?;
+    }
+    let mut res: Manifest = serde_json::from_value(tree)
+        .chain_err(|| format!("--set {} does not fit the manifest", sets.join(" ")))?;
+    res.region = mf.region;
+    res.environment = mf.environment;
+    res.namespace = mf.namespace;
+    res.uid = mf.uid;
+    res.secrets = mf.secrets;
+    res.secretFilesChecksum = mf.secretFilesChecksum;
+    res.state = mf.state;
+    res.podDisruptionBudget = res.pod_disruption_budget();
+    res.canary = res.canary_with_replicas();
+    if let (Some(md), Some(orig)) = (res.metadata.as_mut(), mf.metadata) {
+        md.squad = orig.squad;
+        md.tribe = orig.tribe;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply;
+    use crate::{structs::ResourceRequirements, Manifest, Result};
+
+    #[test]
+    fn sets_scalars_and_nested_fields() -> Result<()> {
+        let mut mf = Manifest::default();
+        mf.name = "fake-svc".into();
+        mf.version = Some("1.0.0".into());
+        mf.region = "dev-uk".into();
+        mf.resources = Some(serde_yaml::from_str::<ResourceRequirements<String>>(
+            "requests: {cpu: 100m, memory: 100Mi}\nlimits: {cpu: 200m, memory: 200Mi}",
+        )?);
+        let mf = apply(
+            mf,
+            &["replicaCount=3", "version=1.2", "resources.limits.memory=1Gi"],
+        )?;
+        assert_eq!(mf.replicaCount, Some(3));
+        assert_eq!(mf.version, Some("1.2".into()));
+        assert_eq!(mf.resources.unwrap().limits.memory, "1Gi");
+        assert_eq!(mf.region, "dev-uk");
+
+        let err = apply(Manifest::default(), &["replicaCount=lots"]).unwrap_err();
+        assert!(err.to_string().contains("replicaCount=lots"));
+        assert!(apply(Manifest::default(), &["replicaCount"]).is_err());
+        Ok(())
+    }
+}
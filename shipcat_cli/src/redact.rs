@@ -0,0 +1,129 @@
+use super::Manifest;
+
+/// Replacement text for a redacted secret
+pub const REDACTED: &str = "************";
+
+/// Secrets shorter than this are not redacted
+///
+/// Mostly for fear of clashing with other parts of the output,
+/// but also because it's an insecure secret anyway.
+const MIN_SECRET_LEN: usize = 8;
+
+/// Central filter scrubbing known secret values from output
+///
+/// Every string shown to a user (or stored in a status condition) should pass through this.
+/// Besides the raw value, this also catches base64 and json/yaml escaped encodings,
+/// and every line of a multiline secret (e.g. when indented in a yaml block).
+#[derive(Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(secrets: Vec<String>) -> Self {
+        let mut variants = vec![];
+        for s in secrets {
+            if let Ok(escaped) = serde_json::to_string(&s) {
+                let escaped = escaped.trim_matches('"').to_string();
+                // kube Secret data is base64 encoded
+                variants.push(base64::encode(&escaped));
+                variants.push(escaped);
+            }
+            variants.push(base64::encode(&s));
+            for l in s.lines() {
+                variants.push(l.trim().to_string());
+            }
+            variants.push(s);
+        }
+        variants.retain(|s| s.len() >= MIN_SECRET_LEN);
+        // longest first so partial matches do not leave fragments behind
+        variants.sort_by(|a, b| b.len().cmp(&a.len()));
+        variants.dedup();
+        Redactor { secrets: variants }
+    }
+
+    /// Filter for the resolved secrets of a manifest (nothing to filter when stubbed)
+    ///
+    /// `secretFiles` are already base64, and are rendered as is.
+    pub fn for_manifest(mf: &Manifest) -> Self {
+        Self::for_manifests(&[mf])
+    }
+
+    /// Filter for the secrets of several manifests, e.g. both sides of a diff
+    pub fn for_manifests(mfs: &[&Manifest]) -> Self {
+        let mut secrets = vec![];
+        for mf in mfs {
+            secrets.extend(mf.get_secrets());
+            secrets.extend(mf.secretFiles.values().cloned());
+        }
+        Self::new(secrets)
+    }
+
+    /// Scrub all known secret values from a string
+    pub fn redact(&self, input: &str) -> String {
+        let mut out = input.to_string();
+        for s in &self.secrets {
+            out = out.replace(s.as_str(), REDACTED);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Redactor, REDACTED};
+    use crate::Manifest;
+    use k8s_openapi::{api::core::v1::Secret, ByteString};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn redacts_secrets_anywhere_in_output() {
+        let r = Redactor::new(vec![
+            "hunter2hunter2".into(),
+            "line-one-secret\nline-two-secret".into(),
+        ]);
+        let out = r.redact("error: connect postgres://u:hunter2hunter2@db failed");
+        assert_eq!(out, format!("error: connect postgres://u:{}@db failed", REDACTED));
+
+        let yaml = "key: |\n    line-one-secret\n    line-two-secret\n";
+        assert!(!r.redact(yaml).contains("secret"));
+        let json = r#"{"key":"line-one-secret\nline-two-secret"}"#;
+        assert_eq!(r.redact(json), format!(r#"{{"key":"{}"}}"#, REDACTED));
+
+        // short secrets are left alone
+        let r = Redactor::new(vec!["short".into()]);
+        assert_eq!(r.redact("short"), "short");
+    }
+
+    #[test]
+    fn redacts_rendered_secrets() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.secrets.insert("DB_PASSWORD".into(), "hunter2hunter2".into());
+        mf.secrets
+            .insert("CERT".into(), "-----BEGIN-----\nline-two-secret".into());
+        mf.secretFiles
+            .insert("fake-ask-ssl".into(), base64::encode("a secret file"));
+
+        let mut data = BTreeMap::new();
+        for (k, v) in &mf.secrets {
+            data.insert(k.clone(), ByteString(v.clone().into_bytes()));
+        }
+        data.insert("file".into(), ByteString(b"a secret file".to_vec()));
+        let secret = Secret {
+            data: Some(data),
+            ..Default::default()
+        };
+        let rendered = serde_yaml::to_string(&secret).unwrap();
+        assert!(rendered.contains(&base64::encode("hunter2hunter2")));
+
+        let out = Redactor::for_manifest(&mf).redact(&rendered);
+        assert!(!out.contains(&base64::encode("hunter2hunter2")));
+        assert!(!out.contains(&base64::encode("-----BEGIN-----\nline-two-secret")));
+        assert!(!out.contains(&base64::encode("a secret file")));
+        assert_eq!(out.matches(REDACTED).count(), 3);
+
+        // json escaped secrets rendered into base64 data are caught as well
+        let escaped = base64::encode(r"-----BEGIN-----\nline-two-secret");
+        assert_eq!(Redactor::for_manifest(&mf).redact(&escaped), REDACTED);
+    }
+}
@@ -1,5 +1,8 @@
-use super::{Config, Region, Result};
-use shipcat_definitions::{ShipcatConfig, ShipcatManifest};
+use super::{
+    redact::{Redactor, REDACTED},
+    Config, Manifest, Region, Result,
+};
+use shipcat_definitions::{status::Applier, ShipcatConfig, ShipcatManifest};
 
 /// Print the config
 ///
@@ -28,3 +31,130 @@ pub async fn manifest_crd(svc: &str, conf: &Config, reg: &Region) -> Result<()>
     println!("{}", serde_yaml::to_string(&crd)?);
     Ok(())
 }
+
+/// Print the fully built manifest model for a service
+///
+/// This is what merged into what before templating, unlike the helm `values`.
+pub async fn manifest(svc: &str, conf: &Config, reg: &Region, json: bool) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    println!("{}", manifest_model(&mf, json)?);
+    Ok(())
+}
+
+/// Serialize a manifest with every secret value redacted
+fn manifest_model(mf: &Manifest, json: bool) -> Result<String> {
+    let mut redacted = mf.clone();
+    for v in redacted.secrets.values_mut() {
+        *v = REDACTED.into();
+    }
+    let out = if json {
+        serde_json::to_string_pretty(&redacted)?
+    } else {
+        serde_yaml::to_string(&redacted)?
+    };
+    Ok(Redactor::for_manifest(mf).redact(&out))
+}
+
+/// Human readable summary of what a manifest will create
+pub fn summary(mf: &Manifest) -> String {
+    let image = mf.image.clone().unwrap_or_else(|| "unset".into());
+    let version = mf.version.clone().unwrap_or_else(|| "unset".into());
+    let replicas = match (&mf.autoScaling, mf.replicaCount) {
+        (Some(hpa), _) => format!("{}-{} (autoscaling)", hpa.minReplicas, hpa.maxReplicas),
+        (None, Some(n)) => n.to_string(),
+        (None, None) => "unset".into(),
+    };
+    let mut ports: Vec<String> = mf.httpPort.iter().map(|p| format!("{} (http)", p)).collect();
+    ports.extend(mf.ports.iter().map(|p| format!("{} ({})", p.port, p.name)));
+    let hosts: Vec<String> = mf.kongApis.iter().flat_map(|k| k.hosts.clone()).collect();
+
+    let mut lines = vec![];
+    lines.push(format!("{} in {}", mf.name, mf.region));
+    lines.push(format!("  image: {}:{}", image, version));
+    lines.push(format!("  replicas: {}", replicas));
+    lines.push(format!("  ports: {}", or_none(ports)));
+    lines.push(format!("  hosts: {}", or_none(hosts)));
+    lines.push(format!("  dependencies: {}", mf.dependencies.len()));
+    lines.join("\n")
+}
+
+/// Who shipcat attributes applies to, and where they would go
+///
+/// The applier is what ends up as the `source` of crd conditions.
+pub fn whoami(applier: &Applier, context: Option<&str>, region: &str, namespace: &str) -> String {
+    let rows = vec![
+        ("applier", applier.name.as_str()),
+        ("source", applier.url.as_deref().unwrap_or("none")),
+        ("context", context.unwrap_or("none")),
+        ("region", region),
+        ("namespace", namespace),
+    ];
+    rows.into_iter()
+        .map(|(k, v)| format!("{:<10} {}", format!("{}:", k), v))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn or_none(xs: Vec<String>) -> String {
+    if xs.is_empty() {
+        "none".into()
+    } else {
+        xs.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manifest_model, summary, whoami};
+    use crate::redact::REDACTED;
+    use shipcat_definitions::{status::Applier, structs::Kong, Manifest};
+
+    #[test]
+    fn manifest_model_redacts_secrets() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.secrets.insert("DB_PASSWORD".into(), "hunter2hunter2".into());
+
+        let yaml = manifest_model(&mf, false).unwrap();
+        assert!(yaml.contains("name: fake-ask"));
+        assert!(!yaml.contains("hunter2hunter2"));
+        let json: serde_json::Value = serde_json::from_str(&manifest_model(&mf, true).unwrap()).unwrap();
+        assert_eq!(json["secrets"]["DB_PASSWORD"], REDACTED);
+        assert_eq!(json["name"], "fake-ask");
+    }
+
+    #[test]
+    fn summary_lists_image_replicas_hosts() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.image = Some("quay.io/babylonhealth/fake-ask".into());
+        mf.version = Some("1.6.0".into());
+        mf.replicaCount = Some(2);
+        let mut kong = Kong::default();
+        kong.hosts = vec!["fake-ask".into(), "fake.example.com".into()];
+        mf.kongApis = vec![kong];
+
+        let res = summary(&mf);
+        assert!(res.contains("image: quay.io/babylonhealth/fake-ask:1.6.0"));
+        assert!(res.contains("replicas: 2"));
+        assert!(res.contains("hosts: fake-ask, fake.example.com"));
+        assert!(res.contains("dependencies: 0"));
+    }
+
+    #[test]
+    fn whoami_shows_applier_and_region() {
+        let applier = Applier {
+            name: "deploy#12".into(),
+            url: Some("https://ci.example.com/12".into()),
+        };
+        let res = whoami(&applier, Some("dev-uk"), "dev-uk", "dev");
+        assert!(res.contains("applier:   deploy#12"));
+        assert!(res.contains("source:    https://ci.example.com/12"));
+        assert!(res.contains("context:   dev-uk"));
+        assert!(res.contains("namespace: dev"));
+
+        let local = Applier {
+            name: "clux".into(),
+            url: None,
+        };
+        assert!(whoami(&local, None, "dev-uk", "dev").contains("context:   none"));
+    }
+}
@@ -9,7 +9,7 @@ use std::{collections::BTreeMap, env};
 use super::{ErrorKind, Result};
 use crate::diff;
 use shipcat_definitions::{
-    structs::{Contact, Metadata, NotificationMode},
+    structs::{Contact, Metadata, NotificationEvent, NotificationMode},
     teams::{Owners, Person},
 };
 
@@ -28,6 +28,9 @@ pub struct Message {
     /// Notification Mode from Manifest
     pub mode: NotificationMode,
 
+    /// Kind of event, selecting the channel from the Manifest
+    pub event: NotificationEvent,
+
     /// Optional color for the attachment API
     pub color: Option<String>,
 
@@ -75,7 +78,7 @@ pub async fn send(msg: Message, owners: &Owners) -> Result<()> {
     let hook_chan: String = env_channel()?;
     send_internal(msg.clone(), hook_chan, owners).await?;
     let md = &msg.metadata;
-    if let Some(chan) = &md.notifications {
+    if let Some(chan) = md.channel_for(msg.event) {
         let c = chan.clone();
         send_internal(msg, c.to_string(), owners).await?;
     }
@@ -1,6 +1,11 @@
-use crate::{kubeapi::ShipKube, track::PodSummary, Result};
+use crate::{
+    kubeapi::{KubeClient, ShipKube},
+    redact::Redactor,
+    track::PodSummary,
+    Result,
+};
 use k8s_openapi::api::core::v1::Pod;
-use shipcat_definitions::status::Condition;
+use shipcat_definitions::status::{ApplyReason, Condition, ConditionSummary};
 use std::convert::TryFrom;
 
 fn format_condition(cond: &Condition) -> Result<String> {
@@ -27,6 +32,13 @@ fn format_condition(cond: &Condition) -> Result<String> {
     Ok(s)
 }
 
+fn format_apply_reason(r: &ApplyReason) -> String {
+    match &r.applier {
+        Some(a) => format!("{:<25} {} via {}", r.timestamp, r.reason, a.name),
+        None => format!("{:<25} {}", r.timestamp, r.reason),
+    }
+}
+
 fn format_pods(pods: Vec<Pod>) -> Result<()> {
     // NB: podname here is our service limit + rs sha len + pod sha len
     println!(
@@ -41,17 +53,79 @@ fn format_pods(pods: Vec<Pod>) -> Result<()> {
 }
 
 use crate::{Config, Region};
+use shipcat_definitions::ShipcatManifest;
+
+/// A named condition in a `StatusReport`
+#[derive(Serialize)]
+pub struct NamedCondition {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+/// Resolved status of a service, shared by the text and json output of `shipcat status`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    pub service: String,
+    pub requesting: String,
+    /// The `lastSuccessfulRolloutVersion`
+    pub running: Option<String>,
+    /// Whether the last successful rollout used another version than is requested
+    pub version_mismatch: bool,
+    pub conditions: Vec<NamedCondition>,
+    /// Recent apply reasons, newest first
+    pub apply_reasons: Vec<ApplyReason>,
+}
+
+impl StatusReport {
+    pub fn new(crd: &ShipcatManifest) -> Self {
+        let requesting = crd.spec.version.clone().expect("need version");
+        let status = crd.status.clone().unwrap_or_default();
+        let summary = status.summary.unwrap_or_default();
+        let running = summary.last_successful_rollout_version;
+        let apply_reasons = summary.apply_reasons.into_iter().rev().collect();
+        let conds = status.conditions;
+        let conditions = vec![
+            ("Generated", conds.generated),
+            ("Applied", conds.applied),
+            ("RolledOut", conds.rolledout),
+        ]
+        .into_iter()
+        .filter_map(|(kind, c)| c.map(|condition| NamedCondition { kind, condition }))
+        .collect();
+        StatusReport {
+            service: crd.spec.name.clone(),
+            version_mismatch: running.as_ref().map_or(false, |r| r != &requesting),
+            requesting,
+            running,
+            conditions,
+            apply_reasons,
+        }
+    }
+}
+
 /// Entry point for `shipcat status`
-pub async fn show(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
+///
+/// Prints the `StatusReport` as json when `json` is set, otherwise for humans.
+pub async fn show(svc: &str, conf: &Config, reg: &Region, json: bool) -> Result<()> {
     let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
-    let api = ShipKube::new(&mf).await?;
+    let api = ShipKube::new(&mf, &KubeClient::new(reg).await?);
     let crd = api.get().await?;
+    let report = StatusReport::new(&crd);
+    // condition messages are written by other shipcats, scrub them again
+    let redactor = Redactor::for_manifest(&mf);
+    if json {
+        println!("{}", redactor.redact(&serde_json::to_string_pretty(&report)?));
+        return Ok(());
+    }
     let pod_res = api.get_pods().await;
 
     let md = mf.metadata.clone().expect("need metadata");
-    let ver = crd.spec.version.expect("need version");
+    let ver = &report.requesting;
     let support = md.support.clone().unwrap();
-    let link = md.github_link_for_version(&ver);
+    let link = md.github_link_for_version(ver);
     // crazy terminal hyperlink escape codes with rust format {} parts:
     let term_repo = format!("\x1B]8;;{}\x07{}\x1B]8;;\x07", md.repo, mf.name.to_uppercase());
     let term_version = format!("\x1B]8;;{}\x07{}\x1B]8;;\x07", link, ver);
@@ -61,42 +135,32 @@ pub async fn show(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
         *support
     );
 
-    let mut printed = false;
-    if let Some(stat) = &crd.status {
-        if let Some(summary) = &stat.summary {
-            if let Some(successver) = &summary.last_successful_rollout_version {
-                if successver != &ver {
-                    print!("==> {} is requesting {}", term_repo, term_version);
-                    print!(" but last successful deploy used {}", successver);
-                    println!();
-                } else {
-                    println!("==> {} is running {}", term_repo, term_version);
-                }
-                printed = true;
-            }
+    match &report.running {
+        Some(successver) if report.version_mismatch => {
+            print!("==> {} is requesting {}", term_repo, term_version);
+            print!(" but last successful deploy used {}", successver);
+            println!();
         }
-    }
-    if !printed {
-        println!("==> {} is requesting {}", term_repo, term_version);
+        Some(_) => println!("==> {} is running {}", term_repo, term_version),
+        None => println!("==> {} is requesting {}", term_repo, term_version),
     }
     println!("{}", slack_link);
     println!();
 
     println!("==> CONDITIONS");
-    if let Some(stat) = crd.status {
-        let conds = &stat.conditions;
-        if let Some(gen) = &conds.generated {
-            println!("Generated {}", format_condition(gen)?);
-        }
-        if let Some(app) = &conds.applied {
-            println!("Applied {}", format_condition(app)?);
-        }
-        if let Some(ro) = &conds.rolledout {
-            println!("RolledOut {}", format_condition(ro)?);
-        }
+    for c in &report.conditions {
+        println!("{} {}", c.kind, redactor.redact(&format_condition(&c.condition)?));
     }
     println!();
 
+    if !report.apply_reasons.is_empty() {
+        println!("==> RECENT APPLIES");
+        for r in &report.apply_reasons {
+            println!("{}", format_apply_reason(r));
+        }
+        println!();
+    }
+
     if let Ok(pods) = pod_res {
         println!("==> RESOURCES");
         let mut pvec = pods.into_iter().collect::<Vec<Pod>>();
@@ -113,3 +177,110 @@ pub async fn show(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
     }
     Ok(())
 }
+
+/// Render the rollout timeline from a status summary
+pub fn format_history(summary: &ConditionSummary) -> String {
+    let rows = vec![
+        (
+            "Last successful version",
+            &summary.last_successful_rollout_version,
+        ),
+        ("Last successful rollout", &summary.last_successful_rollout),
+        ("Last rollout", &summary.last_rollout),
+        ("Last successful apply", &summary.last_successful_apply),
+        ("Last apply", &summary.last_apply),
+        ("Last apply reason", &summary.last_apply_reason),
+        ("Last action", &summary.last_action),
+        ("Last failure reason", &summary.last_failure_reason),
+    ];
+    rows.into_iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| format!("{:<25} {}", k, v)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Entry point for `shipcat history`
+pub async fn history(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let api = ShipKube::new(&mf, &KubeClient::new(reg).await?);
+    let crd = api.get_minimal().await?;
+    let status = crd.status.unwrap_or_default();
+    let redactor = Redactor::for_manifest(&mf);
+    match status.summary {
+        Some(summary) => println!("{}", redactor.redact(&format_history(&summary))),
+        None => warn!("No rollout history recorded for {}", svc),
+    }
+    for r in status.history.iter().rev() {
+        let res = if r.success { "ok" } else { "failed" };
+        println!("{:<25} {} ({})", r.timestamp, r.version, res);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_apply_reason, format_history, StatusReport};
+    use shipcat_definitions::{
+        status::{Applier, ApplyReason, Condition, ConditionSummary},
+        Manifest, ManifestStatus, ShipcatManifest,
+    };
+
+    #[test]
+    fn history_shows_last_successful_rollout() {
+        let summary = ConditionSummary {
+            last_successful_rollout_version: Some("1.2.3".into()),
+            last_successful_rollout: Some("2020-03-01T12:00:00Z".into()),
+            ..Default::default()
+        };
+        let out = format_history(&summary);
+        assert!(out.contains("Last successful version   1.2.3"));
+        assert!(out.contains("Last successful rollout   2020-03-01T12:00:00Z"));
+        assert!(!out.contains("Last failure reason"));
+    }
+
+    #[test]
+    fn status_report_flags_version_mismatch() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.version = Some("1.1.0".into());
+        let mut crd = ShipcatManifest::new("fake-ask", mf);
+        let mut status = ManifestStatus::default();
+        let reason = |r: &str| ApplyReason {
+            reason: r.into(),
+            timestamp: "2020-03-01T12:00:00Z".into(),
+            applier: Some(Applier {
+                name: "deploy#12".into(),
+                url: None,
+            }),
+        };
+        status.summary = Some(ConditionSummary {
+            last_successful_rollout_version: Some("1.0.0".into()),
+            apply_reasons: vec![reason("version change"), reason("secret change")],
+            ..Default::default()
+        });
+        status.conditions.rolledout = Some(Condition {
+            status: false,
+            reason: Some("RolloutTimeout".into()),
+            message: Some("timed out".into()),
+            last_transition: "2020-03-01T12:00:00Z".into(),
+            source: None,
+        });
+        crd.status = Some(status);
+
+        let json = serde_json::to_value(StatusReport::new(&crd)).unwrap();
+        assert_eq!(json["requesting"], "1.1.0");
+        assert_eq!(json["running"], "1.0.0");
+        assert_eq!(json["versionMismatch"], true);
+        assert_eq!(json["conditions"][0]["type"], "RolledOut");
+        assert_eq!(json["conditions"][0]["reason"], "RolloutTimeout");
+        assert_eq!(
+            json["conditions"][0]["lastTransitionTime"],
+            "2020-03-01T12:00:00Z"
+        );
+        assert_eq!(json["applyReasons"][0]["reason"], "secret change");
+        assert_eq!(json["applyReasons"][1]["reason"], "version change");
+        assert_eq!(
+            format_apply_reason(&reason("version change")),
+            "2020-03-01T12:00:00Z      version change via deploy#12"
+        );
+    }
+}
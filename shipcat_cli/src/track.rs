@@ -411,12 +411,48 @@ async fn rollout_status(mf: &Manifest, kube: &ShipKube, hash: &Option<String>) -
     }
 }
 
-/// Track the rollout of the main workload
-pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
+/// How long to wait for a rollout to complete
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RolloutWait {
+    /// Return as soon as the upgrade is accepted
+    Skip,
+    /// Wait for the rollout time estimated from the manifest
+    Estimated,
+    /// Wait for an explicit number of seconds
+    Seconds(u32),
+}
+
+impl RolloutWait {
+    /// Seconds to wait for a manifest's rollout, if waiting at all
+    pub fn timeout(self, mf: &Manifest) -> Option<u32> {
+        match self {
+            RolloutWait::Skip => None,
+            RolloutWait::Estimated => Some(mf.estimate_wait_time()),
+            RolloutWait::Seconds(s) => Some(s),
+        }
+    }
+}
+
+/// Sleeps between rollout polls, together covering the whole wait time
+///
+/// Polls 20 times over the wait, but never more often than once a second.
+fn poll_delays(waittime: u32) -> Vec<u32> {
+    let interval = std::cmp::max(1, waittime / 20);
+    let mut delays = vec![];
+    let mut waited = 0;
+    while waited < waittime {
+        let delay = std::cmp::min(interval, waittime - waited);
+        delays.push(delay);
+        waited += delay;
+    }
+    delays
+}
+
+/// Track the rollout of the main workload for up to `waittime` seconds
+pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube, waittime: u32) -> Result<bool> {
     use futures_timer::Delay;
     use indicatif::{ProgressBar, ProgressStyle};
     let minimum = mf.min_replicas();
-    let waittime = mf.estimate_wait_time();
     let one_sec = std::time::Duration::from_millis(1000);
 
     match rollout_status(mf, kube, &None).await {
@@ -483,15 +519,9 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
         pb.set_prefix(&mf.name);
     }
 
-    for i in 1..20 {
-        trace!("poll iteration {}", i);
-        let mut waited = 0;
-        // sleep until 1/20th of estimated upgrade time and poll for status
-        while waited < waittime / 20 {
-            waited += 1;
-            trace!("sleep 1s (waited {})", waited);
-            Delay::new(one_sec).await;
-        }
+    for (i, delay) in poll_delays(waittime).into_iter().enumerate() {
+        trace!("poll iteration {} after sleeping {}s", i, delay);
+        Delay::new(one_sec * delay).await;
         let rr = rollout_status(mf, kube, &hash).await?;
         debug!("RR: {:?}", rr);
         if let Some(msg) = rr.message {
@@ -506,3 +536,18 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
     }
     Ok(false) // timeout
 }
+
+#[cfg(test)]
+mod tests {
+    use super::poll_delays;
+
+    #[test]
+    fn poll_delays_cover_the_wait() {
+        let delays = poll_delays(300);
+        assert_eq!(delays.len(), 20);
+        assert!(delays.iter().all(|d| *d == 15));
+        assert_eq!(poll_delays(10), vec![1; 10]);
+        assert_eq!(poll_delays(50).iter().sum::<u32>(), 50);
+        assert!(poll_delays(0).is_empty());
+    }
+}
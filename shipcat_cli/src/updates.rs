@@ -0,0 +1,312 @@
+use futures::stream::{self, StreamExt};
+use semver::Version;
+use std::time::Duration;
+
+use super::{Config, ErrorKind, Region, Result, ResultExt};
+use crate::kubeapi::{KubeClient, ShipKube};
+
+/// Tag listing from the docker registry v2 api
+#[derive(Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Split an image into its registry api base url and repository
+///
+/// Images without a registry host are assumed to live on docker hub.
+pub fn registry_location(image: &str) -> (String, String) {
+    let mut parts = image.splitn(2, '/');
+    let first = parts.next().unwrap_or_default();
+    match parts.next() {
+        Some(rest) if first.contains('.') || first.contains(':') => {
+            (format!("https://{}", first), rest.into())
+        }
+        _ if image.contains('/') => ("https://registry-1.docker.io".into(), image.into()),
+        _ => (
+            "https://registry-1.docker.io".into(),
+            format!("library/{}", image),
+        ),
+    }
+}
+
+/// Bearer token for registry requests, read from `SHIPCAT_REGISTRY_TOKEN` if set
+fn registry_token() -> Option<String> {
+    std::env::var("SHIPCAT_REGISTRY_TOKEN").ok()
+}
+
+/// Target of the `rel="next"` entry in a `Link` header, which registries paginate with
+fn next_link(link: &str) -> Option<&str> {
+    link.split(',').find_map(|l| {
+        let mut parts = l.split(';');
+        let target = parts.next()?.trim();
+        if parts.any(|p| p.trim() == r#"rel="next""#) {
+            Some(target.trim_start_matches('<').trim_end_matches('>'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Newest semver tag of a repository in a registry
+///
+/// Follows the pagination of the tag listing through every page.
+pub async fn latest_tag(registry: &str, repo: &str, token: Option<&str>) -> Result<Option<Version>> {
+    let client = reqwest::Client::new();
+    let first = reqwest::Url::parse(&format!("{}/v2/{}/tags/list", registry, repo))?;
+    let mut next = Some(first);
+    let mut latest = None;
+    while let Some(url) = next.take() {
+        let mut req = client.get(url.clone());
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        let res = req
+            .send()
+            .await
+            .chain_err(|| ErrorKind::Url(url.clone()))?
+            .error_for_status()
+            .chain_err(|| ErrorKind::Url(url.clone()))?;
+        if let Some(link) = res.headers().get(reqwest::header::LINK) {
+            if let Some(l) = link.to_str().ok().and_then(next_link) {
+                next = Some(url.join(l)?);
+            }
+        }
+        let list: TagList = res.json().await?;
+        let tags = list.tags.iter().filter_map(|t| Version::parse(t).ok());
+        latest = tags.chain(latest).max();
+    }
+    Ok(latest)
+}
+
+/// Media types accepted when looking up an image manifest
+const MANIFEST_TYPES: &str = "application/vnd.docker.distribution.manifest.v2+json, \
+                              application/vnd.docker.distribution.manifest.list.v2+json, \
+                              application/vnd.oci.image.index.v1+json";
+
+/// Whether a tag exists in a registry, via a v2 manifest HEAD request
+pub async fn tag_exists(
+    registry: &str,
+    repo: &str,
+    tag: &str,
+    token: Option<&str>,
+    timeout: Duration,
+) -> Result<bool> {
+    let url = reqwest::Url::parse(&format!("{}/v2/{}/manifests/{}", registry, repo, tag))?;
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let mut req = client
+        .head(url.clone())
+        .header(reqwest::header::ACCEPT, MANIFEST_TYPES);
+    if let Some(t) = token {
+        req = req.bearer_auth(t);
+    }
+    let res = req.send().await.chain_err(|| ErrorKind::Url(url.clone()))?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    res.error_for_status().chain_err(|| ErrorKind::Url(url.clone()))?;
+    Ok(true)
+}
+
+/// Entry point for `shipcat verify-image`
+///
+/// Fails unless the built `image:version` of a service exists in its registry.
+/// A bearer token for the registry is read from `SHIPCAT_REGISTRY_TOKEN` if set.
+pub async fn verify_image(svc: &str, conf: &Config, reg: &Region, timeout: Duration) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let image = match &mf.image {
+        Some(i) => i,
+        None => bail!("{} has no image in {}", svc, reg.name),
+    };
+    let version = match &mf.version {
+        Some(v) => v,
+        None => bail!("{} has no version pinned in {} to verify", svc, reg.name),
+    };
+    let (registry, repo) = registry_location(image);
+    let token = registry_token();
+    if !tag_exists(&registry, &repo, version, token.as_deref(), timeout).await? {
+        bail!("{}:{} does not exist in {}", image, version, registry);
+    }
+    info!("{}:{} exists in {}", image, version, registry);
+    Ok(())
+}
+
+/// How far a running version is behind the latest one, if at all
+pub fn behind(running: &Version, latest: &Version) -> Option<String> {
+    if running >= latest {
+        None
+    } else if latest.major > running.major {
+        Some(format!("{} major", latest.major - running.major))
+    } else if latest.minor > running.minor {
+        Some(format!("{} minor", latest.minor - running.minor))
+    } else if latest.patch > running.patch {
+        Some(format!("{} patch", latest.patch - running.patch))
+    } else {
+        Some("pre-release".into())
+    }
+}
+
+/// Result of checking one service
+pub struct UpdateCheck {
+    pub name: String,
+    pub running: Version,
+    pub latest: Version,
+    pub behind: Option<String>,
+}
+
+/// Compare a service's running version against the newest tag of its image
+pub async fn check_service(
+    name: &str,
+    image: &str,
+    running: &str,
+    registry: Option<&str>,
+    token: Option<&str>,
+) -> Result<Option<UpdateCheck>> {
+    let running = match Version::parse(running) {
+        Ok(v) => v,
+        Err(_) => {
+            debug!("{} is running non-semver version {}", name, running);
+            return Ok(None);
+        }
+    };
+    let (default_registry, repo) = registry_location(image);
+    let registry = registry.map(String::from).unwrap_or(default_registry);
+    let latest = latest_tag(&registry, &repo, token).await?;
+    Ok(latest.map(|latest| UpdateCheck {
+        name: name.into(),
+        behind: behind(&running, &latest),
+        running,
+        latest,
+    }))
+}
+
+async fn check_running(
+    name: String,
+    image: String,
+    namespace: &str,
+    kube: &KubeClient,
+    token: Option<&str>,
+) -> Result<Option<UpdateCheck>> {
+    let s = ShipKube::new_within(&name, namespace, kube);
+    let status = s.get_minimal().await?.status.unwrap_or_default();
+    let running = status.summary.and_then(|s| s.last_successful_rollout_version);
+    match running {
+        Some(v) => check_service(&name, &image, &v, None, token).await,
+        None => {
+            debug!("{} has no successful rollout recorded", name);
+            Ok(None)
+        }
+    }
+}
+
+/// Entry point for `shipcat check-updates`
+///
+/// Reports every service in the region whose running version is behind its latest image tag.
+/// A bearer token for the registry is read from `SHIPCAT_REGISTRY_TOKEN` if set.
+pub async fn check_updates(conf: &Config, reg: &Region) -> Result<()> {
+    let svcs = shipcat_filebacked::available(conf, reg).await?;
+    let kube = &KubeClient::new(reg).await?;
+    let token = &registry_token();
+    let mut buffered = stream::iter(svcs)
+        .map(|mf| {
+            let image = mf.image.unwrap_or_default();
+            check_running(mf.base.name, image, &reg.namespace, kube, token.as_deref())
+        })
+        .buffer_unordered(10);
+
+    let mut checks = vec![];
+    while let Some(r) = buffered.next().await {
+        match r {
+            Ok(Some(c)) => checks.push(c),
+            Ok(None) => {}
+            Err(e) => warn!("Unable to check for updates: {}", e),
+        }
+    }
+    checks.sort_by(|a, b| a.name.cmp(&b.name));
+    for c in checks {
+        if let Some(b) = c.behind {
+            println!("{:<30} {} -> {} ({} behind)", c.name, c.running, c.latest, b);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_service, next_link, registry_location, tag_exists};
+    use crate::Result;
+    use std::time::Duration;
+
+    #[test]
+    fn registry_locations() {
+        assert_eq!(
+            registry_location("quay.io/babylonhealth/raftcat"),
+            ("https://quay.io".into(), "babylonhealth/raftcat".into())
+        );
+        assert_eq!(
+            registry_location("nginx"),
+            ("https://registry-1.docker.io".into(), "library/nginx".into())
+        );
+    }
+
+    #[test]
+    fn next_links() {
+        let link = r#"</v2/fake-svc/tags/list?last=1.0.0&n=2>; rel="next""#;
+        assert_eq!(next_link(link), Some("/v2/fake-svc/tags/list?last=1.0.0&n=2"));
+        let other = r#"<https://docs.example.com>; rel="help", </v2/x/tags/list?n=2>; rel="next""#;
+        assert_eq!(next_link(other), Some("/v2/x/tags/list?n=2"));
+        assert_eq!(next_link(r#"</v2/x/tags/list>; rel="prev""#), None);
+    }
+
+    #[tokio::test]
+    async fn reports_minor_version_behind() -> Result<()> {
+        let path = "/v2/babylonhealth/fake-svc/tags/list";
+        let first = mockito::mock("GET", path)
+            .match_header("authorization", "Bearer hunter2")
+            .with_header("content-type", "application/json")
+            .with_header("link", &format!(r#"<{}?last=latest&n=2>; rel="next""#, path))
+            .with_body(r#"{"name":"babylonhealth/fake-svc","tags":["1.0.0","latest"]}"#)
+            .expect(1)
+            .create();
+        let last = mockito::mock("GET", format!("{}?last=latest&n=2", path).as_str())
+            .match_header("authorization", "Bearer hunter2")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name":"babylonhealth/fake-svc","tags":["1.1.0","0.9.3"]}"#)
+            .expect(1)
+            .create();
+        let server = mockito::server_url();
+        let check = check_service(
+            "fake-svc",
+            "quay.io/babylonhealth/fake-svc",
+            "1.0.0",
+            Some(&server),
+            Some("hunter2"),
+        )
+        .await?
+        .unwrap();
+        first.assert();
+        last.assert();
+        assert_eq!(check.latest.to_string(), "1.1.0");
+        assert_eq!(check.behind, Some("1 minor".into()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn missing_tag_is_detected() -> Result<()> {
+        let found = mockito::mock("HEAD", "/v2/babylonhealth/fake-ask/manifests/1.0.0")
+            .match_header("authorization", "Bearer hunter2")
+            .with_status(200)
+            .create();
+        let missing = mockito::mock("HEAD", "/v2/babylonhealth/fake-ask/manifests/1.0.1")
+            .with_status(404)
+            .create();
+        let server = mockito::server_url();
+        let repo = "babylonhealth/fake-ask";
+        let timeout = Duration::from_secs(5);
+        assert!(tag_exists(&server, repo, "1.0.0", Some("hunter2"), timeout).await?);
+        assert!(!tag_exists(&server, repo, "1.0.1", None, timeout).await?);
+        found.assert();
+        missing.assert();
+        Ok(())
+    }
+}
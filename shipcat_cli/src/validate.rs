@@ -1,32 +1,64 @@
-use super::{Config, Manifest, Region, Result};
-use crate::{error_chain::ChainedError, git};
+use super::{Config, ErrorKind, Manifest, Region, Result};
+use crate::{
+    error_chain::ChainedError,
+    git,
+    kubeapi::{KubeClient, ShipKube},
+};
 use futures::stream::{self, StreamExt};
+use serde_yaml::Value;
+use shipcat_definitions::{ErrorKind as DefinitionsErrorKind, ValidationError};
+use std::{fs, future::Future};
 
-async fn verify_manifest(svc: String, conf: &Config, reg: &Region) -> Result<Manifest> {
-    let mf = shipcat_filebacked::load_manifest(&svc, &conf, &reg)
-        .await?
-        .stub(&reg)
-        .await?;
+/// Default number of manifests built concurrently
+pub const DEFAULT_JOBS: usize = 16;
+
+async fn verify_manifest(svc: String, conf: &Config, reg: &Region, secrets: bool) -> Result<Manifest> {
+    let mf = shipcat_filebacked::load_manifest(&svc, &conf, &reg).await?;
+    let mf = if secrets {
+        mf.complete(&reg).await?
+    } else {
+        mf.stub(&reg).await?
+    };
     mf.verify(&conf, &reg)?;
     Ok(mf)
 }
 
+/// Build and verify manifests concurrently, at most `jobs` at a time
+///
+/// Results are returned per service in input order, so failures can be reported together.
+pub async fn verify_manifests(
+    services: Vec<String>,
+    conf: &Config,
+    reg: &Region,
+    secrets: bool,
+    jobs: usize,
+) -> Vec<(String, Result<Manifest>)> {
+    stream::iter(services)
+        .map(move |svc| async move {
+            let res = verify_manifest(svc.clone(), conf, reg, secrets).await;
+            (svc, res)
+        })
+        .buffered(jobs.max(1))
+        .collect()
+        .await
+}
+
 /// Validate all manifests in a service directory for a region
 ///
 /// This is meant to replace `shipcat validate ..all_services`
 /// This does not check secrets.
-pub async fn regional_manifests(conf: &Config, reg: &Region) -> Result<()> {
-    let available = shipcat_filebacked::available(conf, &reg).await?;
-
-    let mut buffered = stream::iter(available)
-        .map(move |mf| verify_manifest(mf.base.name, &conf, &reg))
-        .buffer_unordered(16);
+pub async fn regional_manifests(conf: &Config, reg: &Region, jobs: usize) -> Result<()> {
+    let available = shipcat_filebacked::available(conf, &reg)
+        .await?
+        .into_iter()
+        .map(|mf| mf.base.name)
+        .collect();
 
     let mut errs = vec![];
     let mut used_stream_names = vec![];
     let mut used_topic_names = vec![];
     let mut used_user_names = vec![];
-    while let Some(r) = buffered.next().await {
+    for (_, r) in verify_manifests(available, conf, reg, false, jobs).await {
         match r {
             Err(e) => errs.push(e),
             Ok(mf) => {
@@ -73,10 +105,10 @@ pub async fn regional_manifests(conf: &Config, reg: &Region) -> Result<()> {
     Ok(())
 }
 
-async fn verify_region(r: String) -> Result<()> {
+async fn verify_region(r: String, jobs: usize) -> Result<()> {
     use crate::ConfigState;
     let (conf, region) = Config::new(ConfigState::Base, &r).await?;
-    regional_manifests(&conf, &region).await?;
+    regional_manifests(&conf, &region, jobs).await?;
     Ok(())
 }
 
@@ -84,9 +116,11 @@ async fn verify_region(r: String) -> Result<()> {
 ///
 /// This is meant to replace a for loop over shipcat list-regions
 /// This does not check secrets
-pub async fn all_manifests() -> Result<()> {
+pub async fn all_manifests(jobs: usize) -> Result<()> {
     let regions = Config::read().await?.list_regions();
-    let mut buffered = stream::iter(regions).map(verify_region).buffer_unordered(4);
+    let mut buffered = stream::iter(regions)
+        .map(|r| verify_region(r, jobs))
+        .buffer_unordered(4);
 
     let mut errs = vec![];
     while let Some(r) = buffered.next().await {
@@ -104,29 +138,245 @@ pub async fn all_manifests() -> Result<()> {
     Ok(())
 }
 
+/// A validation failure attributed to a region and service
+#[derive(Debug, PartialEq)]
+pub struct RegionFailure {
+    pub region: String,
+    /// Service that failed, or none if the region itself failed to load
+    pub service: Option<String>,
+    /// Dotted path of the offending field, when known
+    pub field: Option<String>,
+    pub error: String,
+}
+
+impl RegionFailure {
+    fn new(region: String, service: Option<String>, e: &crate::Error) -> Self {
+        match validation_error(e) {
+            Some(v) => RegionFailure {
+                region,
+                service,
+                field: Some(v.field_path()).filter(|p| !p.is_empty()),
+                error: v.message,
+            },
+            None => RegionFailure {
+                region,
+                service,
+                field: None,
+                error: one_line(e),
+            },
+        }
+    }
+}
+
+/// Find the field attributed failure behind an error, if there is one
+fn validation_error(e: &crate::Error) -> Option<ValidationError> {
+    use std::error::Error as StdError;
+    // build failures chain the definitions error that caused them
+    let mut next: Option<&(dyn StdError + 'static)> = match e.kind() {
+        ErrorKind::Mani(de) => Some(de),
+        _ => e.source(),
+    };
+    while let Some(err) = next {
+        if let Some(de) = err.downcast_ref::<shipcat_definitions::Error>() {
+            if let DefinitionsErrorKind::Validation(v) = de.kind() {
+                return Some(v.clone());
+            }
+        }
+        next = err.source();
+    }
+    None
+}
+
+/// Validate services in one region, returning the result for each service
+///
+/// Validates all available services if `services` is empty.
+async fn check_region(r: String, services: Vec<String>, jobs: usize) -> Result<Vec<(String, Result<()>)>> {
+    use crate::ConfigState;
+    let (conf, reg) = Config::new(ConfigState::Base, &r).await?;
+    let available: Vec<String> = shipcat_filebacked::available(&conf, &reg)
+        .await?
+        .into_iter()
+        .map(|s| s.base.name)
+        .collect();
+    let svcs = if services.is_empty() {
+        available
+    } else {
+        services.into_iter().filter(|s| available.contains(s)).collect()
+    };
+    let res = verify_manifests(svcs, &conf, &reg, false, jobs).await;
+    Ok(res.into_iter().map(|(svc, r)| (svc, r.map(|_| ()))).collect())
+}
+
+/// Run a region checker across regions in parallel and attribute failures
+async fn validate_regions<F, Fut>(regions: Vec<String>, check: F) -> Vec<RegionFailure>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<(String, Result<()>)>>>,
+{
+    let mut buffered = stream::iter(regions)
+        .map(|r| {
+            let fut = check(r.clone());
+            async move { (r, fut.await) }
+        })
+        .buffer_unordered(4);
+
+    let mut failures = vec![];
+    while let Some((region, res)) = buffered.next().await {
+        match res {
+            Err(e) => failures.push(RegionFailure::new(region, None, &e)),
+            Ok(results) => {
+                for (svc, r) in results {
+                    if let Err(e) = r {
+                        failures.push(RegionFailure::new(region.clone(), Some(svc), &e));
+                    }
+                }
+            }
+        }
+    }
+    failures.sort_by(|a, b| (&a.region, &a.service).cmp(&(&b.region, &b.service)));
+    failures
+}
+
+/// Table of failures grouped by service (region level failures first)
+fn failure_table(failures: &[RegionFailure]) -> String {
+    let mut sorted = failures.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| (&a.service, &a.region).cmp(&(&b.service, &b.region)));
+    let mut rows = vec![format!(
+        "{:<30} {:<20} {:<35} {}",
+        "SERVICE", "REGION", "FIELD", "ERROR"
+    )];
+    let mut last = None;
+    for f in sorted {
+        let svc = f.service.as_ref().map(String::as_str).unwrap_or("*");
+        let shown = if last == Some(svc) { "" } else { svc };
+        last = Some(svc);
+        let field = f.field.as_ref().map(String::as_str).unwrap_or("-");
+        rows.push(format!(
+            "{:<30} {:<20} {:<35} {}",
+            shown, f.region, field, f.error
+        ));
+    }
+    rows.join("\n")
+}
+
+fn one_line(e: &crate::Error) -> String {
+    e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+/// Validate services across every region in the config
+///
+/// Validates all available services per region if `services` is empty.
+/// This does not check secrets.
+pub async fn manifests_in_all_regions(services: Vec<String>, jobs: usize) -> Result<()> {
+    let regions = Config::read().await?.list_regions();
+    let failures = validate_regions(regions, |r| check_region(r, services.clone(), jobs)).await;
+    if !failures.is_empty() {
+        println!("{}", failure_table(&failures));
+        bail!(
+            "Validation failed in {} region/service combinations",
+            failures.len()
+        );
+    }
+    Ok(())
+}
+
 /// Validate the manifest of a service in the services directory
 ///
 /// This will populate the manifest for all supported environments,
 /// and `verify` their parameters.
 /// Optionally, it will also verify that all secrets are found in the corresponding
 /// vault locations serverside (which require vault credentials).
-pub async fn manifest(services: Vec<String>, conf: &Config, reg: &Region, secrets: bool) -> Result<()> {
+/// Services are validated `jobs` at a time, and all failures are reported together.
+pub async fn manifest(
+    services: Vec<String>,
+    conf: &Config,
+    reg: &Region,
+    secrets: bool,
+    jobs: usize,
+) -> Result<()> {
     conf.verify()?; // this should work even with a limited config!
+    let mut errs = vec![];
+    for (svc, r) in verify_manifests(services, conf, reg, secrets, jobs).await {
+        match r {
+            Ok(_) => debug!("validated {} for {}", svc, reg.name),
+            Err(e) => errs.push((svc, e)),
+        }
+    }
+    if !errs.is_empty() {
+        for (svc, e) in &errs {
+            error!("{}: {}", svc, e.display_chain());
+            debug!("{:?}", e.display_chain());
+        }
+        bail!("Invalid shipcat data for {} services", errs.len());
+    }
+    Ok(())
+}
+
+/// Manifest fields that cannot be changed on a live service
+///
+/// Paired with the only workload they are immutable on, if any.
+/// Persistent volumes become StatefulSet `volumeClaimTemplates`.
+const IMMUTABLE_FIELDS: &[(&str, Option<&str>)] =
+    &[("workload", None), ("persistentVolumes", Some("statefulset"))];
+
+/// Immutable fields that differ between a local manifest and the live one
+pub fn immutable_changes(local: &Manifest, live: &Manifest) -> Result<Vec<&'static str>> {
+    let (before, after) = (serde_json::to_value(live)?, serde_json::to_value(local)?);
+    let workload = live.workload.to_string();
+    Ok(IMMUTABLE_FIELDS
+        .iter()
+        .filter(|(_, w)| w.map_or(true, |w| w == workload))
+        .filter(|(f, _)| before.get(f) != after.get(f))
+        .map(|(f, _)| *f)
+        .collect())
+}
+
+/// Validate that services do not change immutable fields of their live shipcatmanifest
+///
+/// Such changes fail during apply, so they need a delete and recreate instead.
+/// Services that are not yet installed have nothing to compare against.
+pub async fn changed_fields(services: Vec<String>, conf: &Config, reg: &Region) -> Result<()> {
+    let kube = KubeClient::new(reg).await?;
+    let mut changed = 0;
     for svc in services {
-        debug!("validating {} for {}", svc, reg.name);
-        let mf = if secrets {
-            shipcat_filebacked::load_manifest(&svc, conf, reg)
-                .await?
-                .complete(reg)
-                .await?
-        } else {
-            shipcat_filebacked::load_manifest(&svc, conf, reg)
-                .await?
-                .stub(reg)
-                .await?
+        let mf = shipcat_filebacked::load_manifest(&svc, conf, reg).await?;
+        let live = match ShipKube::new(&mf, &kube).find().await? {
+            Some(crd) => crd.spec,
+            None => continue,
         };
-        mf.verify(conf, reg)?;
-        debug!("validated {} for {}", svc, reg.name);
+        for f in immutable_changes(&mf, &live)? {
+            error!(
+                "{}: {} cannot be changed on a live {} - delete and recreate {} instead",
+                svc,
+                f,
+                live.workload.to_string(),
+                svc
+            );
+            changed += 1;
+        }
+    }
+    if changed > 0 {
+        bail!("{} immutable fields changed in {}", changed, reg.name);
+    }
+    Ok(())
+}
+
+/// Entry point for `shipcat secret check`
+///
+/// Reports the vault secrets of a service that are missing in the region's vault,
+/// as warnings unless `strict` is set.
+pub async fn secret_check(svc: &str, conf: &Config, reg: &Region, strict: bool) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let missing = mf.missing_secrets(&reg.vault).await?;
+    if missing.is_empty() {
+        info!("All secrets for {} exist in {}", svc, reg.name);
+        return Ok(());
+    }
+    for k in &missing {
+        warn!("{} is missing secret {} in {}", svc, k, reg.name);
+    }
+    if strict {
+        bail!("{} is missing {} secrets in {}", svc, missing.len(), reg.name);
     }
     Ok(())
 }
@@ -217,6 +467,121 @@ pub fn config(conf: Config) -> Result<()> {
     Ok(())
 }
 
+/// A holistic config checker reporting every cross reference problem at once
+pub fn config_check(conf: Config) -> Result<()> {
+    let problems = conf.check();
+    for p in &problems {
+        error!("{}", p);
+    }
+    if !problems.is_empty() {
+        bail!("shipcat.conf has {} problem(s)", problems.len());
+    }
+    info!("shipcat.conf has no cross reference problems");
+    Ok(())
+}
+
+/// Image size used when a manifest does not set one
+const DEFAULT_IMAGE_SIZE: u32 = 512;
+
+/// Apply safe fixes to the `manifest.yml` of the given services
+///
+/// Only fills in defaults and reorders plain env entries; everything else is left to validation.
+pub fn fix(services: &[String], conf: &Config) -> Result<()> {
+    for svc in services {
+        let pth = conf.template_root().join("services").join(svc).join("manifest.yml");
+        let raw = fs::read_to_string(&pth)?;
+        let (fixed, changes) = fix_manifest_source(&raw, &conf.allowedLabels)?;
+        if changes.is_empty() {
+            continue;
+        }
+        for c in &changes {
+            info!("{}: {}", svc, c);
+        }
+        fs::write(&pth, fixed)?;
+    }
+    Ok(())
+}
+
+/// Fix a raw `manifest.yml`, returning the new contents and a description of each change
+///
+/// Edits are made line by line so that comments and formatting are kept.
+pub fn fix_manifest_source(raw: &str, allowed_labels: &[String]) -> Result<(String, Vec<String>)> {
+    let doc: Value = serde_yaml::from_str(raw)?;
+    let mut lines: Vec<String> = raw.lines().map(String::from).collect();
+    let mut changes = vec![];
+
+    let preserve_order = doc["preserveEnvOrder"].as_bool().unwrap_or(false);
+    if let Some(i) = block_key(&lines, "env") {
+        if !preserve_order && sort_block(&mut lines, i) {
+            changes.push("sorted env".to_string());
+        }
+    }
+
+    let allowed = allowed_labels.iter().any(|l| l == "team");
+    let missing = doc["labels"].get("team").is_none();
+    if let Some(team) = doc["metadata"]["team"].as_str().filter(|_| allowed && missing) {
+        let label = format!("team: {}", team);
+        if let Some(i) = block_key(&lines, "labels") {
+            let indent = lines.get(i + 1).map(|l| indent_of(l)).filter(|n| *n > 0);
+            lines.insert(i + 1, format!("{}{}", " ".repeat(indent.unwrap_or(2)), label));
+            changes.push(format!("added label {}", label));
+        } else if doc.get("labels").is_none() {
+            lines.push("labels:".into());
+            lines.push(format!("  {}", label));
+            changes.push(format!("added label {}", label));
+        } else {
+            warn!("Not adding a team label to an inline labels mapping");
+        }
+    }
+
+    if doc.get("imageSize").is_none() {
+        lines.push(format!("imageSize: {}", DEFAULT_IMAGE_SIZE));
+        changes.push(format!("set imageSize: {}", DEFAULT_IMAGE_SIZE));
+    }
+
+    let mut fixed = lines.join("\n");
+    fixed.push('\n');
+    Ok((fixed, changes))
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Index of a top level `key:` line starting a block mapping
+fn block_key(lines: &[String], key: &str) -> Option<usize> {
+    let header = format!("{}:", key);
+    lines.iter().position(|l| l.trim_end() == header)
+}
+
+/// Sort the entries below a block key if they are all single line `KEY: value` pairs
+///
+/// Returns whether anything was reordered.
+fn sort_block(lines: &mut [String], header: usize) -> bool {
+    let start = header + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|l| indent_of(l) == 0)
+        .map_or(lines.len(), |n| start + n);
+    let block = &mut lines[start..end];
+    let indent = block.first().map(|l| indent_of(l));
+    let key = |l: &String| l.trim_start().split(':').next().unwrap_or_default().to_string();
+    let simple = block.iter().all(|l| {
+        let entry = l.trim_start();
+        Some(indent_of(l)) == indent
+            && !entry.starts_with('#')
+            && !entry.starts_with('-')
+            && entry.contains(": ")
+            && !entry.ends_with('|')
+            && !entry.ends_with('>')
+    });
+    if !simple || block.windows(2).all(|w| key(&w[0]) <= key(&w[1])) {
+        return false;
+    }
+    block.sort_by_key(key);
+    true
+}
+
 // Dumb git diff helper that matches normal service files:
 //
 // Effectively checks:
@@ -236,3 +601,119 @@ fn git_diff_changes() -> Result<Vec<String>> {
     }
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{failure_table, fix_manifest_source, immutable_changes, validate_regions, RegionFailure};
+    use crate::{structs::PersistentVolume, Manifest};
+    use shipcat_definitions::PrimaryWorkload;
+
+    #[test]
+    fn immutable_field_changes_flagged() {
+        let mut live = Manifest::test("fake-storage");
+        live.workload = PrimaryWorkload::Statefulset;
+        live.persistentVolumes =
+            serde_yaml::from_str::<Vec<PersistentVolume>>("- name: data\n  mountPath: /data\n  size: 10Gi")
+                .unwrap();
+        let changed = |local: &Manifest| immutable_changes(local, &live).unwrap();
+        let mut local = live.clone();
+        local.replicaCount = Some(3);
+        assert!(changed(&local).is_empty());
+
+        local.persistentVolumes[0].size = "20Gi".into();
+        assert_eq!(changed(&local), vec!["persistentVolumes"]);
+        local.workload = PrimaryWorkload::Deployment;
+        assert_eq!(changed(&local), vec!["workload", "persistentVolumes"]);
+    }
+
+    #[test]
+    fn fix_adds_missing_labels() {
+        let raw = "name: fake-ask\n\
+                   metadata:\n  team: observability\n\
+                   env:\n  ZED: 1\n  # keep me\n  ALPHA: 2\n\
+                   labels:\n  custom-metrics: true\n";
+        let allowed = vec!["team".to_string(), "custom-metrics".to_string()];
+        let (fixed, changes) = fix_manifest_source(raw, &allowed).unwrap();
+        assert_eq!(
+            changes,
+            vec!["added label team: observability", "set imageSize: 512"]
+        );
+        let doc: serde_yaml::Value = serde_yaml::from_str(&fixed).unwrap();
+        assert_eq!(doc["labels"]["team"].as_str(), Some("observability"));
+        assert_eq!(doc["labels"]["custom-metrics"].as_bool(), Some(true));
+        assert_eq!(doc["imageSize"].as_u64(), Some(512));
+        // env with comments is not auto-sorted
+        assert!(fixed.contains("  ZED: 1\n  # keep me\n  ALPHA: 2\n"));
+
+        // fixing again is a no-op
+        let (again, changes) = fix_manifest_source(&fixed, &allowed).unwrap();
+        assert!(changes.is_empty());
+        assert_eq!(again, fixed);
+    }
+
+    #[test]
+    fn fix_sorts_plain_env() {
+        let raw = "name: fake-ask\nimageSize: 256\nenv:\n  ZED: 1\n  ALPHA: 2\n";
+        let (fixed, changes) = fix_manifest_source(raw, &[]).unwrap();
+        assert_eq!(changes, vec!["sorted env"]);
+        assert_eq!(
+            fixed,
+            "name: fake-ask\nimageSize: 256\nenv:\n  ALPHA: 2\n  ZED: 1\n"
+        );
+
+        let preserved = format!("{}preserveEnvOrder: true\n", raw);
+        let (_, changes) = fix_manifest_source(&preserved, &[]).unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failure_attributed_to_region() {
+        let regions = vec!["dev-uk".to_string(), "dev-global".to_string()];
+        let failures = validate_regions(regions, |r| async move {
+            let fake_ask = if r == "dev-global" {
+                Err("missing resources".into())
+            } else {
+                Ok(())
+            };
+            Ok(vec![
+                ("fake-ask".to_string(), fake_ask),
+                ("fake-storage".to_string(), Ok(())),
+            ])
+        })
+        .await;
+        assert_eq!(
+            failures,
+            vec![RegionFailure {
+                region: "dev-global".into(),
+                service: Some("fake-ask".into()),
+                field: None,
+                error: "missing resources".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn failures_grouped_by_service() {
+        let failure = |region: &str, svc: &str, field: Option<&str>| RegionFailure {
+            region: region.into(),
+            service: Some(svc.into()),
+            field: field.map(String::from),
+            error: "bad".into(),
+        };
+        let table = failure_table(&[
+            failure("dev-uk", "fake-storage", None),
+            failure("dev-uk", "fake-ask", Some("overrides.resources.limits.memory")),
+            failure("dev-global", "fake-ask", Some("overrides.image")),
+        ]);
+        let lines = table
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(lines[1], vec!["fake-ask", "dev-global", "overrides.image", "bad"]);
+        assert_eq!(
+            lines[2],
+            vec!["dev-uk", "overrides.resources.limits.memory", "bad"]
+        );
+        assert_eq!(lines[3], vec!["fake-storage", "dev-uk", "-", "bad"]);
+    }
+}
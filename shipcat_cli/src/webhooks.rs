@@ -1,4 +1,4 @@
-use super::{Config, Region, Webhook};
+use super::{structs::NotificationEvent, Config, Region, Webhook};
 use crate::{apply::UpgradeInfo, audit, slack, Result};
 
 /// The different states an upgrade can be in
@@ -76,11 +76,16 @@ pub async fn apply_event(us: UpgradeState, info: &UpgradeInfo, reg: &Region, con
             ),
         ),
     };
+    let event = match us {
+        UpgradeState::Failed => NotificationEvent::Alert,
+        _ => NotificationEvent::Deploy,
+    };
     match us {
         UpgradeState::Completed | UpgradeState::Failed => {
             let _ = slack::send(
                 slack::Message {
                     text,
+                    event,
                     code: info.diff.clone(),
                     color: Some(String::from(color)),
                     version: Some(info.version.clone()),
@@ -120,6 +125,7 @@ pub async fn delete_event(us: &UpgradeState, info: &UpgradeInfo, reg: &Region, c
             let _ = slack::send(
                 slack::Message {
                     text,
+                    event: NotificationEvent::Deploy,
                     code: info.diff.clone(),
                     color: Some(String::from(color)),
                     version: Some(info.version.clone()),
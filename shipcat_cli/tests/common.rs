@@ -187,10 +187,10 @@ async fn templating_test() {
         redis.env.plain["CORE_URL"],
         "https://woot.com/somesvc".to_string()
     );
-    assert_eq!(redis.env.secrets, btree_set![
-        "FAKE_NUMBER".to_string(),
-        "FAKE_SECRET".to_string()
-    ]);
+    assert_eq!(
+        redis.env.secrets,
+        btree_set!["FAKE_NUMBER".to_string(), "FAKE_SECRET".to_string()]
+    );
 
     // verify worker templating
     let w = &mf.workers[0];
@@ -207,13 +207,55 @@ async fn templating_test() {
     assert_eq!(&sec["FAKE_SECRET"], "hello"); // NB: ACTUALLY IN_VAULT
     assert_eq!(&sec["FAKE_NUMBER"], "-2"); // NB: ACTUALLY IN_VAULT
 
-    let configs = mf.configs.clone().unwrap();
+    let configs = mf.configs[0].clone();
     let configini = configs.files[0].clone();
     let cfgtpl = configini.value.unwrap();
     print!("{:?}", cfgtpl);
     assert!(cfgtpl.contains("CORE=https://woot.com/somesvc"));
     assert!(cfgtpl.contains("CLIENT_ID"));
     assert!(cfgtpl.contains("CLIENT_ID=FAKEASKID"));
+    // shared partial from templates/partials
+    assert!(cfgtpl.contains("SERVICE=fake-ask"));
+}
+
+#[tokio::test]
+async fn multiple_configs_test() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let mf = shipcat_filebacked::load_manifest("fake-storage", &conf, &reg)
+        .await
+        .unwrap()
+        .stub(&reg)
+        .await
+        .unwrap();
+
+    let mounts = mf.configs.iter().map(|c| c.mount.as_str()).collect::<Vec<_>>();
+    assert_eq!(mounts, vec!["/newrelic/", "/logging/"]);
+    assert_eq!(mf.configs[1].name, Some("logging".into()));
+    for c in &mf.configs {
+        assert!(c.files[0].value.as_ref().unwrap().contains("fake-storage"));
+    }
+    let logging = mf.configs[1].files[0].value.clone().unwrap();
+    assert!(logging.contains("region: dev-uk"));
+}
+
+#[tokio::test]
+async fn dual_projected_secret_test() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let mf = shipcat_filebacked::load_manifest("fake-storage", &conf, &reg)
+        .await
+        .unwrap()
+        .stub(&reg)
+        .await
+        .unwrap();
+
+    assert_eq!(mf.env.secrets, btree_set!["TLS_CERT".to_string()]);
+    assert_eq!(mf.secretFiles.keys().collect::<Vec<_>>(), vec!["tls-cert"]);
+    // both projections are backed by the single (mocked) vault value
+    assert_eq!(&mf.secrets["TLS_CERT"], "aGVsbG8gd29ybGQ=");
+    assert_eq!(&mf.secretFiles["tls-cert"], "YUdWc2JHOGdkMjl5YkdRPQ==");
+    assert!(mf.secretFilesChecksum.is_some());
 }
 
 #[tokio::test]
@@ -1,13 +1,15 @@
 mod common;
 use crate::common::setup;
-use shipcat::graph::{generate, nodeidx_from_name};
+use shipcat::graph::{
+    build_full, find_cycle, generate, nodeidx_from_name, render, teardown_order, GraphFormat,
+};
 use shipcat_definitions::{Config, ConfigState};
 
 #[tokio::test]
 async fn graph_generate() {
     setup();
     let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
-    let graph = generate("fake-ask", &conf, &reg, true).await.unwrap();
+    let graph = generate("fake-ask", &conf, &reg, GraphFormat::Dot).await.unwrap();
     assert!(graph.edge_count() > 0);
     print!("got struct: \n{:?}\n", serde_yaml::to_string(&graph));
     let askidx = nodeidx_from_name("fake-ask", &graph).unwrap();
@@ -20,3 +22,46 @@ async fn graph_generate() {
     println!("edge: {:?}", edge);
     assert_eq!(edge.intent, Some("testing graph module".into()));
 }
+
+#[tokio::test]
+async fn graph_teardown_order() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let graph = build_full(&conf, &reg).await.unwrap();
+    let order = teardown_order(&graph).unwrap();
+    let askpos = order.iter().position(|s| s == "fake-ask").unwrap();
+    let strgpos = order.iter().position(|s| s == "fake-storage").unwrap();
+    // fake-ask depends on fake-storage so must be removed first
+    assert!(askpos < strgpos);
+}
+
+#[tokio::test]
+async fn graph_formats() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let graph = build_full(&conf, &reg).await.unwrap();
+    assert_eq!(find_cycle(&graph), None);
+
+    let askidx = nodeidx_from_name("fake-ask", &graph).unwrap();
+    assert_eq!(graph[askidx].team, "observability");
+    let dot = render(&graph, GraphFormat::Dot).unwrap();
+    assert!(dot.starts_with("digraph {"));
+    assert!(dot.contains("label = \"fake-ask\" tooltip = \"observability\" style = filled"));
+
+    let json: serde_json::Value = serde_json::from_str(&render(&graph, GraphFormat::Json).unwrap()).unwrap();
+    let nodes = json["nodes"].as_array().unwrap();
+    assert!(nodes.iter().any(|n| n["name"] == "fake-storage"));
+}
+
+#[tokio::test]
+async fn graph_cycle_detected() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let mut graph = build_full(&conf, &reg).await.unwrap();
+    let askidx = nodeidx_from_name("fake-ask", &graph).unwrap();
+    let strgidx = nodeidx_from_name("fake-storage", &graph).unwrap();
+    let edge = graph[graph.find_edge(askidx, strgidx).unwrap()].clone();
+    graph.update_edge(strgidx, askidx, edge);
+    assert!(find_cycle(&graph).is_some());
+    assert!(teardown_order(&graph).is_err());
+}
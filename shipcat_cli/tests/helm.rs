@@ -1,7 +1,7 @@
 mod common;
 use crate::common::setup;
 use shipcat::{helm, Result};
-use shipcat_definitions::{Config, ConfigState};
+use shipcat_definitions::{structs::Canary, Config, ConfigState};
 
 #[tokio::test]
 #[ignore] // This test requires helm cli - not on circle
@@ -13,9 +13,61 @@ async fn helm_template() -> Result<()> {
         .stub(&reg)
         .await?;
 
-    let res = helm::template(&mf, None).await?;
+    let res = helm::template(&mf, None, true).await?;
 
     // verify we have deferred to helm for templating
     assert!(res.contains("image: \"quay.io/babylonhealth/fake-ask:1.6.0\""));
     Ok(())
 }
+
+#[tokio::test]
+#[ignore] // This test requires helm cli - not on circle
+async fn helm_template_canary() -> Result<()> {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await?;
+    let mut mf = shipcat_filebacked::load_manifest("fake-storage", &conf, &reg)
+        .await?
+        .stub(&reg)
+        .await?;
+    mf.canary = Some(Canary {
+        weight: 10,
+        version: "1.7.0".into(),
+        ..Default::default()
+    });
+    mf.canary = mf.canary_with_replicas();
+    mf.verify(&conf, &reg)?;
+
+    let res = helm::template(&mf, None, true).await?;
+    assert!(res.contains("name: fake-storage-canary"));
+    assert!(res.contains("image: \"quay.io/babylonhealth/fake-ask:1.7.0\""));
+    assert!(res.contains("weight: 90"));
+    assert!(res.contains("weight: 10"));
+    assert!(res.contains("replicas: 1"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn helm_values_unchanged_are_not_rewritten() -> Result<()> {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await?;
+    let mf = shipcat_filebacked::load_manifest("fake-ask", &conf, &reg).await?;
+    let output = "fake-ask.values-test.gen.yml";
+    let _ = std::fs::remove_file(output);
+    assert!(helm::values(&mf, output).await?);
+    assert!(!helm::values(&mf, output).await?);
+    let mut changed = mf.clone();
+    changed.version = Some("9.9.9".into());
+    assert!(helm::values(&changed, output).await?);
+    std::fs::remove_file(output)?;
+    Ok(())
+}
+
+#[test]
+fn helm_template_invalid_yaml() {
+    let valid = "---\nkind: Service\nmetadata:\n  name: svc\n---\nkind: Deployment\n";
+    assert!(helm::validate_yaml("svc", valid).is_ok());
+    // a multiline value injected without indentation
+    let invalid = "---\nkind: Service\n---\nkind: ConfigMap\ndata:\n  key: line1\nline2: [\n";
+    let err = helm::validate_yaml("svc", invalid).unwrap_err().to_string();
+    assert!(err.starts_with("Rendered template for svc is not valid yaml"));
+}
@@ -53,7 +53,7 @@ async fn kong_test() {
     assert_eq!(consumer.username, "anonymous");
     assert!(consumer.credentials.is_empty());
 
-    assert_eq!(output.apis.len(), 2);
+    assert_eq!(output.apis.len(), 3);
 
     // fake-ask API
     let mut api = output.apis.remove(0);
@@ -116,13 +116,22 @@ async fn kong_test() {
     );
     assert_eq!(attr.enabled, true);
 
-    assert_plugin_removed!("RateLimiting", api.plugins.remove(0), ApiPlugin::RateLimiting);
+    // gate rate limits
+    let attr = plugin_attributes!("RateLimiting", api.plugins.remove(0), ApiPlugin::RateLimiting);
+    assert_eq!(attr.enabled, true);
+    assert_eq!(attr.config.minute, Some(100));
+    assert_eq!(attr.config.hour, Some(1000));
+    assert_eq!(attr.config.day, None);
+    assert_eq!(attr.config.policy, "local");
     assert_plugin_removed!("UserRateLimit", api.plugins.remove(0), ApiPlugin::UserRateLimit);
 
     assert_upstream_header_transform(api.plugins.remove(0), "fake-ask");
 
     assert!(api.plugins.is_empty());
 
+    // fake-ask-internal host route, see kong_host_routes
+    output.apis.remove(0);
+
     // fake-storage API
     let mut api = output.apis.remove(0);
     assert_eq!(api.name, "fake-storage");
@@ -172,6 +181,7 @@ async fn kong_test() {
         api.plugins.remove(0),
         ApiPlugin::JsonCookiesCsrf
     );
+    // no gate limits
     assert_plugin_removed!("RateLimiting", api.plugins.remove(0), ApiPlugin::RateLimiting);
     assert_plugin_removed!("UserRateLimit", api.plugins.remove(0), ApiPlugin::UserRateLimit);
     assert_upstream_header_transform(api.plugins.remove(0), "fake-storage");
@@ -179,6 +189,26 @@ async fn kong_test() {
     assert!(api.plugins.is_empty());
 }
 
+#[tokio::test]
+async fn kong_host_routes() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let kongrs = generate_kong_output(&conf, &reg).await.unwrap();
+    let output = KongfigOutput::new(kongrs, &reg);
+
+    let main = output.apis.iter().find(|a| a.name == "fake-ask").unwrap();
+    let internal = output.apis.iter().find(|a| a.name == "fake-ask-internal").unwrap();
+    assert_eq!(main.attributes.strip_uri, false);
+    assert_eq!(internal.attributes.strip_uri, true);
+    assert_eq!(internal.attributes.hosts, vec![
+        "fake-ask-internal.dev.something.domain.com".to_string()
+    ]);
+    // everything else is shared with the main api
+    assert_eq!(internal.attributes.uris, main.attributes.uris);
+    assert_eq!(internal.attributes.upstream_url, main.attributes.upstream_url);
+    assert_eq!(internal.plugins.len(), main.plugins.len());
+}
+
 #[cfg(test)]
 fn assert_upstream_header_transform(plugin: ApiPlugin, service: &str) {
     let attr = plugin_attributes!("RequestTransformer", plugin, ApiPlugin::RequestTransformer);
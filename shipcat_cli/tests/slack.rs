@@ -1,7 +1,10 @@
 mod common;
 use crate::common::setup;
 use shipcat::slack::{env_channel, send, send_dumb, DumbMessage, Message};
-use shipcat_definitions::{structs::NotificationMode, Config, ConfigState};
+use shipcat_definitions::{
+    structs::{NotificationEvent, NotificationMode},
+    Config, ConfigState,
+};
 
 // integration temporarily disabled
 #[tokio::test]
@@ -28,6 +31,7 @@ async fn slack_test() {
                 color: Some("good".into()),
                 version: mf.version.clone(),
                 mode: NotificationMode::default(),
+                event: NotificationEvent::Deploy,
                 metadata: mf.base.metadata.clone(),
                 code: Some(format!(
                     "Pod changed:
@@ -46,6 +50,7 @@ async fn slack_test() {
                 text: format!("Non-trivial deploy test of `{}`", "slack"),
                 color: Some("good".into()),
                 mode: NotificationMode::default(),
+                event: NotificationEvent::Deploy,
                 metadata: mf.base.metadata,
                 version: mf.version.clone(),
                 code: Some(format!(
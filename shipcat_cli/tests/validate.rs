@@ -1,15 +1,66 @@
 mod common;
 use crate::common::setup;
 
-use shipcat::validate::manifest as validate;
-use shipcat_definitions::{Config, ConfigState};
+use shipcat::{
+    apply::verify_version,
+    validate::{fix, manifest as validate},
+};
+use shipcat_definitions::{Config, ConfigState, MutableTagPolicy};
 
 #[tokio::test]
 async fn validate_test() {
     setup();
     let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
-    let res = validate(vec!["fake-ask".into()], &conf, &reg, true).await;
+    let res = validate(vec!["fake-ask".into()], &conf, &reg, true, 1).await;
     assert!(res.is_ok());
-    let res2 = validate(vec!["fake-storage".into(), "fake-ask".into()], &conf, &reg, false).await;
+    let svcs = vec!["fake-storage".into(), "fake-ask".into()];
+    let res2 = validate(svcs, &conf, &reg, false, 2).await;
     assert!(res2.is_ok())
 }
+
+#[tokio::test]
+async fn validate_reports_all_failures() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let svcs = vec!["missing-one".into(), "fake-ask".into(), "missing-two".into()];
+    let err = validate(svcs, &conf, &reg, false, 2).await.unwrap_err();
+    assert_eq!(err.to_string(), "Invalid shipcat data for 2 services");
+}
+
+#[tokio::test]
+async fn passed_versions_follow_tag_policy() {
+    setup();
+    let (conf, mut reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    assert!(verify_version(&reg, "1.0.0-stable").is_ok());
+    reg.forbidMutableTags = Some(MutableTagPolicy {
+        denylist: vec!["1.0.0-stable".into()],
+        requireSemverOrSha: false,
+    });
+    let err = verify_version(&reg, "1.0.0-stable").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Tag 1.0.0-stable is mutable and denied by forbidMutableTags in region dev-uk"
+    );
+    assert!(verify_version(&reg, "1.0.1").is_ok());
+    assert!(verify_version(&reg, "latest").is_err());
+
+    // versions passed on the cli are built like pinned ones
+    let passed =
+        shipcat_filebacked::load_manifest_with_version("fake-ask", &conf, &reg, Some("1.0.0-stable"));
+    assert!(passed.await.is_err());
+}
+
+#[tokio::test]
+async fn fix_uses_the_configured_root() {
+    use std::{env, fs};
+    setup();
+    let mut conf = Config::read().await.unwrap();
+    let root = env::temp_dir().join("shipcat-fix-root");
+    let svcdir = root.join("services").join("fake-fix");
+    fs::create_dir_all(&svcdir).unwrap();
+    fs::write(svcdir.join("manifest.yml"), "name: fake-fix\n").unwrap();
+    conf.templateRoot = Some(root);
+    fix(&["fake-fix".into()], &conf).unwrap();
+    let fixed = fs::read_to_string(svcdir.join("manifest.yml")).unwrap();
+    assert_eq!(fixed, "name: fake-fix\nimageSize: 512\n");
+}
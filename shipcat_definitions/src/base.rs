@@ -9,6 +9,10 @@ pub struct BaseManifest {
     pub name: String,
     pub metadata: Metadata,
     pub regions: Vec<String>,
+    /// Service is external (a reference only, never deployed)
+    pub external: bool,
+    /// Service is disabled in all regions
+    pub disabled: bool,
 }
 
 impl fmt::Debug for BaseManifest {
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use kube_derive::CustomResource;
+#[cfg(feature = "filesystem")] use merge::Merge;
 use semver::Version;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -24,6 +25,9 @@ pub struct Cluster {
     /// Teleport url to use with tsh login
     #[serde(default)]
     pub teleport: Option<String>,
+    /// Teleport session ttl in minutes (tsh default if unset)
+    #[serde(default)]
+    pub teleport_ttl: Option<u32>,
     /// Clusternmae for overriding kube config context
     #[serde(default)]
     pub clustername: Option<String>,
@@ -58,6 +62,22 @@ pub struct SlackParameters {
     pub team: String,
 }
 
+/// How strictly a manifest lint is enforced
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// Log the problem, but let the manifest through
+    Warn,
+    /// Fail validation
+    Error,
+}
+
+impl Default for LintSeverity {
+    fn default() -> Self {
+        LintSeverity::Warn
+    }
+}
+
 // ----------------------------------------------------------------------------------
 
 /// Main manifest, serializable from shipcat.conf
@@ -76,6 +96,13 @@ pub struct Config {
     #[cfg(feature = "filesystem")]
     pub defaults: serde_yaml::Value,
 
+    /// Directory containing the `services/` and `templates/` that templates are read from
+    ///
+    /// Defaults to the current directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg(feature = "filesystem")]
+    pub templateRoot: Option<PathBuf>,
+
     /// Cluster definitions
     pub clusters: BTreeMap<String, Cluster>,
 
@@ -106,6 +133,17 @@ pub struct Config {
     #[serde(default)]
     pub allowedCustomMetadata: BTreeSet<String>,
 
+    /// Shared ConfigMaps services may reference in `envFrom`
+    #[serde(default)]
+    pub allowedEnvFrom: Vec<String>,
+
+    /// Severity of missing resource requests on secondary containers
+    ///
+    /// Workers, sidecars, init containers and cron jobs without `resources`
+    /// are only warned about by default. Set to `error` once migrated.
+    #[serde(default)]
+    pub resourceRequestsLint: LintSeverity,
+
     /// Shipcat version pins
     pub versions: BTreeMap<Environment, Version>,
 
@@ -161,6 +199,11 @@ impl Config {
                 bail!("Region {} served by missing cluster '{}'", r.name, r.cluster);
             }
             r.vault.verify(&r.name)?;
+            if let Some(f) = r.replica_scale_factor {
+                if f.is_nan() || f <= 0.0 {
+                    bail!("replica_scale_factor in {} must be positive, got {}", r.name, f);
+                }
+            }
             for v in r.base_urls.values() {
                 if v.ends_with('/') {
                     bail!("A base_url must not end with a slash");
@@ -229,6 +272,20 @@ impl Config {
         Ok(())
     }
 
+    /// Every cross reference problem in the config, rather than just the first
+    ///
+    /// Covers clusters, regions, context aliases and teams.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = check_clusters(&self.clusters, &self.regions, self.has_all_regions());
+        for (k, v) in &self.contextAliases {
+            if !self.has_region(v) {
+                problems.push(format!("context alias {} points to undefined region {}", k, v));
+            }
+        }
+        problems.extend(self.owners.check());
+        problems
+    }
+
     /// Print Config to stdout
     pub fn print(&self) -> Result<()> {
         println!("{}", serde_yaml::to_string(self)?);
@@ -240,6 +297,14 @@ impl Config {
         self.regions.iter().map(|r| r.name.clone()).collect()
     }
 
+    /// Root directory that `services/` and `templates/` are read relative to
+    ///
+    /// Defaults to the current directory, but can be set via `templateRoot`.
+    #[cfg(feature = "filesystem")]
+    pub fn template_root(&self) -> &Path {
+        self.templateRoot.as_deref().unwrap_or_else(|| Path::new("."))
+    }
+
     /// Fill secrets from vault on a Base config for a known to exist region
     ///
     /// This will use the HTTP api of Vault using the configuration parameters.
@@ -296,6 +361,17 @@ impl Config {
         self.state == ConfigState::Filtered
     }
 
+    /// Only configs read from disk carry every region
+    #[cfg(feature = "filesystem")]
+    pub fn has_all_regions(&self) -> bool {
+        self.state == ConfigState::File
+    }
+
+    #[cfg(not(feature = "filesystem"))]
+    pub fn has_all_regions(&self) -> bool {
+        false
+    }
+
     /// Retrieve region name using either a region name, or a context as a fallback
     ///
     /// This returns a a valid key in `self.regions` if Some.
@@ -310,6 +386,17 @@ impl Config {
         }
     }
 
+    /// Infer the region from a kube context
+    ///
+    /// Exact region names and context aliases win, otherwise the context must be
+    /// the serving `cluster` of exactly one region.
+    pub fn region_from_context(&self, ctx: &str) -> Result<String> {
+        if let Some(r) = self.resolve_context(ctx.to_string()) {
+            return Ok(r);
+        }
+        region_for_cluster(ctx, &self.regions)
+    }
+
     fn has_region(&self, region: &str) -> bool {
         self.regions.iter().any(|r| r.name == region)
     }
@@ -348,6 +435,127 @@ impl Config {
     }
 }
 
+/// A partial config file, layered onto earlier files to produce a `Config`
+#[cfg(feature = "filesystem")]
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigLayer {
+    defaults: Option<serde_yaml::Value>,
+    templateRoot: Option<PathBuf>,
+    clusters: BTreeMap<String, Cluster>,
+    contextAliases: BTreeMap<String, String>,
+    /// Regions replace earlier regions of the same name
+    regions: Vec<Region>,
+    locations: BTreeMap<String, Location>,
+    slack: Option<SlackParameters>,
+    github: Option<GithubParameters>,
+    allowedLabels: Option<Vec<String>>,
+    allowedCustomMetadata: Option<BTreeSet<String>>,
+    allowedEnvFrom: Option<Vec<String>>,
+    resourceRequestsLint: Option<LintSeverity>,
+    versions: BTreeMap<Environment, Version>,
+    owners: teams::Owners,
+    /// Teams from earlier layers that this layer deliberately redefines
+    overrideTeams: Vec<String>,
+}
+
+#[cfg(feature = "filesystem")]
+impl Merge for ConfigLayer {
+    fn merge(self, other: Self) -> Self {
+        let mut regions = self.regions;
+        for r in other.regions {
+            match regions.iter_mut().find(|x| x.name == r.name) {
+                Some(x) => *x = r,
+                None => regions.push(r),
+            }
+        }
+        ConfigLayer {
+            defaults: self.defaults.merge(other.defaults),
+            templateRoot: self.templateRoot.merge(other.templateRoot),
+            clusters: Merge::merge(self.clusters, other.clusters),
+            contextAliases: Merge::merge(self.contextAliases, other.contextAliases),
+            regions,
+            locations: Merge::merge(self.locations, other.locations),
+            slack: self.slack.merge(other.slack),
+            github: self.github.merge(other.github),
+            allowedLabels: self.allowedLabels.merge(other.allowedLabels),
+            allowedCustomMetadata: self.allowedCustomMetadata.merge(other.allowedCustomMetadata),
+            allowedEnvFrom: self.allowedEnvFrom.merge(other.allowedEnvFrom),
+            resourceRequestsLint: self.resourceRequestsLint.merge(other.resourceRequestsLint),
+            versions: Merge::merge(self.versions, other.versions),
+            owners: self.owners.merge(other.owners),
+            overrideTeams: other.overrideTeams,
+        }
+    }
+}
+
+#[cfg(feature = "filesystem")]
+impl ConfigLayer {
+    /// The teams.yml owners as the layer every config file is layered onto
+    fn teams(owners: teams::Owners) -> (String, ConfigLayer) {
+        let layer = ConfigLayer {
+            owners,
+            ..Default::default()
+        };
+        ("teams.yml".into(), layer)
+    }
+
+    /// Merge named layers in order, refusing silent team redefinitions
+    ///
+    /// Regions may be redefined by later layers, but not moved to another namespace or environment.
+    fn combine(layers: Vec<(String, ConfigLayer)>) -> Result<ConfigLayer> {
+        use merge::merge_protected;
+        let mut merged = ConfigLayer::default();
+        for (name, layer) in layers {
+            for r in &layer.regions {
+                if let Some(x) = merged.regions.iter().find(|x| x.name == r.name) {
+                    let protect = |field, a: String, b: String| {
+                        merge_protected(field, Some(a), Some(b))
+                            .map_err(|e| format!("Region {} in {}: {}", r.name, name, e))
+                    };
+                    protect("namespace", x.namespace.clone(), r.namespace.clone())?;
+                    protect(
+                        "environment",
+                        x.environment.to_string(),
+                        r.environment.to_string(),
+                    )?;
+                }
+            }
+            for team in layer.owners.squads.keys() {
+                if merged.owners.squads.contains_key(team) && !layer.overrideTeams.contains(team) {
+                    bail!(
+                        "Team {} in {} is already defined in an earlier config (add it to overrideTeams)",
+                        team,
+                        name
+                    );
+                }
+            }
+            merged = merged.merge(layer);
+        }
+        Ok(merged)
+    }
+
+    fn build(self) -> Result<Config> {
+        Ok(Config {
+            defaults: self.defaults.unwrap_or_default(),
+            templateRoot: self.templateRoot,
+            clusters: self.clusters,
+            contextAliases: self.contextAliases,
+            regions: self.regions,
+            locations: self.locations,
+            slack: self.slack.ok_or("No config layer defines slack")?,
+            github: self.github.ok_or("No config layer defines github")?,
+            allowedLabels: self.allowedLabels.unwrap_or_default(),
+            allowedCustomMetadata: self.allowedCustomMetadata.unwrap_or_default(),
+            allowedEnvFrom: self.allowedEnvFrom.unwrap_or_default(),
+            resourceRequestsLint: self.resourceRequestsLint.unwrap_or_default(),
+            versions: self.versions,
+            owners: self.owners,
+            state: Default::default(),
+        })
+    }
+}
+
 /// Simplified config with version information only
 ///
 /// The part of shipcat.conf you never get to break the format of.
@@ -405,14 +613,12 @@ impl Config {
     /// Pass this a region request via argument or a current context
     pub async fn new(state: ConfigState, context: &str) -> Result<(Config, Region)> {
         let mut conf = Self::read().await?;
-        let region = if let Some(r) = conf.resolve_context(context.to_string()) {
-            r
-        } else {
-            error!("Please use an existing kube context or add your current context to shipcat.conf");
-            bail!(
-                "The current kube context ('{}') is not defined in shipcat.conf",
-                context
-            );
+        let region = match conf.region_from_context(context) {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Please use an existing kube context or add your current context to shipcat.conf");
+                return Err(e);
+            }
         };
 
         if state == ConfigState::Filtered || state == ConfigState::Base {
@@ -428,29 +634,45 @@ impl Config {
         Ok((conf, reg))
     }
 
-    /// Read a config file in an arbitrary path
-    async fn read_from(pwd: &PathBuf) -> Result<Config> {
+    /// Config files in an arbitrary path
+    ///
+    /// Overlays listed in `SHIPCAT_CONFIG_LAYERS` (a path list relative to `pwd`) are layered on top.
+    fn layer_paths(pwd: &PathBuf) -> Vec<PathBuf> {
+        let mut paths = vec![pwd.join("shipcat.conf")];
+        if let Some(layers) = std::env::var_os("SHIPCAT_CONFIG_LAYERS") {
+            paths.extend(std::env::split_paths(&layers).map(|p| pwd.join(p)));
+        }
+        paths
+    }
+
+    async fn read_layer_files(paths: &[PathBuf]) -> Result<Vec<(String, ConfigLayer)>> {
         use tokio::fs;
-        let mpath = pwd.join("shipcat.conf");
-        trace!("Using config in {}", mpath.display());
-        if !mpath.exists() {
-            bail!("Config file {} does not exist", mpath.display())
+        let mut layers = vec![];
+        for p in paths {
+            trace!("Using config layer in {}", p.display());
+            if !p.exists() {
+                bail!("Config file {} does not exist", p.display())
+            }
+            let data = fs::read_to_string(p).await?;
+            layers.push((p.display().to_string(), serde_yaml::from_str(&data)?));
         }
-        let data = fs::read_to_string(&mpath).await?;
-        let res = serde_yaml::from_str(&data)?;
-        Ok(res)
+        Ok(layers)
     }
 
-    /// Read a config in pwd and leave placeholders
-    pub async fn read() -> Result<Config> {
-        let pwd = Path::new(".");
-        let mut conf = Config::read_from(&pwd.to_path_buf()).await?;
-        conf.owners = teams::Owners::read()?;
-        Ok(conf)
+    /// Read layered config files, where later files override earlier ones
+    pub async fn read_layers(paths: &[PathBuf]) -> Result<Config> {
+        ConfigLayer::combine(Config::read_layer_files(paths).await?)?.build()
     }
 
-    pub fn has_all_regions(&self) -> bool {
-        self.state == ConfigState::File
+    /// Read a config in pwd and leave placeholders
+    ///
+    /// Teams defined in config layers extend those in teams.yml,
+    /// but redefining one of its squads needs an `overrideTeams` entry.
+    pub async fn read() -> Result<Config> {
+        let pwd = Path::new(".").to_path_buf();
+        let mut layers = vec![ConfigLayer::teams(teams::Owners::read()?)];
+        layers.extend(Config::read_layer_files(&Config::layer_paths(&pwd)).await?);
+        ConfigLayer::combine(layers)?.build()
     }
 
     /// Region retriever for global reducers
@@ -490,9 +712,148 @@ impl Config {
     }
 }
 
+/// Cross reference problems between clusters and the regions they serve
+///
+/// Undefined regions in cluster lists are only reported with `all_regions`,
+/// as a config filtered to one region keeps the full cluster lists.
+fn check_clusters(
+    clusters: &BTreeMap<String, Cluster>,
+    regions: &[Region],
+    all_regions: bool,
+) -> Vec<String> {
+    let mut problems = vec![];
+    for (key, c) in clusters {
+        if key != &c.name {
+            problems.push(format!("cluster '{}' has a different .name '{}'", key, c.name));
+        }
+        for r in &c.regions {
+            if all_regions && !regions.iter().any(|reg| &reg.name == r) {
+                problems.push(format!("cluster {} lists undefined region {}", key, r));
+            }
+        }
+    }
+    let mut namespaces: BTreeMap<(&str, &str), &str> = BTreeMap::new();
+    for r in regions {
+        if r.namespace.is_empty() {
+            problems.push(format!("region {} has no namespace", r.name));
+        }
+        match clusters.get(&r.cluster) {
+            _ if r.cluster.is_empty() => problems.push(format!("region {} has no serving cluster", r.name)),
+            None => problems.push(format!(
+                "region {} is served by missing cluster '{}'",
+                r.name, r.cluster
+            )),
+            Some(c) if !c.regions.contains(&r.name) => problems.push(format!(
+                "cluster {} does not list region {} it serves",
+                c.name, r.name
+            )),
+            Some(_) => {}
+        }
+        if let Some(other) = namespaces.insert((r.cluster.as_str(), r.namespace.as_str()), r.name.as_str()) {
+            problems.push(format!(
+                "regions {} and {} share namespace {} in cluster {}",
+                other, r.name, r.namespace, r.cluster
+            ));
+        }
+    }
+    problems
+}
+
+/// Name of the only region served by a cluster
+fn region_for_cluster(cluster: &str, regions: &[Region]) -> Result<String> {
+    let matches = regions
+        .iter()
+        .filter(|r| r.cluster == cluster)
+        .map(|r| r.name.as_str())
+        .collect::<Vec<_>>();
+    match matches.as_slice() {
+        [r] => Ok(r.to_string()),
+        [] => bail!(
+            "The kube context '{}' matches no region name, alias or cluster in shipcat.conf",
+            cluster
+        ),
+        _ => bail!(
+            "The kube context '{}' matches multiple regions ({}) - please pass --region",
+            cluster,
+            matches.join(", ")
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::region::VersionScheme;
+    use super::{check_clusters, region_for_cluster, Cluster};
+    use crate::region::{Region, VersionScheme};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn cluster_cross_references() {
+        let mkreg = |name: &str, cluster: &str, ns: &str| Region {
+            name: name.into(),
+            cluster: cluster.into(),
+            namespace: ns.into(),
+            ..Default::default()
+        };
+        let mkclust = |name: &str, regions: &[&str]| Cluster {
+            name: name.into(),
+            api: "https://api.example.com".into(),
+            teleport: None,
+            teleport_ttl: None,
+            clustername: None,
+            regions: regions.iter().map(|r| r.to_string()).collect(),
+        };
+        let mut clusters = BTreeMap::new();
+        clusters.insert(
+            "kops-uk".to_string(),
+            mkclust("kops-uk", &["dev-uk", "staging-uk"]),
+        );
+        clusters.insert("kops-us".to_string(), mkclust("kops-eu", &["dev-us", "gone-us"]));
+        let regions = vec![
+            mkreg("dev-uk", "kops-uk", "dev"),
+            mkreg("staging-uk", "kops-uk", "dev"),
+            mkreg("dev-us", "kops-us", "dev"),
+            mkreg("dev-ca", "kops-ca", ""),
+        ];
+        let problems = check_clusters(&clusters, &regions, true);
+        assert_eq!(
+            problems,
+            vec![
+                "cluster 'kops-us' has a different .name 'kops-eu'",
+                "cluster kops-us lists undefined region gone-us",
+                "regions dev-uk and staging-uk share namespace dev in cluster kops-uk",
+                "region dev-ca has no namespace",
+                "region dev-ca is served by missing cluster 'kops-ca'",
+            ]
+        );
+        // a region filtered config does not flag the regions it dropped
+        assert_eq!(
+            check_clusters(&clusters, &regions[..1], false),
+            vec!["cluster 'kops-us' has a different .name 'kops-eu'"]
+        );
+    }
+
+    #[test]
+    fn region_from_cluster_context() {
+        let mkreg = |name: &str, cluster: &str| Region {
+            name: name.into(),
+            cluster: cluster.into(),
+            ..Default::default()
+        };
+        let regions = vec![
+            mkreg("dev-uk", "kops-uk"),
+            mkreg("staging-uk", "kops-shared"),
+            mkreg("preprod-uk", "kops-shared"),
+        ];
+        assert_eq!(region_for_cluster("kops-uk", &regions).unwrap(), "dev-uk");
+        let err = region_for_cluster("kops-shared", &regions).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The kube context 'kops-shared' matches multiple regions (staging-uk, preprod-uk) - please pass \
+             --region"
+        );
+        assert!(region_for_cluster("minikube", &regions).is_err());
+    }
+
     #[test]
     fn version_validate_test() {
         let scheme = VersionScheme::GitShaOrSemver;
@@ -509,4 +870,95 @@ mod tests {
             .verify("e7c1e5dd5de74b2b5da5eef76eb5bf12bdc2ac19")
             .is_err());
     }
+
+    #[cfg(feature = "filesystem")]
+    #[test]
+    fn layered_config_merges_teams_and_regions() {
+        use super::ConfigLayer;
+        let overlay = r#"
+contextAliases:
+  dev-ie-blue: dev-ie
+owners:
+  squads:
+    payments:
+      name: payments
+      members: []
+      github:
+        team: payments
+      slack:
+        support: C12345678
+"#;
+        let base = std::fs::read_to_string("../tests/shipcat.conf").unwrap();
+        let base: ConfigLayer = serde_yaml::from_str(&base).unwrap();
+        let mut ireland = base.regions[0].clone();
+        ireland.name = "dev-ie".into();
+        let mut layer: ConfigLayer = serde_yaml::from_str(overlay).unwrap();
+        layer.regions.push(ireland);
+
+        let layers = vec![("shipcat.conf".into(), base), ("ie.conf".into(), layer)];
+        let conf = ConfigLayer::combine(layers).unwrap().build().unwrap();
+        assert!(conf.has_region("dev-uk"));
+        assert!(conf.has_region("dev-ie"));
+        assert_eq!(conf.contextAliases["dev-ie-blue"], "dev-ie");
+        assert_eq!(conf.slack.team, "T1111111");
+        assert!(conf.owners.squads.contains_key("payments"));
+
+        // redefining a team from an earlier layer must be explicit
+        let layer = || serde_yaml::from_str::<ConfigLayer>(overlay).unwrap();
+        assert!(ConfigLayer::combine(vec![("a".into(), layer()), ("b".into(), layer())]).is_err());
+        let mut explicit = layer();
+        explicit.overrideTeams = vec!["payments".into()];
+        assert!(ConfigLayer::combine(vec![("a".into(), layer()), ("b".into(), explicit)]).is_ok());
+    }
+
+    #[cfg(feature = "filesystem")]
+    #[test]
+    fn layered_config_protects_teams_yml_squads() {
+        use super::ConfigLayer;
+        use crate::teams::Owners;
+        let teams = Owners::read_from(&"../tests".into()).unwrap();
+        let squad = teams.squads["observability"].clone();
+        let redefined = |overrides: Vec<String>| {
+            let mut layer = ConfigLayer::default();
+            layer.owners.squads.insert(squad.name.clone(), squad.clone());
+            layer.overrideTeams = overrides;
+            ("ie.conf".to_string(), layer)
+        };
+
+        let layers = vec![ConfigLayer::teams(teams.clone()), redefined(vec![])];
+        let err = ConfigLayer::combine(layers).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Team observability in ie.conf is already defined in an earlier config (add it to overrideTeams)"
+        );
+        let layers = vec![ConfigLayer::teams(teams), redefined(vec!["observability".into()])];
+        assert!(ConfigLayer::combine(layers).is_ok());
+    }
+
+    #[cfg(feature = "filesystem")]
+    #[test]
+    fn layered_config_protects_region_namespace() {
+        use super::ConfigLayer;
+        let base = std::fs::read_to_string("../tests/shipcat.conf").unwrap();
+        let base = || serde_yaml::from_str::<ConfigLayer>(&base).unwrap();
+        let region = base().regions[0].clone();
+
+        // repeating a protected value is fine
+        let mut same = ConfigLayer::default();
+        same.regions.push(region.clone());
+        assert!(ConfigLayer::combine(vec![("a".into(), base()), ("b".into(), same)]).is_ok());
+
+        let mut moved = ConfigLayer::default();
+        let mut other = region.clone();
+        other.namespace = "elsewhere".into();
+        moved.regions.push(other);
+        let err = ConfigLayer::combine(vec![("a".into(), base()), ("b".into(), moved)]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Region {} in b: namespace cannot be changed from {} to elsewhere",
+                region.name, region.namespace
+            )
+        );
+    }
 }
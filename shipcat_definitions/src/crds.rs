@@ -38,3 +38,17 @@ impl From<Config> for ShipcatConfig {
         ShipcatConfig::new(&rname, conf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ShipcatManifest;
+
+    #[test]
+    fn manifest_crd_printer_columns() {
+        let crd = ShipcatManifest::crd();
+        let cols = crd.spec.additional_printer_columns.unwrap();
+        let paths = cols.iter().map(|c| c.json_path.as_str()).collect::<Vec<_>>();
+        assert!(paths.contains(&".spec.version"));
+        assert!(paths.contains(&".status.conditions.rolledout.status"));
+    }
+}
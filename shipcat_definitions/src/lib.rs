@@ -72,15 +72,25 @@ error_chain! {
             description("failed to build manifest")
             display("failed to build manifest for {} in {}", &service_name, &region_name)
         }
+        Validation(e: ValidationError) {
+            description("manifest validation failed")
+            display("{}", e)
+        }
     }
 }
 
+/// Validation errors attributed to services, regions and fields
+pub mod validation;
+pub use crate::validation::ValidationError;
+
 /// Config with regional data
 pub mod region;
-pub use crate::region::{Environment, KongConfig, ReconciliationMode, Region, VaultConfig, VersionScheme};
+pub use crate::region::{
+    Environment, KongConfig, MutableTagPolicy, ReconciliationMode, Region, VaultConfig, VersionScheme,
+};
 /// Master config with cross-region data
 pub mod config;
-pub use crate::config::{Cluster, Config, ConfigFallback, ShipcatConfig};
+pub use crate::config::{Cluster, Config, ConfigFallback, LintSeverity, ShipcatConfig};
 
 /// Structs for the manifest
 pub mod structs;
@@ -1,11 +1,14 @@
 use crate::vault::Vault;
 use kube_derive::CustomResource;
 use regex::Regex;
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
 
 use super::Result;
 use crate::{
-    config::Config,
+    config::{Config, LintSeverity},
     region::{Region, VaultConfig},
     states::{ManifestState, PrimaryWorkload},
     ManifestStatus,
@@ -19,9 +22,10 @@ use super::structs::{
     sentry::Sentry,
     tolerations::Tolerations,
     volume::{Volume, VolumeMount},
-    ConfigMap, Container, CronJob, Dependency, DestinationRule, EnvVars, EventStream, Gate, HealthCheck,
-    HostAlias, Kafka, KafkaResources, Kong, LifeCycle, Metadata, NotificationMode, PersistentVolume, Port,
-    Probe, PrometheusAlert, Rbac, ResourceRequirements, RollingUpdate, SecurityContext, VaultOpts, Worker,
+    AvailabilityPolicy, Canary, ConfigMap, Container, CronJob, Dependency, DestinationRule, DisruptionBudget,
+    EnvFrom, EnvVars, EventStream, Gate, HealthCheck, HostAlias, Kafka, KafkaResources, Kong, LifeCycle,
+    Metadata, NetworkPolicy, NotificationMode, PersistentVolume, Port, Probe, PrometheusAlert, Rbac,
+    ResourceRequirements, RollingUpdate, SecurityContext, TopologySpreadConstraint, VaultOpts, Worker,
 };
 
 /// Main manifest, serializable from manifest.yml or the shipcat CRD.
@@ -35,7 +39,9 @@ use super::structs::{
     status = "ManifestStatus",
     printcolumn = r#"{"name":"Kong", "jsonPath": ".spec.kong_apis[*].uris", "type": "string", "description": "The URI where the service is available through kong"}"#,
     printcolumn = r#"{"name":"Version", "jsonPath": ".spec.version", "type": "string", "description": "The version of the service that is deployed"}"#,
-    printcolumn = r#"{"name":"Team", "jsonPath": ".spec.metadata.team", "type": "string", "description": "The team that owns the service"}"#
+    printcolumn = r#"{"name":"Team", "jsonPath": ".spec.metadata.team", "type": "string", "description": "The team that owns the service"}"#,
+    printcolumn = r#"{"name":"Rolledout", "jsonPath": ".status.conditions.rolledout.status", "type": "boolean", "description": "Whether the last rollout succeeded"}"#,
+    printcolumn = r#"{"name":"Rollout Version", "jsonPath": ".status.summary.lastSuccessfulRolloutVersion", "type": "string", "description": "The last version that was successfully rolled out"}"#
 )]
 #[kube(apiextensions = "v1beta1")] // kubernetes < 1.16
 pub struct Manifest {
@@ -102,6 +108,14 @@ pub struct Manifest {
     ///
     /// Every region must be listed in here.
     /// Uncommenting a region in here will partially disable this service.
+    ///
+    /// Entries of the form `!region` exclude a region instead,
+    /// and on their own mean every configured region except those.
+    ///
+    /// ```yaml
+    /// regions:
+    /// - "!prod-uk"
+    /// ```
     #[serde(default, skip_serializing)]
     pub regions: Vec<String>,
 
@@ -153,6 +167,18 @@ pub struct Manifest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<String>,
 
+    /// Names of secrets used to pull the image from a private registry
+    ///
+    /// The secrets must already exist in the namespace.
+    /// Can be set per region via `defaultsV2` in `shipcat.conf`.
+    ///
+    /// ```yaml
+    /// imagePullSecrets:
+    /// - registry-credentials
+    /// ```
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imagePullSecrets: Vec<String>,
+
     /// Optional uncompressed image size
     ///
     /// This is used to compute a more accurate wait time for rolling upgrades.
@@ -193,12 +219,15 @@ pub struct Manifest {
 
     /// Extend the workload with a securityContext
     ///
-    /// This allows changing the ownership of mounted volumes
+    /// This allows changing the ownership of mounted volumes, or running as non-root.
+    /// Options missing here are taken from the `securityContextBaseline` default of the region.
     ///
     /// ```yaml
     /// securityContext:
     ///   runAsUser: 1000
     ///   fsGroup: 1000
+    ///   runAsNonRoot: true
+    ///   readOnlyRootFilesystem: true
     /// ```
     #[serde(skip_serializing_if = "Option::is_none")]
     pub securityContext: Option<SecurityContext>,
@@ -279,6 +308,21 @@ pub struct Manifest {
     #[serde(default)]
     pub env: EnvVars,
 
+    /// Environment variables from whole ConfigMaps or Secrets
+    ///
+    /// ConfigMaps must be the service's own (`{name}-config`) or listed in `allowedEnvFrom`.
+    ///
+    /// ```yaml
+    /// envFrom:
+    /// - kind: configMap
+    ///   name: webapp-config
+    ///   prefix: CONFIG_
+    /// - kind: secret
+    ///   name: shared-credentials
+    /// ```
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub envFrom: Vec<EnvFrom>,
+
     /// Kubernetes Secret Files to inject
     ///
     /// These have the same special "IN_VAULT" behavior as `Manifest::env`:
@@ -287,6 +331,7 @@ pub struct Manifest {
     /// Note the lowercase restriction on keys.
     /// All `secretFiles` are expected to be base64 in vault, and are placed into a
     /// kubernetes `Secret` object.
+    /// Env vars marked `IN_VAULT_AND_FILE` are also projected in here, see `EnvVars`.
     ///
     /// ```yaml
     /// secretFiles:
@@ -296,6 +341,18 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub secretFiles: BTreeMap<String, String>,
 
+    /// Names of `secretFiles` to render as templates
+    ///
+    /// The decoded file is rendered with the same context as `configs` templates,
+    /// and encoded again. Other secret files are always used verbatim.
+    ///
+    /// ```yaml
+    /// templatedSecretFiles:
+    /// - webapp-ssl-keystore
+    /// ```
+    #[serde(default, skip_serializing)]
+    pub templatedSecretFiles: Vec<String>,
+
     /// Config files to inline in a kubernetes `ConfigMap`
     ///
     /// These are read and templated by `tera` before they are passed to helm.
@@ -309,8 +366,27 @@ pub struct Manifest {
     ///     dest: webhooks.json
     ///   - name: newrelic-java.yml.j2
     /// ```
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub configs: Option<ConfigMap>,
+    ///
+    /// A list mounts several, each but one needing a `name`:
+    ///
+    /// ```yaml
+    /// configs:
+    /// - mount: /config/
+    ///   files:
+    ///   - name: webhooks.json.j2
+    ///     dest: webhooks.json
+    /// - name: logging
+    ///   mount: /logging/
+    ///   files:
+    ///   - name: logback.xml.j2
+    ///     dest: logback.xml
+    /// ```
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "crate::structs::configmap::one_or_many"
+    )]
+    pub configs: Vec<ConfigMap>,
 
     /// Vault options
     ///
@@ -435,9 +511,13 @@ pub struct Manifest {
     /// Plain sidecars are injected into the main `Deployment` and all the workers' ones.
     /// They scale directly with the sum of `replicaCount`s.
     ///
+    /// Sidecars and workers without their own `resources` can take the main container's
+    /// with `resourcesFromMain: true`, or a fraction of them with a positive factor.
+    ///
     /// ```yaml
     /// sidecars:
     /// - name: redis
+    ///   resourcesFromMain: 0.25
     /// ```
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sidecars: Vec<Container>,
@@ -472,6 +552,15 @@ pub struct Manifest {
     ///   initialDelaySeconds: 15
     ///   periodSeconds: 15
     /// ```
+    ///
+    /// gRPC services can use a native `grpc` probe on a declared port instead of `httpGet`:
+    ///
+    /// ```yaml
+    /// livenessProbe:
+    ///   grpc:
+    ///     port: 50051
+    ///     service: fake-ask
+    /// ```
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub livenessProbe: Option<Probe>,
 
@@ -511,6 +600,22 @@ pub struct Manifest {
     ///       name: cpu
     ///       targetAverageUtilization: 60
     /// ```
+    ///
+    /// Custom and external metrics can be given as `customMetrics`,
+    /// which are appended to `metrics` as `AverageValue` targets:
+    ///
+    /// ```yaml
+    /// autoScaling:
+    ///   minReplicas: 2
+    ///   maxReplicas: 10
+    ///   customMetrics:
+    ///   - name: kafka_consumergroup_lag
+    ///     type: External
+    ///     targetValue: "100"
+    /// ```
+    ///
+    /// A PodDisruptionBudget is derived from it, see `podDisruptionBudget`.
+    /// Only the budget's `minAvailable` may be set here (defaults to `minReplicas - 1`).
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub autoScaling: Option<AutoScaling>,
 
@@ -529,6 +634,22 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tolerations: Vec<Tolerations>,
 
+    /// Topology spread constraints for every kubernetes `Pod`
+    ///
+    /// Spread pods evenly across zones or nodes.
+    /// Straight from [kubernetes pod topology spread constraints](https://kubernetes.io/docs/concepts/workloads/pods/pod-topology-spread-constraints/),
+    /// except `labelSelector` defaults to the pods of the service.
+    /// Usually set as a region default.
+    ///
+    /// ```yaml
+    /// topologySpreadConstraints:
+    /// - maxSkew: 1
+    ///   topologyKey: topology.kubernetes.io/zone
+    ///   whenUnsatisfiable: ScheduleAnyway
+    /// ```
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topologySpreadConstraints: Vec<TopologySpreadConstraint>,
+
     /// Host aliases to inject in /etc/hosts in every kubernetes `Pod`
     ///
     /// Straight from [kubernetes host aliases](https://kubernetes.io/docs/concepts/services-networking/add-entries-to-pod-etc-hosts-with-host-aliases/).
@@ -639,9 +760,23 @@ pub struct Manifest {
     /// podAnnotations:
     ///   iam.amazonaws.com/role: role-arn
     /// ```
+    ///
+    /// Setting `meshInject: false` adds `sidecar.istio.io/inject: "false"` here,
+    /// and to the cron jobs that do not set their own `meshInject`.
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub podAnnotations: BTreeMap<String, String>,
 
+    /// Metadata Annotations for the workload object itself
+    ///
+    /// Set on the `Deployment` or `StatefulSet`, not on its pods.
+    ///
+    /// ```yaml
+    /// deploymentAnnotations:
+    ///   argocd.argoproj.io/sync-wave: "2"
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub deploymentAnnotations: BTreeMap<String, String>,
+
     /// Labels for every kubernetes object
     ///
     /// Injected in all top-level kubernetes object as a prometheus convenience.
@@ -654,6 +789,18 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub labels: BTreeMap<String, String>,
 
+    /// Named feature flags gating experimental config
+    ///
+    /// Region defaults can set baseline flags that services override.
+    /// Available as `features` in templates.
+    ///
+    /// ```yaml
+    /// features:
+    ///   new-cache: true
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, bool>,
+
     /// Kong config
     ///
     /// A mostly straight from API configuration struct for Kong
@@ -689,6 +836,7 @@ pub struct Manifest {
     ///
     /// This is useful for charts that expose a `Service` of `LoadBalancer` type.
     /// IP CIDR ranges, which Kubernetes will use to configure firewall exceptions.
+    /// These are also allowed ingress by a `networkPolicy` with `allowSourceRanges`.
     ///
     /// ```yaml
     /// sourceRanges:
@@ -697,6 +845,19 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sourceRanges: Vec<String>,
 
+    /// Kubernetes NetworkPolicy restricting ingress to the pods
+    ///
+    /// ```yaml
+    /// networkPolicy:
+    ///   allowSourceRanges: true
+    ///   from:
+    ///   - namespaceSelector:
+    ///       matchLabels:
+    ///         name: kong
+    /// ```
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub networkPolicy: Option<NetworkPolicy>,
+
     /// Role-Based Access Control
     ///
     /// A list of resources to allow the service access to use.
@@ -860,6 +1021,20 @@ pub struct Manifest {
     #[serde(default, skip_deserializing, skip_serializing_if = "BTreeMap::is_empty")]
     pub secrets: BTreeMap<String, String>,
 
+    /// Checksum of the resolved `secretFiles`
+    ///
+    /// Injected into the helm chart so a pod annotation changes when a secret file rotates.
+    /// This is an internal property that is exposed as an output only.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub secretFilesChecksum: Option<String>,
+
+    /// PodDisruptionBudget derived from `autoScaling` or `replicaCount`
+    ///
+    /// Keeps node drains from taking every replica down at once.
+    /// This is an internal property that is exposed as an output only.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub podDisruptionBudget: Option<DisruptionBudget>,
+
     /// Internal state of the manifest
     ///
     /// A manifest goes through different stages of serialization, templating,
@@ -867,6 +1042,12 @@ pub struct Manifest {
     #[serde(default, skip_deserializing, skip_serializing)]
     pub state: ManifestState,
 
+    /// Root of the manifests checkout the manifest was built from
+    ///
+    /// Templates include `templates/partials` from here, or the working directory if unset.
+    #[serde(default, skip_deserializing, skip_serializing)]
+    pub templateRoot: Option<PathBuf>,
+
     /// The default workload associated with a Manifest
     ///
     /// Defaults to Deployment
@@ -890,6 +1071,18 @@ pub struct Manifest {
     /// ```
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub prometheusAlerts: Vec<PrometheusAlert>,
+
+    /// Canary deployment with a weighted traffic split
+    ///
+    /// Needs at least 2 replicas (or `autoScaling.minReplicas`) in the primary deployment.
+    ///
+    /// ```yaml
+    /// canary:
+    ///   weight: 10
+    ///   version: 1.2.0
+    /// ```
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<Canary>,
 }
 
 impl Manifest {
@@ -905,6 +1098,30 @@ impl Manifest {
         Ok(())
     }
 
+    /// PodDisruptionBudget for the main workload
+    ///
+    /// Derived from `autoScaling` when set, otherwise services with more than one
+    /// replica keep at least one of them available.
+    pub fn pod_disruption_budget(&self) -> Option<DisruptionBudget> {
+        match &self.autoScaling {
+            Some(a) => a.disruption_budget(),
+            None if self.replicaCount.unwrap_or(1) > 1 => Some(DisruptionBudget {
+                minAvailable: Some(AvailabilityPolicy::Unsigned(1)),
+                maxUnavailable: None,
+            }),
+            None => None,
+        }
+    }
+
+    /// Canary with its pod count derived from the primary replicas
+    pub fn canary_with_replicas(&self) -> Option<Canary> {
+        let replicas = match &self.autoScaling {
+            Some(a) => a.minReplicas,
+            None => self.replicaCount.unwrap_or(1),
+        };
+        self.canary.clone().map(|c| c.with_replicas(replicas))
+    }
+
     /// Verify the region for this manifest is one of its declared ones
     ///
     /// Assumes the manifest has been populated with `implicits`
@@ -933,6 +1150,48 @@ impl Manifest {
         Ok(())
     }
 
+    /// Verify `imagePullSecrets` are valid kube secret names (DNS subdomains)
+    pub fn verify_image_pull_secrets(&self) -> Result<()> {
+        let re = Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$").unwrap();
+        for s in &self.imagePullSecrets {
+            if s.len() > 253 || !re.is_match(s) {
+                bail!("imagePullSecret {} is not a valid DNS subdomain name", s);
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of secondary containers without resource requests
+    pub fn containers_missing_resources(&self) -> Vec<&str> {
+        let sidecars = self.sidecars.iter();
+        let inits = self.initContainers.iter();
+        let workers = self.workers.iter().map(|w| &w.container);
+        let crons = self.cronJobs.iter().map(|c| &c.container);
+        sidecars
+            .chain(inits)
+            .chain(workers)
+            .chain(crons)
+            .filter(|c| c.resources.is_none())
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    /// Lint secondary containers for missing resource requests
+    ///
+    /// The main container always needs `resources`; this covers the rest.
+    pub fn verify_resource_requests(&self, severity: LintSeverity) -> Result<()> {
+        let missing = self.containers_missing_resources();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let msg = format!("{} has containers without resources: {:?}", self.name, missing);
+        match severity {
+            LintSeverity::Warn => warn!("{}", msg),
+            LintSeverity::Error => bail!("{}", msg),
+        }
+        Ok(())
+    }
+
     /// Verify assumptions about manifest
     ///
     /// Assumes the manifest has been populated with `implicits`
@@ -969,6 +1228,7 @@ impl Manifest {
         if let Some(v) = &self.version {
             region.versioningScheme.verify(v)?;
         }
+        self.verify_image_pull_secrets()?;
 
         // TODO [DIP-499]: Separate gate/kong params + adjust the checks
         if let Some(g) = &self.gate {
@@ -978,6 +1238,7 @@ impl Manifest {
             if g.public != self.publiclyAccessible {
                 bail!("[Migration plan] `publiclyAccessible` and `gate.public` must be equal");
             }
+            g.verify_hosts(&self.name, &self.namespace, &self.kongApis)?;
         }
 
         // run the `Verify` trait on all imported structs
@@ -987,6 +1248,7 @@ impl Manifest {
         } else {
             bail!("Resources is mandatory");
         }
+        self.verify_resource_requests(conf.resourceRequestsLint)?;
 
         // optional/vectorised entries
         for d in &self.dependencies {
@@ -999,14 +1261,18 @@ impl Manifest {
         for tl in &self.tolerations {
             tl.verify()?;
         }
+        for tsc in &self.topologySpreadConstraints {
+            tsc.verify()?;
+        }
         for r in &self.rbac {
             r.verify()?;
         }
         for pv in &self.persistentVolumes {
             pv.verify()?;
         }
-        if let Some(ref cmap) = self.configs {
-            cmap.verify()?;
+        crate::structs::configmap::verify_configs(&self.configs)?;
+        for ef in &self.envFrom {
+            ef.verify(&self.name, &conf.allowedEnvFrom)?;
         }
         for k in self.labels.keys() {
             if !conf.allowedLabels.contains(k) {
@@ -1022,6 +1288,9 @@ impl Manifest {
         for pa in &self.prometheusAlerts {
             pa.verify(&self.name)?;
         }
+        if let Some(c) = &self.canary {
+            c.verify(self.version.as_deref(), self.min_replicas())?;
+        }
         // misc minor properties
         if self.replicaCount.unwrap() == 0 {
             bail!("Need replicaCount to be at least 1");
@@ -1029,6 +1298,16 @@ impl Manifest {
         if let Some(ref ru) = &self.rollingUpdate {
             ru.verify(self.replicaCount.unwrap())?;
         }
+        if let Some(a) = &self.autoScaling {
+            a.verify()?;
+        }
+        if let Some(pdb) = &self.podDisruptionBudget {
+            let replicas = match &self.autoScaling {
+                Some(a) => a.minReplicas,
+                None => self.replicaCount.unwrap(),
+            };
+            pdb.verify(replicas)?;
+        }
 
         self.env.verify()?;
 
@@ -1063,14 +1342,39 @@ impl Manifest {
         Ok(())
     }
 
-    fn get_vault_path(&self, vc: &VaultConfig) -> String {
+    fn get_vault_service(&self) -> &str {
         // some services use keys from other services
-        let (svc, reg) = if let Some(ref vopts) = self.vault {
-            (vopts.name.clone(), vc.folder.clone())
+        if let Some(ref vopts) = self.vault {
+            &vopts.name
         } else {
-            (self.name.clone(), vc.folder.clone())
-        };
-        format!("{}/{}", reg, svc)
+            &self.name
+        }
+    }
+
+    fn get_vault_key(&self, vc: &VaultConfig, key: &str) -> String {
+        vc.secret_path(&self.region, self.get_vault_service(), key)
+    }
+
+    /// Vault paths of every secret this manifest fetches, keyed by secret name
+    ///
+    /// Assumes a `Base` manifest, where vault lookups are still marked in `env` and `secretFiles`.
+    pub fn vault_secret_paths(&self, vc: &VaultConfig) -> BTreeMap<String, String> {
+        let mut mf = self.clone();
+        let mut keys = BTreeSet::new();
+        for e in mf.get_env_vars() {
+            keys.append(&mut e.vault_secrets());
+        }
+        for (k, v) in &self.secretFiles {
+            if v == "IN_VAULT" {
+                keys.insert(k.to_string());
+            }
+        }
+        keys.into_iter()
+            .map(|k| {
+                let pth = self.get_vault_key(vc, &k);
+                (k, pth)
+            })
+            .collect()
     }
 
     // Get EnvVars for all containers, workers etc. for this Manifest.
@@ -1097,12 +1401,18 @@ impl Manifest {
     /// This will use the HTTP api of Vault using the configuration parameters
     /// in the `Config`.
     pub async fn secrets(&mut self, client: &Vault, vc: &VaultConfig) -> Result<()> {
-        let pth = self.get_vault_path(vc);
-        debug!("Injecting secrets from vault {} ({:?})", pth, client.mode());
+        let svc = self.get_vault_service().to_string();
+        debug!(
+            "Injecting secrets from vault {} ({:?})",
+            vc.secret_path(&self.region, &svc, "{key}"),
+            client.mode()
+        );
 
         let mut vault_secrets = BTreeSet::new();
+        let mut file_secrets = BTreeSet::new();
         let mut template_secrets = BTreeMap::new();
         for e in &mut self.get_env_vars() {
+            file_secrets.append(&mut e.vault_file_secrets());
             for k in e.vault_secrets() {
                 vault_secrets.insert(k.to_string());
             }
@@ -1124,16 +1434,29 @@ impl Manifest {
 
         // Lookup values for each secret in vault.
         for k in vault_secrets {
-            let vkey = format!("{}/{}", pth, k);
+            let vkey = vc.secret_path(&self.region, &svc, &k);
             self.secrets.insert(k.to_string(), client.read(&vkey).await?);
         }
 
         self.secrets.append(&mut template_secrets);
 
+        // project secrets marked as files from the same vault value
+        for k in file_secrets {
+            let file = EnvVars::secret_file_name(&k);
+            if self.secretFiles.contains_key(&file) {
+                bail!(
+                    "Secret {} is projected onto secretFiles {} which is already set",
+                    k,
+                    file
+                );
+            }
+            self.secretFiles.insert(file, base64::encode(&self.secrets[&k]));
+        }
+
         // do the same for secret secrets
         for (k, v) in &mut self.secretFiles {
             if v == "IN_VAULT" {
-                let vkey = format!("{}/{}", pth, k);
+                let vkey = vc.secret_path(&self.region, &svc, k);
                 *v = client.read(&vkey).await?;
             }
             // sanity check; secretFiles are assumed base64 verify we can decode
@@ -1141,9 +1464,15 @@ impl Manifest {
                 bail!("Secret {} is not base64 encoded", k);
             }
         }
+        self.secretFilesChecksum = secret_files_checksum(&self.secretFiles);
         Ok(())
     }
 
+    /// Whether a feature flag is enabled (unknown flags are not)
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        self.features.get(name).cloned().unwrap_or_default()
+    }
+
     /// Get a list of raw secrets (without associated keys)
     ///
     /// Useful for obfuscation mechanisms so it knows what to obfuscate.
@@ -1156,7 +1485,10 @@ impl Manifest {
         secrets
     }
 
-    pub async fn verify_secrets_exist(&self, vc: &VaultConfig) -> Result<()> {
+    /// Expected vault secrets of the service that are missing from vault
+    ///
+    /// Fails only when vault could not be asked, e.g. for a missing secret folder.
+    pub async fn missing_secrets(&self, vc: &VaultConfig) -> Result<Vec<String>> {
         use std::collections::HashSet;
         // what are we requesting
         // TODO: Use envvars directly
@@ -1165,7 +1497,7 @@ impl Manifest {
             .plain
             .clone()
             .into_iter()
-            .filter(|(_, v)| v == "IN_VAULT")
+            .filter(|(_, v)| EnvVars::is_vault_secret(v))
             .map(|(k, _)| k)
             .collect::<HashSet<_>>();
         let files = self
@@ -1175,18 +1507,30 @@ impl Manifest {
             .filter(|(_, v)| v == "IN_VAULT")
             .map(|(k, _)| k)
             .collect::<HashSet<_>>();
-        let expected = keys.union(&files).cloned().collect::<HashSet<_>>();
+        let expected = keys.union(&files).cloned().collect::<BTreeSet<_>>();
         if expected.is_empty() {
-            return Ok(()); // no point trying to cross reference
+            return Ok(vec![]); // no point trying to cross reference
         }
 
         // what we have
         let v = Vault::regional(vc)?;
-        let secpth = self.get_vault_path(vc);
+        let secpth = match vc.secret_folder(&self.region, self.get_vault_service()) {
+            Some(pth) => pth,
+            None => {
+                // template does not map to a listable folder; read keys individually
+                let mut missing = vec![];
+                for k in expected {
+                    if v.read(&self.get_vault_key(vc, &k)).await.is_err() {
+                        missing.push(k);
+                    }
+                }
+                return Ok(missing);
+            }
+        };
 
         // list secrets; fail immediately if folder is empty
         let found = match v.list(&secpth).await {
-            Ok(lst) => lst.into_iter().collect::<HashSet<_>>(),
+            Ok(lst) => lst.into_iter().collect::<BTreeSet<_>>(),
             Err(e) => bail!(
                 "Missing secret folder {} expected to contain {:?}: {}",
                 secpth,
@@ -1195,16 +1539,131 @@ impl Manifest {
             ),
         };
         debug!("Found secrets {:?} for {}", found, self.name);
+        Ok(expected.difference(&found).cloned().collect())
+    }
 
-        // compare sets
-        let missing = expected.difference(&found).collect::<Vec<_>>();
+    pub async fn verify_secrets_exist(&self, vc: &VaultConfig) -> Result<()> {
+        let missing = self.missing_secrets(vc).await?;
         if !missing.is_empty() {
-            bail!("Missing secrets: {:?} not found in vault {}", missing, secpth);
+            bail!(
+                "Missing secrets: {:?} not found in vault for {}",
+                missing,
+                self.name
+            );
         }
         Ok(())
     }
 }
 
+/// Checksum over a set of resolved secret files
+///
+/// Only used to detect changes. The hash is computed in memory and the values never logged.
+/// A sha256 over the sorted files, so it stays the same across toolchains and shipcat versions.
+pub fn secret_files_checksum(files: &BTreeMap<String, String>) -> Option<String> {
+    use ring::digest::{Context, SHA256};
+    if files.is_empty() {
+        return None;
+    }
+    let mut ctx = Context::new(&SHA256);
+    for (k, v) in files {
+        // length prefixed so key and value boundaries are unambiguous
+        for part in &[k, v] {
+            ctx.update(&(part.len() as u64).to_be_bytes());
+            ctx.update(part.as_bytes());
+        }
+    }
+    Some(
+        ctx.finish()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{secret_files_checksum, LintSeverity, Manifest};
+    use crate::structs::{autoscaling::AutoScaling, AvailabilityPolicy};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn feature_flags() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.features.insert("new-cache".into(), true);
+        mf.features.insert("beta-api".into(), false);
+        assert!(mf.feature_enabled("new-cache"));
+        assert!(!mf.feature_enabled("beta-api"));
+        assert!(!mf.feature_enabled("unknown"));
+    }
+
+    #[test]
+    fn disruption_budget_from_scaling() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.replicaCount = Some(1);
+        assert_eq!(mf.pod_disruption_budget(), None);
+        mf.replicaCount = Some(3);
+        let pdb = mf.pod_disruption_budget().unwrap();
+        assert_eq!(pdb.minAvailable, Some(AvailabilityPolicy::Unsigned(1)));
+
+        let mut hpa: AutoScaling =
+            serde_yaml::from_str("minReplicas: 4\nmaxReplicas: 8\nmetrics: []").unwrap();
+        mf.autoScaling = Some(hpa.clone());
+        let pdb = mf.pod_disruption_budget().unwrap();
+        assert_eq!(pdb.minAvailable, Some(AvailabilityPolicy::Unsigned(3)));
+        assert!(pdb.verify(4).is_ok());
+
+        hpa.minAvailable = Some(5);
+        assert!(hpa.verify().is_err());
+        hpa.minAvailable = Some(0);
+        assert_eq!(hpa.disruption_budget(), None);
+    }
+
+    #[test]
+    fn secret_file_checksum_changes() {
+        let mut files = BTreeMap::new();
+        assert_eq!(secret_files_checksum(&files), None);
+        files.insert("webapp-ssl-keystore".to_string(), base64::encode("old"));
+        let before = secret_files_checksum(&files).unwrap();
+        // pinned, as a changed checksum restarts every pod mounting secret files
+        assert_eq!(
+            before,
+            "be48b1ea8aac51885e2d31ee5a853b9136c4c4b77b7e3ff0b03c19aed80d87fd"
+        );
+        files.insert("webapp-ssl-keystore".to_string(), base64::encode("rotated"));
+        assert_ne!(secret_files_checksum(&files).unwrap(), before);
+    }
+
+    #[test]
+    fn resource_requests_lint() {
+        let mut mf = Manifest::default();
+        mf.name = "fake-ask".into();
+        assert!(mf.verify_resource_requests(LintSeverity::Error).is_ok());
+        mf.sidecars = vec![serde_yaml::from_str("name: redis").unwrap()];
+        mf.initContainers = vec![serde_yaml::from_str(
+            "name: migrate\nresources: {requests: {cpu: 100m, memory: 100Mi}, limits: {cpu: 200m, memory: 200Mi}}",
+        )
+        .unwrap()];
+        assert_eq!(mf.containers_missing_resources(), vec!["redis"]);
+        let err = mf.verify_resource_requests(LintSeverity::Error).unwrap_err();
+        assert!(err.to_string().contains(r#"["redis"]"#));
+        assert!(mf.verify_resource_requests(LintSeverity::Warn).is_ok());
+    }
+
+    #[test]
+    fn image_pull_secret_names() {
+        let mut mf = Manifest::default();
+        mf.imagePullSecrets = vec!["registry-creds".into(), "quay.io-pull".into()];
+        assert!(mf.verify_image_pull_secrets().is_ok());
+        for bad in &["Registry", "creds-", "a..b", "under_score"] {
+            mf.imagePullSecrets = vec![bad.to_string()];
+            assert!(mf.verify_image_pull_secrets().is_err(), "{} accepted", bad);
+        }
+        mf.imagePullSecrets = vec!["a".repeat(254)];
+        assert!(mf.verify_image_pull_secrets().is_err());
+    }
+}
+
 // Cross-crate test manifest creator
 impl Manifest {
     pub fn test(name: &str) -> Manifest {
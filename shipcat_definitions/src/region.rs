@@ -57,6 +57,33 @@ impl VersionScheme {
     }
 }
 
+/// Policy rejecting mutable image tags in a region
+///
+/// Mutable tags like `latest` cannot be rolled back to, and hide what is running.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct MutableTagPolicy {
+    /// Tags that are never allowed
+    #[serde(default = "default_mutable_tags")]
+    pub denylist: Vec<String>,
+    /// Additionally require tags to be semver or a 40 character git sha
+    #[serde(default)]
+    pub requireSemverOrSha: bool,
+}
+
+fn default_mutable_tags() -> Vec<String> {
+    vec!["latest".into(), "master".into(), "main".into(), "stable".into()]
+}
+
+impl Default for MutableTagPolicy {
+    fn default() -> Self {
+        MutableTagPolicy {
+            denylist: default_mutable_tags(),
+            requireSemverOrSha: false,
+        }
+    }
+}
+
 /// Vault configuration for a region
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(Default))]
@@ -68,6 +95,12 @@ pub struct VaultConfig {
     ///
     /// Typically, the name of the region to disambiguate.
     pub folder: String,
+    /// Path template for secrets under secret/
+    ///
+    /// Supports `{region}`, `{service}`, `{folder}` and `{key}` placeholders.
+    /// Defaults to `{folder}/{service}/{key}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pathTemplate: Option<String>,
 }
 
 impl VaultConfig {
@@ -85,9 +118,37 @@ impl VaultConfig {
                 self.url
             );
         }
+        if let Some(tpl) = &self.pathTemplate {
+            if !tpl.contains("{key}") {
+                bail!(
+                    "vault pathTemplate '{}' for {} must contain a {{key}} placeholder",
+                    tpl,
+                    region
+                );
+            }
+        }
         Ok(())
     }
 
+    /// Vault path (under secret/) for a secret key of a service in a region
+    pub fn secret_path(&self, region: &str, service: &str, key: &str) -> String {
+        let tpl = self.pathTemplate.as_deref().unwrap_or("{folder}/{service}/{key}");
+        tpl.replace("{region}", region)
+            .replace("{folder}", &self.folder)
+            .replace("{service}", service)
+            .replace("{key}", key)
+    }
+
+    /// Vault folder containing all secrets of a service, if the template allows listing
+    pub fn secret_folder(&self, region: &str, service: &str) -> Option<String> {
+        let pth = self.secret_path(region, service, "{key}");
+        if pth.ends_with("/{key}") && pth.matches("{key}").count() == 1 {
+            Some(pth.trim_end_matches("/{key}").to_string())
+        } else {
+            None
+        }
+    }
+
     /// Make vault a vault policy for a team based on team ownership
     ///
     /// Returns plaintext hcl
@@ -137,6 +198,28 @@ pub struct KafkaConfig {
     /// A mapping of kafka properties to environment variables (optional)
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub propertyEnvMapping: BTreeMap<String, String>,
+
+    /// Naming policy for topics declared in `kafkaResources` (optional)
+    ///
+    /// Defaults to `^[a-zA-Z0-9._-]+$`.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_regex")]
+    pub topicNamePattern: Option<Regex>,
+}
+
+impl KafkaConfig {
+    /// Verify topic names against the naming policy of the region's brokers
+    pub fn verify_topic_names<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let default = Regex::new(r"^[a-zA-Z0-9._-]+$").unwrap();
+        let pattern = self.topicNamePattern.as_ref().unwrap_or(&default);
+        let invalid = names
+            .into_iter()
+            .filter(|n| !pattern.is_match(n))
+            .collect::<Vec<_>>();
+        if !invalid.is_empty() {
+            bail!("Kafka topic names {:?} do not match {}", invalid, pattern);
+        }
+        Ok(())
+    }
 }
 
 /// Webhook types that shipcat might trigger after actions
@@ -185,6 +268,11 @@ pub struct KongConfig {
     pub internal_ips_whitelist: Vec<String>,
     #[serde(default, skip_serializing)]
     pub extra_apis: BTreeMap<String, Kong>,
+    /// Addresses (ips or hostnames) of the ingress that service hosts should resolve to
+    ///
+    /// Used by `shipcat validate-ingress`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ingress_addresses: Vec<String>,
 }
 
 /// StatusCake configuration for a region
@@ -394,10 +482,50 @@ mod test_webhooks {
     }
 }
 
+#[cfg(test)]
+mod test_vault {
+    use super::VaultConfig;
+
+    #[test]
+    fn region_vault_path_template() {
+        let tpl = Some("{region}/{service}/{key}".to_string());
+        let uk = VaultConfig {
+            folder: "dev-uk".into(),
+            pathTemplate: tpl.clone(),
+            ..Default::default()
+        };
+        let us = VaultConfig {
+            folder: "dev-us".into(),
+            pathTemplate: tpl,
+            ..Default::default()
+        };
+        let svc = "fake-ask";
+        assert_eq!(uk.secret_path("dev-uk", svc, "DB_URL"), "dev-uk/fake-ask/DB_URL");
+        assert_eq!(us.secret_path("dev-us", svc, "DB_URL"), "dev-us/fake-ask/DB_URL");
+        assert_eq!(uk.secret_folder("dev-uk", svc), Some("dev-uk/fake-ask".into()));
+
+        let default = VaultConfig {
+            url: "http://localhost:8200".into(),
+            folder: "devuk".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            default.secret_path("dev-uk", svc, "DB_URL"),
+            "devuk/fake-ask/DB_URL"
+        );
+
+        let nokey = VaultConfig {
+            pathTemplate: Some("{region}/{service}".into()),
+            ..default
+        };
+        assert!(nokey.verify("dev-uk").is_err());
+    }
+}
+
 // ----------------------------------------------------------------------------------
 
 /// Environments are well defined strings
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Environment {
     /// Production environment
@@ -514,6 +642,28 @@ pub struct Region {
     pub webhooks: Vec<Webhook>,
     /// CRD tuning
     pub customResources: Option<CRSettings>,
+    /// Multiplier applied to the base `replicaCount` of services in the region
+    ///
+    /// The result is floored to at least 1. Services setting `replicaCount` in their
+    /// environment or region files are not scaled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica_scale_factor: Option<f64>,
+    /// Append `-{environment}` to service versions, e.g. `1.2.3` -> `1.2.3-dev`
+    ///
+    /// Applied to pinned and cli passed versions alike. Versions already ending
+    /// in `-{environment}` are left alone, so the suffix is never doubled.
+    #[serde(default)]
+    pub version_suffix_from_environment: bool,
+    /// Tear down rendered resources when a shipcatmanifest is deleted
+    ///
+    /// Guarded by a finalizer on the crd so the resources go before the manifest.
+    #[serde(default)]
+    pub cleanupOnDelete: bool,
+    /// Apply shipcatmanifest crds through `kubectl apply` rather than server-side apply
+    ///
+    /// Only needed for clusters older than kubernetes 1.16.
+    #[serde(default)]
+    pub kubectlCrdApply: bool,
 
     /// Old default values for services
     // TODO: Remove after everything has been migrated to `defaultsV2`
@@ -532,9 +682,75 @@ pub struct Region {
     /// The regular expression used to verify destination rules' regions
     #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_regex")]
     pub destinationRuleHostRegex: Option<Regex>,
+
+    /// Ticket reference that every apply reason must contain (e.g. `JIRA-\d+`)
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_regex")]
+    pub applyTicketPattern: Option<Regex>,
+
+    /// Maximum concurrent kube api calls shipcat makes against the region (optional)
+    ///
+    /// Throttled calls are retried with backoff regardless.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kubeApiConcurrency: Option<usize>,
+
+    /// Reject mutable image tags (like `latest`) for services in the region
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forbidMutableTags: Option<MutableTagPolicy>,
 }
 
 impl Region {
+    /// Verify an apply reason references a ticket if the region requires it
+    pub fn verify_apply_reason(&self, reason: Option<&str>) -> Result<()> {
+        if let Some(pattern) = &self.applyTicketPattern {
+            match reason {
+                Some(r) if pattern.is_match(r) => {}
+                Some(r) => bail!(
+                    "Apply reason '{}' does not reference a ticket matching {} required in {}",
+                    r,
+                    pattern,
+                    self.name
+                ),
+                None => bail!(
+                    "Applies to {} need a --reason referencing a ticket matching {}",
+                    self.name,
+                    pattern
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify an image tag is allowed by the region's `forbidMutableTags` policy
+    pub fn verify_tag_policy(&self, tag: &str) -> Result<()> {
+        if let Some(policy) = &self.forbidMutableTags {
+            if policy.denylist.iter().any(|t| t == tag) {
+                bail!(
+                    "Tag {} is mutable and denied by forbidMutableTags in region {}",
+                    tag,
+                    self.name
+                );
+            }
+            if policy.requireSemverOrSha && VersionScheme::GitShaOrSemver.verify(tag).is_err() {
+                bail!(
+                    "Tag {} is not semver or a git sha as required by forbidMutableTags in region {}",
+                    tag,
+                    self.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve a service version, applying the environment suffix when configured
+    pub fn suffixed_version(&self, version: String) -> String {
+        let suffix = format!("-{}", self.environment.to_string());
+        if self.version_suffix_from_environment && !version.ends_with(&suffix) {
+            format!("{}{}", version, suffix)
+        } else {
+            version
+        }
+    }
+
     // Internal secret populator for Config::new
     pub async fn secrets(&mut self) -> Result<()> {
         let v = Vault::regional(&self.vault)?;
@@ -612,3 +828,85 @@ impl Region {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Environment, KafkaConfig, MutableTagPolicy, Region};
+    use regex::Regex;
+
+    #[test]
+    fn kafka_topic_naming() {
+        let kc = KafkaConfig::default();
+        let valid = vec!["orders", "payments.v2", "audit_log-1"];
+        assert!(kc.verify_topic_names(valid).is_ok());
+        let invalid = vec!["orders", "bad topic", "no/slash"];
+        let err = kc.verify_topic_names(invalid).unwrap_err();
+        assert!(err.to_string().contains(r#"["bad topic", "no/slash"]"#));
+
+        let strict = KafkaConfig {
+            topicNamePattern: Some(Regex::new(r"^team\.[a-z]+$").unwrap()),
+            ..Default::default()
+        };
+        assert!(strict.verify_topic_names(vec!["team.orders"]).is_ok());
+        assert!(strict.verify_topic_names(vec!["orders"]).is_err());
+    }
+
+    #[test]
+    fn apply_reason_needs_ticket() {
+        let reg = Region {
+            name: "prod-uk".into(),
+            applyTicketPattern: Some(Regex::new(r"JIRA-\d+").unwrap()),
+            ..Default::default()
+        };
+        assert!(reg.verify_apply_reason(None).is_err());
+        assert!(reg.verify_apply_reason(Some("hotfix")).is_err());
+        assert!(reg.verify_apply_reason(Some("JIRA-123 hotfix")).is_ok());
+
+        let unrestricted = Region::default();
+        assert!(unrestricted.verify_apply_reason(None).is_ok());
+    }
+
+    #[test]
+    fn version_suffix_from_environment() {
+        let reg = Region {
+            name: "dev-uk".into(),
+            environment: Environment::Dev,
+            version_suffix_from_environment: true,
+            ..Default::default()
+        };
+        assert_eq!(reg.suffixed_version("1.2.3".into()), "1.2.3-dev");
+        // never double applied, but prereleases are still suffixed
+        assert_eq!(reg.suffixed_version("1.2.3-dev".into()), "1.2.3-dev");
+        assert_eq!(reg.suffixed_version("1.2.3-rc.1".into()), "1.2.3-rc.1-dev");
+        assert_eq!(Region::default().suffixed_version("1.2.3".into()), "1.2.3");
+    }
+
+    #[test]
+    fn mutable_tags_forbidden() {
+        let mut reg = Region {
+            name: "prod-uk".into(),
+            forbidMutableTags: Some(MutableTagPolicy::default()),
+            ..Default::default()
+        };
+        assert!(reg.verify_tag_policy("1.2.3").is_ok());
+        assert!(reg.verify_tag_policy("feature-x").is_ok());
+        let err = reg.verify_tag_policy("latest").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tag latest is mutable and denied by forbidMutableTags in region prod-uk"
+        );
+        assert!(reg.verify_tag_policy("master").is_err());
+
+        reg.forbidMutableTags = Some(MutableTagPolicy {
+            denylist: vec![],
+            requireSemverOrSha: true,
+        });
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert!(reg.verify_tag_policy("1.2.3").is_ok());
+        assert!(reg.verify_tag_policy(sha).is_ok());
+        assert!(reg.verify_tag_policy("feature-x").is_err());
+
+        // dev regions without the policy stay flexible
+        assert!(Region::default().verify_tag_policy("latest").is_ok());
+    }
+}
@@ -98,6 +98,7 @@ impl Manifest {
 
         // templates last
         self.template_configs(reg)?;
+        self.template_secret_files(reg)?;
         self.state = state;
         Ok(self)
     }
@@ -18,11 +18,55 @@ pub struct ManifestStatus {
     /// A more easily readable summary of why the conditions are what they are
     #[serde(default)]
     pub summary: Option<ConditionSummary>,
+    /// Most recent rollouts, oldest first, capped at `ROLLOUT_HISTORY_LIMIT`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<RolloutRecord>,
     /* TODO: vault secret hash
      * MAYBE: kong status?
      * MAYBE: canary status? */
 }
 
+/// Number of rollouts kept in `ManifestStatus::history`
+pub const ROLLOUT_HISTORY_LIMIT: usize = 10;
+
+/// Number of apply reasons kept in `ConditionSummary::apply_reasons`
+pub const APPLY_REASON_HISTORY_LIMIT: usize = 10;
+
+/// Append to a bounded history, dropping the oldest entries beyond the limit
+fn push_bounded<T>(history: &mut Vec<T>, entry: T, limit: usize) {
+    history.push(entry);
+    if history.len() > limit {
+        let excess = history.len() - limit;
+        history.drain(..excess);
+    }
+}
+
+impl ManifestStatus {
+    /// Append a rollout to the history, dropping the oldest beyond the limit
+    pub fn push_history(&mut self, record: RolloutRecord) {
+        push_bounded(&mut self.history, record, ROLLOUT_HISTORY_LIMIT);
+    }
+
+    /// Last version that was successfully rolled out (if recorded)
+    pub fn last_successful_version(&self) -> Option<&str> {
+        self.summary.as_ref()?.last_successful_rollout_version.as_deref()
+    }
+}
+
+/// A single rollout attempt
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RolloutRecord {
+    /// Version that was rolled out
+    pub version: String,
+    /// Date string (RFC3339) of when the rollout finished
+    pub timestamp: String,
+    /// Whether the rollout succeeded
+    pub success: bool,
+    /// Originator of the rollout
+    #[serde(default)]
+    pub applier: Option<Applier>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Conditions {
@@ -55,12 +99,12 @@ pub struct Conditions {
     pub rolledout: Option<Condition>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ConditionSummary {
     /// Date string (RFC3339) of when we generated the template successfully
     #[serde(default)]
-    last_successful_generate: Option<String>,
+    pub last_successful_generate: Option<String>,
 
     /// Date string (RFC3339) of when we last applied manifest configuration
     #[serde(default)]
@@ -68,31 +112,54 @@ pub struct ConditionSummary {
 
     /// Date string (RFC3339) of when an apply passed all checks
     #[serde(default)]
-    last_successful_apply: Option<String>,
+    pub last_successful_apply: Option<String>,
 
     /// Date string (RFC3339) of when a rollout wait completed
     #[serde(default)]
-    last_rollout: Option<String>,
+    pub last_rollout: Option<String>,
 
     /// Date string (RFC3339) of when a rollout wait completed and passed
     #[serde(default)]
-    last_successful_rollout: Option<String>,
+    pub last_successful_rollout: Option<String>,
 
     // last action we performed
     #[serde(default)]
-    last_action: Option<String>,
+    pub last_action: Option<String>,
 
     /// reason for last failure (if any)
     #[serde(default)]
-    last_failure_reason: Option<String>,
+    pub last_failure_reason: Option<String>,
 
     /// Best effort reason for why an apply was triggered
     #[serde(default)]
-    last_apply_reason: Option<String>,
+    pub last_apply_reason: Option<String>,
 
     /// Last version that was successfully rolled out
     #[serde(default)]
     pub last_successful_rollout_version: Option<String>,
+
+    /// Most recent apply reasons, oldest first, capped at `APPLY_REASON_HISTORY_LIMIT`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apply_reasons: Vec<ApplyReason>,
+}
+
+impl ConditionSummary {
+    /// Append an apply reason, dropping the oldest beyond the limit
+    pub fn push_apply_reason(&mut self, reason: ApplyReason) {
+        push_bounded(&mut self.apply_reasons, reason, APPLY_REASON_HISTORY_LIMIT);
+    }
+}
+
+/// A single apply and why it was triggered
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApplyReason {
+    /// Best effort reason for why the apply was triggered
+    pub reason: String,
+    /// Date string (RFC3339) of when the apply happened
+    pub timestamp: String,
+    /// Originator of the apply
+    #[serde(default)]
+    pub applier: Option<Applier>,
 }
 
 /// Condition
@@ -206,7 +273,67 @@ impl Condition {
 
 #[cfg(test)]
 mod tests {
-    use super::{Applier, Condition};
+    use super::{
+        Applier, ApplyReason, Condition, ConditionSummary, ManifestStatus, RolloutRecord,
+        APPLY_REASON_HISTORY_LIMIT, ROLLOUT_HISTORY_LIMIT,
+    };
+
+    fn rollout(version: &str) -> RolloutRecord {
+        RolloutRecord {
+            version: version.into(),
+            timestamp: "2020-03-01T12:00:00Z".into(),
+            success: true,
+            applier: None,
+        }
+    }
+
+    #[test]
+    fn rollout_history_bounded() {
+        let mut status = ManifestStatus::default();
+        for v in &["1.0.0", "1.1.0", "1.2.0"] {
+            status.push_history(rollout(v));
+        }
+        let versions: Vec<_> = status.history.iter().map(|r| r.version.as_str()).collect();
+        assert_eq!(versions, vec!["1.0.0", "1.1.0", "1.2.0"]);
+
+        for i in 0..ROLLOUT_HISTORY_LIMIT {
+            status.push_history(rollout(&format!("2.{}.0", i)));
+        }
+        assert_eq!(status.history.len(), ROLLOUT_HISTORY_LIMIT);
+        assert_eq!(status.history[0].version, "2.0.0");
+        assert_eq!(status.history.last().unwrap().version, "2.9.0");
+    }
+
+    #[test]
+    fn apply_reasons_bounded() {
+        let mut summary = ConditionSummary::default();
+        for i in 0..APPLY_REASON_HISTORY_LIMIT + 2 {
+            summary.push_apply_reason(ApplyReason {
+                reason: format!("reason {}", i),
+                timestamp: "2020-03-01T12:00:00Z".into(),
+                applier: None,
+            });
+        }
+        assert_eq!(summary.apply_reasons.len(), APPLY_REASON_HISTORY_LIMIT);
+        assert_eq!(summary.apply_reasons[0].reason, "reason 2");
+        assert_eq!(summary.apply_reasons.last().unwrap().reason, "reason 11");
+
+        let encoded = serde_json::to_value(&summary).unwrap();
+        assert_eq!(encoded["applyReasons"][0]["reason"], "reason 2");
+        let empty = serde_json::to_value(&ConditionSummary::default()).unwrap();
+        assert!(empty.get("applyReasons").is_none());
+    }
+
+    #[test]
+    fn last_successful_version() {
+        let mut status = ManifestStatus::default();
+        assert_eq!(status.last_successful_version(), None);
+        status.summary = Some(Default::default());
+        assert_eq!(status.last_successful_version(), None);
+        status.summary.as_mut().unwrap().last_successful_rollout_version = Some("1.2.0".into());
+        assert_eq!(status.last_successful_version(), Some("1.2.0"));
+    }
+
     use chrono::{prelude::*, Utc};
     #[test]
     #[ignore]
@@ -2,8 +2,64 @@
 // https://github.com/kubernetes/kubernetes/blob/master/pkg/apis/autoscaling/types.go
 // https://docs.rs/k8s-openapi/0.7.1/k8s_openapi/api/autoscaling/v2beta2/struct.HorizontalPodAutoscalerSpec.html
 
-use super::Result;
-use k8s_openapi::api::autoscaling::v2beta2::MetricSpec;
+use super::{AvailabilityPolicy, DisruptionBudget, Result};
+use k8s_openapi::{
+    api::autoscaling::v2beta2::{
+        ExternalMetricSource, MetricIdentifier, MetricSpec, MetricTarget, PodsMetricSource,
+    },
+    apimachinery::pkg::api::resource::Quantity,
+};
+
+/// Where a custom metric is read from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CustomMetricType {
+    /// A metric describing each pod, averaged across them
+    Pods,
+    /// A metric not tied to any kube object, like kafka consumer lag
+    External,
+}
+
+/// A custom or external metric to scale on
+///
+/// ```yaml
+/// name: kafka_consumergroup_lag
+/// type: External
+/// targetValue: "100"
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomMetric {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub metricType: CustomMetricType,
+    /// Target value of the metric averaged across all pods
+    pub targetValue: String,
+}
+
+impl CustomMetric {
+    fn metric_spec(&self) -> MetricSpec {
+        let metric = MetricIdentifier {
+            name: self.name.clone(),
+            selector: None,
+        };
+        let target = MetricTarget {
+            type_: "AverageValue".into(),
+            average_value: Some(Quantity(self.targetValue.clone())),
+            ..Default::default()
+        };
+        match self.metricType {
+            CustomMetricType::Pods => MetricSpec {
+                type_: "Pods".into(),
+                pods: Some(PodsMetricSource { metric, target }),
+                ..Default::default()
+            },
+            CustomMetricType::External => MetricSpec {
+                type_: "External".into(),
+                external: Some(ExternalMetricSource { metric, target }),
+                ..Default::default()
+            },
+        }
+    }
+}
 
 /// Configuration parameters for HorizontalPodAutoScaler
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -21,7 +77,21 @@ pub struct AutoScaling {
     /// If not set, the default metric will be set to 80% average CPU utilization.
     ///
     /// The maximum replica count across all metrics will be used.
+    #[serde(default)]
     pub metrics: Vec<MetricSpec>,
+
+    /// Shorthand for scaling on custom or external metrics
+    ///
+    /// Appended to `metrics` when the manifest is built.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub customMetrics: Vec<CustomMetric>,
+
+    /// Replicas that must stay available during voluntary disruptions like node drains
+    ///
+    /// Rendered as a PodDisruptionBudget, defaulting to `minReplicas - 1`.
+    /// Not part of the HorizontalPodAutoscaler spec.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minAvailable: Option<u32>,
 }
 
 impl AutoScaling {
@@ -29,6 +99,65 @@ impl AutoScaling {
         if self.minReplicas > self.maxReplicas {
             bail!("maxReplicas must be > minReplicas");
         }
+        for cm in &self.customMetrics {
+            if cm.name.is_empty() || cm.targetValue.is_empty() {
+                bail!("customMetrics need a name and a targetValue");
+            }
+        }
+        if let Some(ma) = self.minAvailable {
+            if ma > self.minReplicas {
+                bail!(
+                    "minAvailable {} cannot exceed minReplicas {}",
+                    ma,
+                    self.minReplicas
+                );
+            }
+        }
         Ok(())
     }
+
+    /// Fold `customMetrics` into the HorizontalPodAutoscaler `metrics`
+    ///
+    /// Explicit `metrics` are kept, so cpu targets still apply alongside them.
+    pub fn with_custom_metrics(mut self) -> Self {
+        let custom = std::mem::replace(&mut self.customMetrics, vec![]);
+        self.metrics.extend(custom.iter().map(CustomMetric::metric_spec));
+        self
+    }
+
+    /// PodDisruptionBudget keeping all but one of the minimum replicas available
+    ///
+    /// None when nothing would need to stay available.
+    pub fn disruption_budget(&self) -> Option<DisruptionBudget> {
+        let min = self
+            .minAvailable
+            .unwrap_or_else(|| self.minReplicas.saturating_sub(1));
+        if min == 0 {
+            return None;
+        }
+        Some(DisruptionBudget {
+            minAvailable: Some(AvailabilityPolicy::Unsigned(min)),
+            maxUnavailable: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AutoScaling;
+
+    #[test]
+    fn custom_metrics_are_rendered() {
+        let hpa: AutoScaling = serde_yaml::from_str(
+            "minReplicas: 2\nmaxReplicas: 10\ncustomMetrics:\n- name: kafka_consumergroup_lag\n  type: External\n  targetValue: \"100\"",
+        )
+        .unwrap();
+        hpa.verify().unwrap();
+        let values = serde_json::to_value(hpa.with_custom_metrics()).unwrap();
+        let metric = &values["metrics"][0];
+        assert_eq!(metric["type"], "External");
+        assert_eq!(metric["external"]["metric"]["name"], "kafka_consumergroup_lag");
+        assert_eq!(metric["external"]["target"]["averageValue"], "100");
+        assert!(values.get("customMetrics").is_none());
+    }
 }
@@ -0,0 +1,83 @@
+use super::Result;
+
+/// Canary rollout alongside the primary deployment
+///
+/// Charts render a second `{name}-canary` Deployment at the canary version,
+/// and split traffic between the two by weight. The canary runs `replicas` pods,
+/// derived from the weight and the primary replica count.
+///
+/// ```yaml
+/// canary:
+///   weight: 10
+///   version: 1.2.0
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct Canary {
+    /// Percentage of traffic sent to the canary (0-100)
+    pub weight: u32,
+    /// Image version of the canary
+    pub version: String,
+    /// Pods in the canary deployment (output only)
+    #[serde(default, skip_deserializing)]
+    pub replicas: u32,
+}
+
+impl Canary {
+    pub fn verify(&self, primary_version: Option<&str>, primary_replicas: u32) -> Result<()> {
+        if self.weight > 100 {
+            bail!("Canary weight must be between 0 and 100, got {}", self.weight);
+        }
+        if primary_replicas < 2 {
+            bail!("Canary needs at least 2 primary replicas to take effect");
+        }
+        if self.version.is_empty() {
+            bail!("Canary version must be set");
+        }
+        if primary_version == Some(self.version.as_str()) {
+            bail!(
+                "Canary version {} must differ from the primary version",
+                self.version
+            );
+        }
+        Ok(())
+    }
+
+    /// Canary pods for a weight of the primary replicas, rounded up
+    pub fn with_replicas(mut self, primary_replicas: u32) -> Self {
+        self.replicas = ((primary_replicas * self.weight.min(100) + 99) / 100).max(1);
+        self
+    }
+
+    /// Percentage of traffic left on the primary deployment
+    pub fn primary_weight(&self) -> u32 {
+        100 - self.weight.min(100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Canary;
+
+    #[test]
+    fn canary_verify() {
+        let c = Canary {
+            weight: 10,
+            version: "1.1.0".into(),
+            ..Default::default()
+        };
+        assert!(c.verify(Some("1.0.0"), 2).is_ok());
+        assert!(c.verify(None, 2).is_ok());
+        assert!(c.verify(Some("1.1.0"), 2).is_err());
+        assert!(c.verify(Some("1.0.0"), 1).is_err());
+        assert_eq!(c.primary_weight(), 90);
+        assert_eq!(c.clone().with_replicas(4).replicas, 1);
+        let quarter = Canary {
+            weight: 25,
+            ..c.clone()
+        };
+        assert_eq!(quarter.with_replicas(10).replicas, 3);
+        let heavy = Canary { weight: 101, ..c };
+        assert!(heavy.verify(Some("1.0.0"), 2).is_err());
+    }
+}
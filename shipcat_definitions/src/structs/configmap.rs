@@ -1,3 +1,6 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeSet;
+
 use super::Result;
 
 /// ConfigMap
@@ -5,10 +8,13 @@ use super::Result;
 /// A special abstraction that is used to create a kubernetes ConfigMap
 /// Deals with automatic mounting into the pods.
 ///
-/// Only one of these is supported.
+/// Several can be mounted at different paths, as long as all but one are named.
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct ConfigMap {
+    /// Suffix for the kubernetes ConfigMap name (`{service}-config-{name}`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     /// Container-local directory path where configs are available
     pub mount: String,
     /// Files from the config map to mount at this mountpath
@@ -54,3 +60,54 @@ impl ConfigMap {
         Ok(())
     }
 }
+
+/// Verify a set of configmaps can be mounted side by side
+pub fn verify_configs(configs: &[ConfigMap]) -> Result<()> {
+    let mut names = BTreeSet::new();
+    let mut mounts = BTreeSet::new();
+    for c in configs {
+        c.verify()?;
+        if !names.insert(c.name.clone()) {
+            bail!("configs need distinct names (duplicate {:?})", c.name);
+        }
+        if !mounts.insert(c.mount.clone()) {
+            bail!("configs need distinct mounts (duplicate {})", c.mount);
+        }
+    }
+    Ok(())
+}
+
+/// Deserialize a single configmap (legacy) or a list of them
+pub fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<ConfigMap>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(ConfigMap),
+        Many(Vec<ConfigMap>),
+    }
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(c)) => vec![c],
+        Some(OneOrMany::Many(cs)) => cs,
+        None => vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_configs, ConfigMap};
+
+    #[test]
+    fn distinct_configs() {
+        let mk = |name: Option<&str>, mount: &str| ConfigMap {
+            name: name.map(String::from),
+            mount: mount.into(),
+            files: vec![],
+        };
+        assert!(verify_configs(&[mk(None, "/config/"), mk(Some("logging"), "/logging/")]).is_ok());
+        assert!(verify_configs(&[mk(None, "/config/"), mk(None, "/logging/")]).is_err());
+        assert!(verify_configs(&[mk(None, "/config/"), mk(Some("logging"), "/config/")]).is_err());
+    }
+}
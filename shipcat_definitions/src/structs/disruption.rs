@@ -1,16 +1,17 @@
-/// Source for PodDisruptionBudget representation
-///
-/// Ultimately, this is left unused and unimported due to significant limitations in kube.
-/// In particular; https://github.com/kubernetes/kubernetes/issues/45398
-/// which causes us to not reliably be able to change them.
-///
-/// This work is left here in case it becomes useful.
-/// Users may wish to look at rollingupdate.rs instead, which has a useful alternative.
+//! Source for PodDisruptionBudget representation
+//!
+//! Not settable directly in manifests, because of limitations in kube;
+//! https://github.com/kubernetes/kubernetes/issues/45398
+//! which causes us to not reliably be able to change them.
+//!
+//! Instead it is derived from `autoScaling` or `replicaCount`,
+//! see `Manifest::pod_disruption_budget`.
+//! Users may wish to look at rollingupdate.rs instead, which has a useful alternative.
 
-use super::{Result};
+use super::Result;
 
 // Untagged enum to get around the weird validation
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum AvailabilityPolicy {
     Percentage(String),
@@ -25,13 +26,13 @@ impl AvailabilityPolicy {
                 if n > &maxNumber {
                     bail!("Cannot have {} set higher than replicaCount {}", name, maxNumber);
                 }
-            },
+            }
             AvailabilityPolicy::Percentage(s) => {
                 if !s.ends_with('%') {
                     bail!("{} must end with a '%' sign", name);
                 }
                 let digits = s.chars().take_while(|ch| *ch != '%').collect::<String>();
-                let res : u32 = digits.parse()?;
+                let res: u32 = digits.parse()?;
                 if res > 100 {
                     bail!("Percentage value for {} cannot exceed 100", name);
                 }
@@ -46,7 +47,7 @@ impl AvailabilityPolicy {
 /// Users need to set exactly one of these to pass validation.
 /// The values are "how many replicas" when integer values are used,
 /// and "what percentage of total replicas" when a % is added to the string.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct DisruptionBudget {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub minAvailable: Option<AvailabilityPolicy>,
@@ -55,7 +56,7 @@ pub struct DisruptionBudget {
 }
 
 impl DisruptionBudget {
-     pub fn verify(&self, replicas: u32) -> Result<()> {
+    pub fn verify(&self, replicas: u32) -> Result<()> {
         if self.minAvailable.is_none() && self.maxUnavailable.is_none() {
             bail!("Need to set one of minAvailable or maxUnavailable in disruptionBudget");
         }
@@ -69,5 +70,5 @@ impl DisruptionBudget {
             mu.verify("maxUnavailable", replicas)?;
         }
         Ok(())
-     }
+    }
 }
@@ -18,6 +18,9 @@ use std::collections::{BTreeMap, BTreeSet};
 ///   # vault lookup:
 ///   DATABASE_URL: IN_VAULT
 ///
+///   # vault lookup, also projected into `secretFiles` as `tls-cert`:
+///   TLS_CERT: IN_VAULT_AND_FILE
+///
 ///   # templated evars:
 ///   INTERNAL_AUTH_URL: "{{ base_urls.services }}/auth/internal"
 /// ```
@@ -25,6 +28,9 @@ use std::collections::{BTreeMap, BTreeSet};
 /// The vault lookup will GET from the region specific path for vault, in the
 /// webapp subfolder, getting the `DATABASE_URL` secret.
 ///
+/// A secret marked `IN_VAULT_AND_FILE` is fetched once and backs both the
+/// environment variable and a secret file named after it in lowercase kebab-case.
+///
 /// The `kong` templating will use the secrets read from the `Config` for this
 /// region, and replace them internally.
 ///
@@ -41,6 +47,12 @@ pub struct EnvVars {
     /// This is an internal property that is exposed as an output only.
     #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
     pub secrets: BTreeSet<String>,
+
+    /// Declaration order of all environment variable names
+    ///
+    /// Only set when `preserveEnvOrder` is enabled, so charts can render in source order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
 }
 
 impl EnvVars {
@@ -48,11 +60,21 @@ impl EnvVars {
         EnvVars {
             plain: env,
             secrets: Default::default(),
+            order: Default::default(),
         }
     }
 
-    fn is_vault_secret(value: &str) -> bool {
-        value == "IN_VAULT"
+    pub fn is_vault_secret(value: &str) -> bool {
+        value == "IN_VAULT" || EnvVars::is_vault_file_secret(value)
+    }
+
+    fn is_vault_file_secret(value: &str) -> bool {
+        value == "IN_VAULT_AND_FILE"
+    }
+
+    /// Name of the secret file a `IN_VAULT_AND_FILE` variable is projected onto
+    pub fn secret_file_name(key: &str) -> String {
+        key.to_lowercase().replace('_', "-")
     }
 
     fn template_secret_value(value: &str) -> Option<String> {
@@ -73,6 +95,15 @@ impl EnvVars {
         Ok(())
     }
 
+    // Variables with a value "IN_VAULT_AND_FILE", which need projecting as secret files.
+    pub fn vault_file_secrets(&self) -> BTreeSet<String> {
+        self.plain
+            .iter()
+            .filter(|(_, v)| EnvVars::is_vault_file_secret(v))
+            .map(|(k, _)| k.to_string())
+            .collect()
+    }
+
     // Remove variables with a value "IN_VAULT", mark them as a secret and return them.
     pub fn vault_secrets(&mut self) -> BTreeSet<String> {
         let mut plain = BTreeMap::new();
@@ -108,3 +139,59 @@ impl EnvVars {
         ts
     }
 }
+
+/// Name of a whole ConfigMap or Secret referenced from `envFrom`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EnvFromRef {
+    pub name: String,
+}
+
+/// Environment variables pulled in from a whole ConfigMap or Secret
+///
+/// Rendered in the kubernetes `envFrom` format; exactly one reference is set.
+///
+/// ```yaml
+/// envFrom:
+/// - configMapRef:
+///     name: webapp-config
+///   prefix: CONFIG_
+/// ```
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvFrom {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_map_ref: Option<EnvFromRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_ref: Option<EnvFromRef>,
+    /// Prefix prepended to every key pulled in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+impl EnvFrom {
+    /// ConfigMaps must be the service's own config or explicitly allowed in config
+    pub fn verify(&self, service: &str, allowed: &[String]) -> Result<()> {
+        match (&self.config_map_ref, &self.secret_ref) {
+            (Some(cm), None) => {
+                if cm.name != format!("{}-config", service) && !allowed.contains(&cm.name) {
+                    bail!(
+                        "envFrom ConfigMap {} for {} is neither its own config nor in allowedEnvFrom",
+                        cm.name,
+                        service
+                    );
+                }
+            }
+            (None, Some(_)) => {}
+            _ => bail!(
+                "envFrom entries for {} need exactly one of configMapRef or secretRef",
+                service
+            ),
+        }
+        if let Some(p) = &self.prefix {
+            if p != &p.to_uppercase() {
+                bail!("envFrom prefix needs to be uppercase, found: {}", p);
+            }
+        }
+        Ok(())
+    }
+}
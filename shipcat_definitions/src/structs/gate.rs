@@ -1,3 +1,4 @@
+use super::{Kong, Result};
 use std::ops::Not;
 
 /// Gate service configuration
@@ -14,4 +15,92 @@ pub struct Gate {
     /// Allow connection upgrade to websockets
     #[serde(default, skip_serializing_if = "Not::not")]
     pub websockets: bool,
+
+    /// Hostnames gate routes straight to this service
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+
+    /// Request limits enforced at the edge
+    ///
+    /// ```yaml
+    /// rate_limits:
+    ///   per_minute: 100
+    ///   per_hour: 1000
+    ///   policy: cluster
+    /// ```
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<GateRateLimits>,
+}
+
+/// Gate request limits, translated into a kong rate-limiting plugin
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct GateRateLimits {
+    pub per_minute: Option<u32>,
+    pub per_hour: Option<u32>,
+    /// Kong rate-limiting policy (`local`, `cluster` or `redis`)
+    #[serde(default = "default_policy")]
+    pub policy: String,
+}
+
+fn default_policy() -> String {
+    "cluster".into()
+}
+
+impl GateRateLimits {
+    /// Whether any limit is actually configured
+    pub fn is_set(&self) -> bool {
+        self.per_minute.is_some() || self.per_hour.is_some()
+    }
+}
+
+impl Gate {
+    /// Ensure hosts shared with kong apis route to the same upstream as gate
+    ///
+    /// Gate always routes to the service itself, so kong apis on the same host must too.
+    pub fn verify_hosts(&self, service: &str, namespace: &str, kongs: &[Kong]) -> Result<()> {
+        let upstream = format!("http://{}.{}.svc.cluster.local", service, namespace);
+        for k in kongs {
+            for h in k.hosts.iter().filter(|h| self.hosts.contains(h)) {
+                if k.upstream_url.trim_end_matches('/') != upstream {
+                    bail!(
+                        "Host {} is routed by gate to {} but by kong to {}",
+                        h,
+                        upstream,
+                        k.upstream_url
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Gate, Kong};
+
+    #[test]
+    fn gate_kong_host_conflict() {
+        let gate = Gate {
+            hosts: vec!["fake.example.com".into()],
+            ..Default::default()
+        };
+        let mut kong = Kong::default();
+        kong.hosts = vec!["fake.example.com".into()];
+        kong.upstream_url = "http://fake-ask.dev.svc.cluster.local".into();
+        assert!(gate.verify_hosts("fake-ask", "dev", &[kong.clone()]).is_ok());
+
+        kong.upstream_url = "http://other.dev.svc.cluster.local".into();
+        let err = gate.verify_hosts("fake-ask", "dev", &[kong.clone()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Host fake.example.com is routed by gate to http://fake-ask.dev.svc.cluster.local but by kong to \
+             http://other.dev.svc.cluster.local"
+        );
+
+        // disjoint hosts are fine
+        kong.hosts = vec!["other.example.com".into()];
+        assert!(gate.verify_hosts("fake-ask", "dev", &[kong]).is_ok());
+    }
 }
@@ -1,6 +1,6 @@
 use std::{collections::BTreeMap, ops::Not};
 
-use super::Authorization;
+use super::{Authorization, GateRateLimits};
 use crate::deserializers::comma_separated_string;
 
 /// Kong setup for a service
@@ -22,6 +22,12 @@ pub struct Kong {
     /// If left blank, this value will be generated with the service name instead of raftcat.
     pub upstream_url: String,
 
+    /// Weighted upstream that `upstream_url` points to
+    ///
+    /// Set internally from `upstream_targets` in the manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<KongUpstream>,
+
     /// Whether or not to apply the ip whitelisting (?)
     #[serde(skip_serializing_if = "Not::not")]
     pub internal: bool,
@@ -43,6 +49,8 @@ pub struct Kong {
     /// A comma-separated list of domain names that point to your API.
     ///
     /// For example: example.com. At least one of hosts, uris, or methods should be specified
+    ///
+    /// Manifests can use `{{ region }}` and `{{ env }}` in hosts, e.g. `api.{{ region }}.example.com`.
     #[serde(
         skip_serializing_if = "Vec::is_empty",
         deserialize_with = "comma_separated_string"
@@ -57,6 +65,20 @@ pub struct Kong {
     /// true => application has to listen on `/`, but use prefix agnostic urls everywhere.
     pub strip_uri: bool,
 
+    /// Hosts routed with their own `uris` or `strip_uri`
+    ///
+    /// Each route becomes a separate Kong API named `${name}-${route}`, otherwise identical.
+    /// `uris` and `strip_uri` default to the values of the main API.
+    ///
+    /// ```yaml
+    /// host_routes:
+    /// - name: internal
+    ///   hosts: [raftcat-internal]
+    ///   strip_uri: true
+    /// ```
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub host_routes: Vec<KongHostRoute>,
+
     /// Preserves host headers to backend service
     ///
     /// When matching an API via one of the hosts domain names, make sure the request
@@ -119,12 +141,81 @@ pub struct Kong {
 
     pub ip_rate_limits: Option<KongRateLimit>,
     pub user_rate_limits: Option<KongRateLimit>,
+
+    /// Rate limits from the service's gate config
+    ///
+    /// Set internally when generating kong config (discards value in manifest)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gate_rate_limits: Option<GateRateLimits>,
 }
 
 fn preserve_host_default() -> bool {
     true
 }
 
+/// A group of hosts needing different routing than the main hosts of a Kong API
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct KongHostRoute {
+    /// Suffix of the name of the Kong API generated for these hosts
+    pub name: String,
+    pub hosts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uris: Option<String>,
+    pub strip_uri: bool,
+}
+
+impl Kong {
+    /// Split the `host_routes` out into Kong APIs of their own
+    ///
+    /// The main API comes first, and always exists.
+    pub fn split_host_routes(mut self) -> Vec<Kong> {
+        let routes = std::mem::take(&mut self.host_routes);
+        let mut apis = Vec::with_capacity(routes.len() + 1);
+        for r in routes {
+            let mut api = self.clone();
+            api.name = format!("{}-{}", self.name, r.name);
+            api.hosts = r.hosts;
+            api.uris = r.uris;
+            api.strip_uri = r.strip_uri;
+            apis.push(api);
+        }
+        apis.insert(0, self);
+        apis
+    }
+}
+
+/// A Kong upstream balancing between weighted targets
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct KongUpstream {
+    /// Hostname used in the `upstream_url` of the API
+    pub name: String,
+    pub targets: Vec<KongUpstreamTarget>,
+}
+
+/// A `host:port` target of an upstream
+///
+/// ```yaml
+/// upstream_targets:
+/// - target: fake-ask.dev.svc.cluster.local:80
+///   weight: 90
+/// - target: fake-ask-canary.dev.svc.cluster.local:80
+///   weight: 10
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct KongUpstreamTarget {
+    pub target: String,
+    /// Share of traffic relative to the other targets, between 0 and 1000 (Kong defaults to 100)
+    #[serde(default = "upstream_target_weight_default")]
+    pub weight: u32,
+}
+
+fn upstream_target_weight_default() -> u32 {
+    100
+}
+
 /// Cors plugin data
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
@@ -5,7 +5,7 @@ use crate::{
     Region,
 };
 use serde::ser::{Serialize, SerializeMap, Serializer};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Kongfig structs
 /// https://github.com/mybuilder/kongfig
@@ -472,6 +472,7 @@ pub fn kongfig_apis(from: BTreeMap<String, Kong>, config: KongConfig, region: &R
             plugins.push(ApiPlugin::JwtValidator(PluginBase::removed()));
         }
 
+        let gate_limits = v.gate_rate_limits.filter(|g| g.is_set());
         plugins.push(ApiPlugin::RateLimiting(if let Some(limits) = v.ip_rate_limits {
             PluginBase::new(RateLimitingPluginConfig {
                 minute: limits.per_minute,
@@ -487,6 +488,21 @@ pub fn kongfig_apis(from: BTreeMap<String, Kong>, config: KongConfig, region: &R
                 redis_timeout: 2000,
                 redis_database: 0,
             })
+        } else if let Some(limits) = gate_limits {
+            PluginBase::new(RateLimitingPluginConfig {
+                minute: limits.per_minute,
+                hour: limits.per_hour,
+                day: None,
+                limit_by: "ip".to_string(),
+                policy: limits.policy,
+                fault_tolerant: true,
+                hide_client_headers: true,
+                redis_host: None,
+                redis_port: 6379,
+                redis_password: None,
+                redis_timeout: 2000,
+                redis_database: 0,
+            })
         } else {
             PluginBase::removed()
         }));
@@ -592,6 +608,61 @@ pub fn kongfig_consumers(k: KongConfig) -> Vec<Consumer> {
     consumers
 }
 
+/// Upstreams of the APIs balancing between weighted targets
+///
+/// Host routes share the upstream of their main API, so each upstream appears once.
+pub fn kongfig_upstreams(apis: &BTreeMap<String, Kong>) -> Vec<Upstream> {
+    let mut names = BTreeSet::new();
+    let mut upstreams = vec![];
+    for u in apis.values().filter_map(|k| k.upstream.as_ref()) {
+        if !names.insert(u.name.clone()) {
+            continue;
+        }
+        upstreams.push(Upstream {
+            name: u.name.clone(),
+            targets: u
+                .targets
+                .iter()
+                .map(|t| UpstreamTarget {
+                    target: t.target.clone(),
+                    attributes: UpstreamTargetAttributes { weight: t.weight },
+                })
+                .collect(),
+            attributes: UpstreamAttributes::default(),
+        });
+    }
+    upstreams
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Upstream {
+    pub name: String,
+    pub targets: Vec<UpstreamTarget>,
+    pub attributes: UpstreamAttributes,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UpstreamAttributes {
+    pub slots: u32,
+}
+
+impl Default for UpstreamAttributes {
+    fn default() -> Self {
+        UpstreamAttributes { slots: 1000 }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UpstreamTarget {
+    pub target: String,
+    pub attributes: UpstreamTargetAttributes,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UpstreamTargetAttributes {
+    pub weight: u32,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct Consumer {
     pub username: String,
@@ -616,8 +687,5 @@ pub struct JwtCredentialsAttributes {
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct Plugin {}
 
-#[derive(Serialize, Debug, Clone, Default)]
-pub struct Upstream {}
-
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct Certificate {}
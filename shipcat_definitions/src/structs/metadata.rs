@@ -5,7 +5,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use super::Result;
+use super::{NotificationChannels, NotificationEvent, Result};
 use crate::config::SlackParameters;
 
 /// Legacy contact data
@@ -186,6 +186,9 @@ pub struct Metadata {
     /// Notifications channel - automated messages
     #[serde(default)]
     pub notifications: Option<SlackChannel>,
+    /// Notification channels per event type - defaulting to notifications
+    #[serde(default, skip_serializing_if = "NotificationChannels::is_empty")]
+    pub channels: NotificationChannels,
     /// Runbook name in repo
     pub runbook: Option<String>,
     /// Description of the service
@@ -243,6 +246,11 @@ impl Metadata {
             format!("{}/commit/{}", self.repo, ver)
         }
     }
+
+    /// Channel to notify about an event, falling back to the notifications channel
+    pub fn channel_for(&self, event: NotificationEvent) -> Option<&SlackChannel> {
+        self.channels.get(event).or_else(|| self.notifications.as_ref())
+    }
 }
 
 impl Metadata {
@@ -292,6 +300,15 @@ impl Metadata {
         if let Some(channel) = &self.notifications {
             channel.verify()?;
         }
+        for e in &[
+            NotificationEvent::Deploy,
+            NotificationEvent::Alert,
+            NotificationEvent::Oncall,
+        ] {
+            if let Some(channel) = self.channels.get(*e) {
+                channel.verify()?;
+            }
+        }
 
         // Document field formats
         self.verify_optional_hyperlink(&self.ped, "ped")?;
@@ -322,6 +339,7 @@ impl Metadata {
 #[cfg(test)]
 mod tests {
     use super::{default_format_string, Metadata, SlackChannel};
+    use crate::structs::NotificationEvent;
     use crate::teams::{GithubTeams, Owners, SlackSet, Squad};
     use std::collections::{BTreeMap, BTreeSet};
 
@@ -356,6 +374,8 @@ mod tests {
                 support: Option::None,
                 notifications: Option::None,
                 alerts: Option::None,
+                deploys: Option::None,
+                oncall: Option::None,
             },
         });
         owners
@@ -379,6 +399,26 @@ mod tests {
         assert_eq!(ru, "prefix-0.1.2-suffix")
     }
 
+    #[test]
+    fn deploy_channel_falls_back_to_notifications() {
+        let mut md = default_metadata();
+        md.notifications = Some(SlackChannel::new("#robots"));
+        assert_eq!(
+            md.channel_for(NotificationEvent::Deploy),
+            Some(&SlackChannel::new("#robots"))
+        );
+
+        md.channels.deploy = Some(SlackChannel::new("#deploys"));
+        assert_eq!(
+            md.channel_for(NotificationEvent::Deploy),
+            Some(&SlackChannel::new("#deploys"))
+        );
+        assert_eq!(
+            md.channel_for(NotificationEvent::Alert),
+            Some(&SlackChannel::new("#robots"))
+        );
+    }
+
     #[test]
     fn valid_slack_channel() {
         let sc = SlackChannel::new("#dev-platform");
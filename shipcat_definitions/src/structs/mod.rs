@@ -21,14 +21,17 @@ pub use self::worker::Worker;
 
 /// Kong configs
 pub mod kong;
-pub use self::kong::{Authentication, BabylonAuthHeader, Cors, Kong, KongRateLimit};
+pub use self::kong::{
+    Authentication, BabylonAuthHeader, Cors, Kong, KongHostRoute, KongRateLimit, KongUpstream,
+    KongUpstreamTarget,
+};
 
 pub mod authorization;
 pub use self::authorization::Authorization;
 
 /// Gate configs
 pub mod gate;
-pub use self::gate::Gate;
+pub use self::gate::{Gate, GateRateLimits};
 
 /// Kongfig configs
 pub mod kongfig;
@@ -42,14 +45,14 @@ pub use self::kafka::Kafka;
 
 // abstractions - these have special handling
 /// Templated configmap abstractions
-mod configmap;
+pub mod configmap;
 pub use self::configmap::{ConfigMap, ConfigMappedFile};
 /// Healthcheck abstraction
 mod healthcheck;
 pub use self::healthcheck::HealthCheck;
 
 mod env;
-pub use self::env::EnvVars;
+pub use self::env::{EnvFrom, EnvFromRef, EnvVars};
 
 // translations - these are typically inlined in templates as yaml
 /// Kubernetes resource structs
@@ -63,16 +66,28 @@ mod hostalias;
 pub use self::hostalias::HostAlias;
 /// Kubernetes health check probes
 mod probes;
-pub use self::probes::Probe;
+pub use self::probes::{GrpcProbe, Probe};
 /// Kubernetes rolling-update settings
 pub mod rollingupdate;
 pub use self::rollingupdate::RollingUpdate;
+/// Canary deployments
+mod canary;
+pub use self::canary::Canary;
 /// Kubernetes horizontal pod autoscaler
 pub mod autoscaling;
+/// Kubernetes pod disruption budgets
+mod disruption;
+pub use self::disruption::{AvailabilityPolicy, DisruptionBudget};
 /// Kubernetes container lifecycle events
 mod lifecycle;
 /// Kuberneter tolerations
 pub mod tolerations;
+/// Kubernetes topology spread constraints
+mod topologyspread;
+pub use self::topologyspread::{TopologySpreadConstraint, WhenUnsatisfiable};
+/// Kubernetes network policies
+mod networkpolicy;
+pub use self::networkpolicy::NetworkPolicy;
 pub use self::lifecycle::{LifeCycle, LifeCycleHandler};
 
 pub mod metadata;
@@ -111,7 +126,7 @@ pub mod newrelic;
 pub mod sentry;
 
 mod notifications;
-pub use notifications::NotificationMode;
+pub use notifications::{NotificationChannels, NotificationEvent, NotificationMode};
 
 // EventStreams / Kafka related struct
 mod eventstream;
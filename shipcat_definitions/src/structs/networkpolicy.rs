@@ -0,0 +1,41 @@
+use k8s_openapi::api::networking::v1::NetworkPolicyPeer;
+use std::{collections::BTreeMap, ops::Not};
+
+/// Kubernetes NetworkPolicy restricting ingress to the pods of a service
+///
+/// Opt-in; ingress is allowed from the listed peers, and optionally from `sourceRanges`.
+///
+/// ```yaml
+/// networkPolicy:
+///   allowSourceRanges: true
+///   from:
+///   - namespaceSelector:
+///       matchLabels:
+///         name: kong
+///   - podSelector:
+///       matchLabels:
+///         app: fake-storage
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct NetworkPolicy {
+    /// Also allow ingress from the CIDRs in `sourceRanges`
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub allowSourceRanges: bool,
+
+    /// Pods and namespaces allowed ingress
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub from: Vec<NetworkPolicyPeer>,
+
+    /// CIDRs allowed ingress
+    ///
+    /// Set internally from `sourceRanges` (discards value in manifest)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cidrs: Vec<String>,
+
+    /// Labels of the pods the policy applies to
+    ///
+    /// Set internally (discards value in manifest)
+    #[serde(default)]
+    pub podSelector: BTreeMap<String, String>,
+}
@@ -1,3 +1,5 @@
+use super::SlackChannel;
+
 /// Modes for slack upgrade notifications in this region
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum NotificationMode {
@@ -15,3 +17,41 @@ impl Default for NotificationMode {
         Self::NotifyMaintainers
     }
 }
+
+/// Kinds of events that produce slack notifications
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationEvent {
+    /// Upgrades and deletions
+    Deploy,
+    /// Failures that need attention
+    Alert,
+    /// Incidents that need a human right now
+    Oncall,
+}
+
+/// Slack channels per notification event
+///
+/// Unset channels fall back to the team's channel, then to `metadata.notifications`.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct NotificationChannels {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<SlackChannel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert: Option<SlackChannel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oncall: Option<SlackChannel>,
+}
+
+impl NotificationChannels {
+    pub fn is_empty(&self) -> bool {
+        self.deploy.is_none() && self.alert.is_none() && self.oncall.is_none()
+    }
+
+    pub fn get(&self, event: NotificationEvent) -> Option<&SlackChannel> {
+        match event {
+            NotificationEvent::Deploy => self.deploy.as_ref(),
+            NotificationEvent::Alert => self.alert.as_ref(),
+            NotificationEvent::Oncall => self.oncall.as_ref(),
+        }
+    }
+}
@@ -36,6 +36,19 @@ pub struct TcpSocket {
     pub port: String,
 }
 
+/// Native kubernetes gRPC health check
+///
+/// Queries the standard `grpc.health.v1.Health` service on a container port.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct GrpcProbe {
+    /// Port number the gRPC server listens on
+    pub port: u32,
+    /// Service name to check (the server's overall health if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
 /// Liveness or readiness Probe
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
@@ -52,6 +65,10 @@ pub struct Probe {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     tcpSocket: Option<TcpSocket>,
 
+    /// gRPC health probe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grpc: Option<GrpcProbe>,
+
     /// How long to wait before kube performs first probe
     #[serde(default = "initial_delay_seconds_default")]
     pub initialDelaySeconds: u32,
@@ -93,12 +110,36 @@ fn timeout_seconds_default() -> u32 {
 }
 
 impl Probe {
+    /// Named or numeric port targeted by an `httpGet` or `tcpSocket` probe
+    pub fn target_port(&self) -> Option<&str> {
+        self.httpGet
+            .as_ref()
+            .map(|h| h.port.as_str())
+            .or_else(|| self.tcpSocket.as_ref().map(|t| t.port.as_str()))
+    }
+
+    /// Port targeted by a `grpc` probe
+    pub fn grpc_port(&self) -> Option<u32> {
+        self.grpc.as_ref().map(|g| g.port)
+    }
+
     pub fn verify(&self) -> Result<()> {
+        if self.httpGet.is_some() && self.grpc.is_some() {
+            bail!("Probe cannot define both 'httpGet' and 'grpc'");
+        }
         if self.httpGet.is_some() && (self.exec.is_some() || self.tcpSocket.is_some()) {
             bail!("Probe needs to have at most one of 'httpGet' or 'exec'");
         }
-        if self.httpGet.is_none() && self.exec.is_none() && self.tcpSocket.is_none() {
-            bail!("Probe needs to define one of 'httpGet', 'exec', 'tcpSocket");
+        if self.grpc.is_some() && (self.exec.is_some() || self.tcpSocket.is_some()) {
+            bail!("Probe needs to have at most one of 'grpc', 'exec' or 'tcpSocket'");
+        }
+        if self.httpGet.is_none() && self.exec.is_none() && self.tcpSocket.is_none() && self.grpc.is_none() {
+            bail!("Probe needs to define one of 'httpGet', 'exec', 'tcpSocket', 'grpc'");
+        }
+        if let Some(g) = &self.grpc {
+            if g.port == 0 || g.port > 65535 {
+                bail!("Probe grpc port {} is not a valid port", g.port);
+            }
         }
         Ok(())
     }
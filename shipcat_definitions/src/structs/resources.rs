@@ -47,6 +47,21 @@ impl ResourceRequirements<String> {
     }
 }
 
+impl ResourceRequirements<String> {
+    /// Scale requests and limits, rounding up to whole millicores and mebibytes
+    pub fn scaled(&self, factor: f64) -> Result<ResourceRequirements<String>> {
+        let n = self.normalised()?;
+        let scale = |r: &Resources<f64>| Resources {
+            cpu: format!("{}m", (r.cpu * factor * 1000.0).ceil()),
+            memory: format!("{}Mi", (r.memory * factor / (1024.0 * 1024.0)).ceil()),
+        };
+        Ok(ResourceRequirements {
+            requests: scale(&n.requests),
+            limits: scale(&n.limits),
+        })
+    }
+}
+
 // For aggregation of resource use, implement addition on normalised versions
 impl Add for ResourceRequirements<f64> {
     type Output = ResourceRequirements<f64>;
@@ -1,15 +1,94 @@
-/// Security context for ownership of volumes
+/// Security context for the pods and main container of a workload
 ///
-/// Verbatim from [kubernetes SecurityContext](https://kubernetes.io/docs/tasks/configure-pod-container/security-context/#configure-volume-permission-and-ownership-change-policy-for-pods)
+/// Verbatim from [kubernetes SecurityContext](https://kubernetes.io/docs/tasks/configure-pod-container/security-context/#configure-volume-permission-and-ownership-change-policy-for-pods).
+/// `readOnlyRootFilesystem` and `allowPrivilegeEscalation` apply to the main container,
+/// everything else to the pod.
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 #[serde(default)]
 pub struct SecurityContext {
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    runAsUser: Option<u32>,
+    pub runAsUser: Option<u32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    runAsGroup: Option<u32>,
+    pub runAsGroup: Option<u32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    fsGroup: Option<u32>,
+    pub runAsNonRoot: Option<bool>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    fsGroupChangePolicy: Option<String>,
+    pub fsGroup: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fsGroupChangePolicy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readOnlyRootFilesystem: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowPrivilegeEscalation: Option<bool>,
+}
+
+impl SecurityContext {
+    /// Fill unset options from a baseline
+    ///
+    /// Also returns the names of the options set to something other than the baseline.
+    pub fn with_baseline(self, baseline: &SecurityContext) -> (SecurityContext, Vec<&'static str>) {
+        let mut overridden = vec![];
+        let b = baseline.clone();
+        let sc = SecurityContext {
+            runAsUser: inherit(self.runAsUser, b.runAsUser, "runAsUser", &mut overridden),
+            runAsGroup: inherit(self.runAsGroup, b.runAsGroup, "runAsGroup", &mut overridden),
+            runAsNonRoot: inherit(self.runAsNonRoot, b.runAsNonRoot, "runAsNonRoot", &mut overridden),
+            fsGroup: inherit(self.fsGroup, b.fsGroup, "fsGroup", &mut overridden),
+            fsGroupChangePolicy: inherit(
+                self.fsGroupChangePolicy,
+                b.fsGroupChangePolicy,
+                "fsGroupChangePolicy",
+                &mut overridden,
+            ),
+            readOnlyRootFilesystem: inherit(
+                self.readOnlyRootFilesystem,
+                b.readOnlyRootFilesystem,
+                "readOnlyRootFilesystem",
+                &mut overridden,
+            ),
+            allowPrivilegeEscalation: inherit(
+                self.allowPrivilegeEscalation,
+                b.allowPrivilegeEscalation,
+                "allowPrivilegeEscalation",
+                &mut overridden,
+            ),
+        };
+        (sc, overridden)
+    }
+}
+
+fn inherit<T: PartialEq>(
+    own: Option<T>,
+    baseline: Option<T>,
+    name: &'static str,
+    overridden: &mut Vec<&'static str>,
+) -> Option<T> {
+    match (own, baseline) {
+        (Some(o), Some(b)) if o != b => {
+            overridden.push(name);
+            Some(o)
+        }
+        (own, baseline) => own.or(baseline),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecurityContext;
+
+    #[test]
+    fn security_baseline() {
+        let baseline: SecurityContext = serde_yaml::from_str("runAsNonRoot: true\nfsGroup: 1000").unwrap();
+        let own: SecurityContext = serde_yaml::from_str("runAsUser: 1000\nfsGroup: 1000").unwrap();
+        let (sc, overridden) = own.with_baseline(&baseline);
+        assert!(overridden.is_empty());
+        assert_eq!(sc.runAsNonRoot, Some(true));
+        assert_eq!(sc.runAsUser, Some(1000));
+
+        let optout: SecurityContext = serde_yaml::from_str("runAsNonRoot: false").unwrap();
+        let (sc, overridden) = optout.with_baseline(&baseline);
+        assert_eq!(overridden, vec!["runAsNonRoot"]);
+        assert_eq!(sc.runAsNonRoot, Some(false));
+        assert_eq!(sc.fsGroup, Some(1000));
+    }
 }
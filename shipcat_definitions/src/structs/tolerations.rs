@@ -25,7 +25,7 @@ impl Default for Effect {
 pub struct Tolerations {
     /// What key does the toleration apply to?
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    key: Option<String>,
+    pub key: Option<String>,
     /// Operator (Exists / Equal)
     pub operator: Operator,
     /// Value to match against (if Operator::Equal)
@@ -0,0 +1,90 @@
+use super::Result;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use std::collections::BTreeMap;
+
+/// What the scheduler does with a pod that would break a spread constraint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WhenUnsatisfiable {
+    DoNotSchedule,
+    ScheduleAnyway,
+}
+impl Default for WhenUnsatisfiable {
+    fn default() -> Self {
+        WhenUnsatisfiable::DoNotSchedule
+    }
+}
+
+/// Kubernetes topology spread constraint for the pods of a service
+///
+/// Straight from [pod topology spread constraints](https://kubernetes.io/docs/concepts/workloads/pods/pod-topology-spread-constraints/).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopologySpreadConstraint {
+    /// Maximum allowed difference in pod counts between two topology domains
+    pub maxSkew: u32,
+    /// Node label defining the topology domains, e.g. `topology.kubernetes.io/zone`
+    pub topologyKey: String,
+    /// Whether to still schedule pods that cannot satisfy the constraint
+    #[serde(default)]
+    pub whenUnsatisfiable: WhenUnsatisfiable,
+    /// Pods counted when spreading (defaults to the pods of the service)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labelSelector: Option<LabelSelector>,
+}
+
+impl TopologySpreadConstraint {
+    pub fn verify(&self) -> Result<()> {
+        if self.maxSkew < 1 {
+            bail!("topologySpreadConstraints.maxSkew must be at least 1");
+        }
+        if self.topologyKey.trim().is_empty() {
+            bail!("topologySpreadConstraints.topologyKey must be set");
+        }
+        Ok(())
+    }
+
+    /// Select the pods of the service `app` when no selector was given
+    pub fn with_default_selector(mut self, app: &str) -> Self {
+        if self.labelSelector.is_none() {
+            let mut labels = BTreeMap::new();
+            labels.insert("app".to_string(), app.to_string());
+            self.labelSelector = Some(LabelSelector {
+                match_labels: Some(labels),
+                ..Default::default()
+            });
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TopologySpreadConstraint, WhenUnsatisfiable};
+
+    #[test]
+    fn topology_spread_constraints() {
+        let zone: TopologySpreadConstraint =
+            serde_yaml::from_str("maxSkew: 1\ntopologyKey: topology.kubernetes.io/zone").unwrap();
+        assert!(zone.verify().is_ok());
+        assert_eq!(zone.whenUnsatisfiable, WhenUnsatisfiable::DoNotSchedule);
+        let zone = zone.with_default_selector("fake-ask");
+        let labels = zone.labelSelector.unwrap().match_labels.unwrap();
+        assert_eq!(labels["app"], "fake-ask");
+
+        let custom: TopologySpreadConstraint = serde_yaml::from_str(
+            "maxSkew: 2\ntopologyKey: kubernetes.io/hostname\nlabelSelector:\n  matchLabels:\n    tier: web",
+        )
+        .unwrap();
+        let labels = custom
+            .with_default_selector("fake-ask")
+            .labelSelector
+            .unwrap()
+            .match_labels;
+        assert_eq!(labels.unwrap()["tier"], "web");
+
+        let noskew: TopologySpreadConstraint =
+            serde_yaml::from_str("maxSkew: 0\ntopologyKey: kubernetes.io/hostname").unwrap();
+        assert!(noskew.verify().is_err());
+        let nokey: TopologySpreadConstraint = serde_yaml::from_str("maxSkew: 1\ntopologyKey: ''").unwrap();
+        assert!(nokey.verify().is_err());
+    }
+}
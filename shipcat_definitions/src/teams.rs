@@ -1,5 +1,6 @@
 use super::Result;
 use crate::structs::SlackChannel;
+use merge::Merge;
 use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
@@ -52,6 +53,7 @@ pub struct Tribe {
 /// Contains all data from all 4 folders in a EWOK_TEAMS_DIR
 /// All entries are sorted by filename (.name properties)
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Owners {
     /// All people in people/{key}.toml
     pub people: BTreeMap<String, Person>,
@@ -61,6 +63,16 @@ pub struct Owners {
     pub tribes: BTreeMap<String, Tribe>,
 }
 
+impl Merge for Owners {
+    fn merge(self, other: Self) -> Self {
+        Owners {
+            people: Merge::merge(self.people, other.people),
+            squads: Merge::merge(self.squads, other.squads),
+            tribes: Merge::merge(self.tribes, other.tribes),
+        }
+    }
+}
+
 impl Owners {
     /// Read a config in pwd and leave placeholders
     pub fn read() -> Result<Owners> {
@@ -68,6 +80,40 @@ impl Owners {
         Owners::read_from(&pwd.to_path_buf())
     }
 
+    /// Problems with the references between people, squads and tribes
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for (key, p) in &self.people {
+            if key != &p.name {
+                problems.push(format!("person {} has a different .name {}", key, p.name));
+            }
+        }
+        for (key, sq) in &self.squads {
+            if key != &sq.name {
+                problems.push(format!("squad {} has a different .name {}", key, sq.name));
+            }
+            for m in sq.members.iter().chain(&sq.owners) {
+                if !self.people.contains_key(m) {
+                    problems.push(format!("squad {} references undefined person {}", key, m));
+                }
+            }
+            if sq.slack.support.is_none() && sq.slack.internal.is_none() {
+                problems.push(format!("squad {} needs a support or internal slack channel", key));
+            }
+        }
+        for (key, tr) in &self.tribes {
+            if key != &tr.name {
+                problems.push(format!("tribe {} has a different .name {}", key, tr.name));
+            }
+            for sq in &tr.squads {
+                if !self.squads.contains_key(sq) {
+                    problems.push(format!("tribe {} references undefined squad {}", key, sq));
+                }
+            }
+        }
+        problems
+    }
+
     pub fn read_from(pwd: &PathBuf) -> Result<Owners> {
         use std::fs;
         let mpath = pwd.join("teams.yml");
@@ -107,6 +153,12 @@ pub struct SlackSet {
     ///
     /// Test failures in prod, production issues,
     pub alerts: Option<SlackChannel>,
+    /// A slack channel for robots (deploy notifications)
+    ///
+    /// Upgrades and deletions, when they should not go to notifications.
+    pub deploys: Option<SlackChannel>,
+    /// A slack channel for robots (incidents)
+    pub oncall: Option<SlackChannel>,
 }
 
 /// A set of github teams
@@ -117,3 +169,35 @@ pub struct GithubTeams {
     /// Team on github with elevated permissions. Lowercase, dash-separated form.
     pub admins: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Owners;
+
+    #[test]
+    fn owners_cross_references() {
+        let owners: Owners = serde_yaml::from_str(
+            r#"
+people:
+  jane.doe: {name: jane.doe, slack: U1, email: jane.doe@example.com}
+squads:
+  platform:
+    name: platform
+    members: [jane.doe, john.doe]
+    github: {team: platform}
+    slack: {notifications: C1}
+tribes:
+  core: {name: core, squads: [platform, security]}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            owners.check(),
+            vec![
+                "squad platform references undefined person john.doe",
+                "squad platform needs a support or internal slack channel",
+                "tribe core references undefined squad security",
+            ]
+        );
+    }
+}
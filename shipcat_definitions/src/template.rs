@@ -1,6 +1,7 @@
-use std::{collections::HashMap, iter};
+use std::{collections::HashMap, iter, path::Path};
 
 use super::{ErrorKind, Result, ResultExt};
+use regex::Regex;
 use tera::{self, try_get_value, Context, Tera, Value};
 
 #[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
@@ -28,13 +29,60 @@ fn as_secret(v: Value, _: HashMap<String, Value>) -> tera::Result<Value> {
     Ok(format!("SHIPCAT_SECRET::{}", s).into())
 }
 
+/// Read shared fragments from {root}/templates/partials
+///
+/// These are registered as `partials/{filename}` for use with `{% include %}`.
+#[cfg(feature = "filesystem")]
+fn read_partials(root: &Path) -> Result<Vec<(String, String)>> {
+    use std::fs;
+    let dir = root.join("templates").join("partials");
+    let mut res = vec![];
+    if !dir.is_dir() {
+        return Ok(res);
+    }
+    for entry in fs::read_dir(&dir)? {
+        let pth = entry?.path();
+        if pth.is_file() {
+            let name = pth.file_name().unwrap().to_string_lossy();
+            res.push((format!("partials/{}", name), fs::read_to_string(&pth)?));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+#[cfg(not(feature = "filesystem"))]
+fn read_partials(_root: &Path) -> Result<Vec<(String, String)>> {
+    Ok(vec![])
+}
+
+/// Ensure every `{% include %}` in a template refers to a known partial
+fn verify_includes(data: &str, partials: &[(String, String)]) -> Result<()> {
+    let include_re = Regex::new(r#"\{%-?\s*include\s+"([^"]+)"\s*-?%\}"#).unwrap();
+    for cap in include_re.captures_iter(data) {
+        let name = &cap[1];
+        if !partials.iter().any(|(p, _)| p == name) {
+            bail!("Included template {} not found in templates/partials", name);
+        }
+    }
+    Ok(())
+}
+
 /// Render convenience function that also trims whitespace
 ///
 /// Takes a template to render either in the service folder or the templates folder.
 /// The first takes precendense if it exists.
-pub fn render_file_data(data: String, context: &Context) -> Result<String> {
+/// Shared partials in {root}/templates/partials can be included by the template.
+pub fn render_file_data(data: String, context: &Context, root: &Path) -> Result<String> {
+    let partials = read_partials(root)?;
+    verify_includes(&data, &partials)?;
     let mut tera = Tera::default();
-    tera.add_raw_template("one_off", &data)?;
+    tera.add_raw_templates(
+        partials
+            .iter()
+            .map(|(n, p)| (n.as_str(), p.as_str()))
+            .chain(iter::once(("one_off", data.as_str())))
+            .collect(),
+    )?;
     tera.autoescape_on(vec!["html"]);
     tera.register_filter("indent", indent);
     tera.register_filter("as_secret", as_secret);
@@ -63,8 +111,13 @@ pub fn one_off(tpl: &str, ctx: &Context) -> Result<String> {
 }
 
 // main helpers for the manifest
-use super::{Manifest, Region};
+use super::{manifest::secret_files_checksum, Manifest, Region};
 impl Manifest {
+    /// Where templates of this manifest include partials from
+    fn template_root(&self) -> &Path {
+        self.templateRoot.as_deref().unwrap_or_else(|| Path::new("."))
+    }
+
     // This function defines what variables are available within .j2 templates and evars
     fn make_template_context(&self, reg: &Region) -> Result<Context> {
         // same context as normal templates + base_urls
@@ -76,6 +129,7 @@ impl Manifest {
         full_env.append(&mut self.secrets.clone());
 
         ctx.insert("env", &full_env);
+        ctx.insert("env_from", &self.envFrom);
         ctx.insert("service", &self.name.clone());
         ctx.insert("environment", &reg.environment.to_string());
         ctx.insert("region", &reg.name.clone());
@@ -84,18 +138,21 @@ impl Manifest {
         ctx.insert("kong", &reg.kong);
         ctx.insert("cluster", &reg.cluster.clone());
         ctx.insert("namespace", &reg.namespace.clone());
+        ctx.insert("features", &self.features);
+        ctx.insert("network_policy", &self.networkPolicy);
         Ok(ctx)
     }
 
     /// Replace template in values with template result inplace
     pub fn template_configs(&mut self, reg: &Region) -> Result<()> {
         let ctx = self.make_template_context(reg)?;
-        if let Some(ref mut cfg) = self.configs {
+        let root = self.template_root().to_path_buf();
+        for cfg in &mut self.configs {
             for f in &mut cfg.files {
                 if let Some(ref mut v) = f.value {
                     let data: String = v.clone();
                     let svc = self.name.clone();
-                    *v = render_file_data(data, &ctx).chain_err(|| ErrorKind::InvalidTemplate(svc))?;
+                    *v = render_file_data(data, &ctx, &root).chain_err(|| ErrorKind::InvalidTemplate(svc))?;
                 } else {
                     bail!("configs must be read first - missing {}", f.name); // internal error
                 }
@@ -104,6 +161,47 @@ impl Manifest {
         Ok(())
     }
 
+    /// Render opted in `secretFiles` as templates inplace
+    ///
+    /// Must happen after secrets are resolved, as vault holds the (base64) templates.
+    pub fn template_secret_files(&mut self, reg: &Region) -> Result<()> {
+        if self.templatedSecretFiles.is_empty() {
+            return Ok(());
+        }
+        let ctx = self.make_template_context(reg)?;
+        let root = self.template_root().to_path_buf();
+        for name in &self.templatedSecretFiles {
+            let v = match self.secretFiles.get_mut(name) {
+                Some(v) => v,
+                None => bail!("templatedSecretFiles entry {} is not a secretFile", name),
+            };
+            let raw = base64::decode(v.as_bytes()).ok();
+            let data = match raw.and_then(|r| String::from_utf8(r).ok()) {
+                Some(d) => d,
+                None => bail!("Secret {} is not base64 encoded text", name),
+            };
+            let svc = self.name.clone();
+            let res = render_file_data(data, &ctx, &root).chain_err(|| ErrorKind::InvalidTemplate(svc))?;
+            *v = base64::encode(&res);
+        }
+        self.secretFilesChecksum = secret_files_checksum(&self.secretFiles);
+        Ok(())
+    }
+
+    /// Render any template file with the context available to `configs` templates
+    ///
+    /// For developing templates against a service; the manifest is left untouched.
+    #[cfg(feature = "filesystem")]
+    pub async fn render_template_file(&self, reg: &Region, pth: &std::path::Path) -> Result<String> {
+        if !pth.is_file() {
+            bail!("Template file {} does not exist", pth.display());
+        }
+        let data = tokio::fs::read_to_string(pth).await?;
+        let ctx = self.make_template_context(reg)?;
+        render_file_data(data, &ctx, self.template_root())
+            .chain_err(|| ErrorKind::InvalidTemplate(self.name.clone()))
+    }
+
     /// Template evars - must happen before inline templates!
     pub fn template_evars(&mut self, reg: &Region) -> Result<()> {
         let ctx = self.make_template_context(reg)?;
@@ -155,8 +253,54 @@ impl VaultConfig {
         } else {
             read_arbitrary_template_file("vault", "team-policy.hcl").await?
         };
-        let res =
-            render_file_data(tpl, &ctx).chain_err(|| ErrorKind::InvalidTemplate("vault-template".into()))?;
+        let res = render_file_data(tpl, &ctx, Path::new("."))
+            .chain_err(|| ErrorKind::InvalidTemplate("vault-template".into()))?;
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Manifest, Region};
+
+    #[test]
+    fn templated_secret_files() {
+        let reg = Region {
+            name: "dev-uk".into(),
+            ..Default::default()
+        };
+        let mut mf = Manifest::test("fake-ask");
+        let files = &mut mf.secretFiles;
+        files.insert("app-conf".into(), base64::encode("region: {{ region }}"));
+        files.insert("literal".into(), base64::encode("{{ region }}"));
+        mf.templatedSecretFiles = vec!["app-conf".into()];
+        mf.template_secret_files(&reg).unwrap();
+        assert_eq!(mf.secretFiles["app-conf"], base64::encode("region: dev-uk"));
+        assert_eq!(mf.secretFiles["literal"], base64::encode("{{ region }}"));
+        assert!(mf.secretFilesChecksum.is_some());
+
+        let broken = base64::encode("{{ nope }}");
+        mf.secretFiles.insert("broken".into(), broken);
+        mf.templatedSecretFiles = vec!["broken".into()];
+        assert!(mf.template_secret_files(&reg).is_err());
+    }
+
+    #[cfg(feature = "filesystem")]
+    #[tokio::test]
+    async fn render_template_file() {
+        let reg = Region {
+            name: "dev-uk".into(),
+            namespace: "dev".into(),
+            ..Default::default()
+        };
+        let mf = Manifest::test("fake-ask");
+        let pth = std::env::temp_dir().join("shipcat-render-template-file.yaml.j2");
+        std::fs::write(&pth, "svc: {{ service }}.{{ namespace }}\nregion: {{ region }}").unwrap();
+        let out = mf.render_template_file(&reg, &pth).await.unwrap();
+        assert_eq!(out, "svc: fake-ask.dev\nregion: dev-uk");
+        std::fs::remove_file(&pth).unwrap();
+
+        let err = mf.render_template_file(&reg, &pth).await.unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use super::{Error, ErrorKind};
+
+/// A manifest build failure attributed to a service, region and field
+///
+/// The field path is built up innermost first while the error propagates,
+/// e.g. `overrides.resources.limits.memory`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationError {
+    pub service: String,
+    pub region: String,
+    /// Field path segments, outermost first
+    pub path: Vec<String>,
+    pub message: String,
+}
+
+impl ValidationError {
+    /// Wrap an arbitrary error, keeping any validation context already attached
+    pub fn from_error(e: Error) -> Self {
+        match e.kind() {
+            ErrorKind::Validation(v) => v.clone(),
+            _ => ValidationError {
+                message: e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": "),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Nest the error under a parent field (dotted names are allowed)
+    pub fn within(mut self, field: &str) -> Self {
+        self.path.insert(0, field.to_string());
+        self
+    }
+
+    /// Attribute the error to a service in a region
+    pub fn attribute(mut self, service: &str, region: &str) -> Self {
+        self.service = service.to_string();
+        self.region = region.to_string();
+        self
+    }
+
+    /// Dotted field path, e.g. `overrides.resources.limits.memory`
+    pub fn field_path(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.service.is_empty() {
+            write!(f, "{} in {}: ", self.service, self.region)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.field_path())?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<ValidationError> for ErrorKind {
+    fn from(e: ValidationError) -> Self {
+        ErrorKind::Validation(e)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        ErrorKind::Validation(e).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationError;
+    use crate::{Error, ErrorKind, Result};
+
+    fn memory() -> Result<()> {
+        bail!("memory is required")
+    }
+
+    #[test]
+    fn nests_field_paths() {
+        let e = ValidationError::from_error(memory().unwrap_err())
+            .within("memory")
+            .within("limits");
+        let e: Error = e.within("overrides.resources").into();
+        let v = ValidationError::from_error(e).attribute("fake-svc", "dev-uk");
+        assert_eq!(v.field_path(), "overrides.resources.limits.memory");
+        assert_eq!(
+            v.to_string(),
+            "fake-svc in dev-uk: overrides.resources.limits.memory: memory is required"
+        );
+        match Error::from(v).kind() {
+            ErrorKind::Validation(_) => {}
+            k => panic!("unexpected error kind {:?}", k),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use shipcat_definitions::Result;
+use std::collections::BTreeMap;
+
+use crate::util::{Build, RelaxedString};
+
+/// Pod annotation telling the istio injector whether to add its sidecar
+const MESH_INJECT_ANNOTATION: &str = "sidecar.istio.io/inject";
+
+/// Build pod annotations, opting out of mesh sidecar injection with `meshInject: false`
+///
+/// Only the injection annotation is set, other annotations are kept as given.
+pub fn build_pod_annotations(
+    annotations: BTreeMap<String, RelaxedString>,
+    mesh_inject: Option<bool>,
+) -> Result<BTreeMap<String, String>> {
+    let mut res = annotations.build(&())?;
+    if let Some(false) = mesh_inject {
+        res.insert(MESH_INJECT_ANNOTATION.into(), "false".into());
+    }
+    Ok(res)
+}
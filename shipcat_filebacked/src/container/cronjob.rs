@@ -8,7 +8,10 @@ use shipcat_definitions::{
 use crate::util::{Build, RelaxedString, Require};
 use std::collections::BTreeMap;
 
-use super::source::{ContainerBuildParams, ContainerSource};
+use super::{
+    annotations::build_pod_annotations,
+    source::{ContainerBuildParams, ContainerSource},
+};
 
 #[derive(Deserialize, Merge, Clone, Default)]
 #[serde(default, rename_all = "camelCase")]
@@ -18,6 +21,7 @@ pub struct CronJobSource {
     pub timeout: Option<u32>,
     pub backoff_limit: Option<u16>,
     pub pod_annotations: BTreeMap<String, RelaxedString>,
+    pub mesh_inject: Option<bool>,
 
     #[serde(flatten)]
     pub container: ContainerSource,
@@ -31,13 +35,201 @@ impl Build<CronJob, ContainerBuildParams> for CronJobSource {
             (None, Some(_)) => bail!("Cannot specify the version without specifying an image in CronJob"),
             (_, _) => (),
         };
+        let schedule = self.schedule.require("schedule")?;
+        if let Err(e) = verify_schedule(&schedule) {
+            bail!(
+                "Invalid schedule {:?} for cron job {}: {}",
+                schedule,
+                container.name,
+                e
+            );
+        }
         Ok(CronJob {
             container,
-            schedule: self.schedule.require("schedule")?,
+            schedule,
             volumeClaim: self.volume_claim,
             timeout: self.timeout,
             backoffLimit: self.backoff_limit,
-            podAnnotations: self.pod_annotations.build(&())?,
+            podAnnotations: build_pod_annotations(self.pod_annotations, self.mesh_inject)?,
         })
     }
 }
+
+/// Macros kubernetes accepts in place of the five schedule fields
+const SCHEDULE_MACROS: &[&str] = &[
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+/// One of the five fields of a cron schedule
+struct ScheduleField {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    /// Names accepted for the values, starting at `min`
+    names: &'static [&'static str],
+}
+
+const SCHEDULE_FIELDS: &[ScheduleField] = &[
+    ScheduleField {
+        name: "minute",
+        min: 0,
+        max: 59,
+        names: &[],
+    },
+    ScheduleField {
+        name: "hour",
+        min: 0,
+        max: 23,
+        names: &[],
+    },
+    ScheduleField {
+        name: "day of month",
+        min: 1,
+        max: 31,
+        names: &[],
+    },
+    ScheduleField {
+        name: "month",
+        min: 1,
+        max: 12,
+        names: &[
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ],
+    },
+    ScheduleField {
+        name: "day of week",
+        min: 0,
+        max: 6,
+        names: &["sun", "mon", "tue", "wed", "thu", "fri", "sat"],
+    },
+];
+
+/// Check a schedule in the standard 5 field cron syntax, or a macro like `@daily`
+fn verify_schedule(schedule: &str) -> std::result::Result<(), String> {
+    let schedule = schedule.trim();
+    if schedule.starts_with('@') {
+        if SCHEDULE_MACROS.contains(&schedule) {
+            return Ok(());
+        }
+        return Err(format!("unknown macro {}", schedule));
+    }
+    let exprs = schedule.split_whitespace().collect::<Vec<_>>();
+    if exprs.len() != SCHEDULE_FIELDS.len() {
+        return Err(format!("expected 5 fields, found {}", exprs.len()));
+    }
+    for (expr, field) in exprs.iter().zip(SCHEDULE_FIELDS) {
+        for part in expr.split(',') {
+            verify_schedule_part(part, field)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a single list entry of a field: `*`, `v`, or `a-b`, optionally with a `/step`
+fn verify_schedule_part(part: &str, field: &ScheduleField) -> std::result::Result<(), String> {
+    let mut split = part.splitn(2, '/');
+    let range = split.next().unwrap_or_default();
+    if let Some(step) = split.next() {
+        match step.parse::<u32>() {
+            Ok(s) if s > 0 => {}
+            _ => return Err(format!("invalid {} step {:?}", field.name, step)),
+        }
+    }
+    if range == "*" || range == "?" {
+        return Ok(());
+    }
+    let mut bounds = range.splitn(2, '-');
+    let low = schedule_value(bounds.next().unwrap_or_default(), field)?;
+    if let Some(high) = bounds.next() {
+        if schedule_value(high, field)? < low {
+            return Err(format!("{} range {} is reversed", field.name, range));
+        }
+    }
+    Ok(())
+}
+
+fn schedule_value(value: &str, field: &ScheduleField) -> std::result::Result<u32, String> {
+    let lower = value.to_lowercase();
+    let n = match field.names.iter().position(|n| *n == lower) {
+        Some(i) => i as u32 + field.min,
+        None => match value.parse::<u32>() {
+            Ok(n) => n,
+            Err(_) => return Err(format!("invalid {} {:?}", field.name, value)),
+        },
+    };
+    if n < field.min || n > field.max {
+        return Err(format!(
+            "{} {} is outside {}-{}",
+            field.name, n, field.min, field.max
+        ));
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_schedule, CronJobSource};
+    use crate::{container::ContainerBuildParams, util::Build};
+
+    #[test]
+    fn cron_schedules() {
+        assert!(verify_schedule("*/15 9-17 * jan-JUN MON-FRI").is_ok());
+        assert!(verify_schedule("0 0,12 1 */2 ?").is_ok());
+        assert!(verify_schedule("@daily").is_ok());
+        assert!(verify_schedule("@hourly").is_ok());
+
+        assert_eq!(
+            verify_schedule("* * *").unwrap_err(),
+            "expected 5 fields, found 3"
+        );
+        assert_eq!(
+            verify_schedule("60 * * * *").unwrap_err(),
+            "minute 60 is outside 0-59"
+        );
+        assert!(verify_schedule("@fortnightly").is_err());
+        assert!(verify_schedule("*/0 * * * *").is_err());
+        assert!(verify_schedule("0 17-9 * * *").is_err());
+        assert!(verify_schedule("0 0 * * funday").is_err());
+
+        let job: CronJobSource = serde_yaml::from_str("name: nightly\nschedule: '0 25 * * *'").unwrap();
+        let params = ContainerBuildParams {
+            main_envs: Default::default(),
+            preserve_env_order: false,
+            main_resources: None,
+        };
+        let err = job.build(&params).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid schedule \"0 25 * * *\" for cron job nightly: hour 25 is outside 0-23"
+        );
+    }
+
+    #[test]
+    fn mesh_inject_annotation() {
+        let params = ContainerBuildParams {
+            main_envs: Default::default(),
+            preserve_env_order: false,
+            main_resources: None,
+        };
+        let job = |extra: &str| -> CronJobSource {
+            let yaml = format!(
+                "name: nightly\nschedule: '0 2 * * *'\npodAnnotations:\n  iam.amazonaws.com/role: arn\n{}",
+                extra
+            );
+            serde_yaml::from_str(&yaml).unwrap()
+        };
+        let opted_out = job("meshInject: false").build(&params).unwrap();
+        assert_eq!(opted_out.podAnnotations["sidecar.istio.io/inject"], "false");
+        assert_eq!(opted_out.podAnnotations["iam.amazonaws.com/role"], "arn");
+
+        let default = job("").build(&params).unwrap();
+        assert!(default.podAnnotations.get("sidecar.istio.io/inject").is_none());
+        assert_eq!(default.podAnnotations.len(), 1);
+    }
+}
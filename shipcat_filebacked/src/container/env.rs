@@ -1,29 +1,197 @@
 use merge::Merge;
-use std::collections::BTreeMap;
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, Visitor};
+use std::{collections::BTreeMap, fmt};
 
-use shipcat_definitions::{structs::EnvVars, Result};
+use shipcat_definitions::{
+    structs::{EnvFrom, EnvFromRef, EnvVars},
+    Result,
+};
 
 use crate::util::{Build, RelaxedString};
 
-#[derive(Deserialize, Clone, Default, Debug, PartialEq, Merge)]
-pub struct EnvVarsSource(BTreeMap<String, RelaxedString>);
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct EnvVarsSource {
+    vars: BTreeMap<String, RelaxedString>,
+    /// Declaration order of `vars` across merged sources
+    order: Vec<String>,
+}
 
 impl Build<EnvVars, ()> for EnvVarsSource {
-    fn build(self, params: &()) -> Result<EnvVars> {
-        let Self(plain) = self;
-        let env = EnvVars::new(plain.build(params)?);
+    fn build(self, _: &()) -> Result<EnvVars> {
+        self.build_env(false)
+    }
+}
+
+impl EnvVarsSource {
+    /// Build env vars, optionally recording their declaration order
+    pub fn build_env(self, preserve_order: bool) -> Result<EnvVars> {
+        let mut env = EnvVars::new(self.vars.build(&())?);
+        if preserve_order {
+            env.order = self.order;
+        }
         // TODO: Inline
         env.verify()?;
         Ok(env)
     }
 }
 
+impl Merge for EnvVarsSource {
+    fn merge(self, other: Self) -> Self {
+        let mut order = self.order;
+        for k in other.order.iter() {
+            if !order.contains(k) {
+                order.push(k.clone());
+            }
+        }
+        Self {
+            vars: self.vars.merge(other.vars),
+            order,
+        }
+    }
+}
+
 impl<K: ToString, V: Into<RelaxedString>> From<BTreeMap<K, V>> for EnvVarsSource {
     fn from(v: BTreeMap<K, V>) -> Self {
-        let mut env = BTreeMap::new();
+        let mut env = Self::default();
         for (k, v) in v {
-            env.insert(k.to_string(), v.into());
+            env.order.push(k.to_string());
+            env.vars.insert(k.to_string(), v.into());
+        }
+        env
+    }
+}
+
+impl<'de> Deserialize<'de> for EnvVarsSource {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<EnvVarsSource, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(EnvVarsVisitor)
+    }
+}
+
+struct EnvVarsVisitor;
+
+impl<'de> Visitor<'de> for EnvVarsVisitor {
+    type Value = EnvVarsSource;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of environment variables")
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Self::Value, E> {
+        Ok(EnvVarsSource::default())
+    }
+
+    fn visit_map<A>(self, mut access: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut env = EnvVarsSource::default();
+        while let Some((k, v)) = access.next_entry::<String, RelaxedString>()? {
+            // yaml silently keeps the last duplicate, which is never intended within one file
+            if env.vars.contains_key(&k) {
+                return Err(A::Error::custom(format!("duplicate env var {}", k)));
+            }
+            env.order.push(k.clone());
+            env.vars.insert(k, v);
         }
-        EnvVarsSource(env)
+        Ok(env)
+    }
+}
+
+/// Kind of object referenced by an `envFrom` entry
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum EnvFromKind {
+    ConfigMap,
+    Secret,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvFromSource {
+    pub kind: EnvFromKind,
+    pub name: String,
+    pub prefix: Option<String>,
+}
+
+impl Build<EnvFrom, ()> for EnvFromSource {
+    fn build(self, _: &()) -> Result<EnvFrom> {
+        let r = Some(EnvFromRef { name: self.name });
+        let (config_map_ref, secret_ref) = match self.kind {
+            EnvFromKind::ConfigMap => (r, None),
+            EnvFromKind::Secret => (None, r),
+        };
+        Ok(EnvFrom {
+            config_map_ref,
+            secret_ref,
+            prefix: self.prefix,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvFromSource, EnvVarsSource};
+    use crate::util::Build;
+    use merge::Merge;
+
+    #[test]
+    fn declaration_order_preserved() {
+        let base: EnvVarsSource = serde_yaml::from_str("PATH: /bin\nZED: z\nAPP: a").unwrap();
+        let over: EnvVarsSource = serde_yaml::from_str("ZED: zz\nEXTRA: e").unwrap();
+        let merged = base.merge(over);
+
+        let ordered = merged.clone().build_env(true).unwrap();
+        assert_eq!(ordered.order, vec!["PATH", "ZED", "APP", "EXTRA"]);
+        assert_eq!(ordered.plain["ZED"], "zz");
+
+        let sorted = merged.build_env(false).unwrap();
+        assert!(sorted.order.is_empty());
+        let keys: Vec<_> = sorted.plain.keys().cloned().collect();
+        assert_eq!(keys, vec!["APP", "EXTRA", "PATH", "ZED"]);
+    }
+
+    #[test]
+    fn sorted_regardless_of_layer_order() {
+        let shuffled = || -> Vec<EnvVarsSource> {
+            vec!["ZED: z\nAPP: a", "MID: m\nBAR: b", "CAT: c"]
+                .into_iter()
+                .map(|s| serde_yaml::from_str(s).unwrap())
+                .collect()
+        };
+        let forward = shuffled()
+            .into_iter()
+            .fold(EnvVarsSource::default(), Merge::merge);
+        let backward = shuffled()
+            .into_iter()
+            .rev()
+            .fold(EnvVarsSource::default(), Merge::merge);
+        let forward = serde_yaml::to_string(&forward.build_env(false).unwrap()).unwrap();
+        let backward = serde_yaml::to_string(&backward.build_env(false).unwrap()).unwrap();
+        assert_eq!(forward, backward);
+        assert_eq!(
+            forward,
+            "---\nplain:\n  APP: a\n  BAR: b\n  CAT: c\n  MID: m\n  ZED: z"
+        );
+
+        let err = serde_yaml::from_str::<EnvVarsSource>("APP: a\nZED: z\nAPP: b").unwrap_err();
+        assert!(err.to_string().contains("duplicate env var APP"));
+    }
+
+    #[test]
+    fn env_from_config_map_with_prefix() {
+        let src: EnvFromSource =
+            serde_yaml::from_str("kind: configMap\nname: webapp-config\nprefix: CONFIG_").unwrap();
+        let ef = src.build(&()).unwrap();
+        ef.verify("webapp", &[]).unwrap();
+        let rendered = serde_yaml::to_string(&ef).unwrap();
+        assert_eq!(
+            rendered,
+            "---\nconfigMapRef:\n  name: webapp-config\nprefix: CONFIG_"
+        );
+        assert!(ef.verify("other", &[]).is_err());
+        assert!(ef.verify("other", &["webapp-config".into()]).is_ok());
     }
 }
@@ -29,6 +29,12 @@ impl Build<String, ()> for ImageNameSource {
 #[derive(Deserialize, Clone)]
 pub struct ImageTagSource(String);
 
+impl From<&str> for ImageTagSource {
+    fn from(tag: &str) -> Self {
+        Self(tag.to_string())
+    }
+}
+
 impl Build<String, ()> for ImageTagSource {
     fn build(self, _: &()) -> Result<String> {
         let Self(tag) = self;
@@ -1,7 +1,8 @@
+use merge::Merge;
 use shipcat_definitions::{structs::Container, Result};
 
 use super::source::{ContainerBuildParams, ContainerSource};
-use crate::util::{Build, Require};
+use crate::util::{Build, Keyed, Require};
 
 #[derive(Deserialize, Clone, Default)]
 pub struct InitContainerSource(ContainerSource);
@@ -13,3 +14,13 @@ impl Build<Container, ContainerBuildParams> for InitContainerSource {
         Ok(container)
     }
 }
+
+impl Keyed for InitContainerSource {
+    fn key(&self) -> Option<String> {
+        self.0.merge_key()
+    }
+
+    fn merge_entry(self, other: Self) -> Self {
+        InitContainerSource(self.0.merge(other.0))
+    }
+}
@@ -5,10 +5,13 @@ mod env;
 mod image;
 mod resources;
 
-pub use env::EnvVarsSource;
+pub use env::{EnvFromSource, EnvVarsSource};
 pub use image::{ImageNameSource, ImageTagSource};
 pub use resources::ResourceRequirementsSource;
 
+mod annotations;
+pub use annotations::build_pod_annotations;
+
 mod cronjob;
 mod initcontainer;
 
@@ -1,9 +1,12 @@
 use shipcat_definitions::{
-    structs::resources::{ResourceRequirements, Resources},
+    structs::{
+        resources::{ResourceRequirements, Resources},
+        Container,
+    },
     Result,
 };
 
-use crate::util::{Build, RelaxedString, Require};
+use crate::util::{Build, Field, RelaxedString, Require};
 
 #[derive(Deserialize, Clone, Default)]
 #[serde(default, rename_all = "camelCase", deny_unknown_fields)]
@@ -15,8 +18,8 @@ pub struct ResourceRequirementsSource {
 impl Build<ResourceRequirements<String>, ()> for ResourceRequirementsSource {
     fn build(self, params: &()) -> Result<ResourceRequirements<String>> {
         let resources = ResourceRequirements {
-            requests: self.requests.build(params)?,
-            limits: self.limits.build(params)?,
+            requests: self.requests.build(params).field("requests")?,
+            limits: self.limits.build(params).field("limits")?,
         };
         resources.verify()?;
         Ok(resources)
@@ -33,8 +36,97 @@ pub struct ResourcesSource {
 impl Build<Resources<String>, ()> for ResourcesSource {
     fn build(self, params: &()) -> Result<Resources<String>> {
         Ok(Resources {
-            cpu: self.cpu.require("cpu")?.build(params)?,
-            memory: self.memory.require("cpu")?.build(params)?,
+            cpu: self
+                .cpu
+                .require("cpu")
+                .and_then(|c| c.build(params))
+                .field("cpu")?,
+            memory: self
+                .memory
+                .require("memory")
+                .and_then(|m| m.build(params))
+                .field("memory")?,
         })
     }
 }
+
+/// Opt-in to the main container's resources, as `true` or a positive scaling factor
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ResourcesFromMainSource {
+    Enabled(bool),
+    Factor(f64),
+}
+
+impl ResourcesFromMainSource {
+    fn factor(&self) -> Result<Option<f64>> {
+        match *self {
+            ResourcesFromMainSource::Enabled(false) => Ok(None),
+            ResourcesFromMainSource::Enabled(true) => Ok(Some(1.0)),
+            ResourcesFromMainSource::Factor(f) if f > 0.0 && f.is_finite() => Ok(Some(f)),
+            ResourcesFromMainSource::Factor(f) => bail!("resourcesFromMain must be positive, not {}", f),
+        }
+    }
+
+    /// Give a container the scaled main resources, unless it has its own
+    pub fn inherit(
+        &self,
+        container: &mut Container,
+        main: Option<&ResourceRequirements<String>>,
+    ) -> Result<()> {
+        let factor = match self.factor()? {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        if container.resources.is_some() {
+            return Ok(());
+        }
+        let resources = match main {
+            Some(m) => m.scaled(factor)?,
+            None => bail!("resourcesFromMain requires resources on the main container"),
+        };
+        resources.verify()?;
+        container.resources = Some(resources);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourcesFromMainSource;
+    use shipcat_definitions::structs::{Container, ResourceRequirements};
+
+    fn resources(cpu: &str, memory: &str) -> ResourceRequirements<String> {
+        let yaml = format!(
+            "requests: {{cpu: {0}, memory: {1}}}\nlimits: {{cpu: {0}, memory: {1}}}",
+            cpu, memory
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn resources_from_main() {
+        let main = resources("1", "1Gi");
+        let half: ResourcesFromMainSource = serde_yaml::from_str("0.5").unwrap();
+        let mut sidecar = Container::default();
+        half.inherit(&mut sidecar, Some(&main)).unwrap();
+        let res = sidecar.resources.unwrap();
+        assert_eq!(res.requests.cpu, "500m");
+        assert_eq!(res.limits.memory, "512Mi");
+
+        let enabled: ResourcesFromMainSource = serde_yaml::from_str("true").unwrap();
+        let mut own = Container::default();
+        own.resources = Some(resources("100m", "100Mi"));
+        enabled.inherit(&mut own, Some(&main)).unwrap();
+        assert_eq!(own.resources.unwrap().requests.cpu, "100m");
+
+        let disabled: ResourcesFromMainSource = serde_yaml::from_str("false").unwrap();
+        let mut none = Container::default();
+        disabled.inherit(&mut none, None).unwrap();
+        assert!(none.resources.is_none());
+
+        assert!(enabled.inherit(&mut Container::default(), None).is_err());
+        let negative: ResourcesFromMainSource = serde_yaml::from_str("-1.0").unwrap();
+        assert!(negative.inherit(&mut Container::default(), Some(&main)).is_err());
+    }
+}
@@ -1,13 +1,37 @@
+use merge::Merge;
 use shipcat_definitions::{structs::Container, Result};
 
-use super::source::{ContainerBuildParams, ContainerSource};
-use crate::util::Build;
+use super::{
+    resources::ResourcesFromMainSource,
+    source::{ContainerBuildParams, ContainerSource},
+};
+use crate::util::{Build, Keyed};
 
-#[derive(Deserialize, Clone, Default)]
-pub struct SidecarSource(ContainerSource);
+#[derive(Deserialize, Merge, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SidecarSource {
+    pub resources_from_main: Option<ResourcesFromMainSource>,
+
+    #[serde(flatten)]
+    pub container: ContainerSource,
+}
 
 impl Build<Container, ContainerBuildParams> for SidecarSource {
     fn build(self, params: &ContainerBuildParams) -> Result<Container> {
-        self.0.build(params)
+        let mut container = self.container.build(params)?;
+        if let Some(from_main) = &self.resources_from_main {
+            from_main.inherit(&mut container, params.main_resources.as_ref())?;
+        }
+        Ok(container)
+    }
+}
+
+impl Keyed for SidecarSource {
+    fn key(&self) -> Option<String> {
+        self.container.merge_key()
+    }
+
+    fn merge_entry(self, other: Self) -> Self {
+        self.merge(other)
     }
 }
@@ -2,7 +2,7 @@ use merge::Merge;
 use regex::Regex;
 
 use shipcat_definitions::{
-    structs::{Container, Probe, VolumeMount},
+    structs::{Container, Probe, ResourceRequirements, VolumeMount},
     Result,
 };
 
@@ -51,8 +51,18 @@ pub struct ContainerSource {
     pub volume_mounts: Option<Vec<VolumeMount>>,
 }
 
+impl ContainerSource {
+    /// Container name, used to match containers across override files
+    pub fn merge_key(&self) -> Option<String> {
+        self.name.as_ref().map(|ContainerName(n)| n.clone())
+    }
+}
+
 pub struct ContainerBuildParams {
     pub main_envs: EnvVarsSource,
+    pub preserve_env_order: bool,
+    /// Built resources of the main container, for `resourcesFromMain`
+    pub main_resources: Option<ResourceRequirements<String>>,
 }
 
 impl Build<Container, ContainerBuildParams> for ContainerSource {
@@ -78,7 +88,7 @@ impl Build<Container, ContainerBuildParams> for ContainerSource {
             resources: self.resources.build(&())?,
 
             command: self.command.unwrap_or_default(),
-            env: env.build(&())?,
+            env: env.build_env(params.preserve_env_order)?,
 
             readiness_probe: self.readiness_probe,
             liveness_probe: self.liveness_probe,
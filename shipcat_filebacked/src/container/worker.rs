@@ -5,8 +5,11 @@ use shipcat_definitions::{
     Result,
 };
 
-use super::source::{ContainerBuildParams, ContainerSource};
-use crate::util::{Build, RelaxedString, Require};
+use super::{
+    resources::ResourcesFromMainSource,
+    source::{ContainerBuildParams, ContainerSource},
+};
+use crate::util::{Build, Keyed, RelaxedString, Require};
 use std::collections::BTreeMap;
 
 #[derive(Deserialize, Merge, Clone, Default)]
@@ -16,20 +19,35 @@ pub struct WorkerSource {
     pub auto_scaling: Option<AutoScaling>,
     pub http_port: Option<u32>,
     pub pod_annotations: BTreeMap<String, RelaxedString>,
+    pub resources_from_main: Option<ResourcesFromMainSource>,
 
     #[serde(flatten)]
     pub container: ContainerSource,
 }
 
+impl Keyed for WorkerSource {
+    fn key(&self) -> Option<String> {
+        self.container.merge_key()
+    }
+
+    fn merge_entry(self, other: Self) -> Self {
+        self.merge(other)
+    }
+}
+
 impl Build<Worker, ContainerBuildParams> for WorkerSource {
     fn build(self, params: &ContainerBuildParams) -> Result<Worker> {
         if let Some(a) = &self.auto_scaling {
             a.verify()?;
         }
+        let mut container = self.container.build(params)?;
+        if let Some(from_main) = &self.resources_from_main {
+            from_main.inherit(&mut container, params.main_resources.as_ref())?;
+        }
         Ok(Worker {
-            container: self.container.build(params)?,
+            container,
             replicaCount: self.replica_count.require("replicaCount")?,
-            autoScaling: self.auto_scaling,
+            autoScaling: self.auto_scaling.map(AutoScaling::with_custom_metrics),
             httpPort: self.http_port,
             podAnnotations: self.pod_annotations.build(&())?,
         })
@@ -1,8 +1,12 @@
 use merge::Merge;
-use std::collections::BTreeMap;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
 
 use shipcat_definitions::{
-    structs::{Authentication, Authorization, BabylonAuthHeader, Cors, Kong, KongRateLimit},
+    structs::{
+        Authentication, Authorization, BabylonAuthHeader, Cors, Kong, KongHostRoute, KongRateLimit,
+        KongUpstream, KongUpstreamTarget,
+    },
     KongConfig, Region, Result,
 };
 
@@ -97,9 +101,11 @@ impl KongApisSource {
 #[serde(default, deny_unknown_fields)]
 pub struct KongSource {
     pub upstream_url: Option<String>,
+    pub upstream_targets: Option<Vec<KongUpstreamTarget>>,
     pub uris: Option<String>,
     pub hosts: Option<Vec<String>>,
     pub strip_uri: Option<bool>,
+    pub host_routes: Option<Vec<KongHostRouteSource>>,
     pub preserve_host: Option<bool>,
     pub cors: Option<Cors>,
     pub additional_internal_ips: Option<Vec<String>>,
@@ -123,6 +129,15 @@ pub struct KongSource {
     pub user_rate_limits: Enabled<KongRateLimitSource>,
 }
 
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct KongHostRouteSource {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub uris: Option<String>,
+    pub strip_uri: Option<bool>,
+}
+
 struct KongBuildParams {
     pub name: String,
     pub service: String,
@@ -141,12 +156,15 @@ impl Build<Kong, KongBuildParams> for KongSource {
         } = params;
         debug!("Building Kong API {} for {}", &name, &service);
 
-        let hosts = self.build_hosts(&kong.base_url)?;
+        let hosts = self.build_hosts(&kong.base_url, region)?;
         if hosts.is_empty() && self.uris.is_none() {
             bail!("At least one of hosts or uris must be set on a Kong API")
         }
 
-        let upstream_url = self.build_upstream_url(&service, &region.namespace);
+        let strip_uri = self.strip_uri.unwrap_or_default();
+        let host_routes = self.build_host_routes(&kong.base_url, region, strip_uri)?;
+
+        let (upstream_url, upstream) = self.build_upstream(&name, &service, &region.namespace)?;
         let (auth, authorization) = KongSource::build_auth(self.auth, self.authorization)?;
 
         let preserve_host = self.preserve_host.unwrap_or(true);
@@ -154,6 +172,7 @@ impl Build<Kong, KongBuildParams> for KongSource {
         Ok(Kong {
             name: name.to_string(),
             upstream_url: upstream_url,
+            upstream,
             upstream_service: if preserve_host {
                 Some(service.to_string())
             } else {
@@ -164,7 +183,8 @@ impl Build<Kong, KongBuildParams> for KongSource {
             uris: self.uris,
             hosts,
             authorization,
-            strip_uri: self.strip_uri.unwrap_or_default(),
+            strip_uri,
+            host_routes,
             preserve_host,
             cors: self.cors,
             additional_internal_ips: self.additional_internal_ips.unwrap_or_default(),
@@ -181,6 +201,7 @@ impl Build<Kong, KongBuildParams> for KongSource {
 
             ip_rate_limits: self.ip_rate_limits.build(&())?,
             user_rate_limits: self.user_rate_limits.build(&())?,
+            gate_rate_limits: None,
         })
     }
 }
@@ -194,6 +215,55 @@ impl KongSource {
         }
     }
 
+    /// Point the API at a weighted upstream when targets are given, otherwise at the upstream url
+    fn build_upstream(
+        &self,
+        name: &str,
+        service: &str,
+        namespace: &str,
+    ) -> Result<(String, Option<KongUpstream>)> {
+        let targets = self.upstream_targets.clone().unwrap_or_default();
+        if targets.is_empty() {
+            return Ok((self.build_upstream_url(service, namespace), None));
+        }
+        if self.upstream_url.is_some() {
+            bail!(
+                "Kong API {} cannot set both upstream_url and upstream_targets",
+                name
+            );
+        }
+        let mut seen = BTreeSet::new();
+        for t in &targets {
+            if t.target.trim().is_empty() {
+                bail!("Kong upstream_targets of {} need a target", name);
+            }
+            if !seen.insert(t.target.clone()) {
+                bail!("Kong upstream target {} is defined more than once", t.target);
+            }
+            if t.weight > 1000 {
+                bail!(
+                    "Kong upstream target {} has weight {} above 1000",
+                    t.target,
+                    t.weight
+                );
+            }
+        }
+        if targets.iter().all(|t| t.weight == 0) {
+            bail!(
+                "Kong upstream_targets of {} need at least one positive weight",
+                name
+            );
+        }
+        let upstream = format!("{}-upstream", name);
+        Ok((
+            format!("http://{}", upstream),
+            Some(KongUpstream {
+                name: upstream,
+                targets,
+            }),
+        ))
+    }
+
     fn build_auth(
         auth: Option<Authentication>,
         authz: Enabled<AuthorizationSource>,
@@ -205,22 +275,72 @@ impl KongSource {
         })
     }
 
-    fn build_hosts(&self, base_url: &str) -> Result<Vec<String>> {
-        Ok(self
-            .hosts
-            .clone()
-            .unwrap_or_default()
-            .into_iter()
-            .map(|h| {
-                let fully_qualified = h.contains('.');
-                if fully_qualified {
-                    h
-                } else {
-                    format!("{}{}", h, base_url)
-                }
-            })
-            .collect())
+    fn build_hosts(&self, base_url: &str, region: &Region) -> Result<Vec<String>> {
+        qualify_hosts(self.hosts.clone().unwrap_or_default(), base_url, region)
+    }
+
+    fn build_host_routes(
+        &self,
+        base_url: &str,
+        region: &Region,
+        strip_uri: bool,
+    ) -> Result<Vec<KongHostRoute>> {
+        let mut names = BTreeSet::new();
+        let mut routes = vec![];
+        for r in self.host_routes.clone().unwrap_or_default() {
+            if r.name.is_empty() {
+                bail!("Kong host_routes need a name");
+            }
+            if !names.insert(r.name.clone()) {
+                bail!("Kong host route {} is defined more than once", r.name);
+            }
+            if r.hosts.is_empty() {
+                bail!("Kong host route {} has no hosts", r.name);
+            }
+            routes.push(KongHostRoute {
+                name: r.name,
+                hosts: qualify_hosts(r.hosts, base_url, region)?,
+                uris: r.uris.or_else(|| self.uris.clone()),
+                strip_uri: r.strip_uri.unwrap_or(strip_uri),
+            });
+        }
+        Ok(routes)
+    }
+}
+
+/// Resolve host variables, then append the region's base url to hosts that are not fully qualified
+fn qualify_hosts(hosts: Vec<String>, base_url: &str, region: &Region) -> Result<Vec<String>> {
+    let env = region.environment.to_string();
+    let mut qualified = vec![];
+    for h in hosts {
+        let h = interpolate_host(&h, &region.name, &env)?;
+        let fully_qualified = h.contains('.');
+        if fully_qualified {
+            qualified.push(h)
+        } else {
+            qualified.push(format!("{}{}", h, base_url))
+        }
+    }
+    Ok(qualified)
+}
+
+/// Replace the `{{ region }}` and `{{ env }}` variables in a host
+fn interpolate_host(host: &str, region: &str, env: &str) -> Result<String> {
+    let re = Regex::new(r"\{\{\s*([^{}\s]*)\s*\}\}").unwrap();
+    for cap in re.captures_iter(host) {
+        match &cap[1] {
+            "region" | "env" => {}
+            var => bail!("Unknown variable '{}' in host {}", var, host),
+        }
     }
+    let resolved = re.replace_all(host, |cap: &regex::Captures<'_>| match &cap[1] {
+        "region" => region.to_string(),
+        _ => env.to_string(),
+    });
+    if resolved.contains("{{") || resolved.contains("}}") {
+        bail!("Unterminated variable in host {}", host);
+    }
+    Ok(resolved.into_owned())
 }
 
 #[derive(Deserialize, Default, Merge, Clone)]
@@ -242,3 +362,72 @@ impl Build<KongRateLimit, ()> for KongRateLimitSource {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{interpolate_host, KongSource, KongUpstreamTarget};
+
+    #[test]
+    fn host_variables() {
+        let host = interpolate_host("api.{{ region }}.example.com", "dev-uk", "dev").unwrap();
+        assert_eq!(host, "api.dev-uk.example.com");
+        let host = interpolate_host("{{env}}-api.{{ region }}.example.com", "dev-uk", "dev").unwrap();
+        assert_eq!(host, "dev-api.dev-uk.example.com");
+        assert_eq!(interpolate_host("fake-ask", "dev-uk", "dev").unwrap(), "fake-ask");
+
+        let err = interpolate_host("api.{{ cluster }}.example.com", "dev-uk", "dev").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown variable 'cluster' in host api.{{ cluster }}.example.com"
+        );
+        assert!(interpolate_host("api.{{ region.example.com", "dev-uk", "dev").is_err());
+    }
+
+    fn target(target: &str, weight: u32) -> KongUpstreamTarget {
+        KongUpstreamTarget {
+            target: target.into(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn upstream_targets() {
+        let single = KongSource::default();
+        let (url, upstream) = single.build_upstream("fake-ask", "fake-ask", "dev").unwrap();
+        assert_eq!(url, "http://fake-ask.dev.svc.cluster.local");
+        assert!(upstream.is_none());
+
+        let weighted = KongSource {
+            upstream_targets: Some(vec![
+                target("fake-ask.dev.svc.cluster.local:80", 90),
+                target("fake-ask-canary.dev.svc.cluster.local:80", 10),
+            ]),
+            ..Default::default()
+        };
+        let (url, upstream) = weighted.build_upstream("fake-ask", "fake-ask", "dev").unwrap();
+        assert_eq!(url, "http://fake-ask-upstream");
+        let upstream = upstream.unwrap();
+        assert_eq!(upstream.name, "fake-ask-upstream");
+        assert_eq!(upstream.targets.len(), 2);
+        assert_eq!(upstream.targets[1].weight, 10);
+
+        let both = KongSource {
+            upstream_url: Some("http://example.com".into()),
+            ..weighted.clone()
+        };
+        assert!(both.build_upstream("fake-ask", "fake-ask", "dev").is_err());
+        let heavy = KongSource {
+            upstream_targets: Some(vec![target("fake-ask:80", 1001)]),
+            ..Default::default()
+        };
+        assert!(heavy.build_upstream("fake-ask", "fake-ask", "dev").is_err());
+        let drained = KongSource {
+            upstream_targets: Some(vec![target("fake-ask:80", 0), target("fake-ask-canary:80", 0)]),
+            ..Default::default()
+        };
+        assert!(drained.build_upstream("fake-ask", "fake-ask", "dev").is_err());
+
+        let target: KongUpstreamTarget = serde_yaml::from_str("target: fake-ask:80").unwrap();
+        assert_eq!(target.weight, 100);
+    }
+}
@@ -2,6 +2,7 @@
 #[macro_use] extern crate merge_derive;
 #[macro_use] extern crate log;
 #[macro_use] extern crate error_chain;
+#[macro_use] extern crate lazy_static;
 
 // Structs
 mod authorization;
@@ -14,6 +15,10 @@ pub use crate::simple::SimpleManifest;
 mod kong;
 
 mod load;
+mod migrate;
+pub use crate::migrate::SCHEMA_VERSION;
+mod region;
+mod schema;
 mod util;
 
 use manifest::ManifestSource;
@@ -23,6 +28,26 @@ pub async fn load_manifest(service: &str, conf: &Config, reg: &Region) -> Result
     ManifestSource::load_manifest(service, conf, reg).await
 }
 
+/// Load a manifest with an optional version override (e.g. for hotfixes)
+pub async fn load_manifest_with_version(
+    service: &str,
+    conf: &Config,
+    reg: &Region,
+    version: Option<&str>,
+) -> Result<Manifest> {
+    ManifestSource::load_manifest_with_version(service, conf, reg, version).await
+}
+
+/// Load a manifest, falling back to `version` when the manifests do not pin one (e.g. `-t`)
+pub async fn load_manifest_with_default_version(
+    service: &str,
+    conf: &Config,
+    reg: &Region,
+    version: Option<&str>,
+) -> Result<Manifest> {
+    ManifestSource::load_manifest_with_default_version(service, conf, reg, version).await
+}
+
 pub async fn load_metadata(service: &str, conf: &Config, reg: &Region) -> Result<SimpleManifest> {
     ManifestSource::load_metadata(service, conf, reg).await
 }
@@ -31,6 +56,21 @@ pub async fn all(conf: &Config) -> Result<Vec<BaseManifest>> {
     ManifestSource::all(conf).await
 }
 
+/// Build every enabled manifest in a region, with cross manifest validation
+pub async fn load_region(conf: &Config, reg: &Region) -> Result<Vec<Manifest>> {
+    ManifestSource::load_region(conf, reg).await
+}
+
+/// JSON Schema describing `manifest.yml`
+pub fn manifest_schema() -> serde_json::Value {
+    schema::manifest_schema()
+}
+
+/// Names of all service directories, sorted
+pub fn service_names() -> Vec<String> {
+    ManifestSource::all_names()
+}
+
 pub async fn available(conf: &Config, reg: &Region) -> Result<Vec<SimpleManifest>> {
     ManifestSource::available(conf, reg).await
 }
@@ -5,17 +5,67 @@ use serde::de::DeserializeOwned;
 use shipcat_definitions::{Config, ErrorKind, Manifest, Region, Result, ResultExt};
 use walkdir::WalkDir;
 
-use super::{authorization::AuthorizationSource, util::Enabled, BaseManifest, SimpleManifest};
-use crate::manifest::{ManifestDefaults, ManifestOverrides, ManifestSource};
+use super::{
+    authorization::AuthorizationSource,
+    container::ImageTagSource,
+    util::{closest, Build, Enabled},
+    BaseManifest, SimpleManifest,
+};
+use crate::{
+    manifest::{
+        ManifestDefaults, ManifestOverrides, ManifestSource, DEFAULT_FIELDS, OVERRIDE_FIELDS, SOURCE_FIELDS,
+    },
+    migrate::{self, SCHEMA_VERSION},
+};
 
 impl ManifestSource {
     pub async fn load_manifest(service: &str, conf: &Config, reg: &Region) -> Result<Manifest> {
+        Self::load_manifest_with_version(service, conf, reg, None).await
+    }
+
+    /// Load a manifest, optionally pinning a version that replaces the one in the manifests
+    pub async fn load_manifest_with_version(
+        service: &str,
+        conf: &Config,
+        reg: &Region,
+        version: Option<&str>,
+    ) -> Result<Manifest> {
+        Self::load_versioned(service, conf, reg, version, true).await
+    }
+
+    /// Load a manifest, using `version` only when the manifests do not pin one
+    pub async fn load_manifest_with_default_version(
+        service: &str,
+        conf: &Config,
+        reg: &Region,
+        version: Option<&str>,
+    ) -> Result<Manifest> {
+        Self::load_versioned(service, conf, reg, version, false).await
+    }
+
+    /// Versions are built like pinned ones, so region suffixes and tag policies apply to both
+    async fn load_versioned(
+        service: &str,
+        conf: &Config,
+        reg: &Region,
+        version: Option<&str>,
+        replace_pinned: bool,
+    ) -> Result<Manifest> {
         let reg_name = reg.name.clone();
         let service_name = service.to_string();
 
-        let merged = ManifestSource::load_merged(service, conf, reg)
+        let mut merged = ManifestSource::load_merged(service, conf, reg)
             .await
             .chain_err(|| ErrorKind::FailedToBuildManifest(service_name.clone(), reg_name.clone()))?;
+        let pinned = merged.overrides.version.is_some();
+        if let Some(v) = version.filter(|_| replace_pinned || !pinned) {
+            let tag = ImageTagSource::from(v).build(&())?;
+            reg.versioningScheme.verify(&tag)?;
+            if pinned {
+                warn!("Pinning {} to {} (diverges from git)", service, tag);
+            }
+            merged.overrides.version = Some(ImageTagSource::from(tag.as_str()));
+        }
         merged
             .build(&(conf.clone(), reg.clone()))
             .await
@@ -41,27 +91,29 @@ impl ManifestSource {
 
         let source_path = Self::services_dir().join(service).join("manifest.yml");
         debug!("Loading service manifest from {:?}", source_path);
-        let source: ManifestSource = read_from(&source_path).await?;
-        let mut manifest = defaults.merge_source(source);
+        let source: ManifestSource = read_manifest_from(&source_path, true, SCHEMA_VERSION).await?;
+        let schema = source.schema_version.unwrap_or(SCHEMA_VERSION);
+        let manifest = defaults.merge_source(source);
 
+        let mut overrides = vec![];
         let env_path = dir.join(format!("{}.yml", reg.environment.to_string()));
         if env_path.is_file() {
             debug!("Loading service overrides from {:?}", env_path);
-            let env: ManifestOverrides = read_from(&env_path).await?;
-            manifest = manifest.merge_overrides(env);
+            let env: ManifestOverrides = read_manifest_from(&env_path, false, schema).await?;
+            overrides.push(env);
         }
 
         let region_path = dir.join(format!("{}.yml", reg.name));
         if region_path.is_file() {
             debug!("Loading service overrides from {:?}", region_path);
-            let region: ManifestOverrides = read_from(&region_path).await?;
-            manifest = manifest.merge_overrides(region);
+            let region: ManifestOverrides = read_manifest_from(&region_path, false, schema).await?;
+            overrides.push(region);
         }
 
-        Ok(manifest)
+        Ok(manifest.merge_regional(overrides, reg.replica_scale_factor))
     }
 
-    fn all_names() -> Vec<String> {
+    pub(crate) fn all_names() -> Vec<String> {
         let mut res: Vec<_> = WalkDir::new(&ManifestSource::services_dir())
             .min_depth(1)
             .max_depth(1)
@@ -179,7 +231,69 @@ impl ManifestDefaults {
     }
 }
 
+/// Ensure all top level keys in a manifest or override file are known
+///
+/// Serde's `deny_unknown_fields` does not work through the flattened overrides and defaults,
+/// so this explains which field was likely intended.
+fn check_fields(path: &PathBuf, value: &serde_yaml::Value, is_source: bool) -> Result<()> {
+    let mapping = match value.as_mapping() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    for key in mapping.iter().filter_map(|(k, _)| k.as_str()) {
+        if OVERRIDE_FIELDS.contains(&key)
+            || DEFAULT_FIELDS.contains(&key)
+            || (is_source && SOURCE_FIELDS.contains(&key))
+        {
+            continue;
+        }
+        if !is_source && SOURCE_FIELDS.contains(&key) {
+            bail!(
+                "Field `{}` in {} can only be set in manifest.yml",
+                key,
+                path.display()
+            );
+        }
+        let candidates = OVERRIDE_FIELDS.iter().chain(DEFAULT_FIELDS).cloned();
+        let hint = match closest(key, candidates) {
+            Some(c) if DEFAULT_FIELDS.contains(&c) => format!(": did you mean the default `{}`?", c),
+            Some(c) => format!(": did you mean the override `{}`?", c),
+            None => "".into(),
+        };
+        bail!("Unknown field `{}` in {}{}", key, path.display(), hint);
+    }
+    Ok(())
+}
+
+/// Read a manifest or override file, migrating it from older schema versions first
+///
+/// Sources declare their own `schemaVersion`, override files follow their source's.
+async fn read_manifest_from<T: DeserializeOwned>(path: &PathBuf, is_source: bool, schema: u32) -> Result<T> {
+    let data = read_data(path).await?;
+    let mut value: serde_yaml::Value = parse(path, &data)?;
+    let schema = if is_source {
+        migrate::schema_version(path, &value)?
+    } else {
+        schema
+    };
+    if schema == SCHEMA_VERSION {
+        // current manifests are deserialized from the raw file to keep serde's strictness
+        check_fields(path, &value, is_source)?;
+        return parse(path, &data);
+    }
+    migrate::migrate(path, &mut value, schema)?;
+    check_fields(path, &value, is_source)?;
+    match serde_yaml::from_value(value) {
+        Err(e) => bail!("Manifest file {} did not parse as YAML: {}", path.display(), e),
+        Ok(d) => Ok(d),
+    }
+}
+
 async fn read_from<T: DeserializeOwned>(path: &PathBuf) -> Result<T> {
+    parse(path, &read_data(path).await?)
+}
+
+async fn read_data(path: &PathBuf) -> Result<String> {
     use tokio::fs;
     trace!("Reading manifest in {}", path.display());
     if !path.exists() {
@@ -189,7 +303,11 @@ async fn read_from<T: DeserializeOwned>(path: &PathBuf) -> Result<T> {
     if data.is_empty() {
         bail!("Manifest file {} is empty", path.display());
     }
-    match serde_yaml::from_str(&data) {
+    Ok(data)
+}
+
+fn parse<T: DeserializeOwned>(path: &PathBuf, data: &str) -> Result<T> {
+    match serde_yaml::from_str(data) {
         Err(e) => bail!("Manifest file {} did not parse as YAML: {}", path.display(), e),
         Ok(d) => Ok(d),
     }
@@ -208,6 +326,79 @@ mod tests {
         std::env::set_current_dir(pth).unwrap();
     }
 
+    #[test]
+    fn unknown_field_suggestion() {
+        let check_fields = |pth, data: &str, is_source| {
+            super::check_fields(pth, &serde_yaml::from_str(data).unwrap(), is_source)
+        };
+        let pth = Path::new("services").join("fake-ask").join("dev-uk.yml");
+        let err = check_fields(&pth, "replicaCnt: 2\n", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown field `replicaCnt` in services/fake-ask/dev-uk.yml: did you mean the default `replicaCount`?"
+        );
+        assert!(check_fields(&pth, "replicaCount: 2\nresources: {}\n", false).is_ok());
+        assert!(check_fields(&pth, "regions: [dev-uk]\n", false).is_err());
+        assert!(check_fields(&pth, "regions: [dev-uk]\n", true).is_ok());
+    }
+
+    #[test]
+    fn known_fields_are_accepted() {
+        use crate::manifest::{ManifestOverrides, DEFAULT_FIELDS, OVERRIDE_FIELDS};
+        for f in OVERRIDE_FIELDS.iter().chain(DEFAULT_FIELDS) {
+            let yaml = format!("{}: ~", f);
+            if let Err(e) = serde_yaml::from_str::<ManifestOverrides>(&yaml) {
+                assert!(!e.to_string().contains("unknown field"), "{} not a field", f);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_template_error() {
+        use crate::manifest::find_template_file_in;
+        setup();
+        let root = Path::new(".");
+        assert!(find_template_file_in(root, "fake-ask", "config.ini.j2").is_ok());
+        let err = find_template_file_in(root, "fake-ask", "missing.ini.j2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Template missing.ini.j2 not found in services/fake-ask or templates/"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_template_root() {
+        use crate::manifest::find_template_file_in;
+        setup();
+        let mut conf = Config::read().await.unwrap();
+        assert_eq!(conf.template_root(), Path::new("."));
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("tests");
+        conf.templateRoot = Some(root.clone());
+        let pth = find_template_file_in(conf.template_root(), "fake-ask", "config.ini.j2").unwrap();
+        assert!(pth.starts_with(&root));
+        let wrongroot = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(find_template_file_in(wrongroot, "fake-ask", "config.ini.j2").is_err());
+
+        // partials are included from the template root rather than the working directory
+        let altroot = env::temp_dir().join("shipcat-template-root");
+        fs::create_dir_all(altroot.join("services").join("fake-ask")).unwrap();
+        fs::create_dir_all(altroot.join("templates").join("partials")).unwrap();
+        let tmpl = altroot.join("services").join("fake-ask").join("config.ini.j2");
+        fs::write(tmpl, "{% include \"partials/shared.ini.j2\" %}\n").unwrap();
+        let partial = altroot.join("templates").join("partials").join("shared.ini.j2");
+        fs::write(partial, "[ALTROOT]\nSERVICE={{ service }}\n").unwrap();
+        conf.templateRoot = Some(altroot);
+        let region = conf.get_region("dev-uk").unwrap();
+        let mut mf = ManifestSource::load_manifest("fake-ask", &conf, &region)
+            .await
+            .unwrap();
+        mf.template_configs(&region).unwrap();
+        let cfg = mf.configs[0].files[0].value.clone().unwrap();
+        assert!(cfg.contains("[ALTROOT]"));
+        assert!(!cfg.contains("[SHARED]"));
+    }
+
     #[tokio::test]
     async fn load_fake_ask() {
         setup();
@@ -219,6 +410,62 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(manifest.name, "fake-ask".to_string());
+        let wave = "argocd.argoproj.io/sync-wave";
+        assert_eq!(manifest.deploymentAnnotations.get(wave), Some(&"2".to_string()));
+        assert!(manifest.podAnnotations.get(wave).is_none());
+    }
+
+    #[tokio::test]
+    async fn load_pinned_version() {
+        setup();
+
+        let conf = Config::read().await.unwrap();
+        let region = conf.get_region("dev-uk").unwrap();
+
+        let manifest = ManifestSource::load_manifest_with_version("fake-ask", &conf, &region, Some("1.7.0"))
+            .await
+            .unwrap();
+        assert_eq!(manifest.version, Some("1.7.0".to_string()));
+        let values = serde_yaml::to_string(&manifest).unwrap();
+        assert!(values.contains("version: 1.7.0"));
+
+        let invalid = ManifestSource::load_manifest_with_version("fake-ask", &conf, &region, Some("-bad"));
+        assert!(invalid.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn migrates_v1_manifest() {
+        use super::read_manifest_from;
+        use crate::{manifest::ManifestDefaults, migrate::SCHEMA_VERSION};
+        use merge::Merge;
+        setup();
+
+        let conf = Config::read().await.unwrap();
+        let region = conf.get_region("dev-uk").unwrap();
+        let current = fs::read_to_string("services/fake-ask/manifest.yml").unwrap();
+        assert!(current.contains("\nreplicaCount: 2\n"));
+        let v1 = current.replace("\nreplicaCount: 2\n", "\nreplicas: 3\n");
+        let pth = env::temp_dir().join("shipcat-fake-ask-v1.yml");
+        fs::write(&pth, format!("schemaVersion: 1\n{}", v1)).unwrap();
+
+        let source: ManifestSource = read_manifest_from(&pth, true, SCHEMA_VERSION).await.unwrap();
+        let defaults = ManifestDefaults::builtin().merge(
+            ManifestDefaults::from_global(&conf)
+                .unwrap()
+                .merge(ManifestDefaults::from_region(&region).unwrap()),
+        );
+        let manifest = defaults
+            .merge_source(source)
+            .build(&(conf.clone(), region.clone()))
+            .await
+            .unwrap();
+        assert_eq!(manifest.replicaCount, Some(3));
+
+        // future versions are refused
+        fs::write(&pth, format!("schemaVersion: 99\n{}", current)).unwrap();
+        let res = read_manifest_from::<ManifestSource>(&pth, true, SCHEMA_VERSION).await;
+        assert!(res.is_err());
+        fs::remove_file(&pth).unwrap();
     }
 
     #[tokio::test]
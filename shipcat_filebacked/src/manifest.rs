@@ -1,7 +1,12 @@
 #![allow(non_snake_case)]
 
 use merge::Merge;
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
 
 use shipcat_definitions::{
     structs::{
@@ -10,22 +15,24 @@ use shipcat_definitions::{
         security::DataHandling,
         tolerations::Tolerations,
         volume::Volume,
-        ConfigMap, Dependency, DestinationRule, EventStream, Gate, HealthCheck, HostAlias, Kafka,
-        KafkaResources, LifeCycle, Metadata, NotificationMode, PersistentVolume, Probe, PrometheusAlert,
-        Rbac, RollingUpdate, SecurityContext, VaultOpts, VolumeMount,
+        Canary, ConfigMap, Container, Dependency, DestinationRule, EventStream, Gate, HealthCheck, HostAlias,
+        Kafka, KafkaResources, LifeCycle, Metadata, NetworkPolicy, NotificationChannels, NotificationMode,
+        PersistentVolume, Port, Probe, PrometheusAlert, Rbac, RollingUpdate, SecurityContext,
+        TopologySpreadConstraint, VaultOpts, VolumeMount,
     },
-    BaseManifest, Config, Manifest, PrimaryWorkload, Region, Result,
+    BaseManifest, Config, Manifest, PrimaryWorkload, Region, Result, ValidationError,
 };
 
 use super::{
     container::{
-        ContainerBuildParams, CronJobSource, EnvVarsSource, ImageNameSource, ImageTagSource,
-        InitContainerSource, PortSource, ResourceRequirementsSource, SidecarSource, WorkerSource,
+        build_pod_annotations, ContainerBuildParams, CronJobSource, EnvFromSource, EnvVarsSource,
+        ImageNameSource, ImageTagSource, InitContainerSource, PortSource, ResourceRequirementsSource,
+        SidecarSource, WorkerSource,
     },
     kong::{KongApisBuildParams, KongApisSource, KongSource},
     newrelic_source::NewrelicSource,
     sentry_source::SentrySource,
-    util::{Build, Enabled, RelaxedString, Require},
+    util::{closest, merge_by_key, Build, Enabled, Field, ListMerge, RelaxedString, Require},
     SimpleManifest,
 };
 
@@ -60,6 +67,7 @@ pub struct MetadataSource {
     pub maintainers: Vec<String>,
     pub support: Option<SlackChannel>,
     pub notifications: Option<SlackChannel>,
+    pub channels: NotificationChannels,
     pub runbook: Option<String>,
     pub description: Option<String>,
     pub docs: Option<String>,
@@ -80,6 +88,8 @@ pub struct MetadataSource {
 #[derive(Deserialize, Default)]
 #[serde(default, rename_all = "camelCase")]
 pub struct ManifestSource {
+    /// Schema version the manifest was written against (current if unset)
+    pub schema_version: Option<u32>,
     pub name: Option<String>,
     pub external: bool,
     pub disabled: bool,
@@ -105,7 +115,8 @@ pub struct ManifestOverrides {
     pub data_handling: Option<DataHandling>,
     pub resources: Option<ResourceRequirementsSource>,
     pub secret_files: BTreeMap<String, String>,
-    pub configs: Option<ConfigMap>,
+    pub templated_secret_files: Option<Vec<String>>,
+    pub configs: Option<OneOrMany<ConfigMap>>,
     pub vault: Option<VaultOpts>,
     pub http_port: Option<u32>,
     pub ports: Option<Vec<PortSource>>,
@@ -129,10 +140,13 @@ pub struct ManifestOverrides {
     pub cron_jobs: Option<Vec<CronJobSource>>,
     pub service_annotations: BTreeMap<String, String>,
     pub pod_annotations: BTreeMap<String, RelaxedString>,
+    pub mesh_inject: Option<bool>,
+    pub deployment_annotations: BTreeMap<String, RelaxedString>,
     pub labels: BTreeMap<String, RelaxedString>,
     pub gate: Option<Gate>,
     pub kafka: Option<Kafka>,
     pub source_ranges: Option<Vec<String>>,
+    pub network_policy: Option<NetworkPolicy>,
     pub rbac: Option<Vec<Rbac>>,
     pub sentry: Option<SentrySource>,
     pub event_streams: Option<Vec<EventStream>>,
@@ -142,19 +156,119 @@ pub struct ManifestOverrides {
     pub newrelic: NewrelicSource,
     pub upgrade_notifications: Option<NotificationMode>,
     pub prometheus_alerts: Option<Vec<PrometheusAlert>>,
+    pub canary: Option<Canary>,
+    pub preserve_env_order: Option<bool>,
+    pub env_from: Option<Vec<EnvFromSource>>,
+    /// How this file's lists combine with earlier files (replace by default)
+    ///
+    /// With `mergeByKey`, entries matching an earlier entry are merged into it:
+    /// `sidecars`, `workers` and `initContainers` field by field on `name`,
+    /// while `volumes` (on `name`), `volumeMounts` (on `mountPath`),
+    /// `tolerations` (on `key`) and `hostAliases` (on `ip`) replace the earlier entry.
+    /// Other entries are appended.
+    pub list_merge: Option<ListMerge>,
 
     #[serde(flatten)]
     pub defaults: ManifestDefaults,
 }
 
+/// Top level fields of `ManifestOverrides` (excluding the flattened defaults)
+///
+/// Used to explain unknown fields, as serde cannot list them through `flatten`.
+pub(crate) const OVERRIDE_FIELDS: &[&str] = &[
+    "workload",
+    "publiclyAccessible",
+    "kompassPlugin",
+    "image",
+    "imageSize",
+    "version",
+    "command",
+    "securityContext",
+    "dataHandling",
+    "resources",
+    "secretFiles",
+    "templatedSecretFiles",
+    "configs",
+    "vault",
+    "httpPort",
+    "ports",
+    "externalPort",
+    "health",
+    "dependencies",
+    "destinationRules",
+    "workers",
+    "sidecars",
+    "readinessProbe",
+    "livenessProbe",
+    "lifecycle",
+    "rollingUpdate",
+    "autoScaling",
+    "tolerations",
+    "hostAliases",
+    "initContainers",
+    "volumes",
+    "volumeMounts",
+    "persistentVolumes",
+    "cronJobs",
+    "serviceAnnotations",
+    "podAnnotations",
+    "meshInject",
+    "deploymentAnnotations",
+    "labels",
+    "gate",
+    "kafka",
+    "sourceRanges",
+    "networkPolicy",
+    "rbac",
+    "sentry",
+    "eventStreams",
+    "kafkaResources",
+    "newrelic",
+    "upgradeNotifications",
+    "prometheusAlerts",
+    "canary",
+    "preserveEnvOrder",
+    "envFrom",
+    "listMerge",
+];
+
+/// Fields of `ManifestDefaults`
+pub(crate) const DEFAULT_FIELDS: &[&str] = &[
+    "imagePrefix",
+    "imagePullSecrets",
+    "topologySpreadConstraints",
+    "securityContextBaseline",
+    "chart",
+    "replicaCount",
+    "env",
+    "features",
+    "kongApis",
+    "kong",
+];
+
+/// Fields only valid in `manifest.yml`
+pub(crate) const SOURCE_FIELDS: &[&str] = &[
+    "schemaVersion",
+    "name",
+    "external",
+    "disabled",
+    "regions",
+    "metadata",
+];
+
 /// Global/regional manifest defaults, deserialized from `shipcat.conf` etc.
 #[derive(Deserialize, Default, Merge, Clone)]
 #[serde(default, deny_unknown_fields, rename_all = "camelCase")]
 pub struct ManifestDefaults {
     pub image_prefix: Option<String>,
+    pub image_pull_secrets: Option<Vec<String>>,
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    /// Security context options services get unless they set their own
+    pub security_context_baseline: Option<SecurityContext>,
     pub chart: Option<String>,
     pub replica_count: Option<u32>,
     pub env: EnvVarsSource,
+    pub features: BTreeMap<String, bool>,
     pub kong_apis: KongApisSource,
     // TODO: Migrate to kong_apis
     pub kong: Enabled<KongSource>,
@@ -163,18 +277,40 @@ pub struct ManifestDefaults {
 // impl Build<Manifest, (Config, Region)> - but no need to have this as a trait
 impl ManifestSource {
     /// Build a Manifest from a ManifestSource, validating and mutating properties.
-    pub async fn build(self, (conf, region): &(Config, Region)) -> Result<Manifest> {
+    ///
+    /// Failures are `ValidationError`s attributed to the service, region and field.
+    pub async fn build(self, params: &(Config, Region)) -> Result<Manifest> {
+        let service = self.name.clone().unwrap_or_default();
+        let region = params.1.name.clone();
+        self.build_fields(params)
+            .await
+            .map_err(|e| ValidationError::from_error(e).attribute(&service, &region).into())
+    }
+
+    async fn build_fields(self, (conf, region): &(Config, Region)) -> Result<Manifest> {
         let simple = self.build_simple(conf, region)?;
         let name = simple.base.name;
         let data_handling = self.build_data_handling();
-        let kafka = self.build_kafka(&name, region);
-        let configs = self.build_configs(&name).await?;
+        let kafka = self.build_kafka(&name, region)?;
+        let configs = self
+            .build_configs(&name, conf.template_root())
+            .await
+            .field("overrides.configs")?;
+        let source_ranges = self.build_source_ranges().field("overrides.sourceRanges")?;
+        let network_policy = self
+            .build_network_policy(&name, &source_ranges)
+            .field("overrides.networkPolicy")?;
 
         let overrides = self.overrides;
         let defaults = overrides.defaults;
 
+        let preserve_env_order = overrides.preserve_env_order.unwrap_or_default();
+        let resources = overrides.resources.build(&()).field("overrides.resources")?;
+        let mesh_inject = overrides.mesh_inject;
         let container_build_params = ContainerBuildParams {
             main_envs: defaults.env.clone(),
+            preserve_env_order,
+            main_resources: resources.clone(),
         };
 
         let team_notifications = simple
@@ -184,7 +320,52 @@ impl ManifestSource {
             .notifications
             .expect("notifications channel is always defined");
 
-        Ok(Manifest {
+        let ports = overrides
+            .ports
+            .unwrap_or_default()
+            .build(&())
+            .field("overrides.ports")?;
+        if let Some(a) = &overrides.auto_scaling {
+            a.verify().field("overrides.autoScaling")?;
+        }
+        let topology_spread_constraints = defaults
+            .topology_spread_constraints
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.verify().map(|_| c.with_default_selector(&name)))
+            .collect::<Result<Vec<_>>>()
+            .field("defaults.topologySpreadConstraints")?;
+        let health_port = overrides.health.as_ref().and_then(|h| h.port);
+        let probes = vec![
+            ("overrides.readinessProbe", &overrides.readiness_probe),
+            ("overrides.livenessProbe", &overrides.liveness_probe),
+        ];
+        for (field, probe) in probes {
+            if let Some(p) = probe {
+                p.verify().field(field)?;
+                verify_probe_port(p, overrides.http_port, health_port, &ports).field(field)?;
+            }
+        }
+        verify_external_port(
+            overrides.external_port,
+            overrides.http_port,
+            &ports,
+            simple.external,
+        )
+        .field("overrides.externalPort")?;
+        let volumes = overrides.volumes.unwrap_or_default();
+        let volume_mounts = overrides.volume_mounts.unwrap_or_default();
+        let persistent_volumes = overrides.persistent_volumes.unwrap_or_default();
+        verify_volume_mounts(&name, &volume_mounts, &volumes, &persistent_volumes, &configs)
+            .field("overrides.volumeMounts")?;
+
+        let security_context = build_security_context(
+            &name,
+            overrides.security_context,
+            defaults.security_context_baseline,
+        );
+
+        let mut mf = Manifest {
             name,
             publiclyAccessible: overrides.publicly_accessible.unwrap_or_default(),
             kompass_plugin: overrides.kompass_plugin.unwrap_or_default(),
@@ -200,18 +381,25 @@ impl ManifestSource {
             // TODO: Make imageSize non-optional
             imageSize: overrides.image_size.or(Some(512)),
             image: simple.image,
+            imagePullSecrets: defaults.image_pull_secrets.unwrap_or_default(),
             version: simple.version,
             command: overrides.command.unwrap_or_default(),
-            securityContext: overrides.security_context,
+            securityContext: security_context,
             dataHandling: data_handling,
-            resources: overrides.resources.build(&())?,
+            resources,
             replicaCount: defaults.replica_count,
-            env: defaults.env.build(&())?,
+            env: defaults.env.build_env(preserve_env_order).field("defaults.env")?,
+            envFrom: overrides
+                .env_from
+                .unwrap_or_default()
+                .build(&())
+                .field("overrides.envFrom")?,
             secretFiles: overrides.secret_files,
+            templatedSecretFiles: overrides.templated_secret_files.unwrap_or_default(),
             configs: configs,
             vault: overrides.vault,
             httpPort: overrides.http_port,
-            ports: overrides.ports.unwrap_or_default().build(&())?,
+            ports,
             externalPort: overrides.external_port,
             health: overrides.health,
             dependencies: overrides.dependencies.unwrap_or_default(),
@@ -219,42 +407,65 @@ impl ManifestSource {
             workers: overrides
                 .workers
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .field("overrides.workers")?,
             sidecars: overrides
                 .sidecars
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .field("overrides.sidecars")?,
             readinessProbe: overrides.readiness_probe,
             livenessProbe: overrides.liveness_probe,
             lifecycle: overrides.lifecycle,
             rollingUpdate: overrides.rolling_update,
-            autoScaling: overrides.auto_scaling,
+            autoScaling: overrides.auto_scaling.map(AutoScaling::with_custom_metrics),
             tolerations: overrides.tolerations.unwrap_or_default(),
+            topologySpreadConstraints: topology_spread_constraints,
             hostAliases: overrides.host_aliases.unwrap_or_default(),
             initContainers: overrides
                 .init_containers
                 .unwrap_or_default()
-                .build(&container_build_params)?,
-            volumes: overrides.volumes.unwrap_or_default(),
-            volumeMounts: overrides.volume_mounts.unwrap_or_default(),
-            persistentVolumes: overrides.persistent_volumes.unwrap_or_default(),
+                .build(&container_build_params)
+                .and_then(unique_container_names)
+                .field("overrides.initContainers")?,
+            volumes,
+            volumeMounts: volume_mounts,
+            persistentVolumes: persistent_volumes,
             cronJobs: overrides
                 .cron_jobs
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .into_iter()
+                .map(|mut cj| {
+                    cj.mesh_inject = cj.mesh_inject.or(mesh_inject);
+                    cj
+                })
+                .collect::<Vec<_>>()
+                .build(&container_build_params)
+                .field("overrides.cronJobs")?,
             serviceAnnotations: overrides.service_annotations,
-            podAnnotations: overrides.pod_annotations.build(&())?,
-            labels: overrides.labels.build(&())?,
+            podAnnotations: build_pod_annotations(overrides.pod_annotations, mesh_inject)
+                .field("overrides.podAnnotations")?,
+            deploymentAnnotations: overrides
+                .deployment_annotations
+                .build(&())
+                .field("overrides.deploymentAnnotations")?,
+            labels: overrides.labels.build(&()).field("overrides.labels")?,
+            features: defaults.features,
             kongApis: simple.kong_apis,
             gate: overrides.gate,
             kafka: kafka,
-            sourceRanges: overrides.source_ranges.unwrap_or_default(),
+            sourceRanges: source_ranges,
+            networkPolicy: network_policy,
             rbac: overrides.rbac.unwrap_or_default(),
-            newrelic: overrides.newrelic.build(&team_notifications)?,
+            newrelic: overrides
+                .newrelic
+                .build(&team_notifications)
+                .field("overrides.newrelic")?,
             sentry: overrides
                 .sentry
                 .map(|sentry| sentry.build(&team_notifications))
-                .transpose()?,
+                .transpose()
+                .field("overrides.sentry")?,
             eventStreams: overrides.event_streams.unwrap_or_default(),
             kafkaResources: overrides.kafka_resources,
             upgradeNotifications: Default::default(),
@@ -263,10 +474,17 @@ impl ManifestSource {
             namespace: region.namespace.clone(),
             uid: Default::default(),
             secrets: Default::default(),
+            secretFilesChecksum: Default::default(),
+            podDisruptionBudget: Default::default(),
             state: Default::default(),
+            templateRoot: conf.templateRoot.clone(),
             workload: overrides.workload.unwrap_or_default(),
             prometheusAlerts: overrides.prometheus_alerts.unwrap_or_default(),
-        })
+            canary: overrides.canary,
+        };
+        mf.podDisruptionBudget = mf.pod_disruption_budget();
+        mf.canary = mf.canary_with_replicas();
+        Ok(mf)
     }
 }
 
@@ -276,17 +494,26 @@ impl ManifestSource {
 
         let overrides = self.overrides.clone();
         let defaults = overrides.defaults;
-        let kong_apis = if let Some(k) = &region.kong {
-            defaults.kong_apis.build(&KongApisBuildParams {
-                service: base.name.to_string(),
-                region: region.clone(),
-                kong: k.clone(),
-                single_api: defaults.kong,
-            })?
+        let mut kong_apis = if let Some(k) = &region.kong {
+            defaults
+                .kong_apis
+                .build(&KongApisBuildParams {
+                    service: base.name.to_string(),
+                    region: region.clone(),
+                    kong: k.clone(),
+                    single_api: defaults.kong,
+                })
+                .field("defaults.kong")?
         } else {
             // NB: this drops kong entries on the floor if region.kong is None
             vec![]
         };
+        // gate limits are enforced by kong
+        if let Some(limits) = overrides.gate.and_then(|g| g.rate_limits) {
+            for k in &mut kong_apis {
+                k.gate_rate_limits = Some(limits.clone());
+            }
+        }
 
         Ok(SimpleManifest {
             region: region.name.to_string(),
@@ -295,8 +522,14 @@ impl ManifestSource {
             external: self.external,
 
             // TODO: Make image non-optional
-            image: Some(self.build_image(&base.name)?),
-            version: overrides.version.build(&())?,
+            image: Some(self.build_image(&base.name).field("overrides.image")?),
+            version: overrides
+                .version
+                .build(&())
+                .field("overrides.version")?
+                .map(|v| region.verify_tag_policy(&v).map(|_| region.suffixed_version(v)))
+                .transpose()
+                .field("overrides.version")?,
             kong_apis,
             base,
         })
@@ -304,17 +537,55 @@ impl ManifestSource {
 
     pub fn build_base(&self, conf: &Config) -> Result<BaseManifest> {
         // TODO: Remove and use folder name
-        let name = self.name.clone().require("name")?;
-        let metadata = self.build_metadata(conf)?;
-        let regions = self.regions.clone();
+        let name = self.name.clone().require("name").field("name")?;
+        let metadata = self.build_metadata(conf).field("metadata")?;
+        let regions = resolve_regions(&self.regions, &conf.list_regions()).field("regions")?;
 
         Ok(BaseManifest {
             name,
             regions,
             metadata,
+            external: self.external,
+            disabled: self.disabled,
         })
     }
 
+    fn build_source_ranges(&self) -> Result<Vec<String>> {
+        let ranges = self.overrides.source_ranges.clone().unwrap_or_default();
+        for r in &ranges {
+            if !is_cidr(r) {
+                bail!("sourceRanges entry '{}' is not a valid CIDR", r);
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn build_network_policy(&self, service: &str, source_ranges: &[String]) -> Result<Option<NetworkPolicy>> {
+        let mut np = match self.overrides.network_policy.clone() {
+            Some(np) => np,
+            None => return Ok(None),
+        };
+        np.cidrs = if np.allowSourceRanges {
+            if source_ranges.is_empty() {
+                bail!("allowSourceRanges needs sourceRanges to be set");
+            }
+            source_ranges.to_vec()
+        } else {
+            vec![]
+        };
+        for peer in &np.from {
+            if peer.pod_selector.is_none() && peer.namespace_selector.is_none() && peer.ip_block.is_none() {
+                bail!("networkPolicy.from entries need a podSelector, namespaceSelector or ipBlock");
+            }
+        }
+        if np.cidrs.is_empty() && np.from.is_empty() {
+            bail!("networkPolicy must allow ingress from some peers or sourceRanges");
+        }
+        np.podSelector = BTreeMap::new();
+        np.podSelector.insert("app".into(), service.into());
+        Ok(Some(np))
+    }
+
     fn build_image(&self, service: &str) -> Result<String> {
         if let Some(image) = &self.overrides.image {
             image.clone().build(&())
@@ -346,11 +617,28 @@ impl ManifestSource {
             if md.notifications.is_none() {
                 md.notifications = s.slack.notifications.as_ref().map(Clone::clone);
             }
+            if md.channels.deploy.is_none() {
+                md.channels.deploy = s.slack.deploys.as_ref().map(Clone::clone);
+            }
+            if md.channels.alert.is_none() {
+                md.channels.alert = s.slack.alerts.as_ref().map(Clone::clone);
+            }
+            if md.channels.oncall.is_none() {
+                md.channels.oncall = s.slack.oncall.as_ref().map(Clone::clone);
+            }
         } else {
+            let squads = conf.owners.squads.keys().map(String::as_str);
+            let hint = match closest(&md.team, squads) {
+                Some(c) => format!(" - did you mean '{}'?", c),
+                None => "".into(),
+            };
+            let valid = conf.owners.squads.keys().cloned().collect::<Vec<_>>();
             bail!(
-                "{}: metadata.team '{}' must match a squad in teams.yml",
+                "{}: metadata.team '{}' must match a squad in teams.yml{} (valid teams: {})",
                 name,
-                md.team
+                md.team,
+                hint,
+                valid.join(", ")
             )
         }
 
@@ -371,6 +659,7 @@ impl ManifestSource {
             maintainers: md.maintainers,
             support: md.support,
             notifications: md.notifications,
+            channels: md.channels,
             runbook: md.runbook,
             description: md.description,
             docs: md.docs,
@@ -399,56 +688,116 @@ impl ManifestSource {
     }
 
     // TODO: Extract KafkaSource
-    fn build_kafka(&self, service: &str, reg: &Region) -> Option<Kafka> {
+    fn build_kafka(&self, service: &str, reg: &Region) -> Result<Option<Kafka>> {
         let original = &self.overrides.kafka;
-        original.clone().map(|mut kf| {
+        let kafka = original.clone().map(|mut kf| {
             kf.implicits(service, reg.clone());
             kf
-        })
+        });
+        if let Some(kr) = &self.overrides.kafka_resources {
+            let names = kr.topics.iter().map(|t| t.name.as_str());
+            reg.kafka
+                .verify_topic_names(names)
+                .field("overrides.kafkaResources.topics")?;
+        }
+        Ok(kafka)
     }
 
     // TODO: Extract ConfigsSource
-    async fn build_configs(&self, service: &str) -> Result<Option<ConfigMap>> {
-        let original = &self.overrides.configs;
-        if original.is_none() {
-            return Ok(None);
+    async fn build_configs(&self, service: &str, root: &Path) -> Result<Vec<ConfigMap>> {
+        let mut configs = match self.overrides.configs.clone() {
+            None => return Ok(vec![]),
+            Some(OneOrMany::One(x)) => vec![x],
+            Some(OneOrMany::Many(xs)) => xs,
+        };
+        // Ensure every template can be found before reading any of them
+        for f in configs.iter().flat_map(|c| &c.files) {
+            find_template_file_in(root, service, &f.name)?;
         }
-        let mut configs = original.clone().unwrap();
-        for f in &mut configs.files {
-            f.value = Some(read_template_file(service, &f.name).await?);
+        for f in configs.iter_mut().flat_map(|c| &mut c.files) {
+            f.value = Some(read_template_file(root, service, &f.name).await?);
         }
-        Ok(Some(configs))
+        Ok(configs)
     }
 
-    pub(crate) fn merge_overrides(mut self, other: ManifestOverrides) -> Self {
+    pub(crate) fn merge_overrides(mut self, mut other: ManifestOverrides) -> Self {
+        if other.list_merge == Some(ListMerge::MergeByKey) {
+            let base = &mut self.overrides;
+            other.sidecars = merge_by_key(base.sidecars.take(), other.sidecars);
+            other.workers = merge_by_key(base.workers.take(), other.workers);
+            other.init_containers = merge_by_key(base.init_containers.take(), other.init_containers);
+            other.volumes = merge_by_key(base.volumes.take(), other.volumes);
+            other.volume_mounts = merge_by_key(base.volume_mounts.take(), other.volume_mounts);
+            other.tolerations = merge_by_key(base.tolerations.take(), other.tolerations);
+            other.host_aliases = merge_by_key(base.host_aliases.take(), other.host_aliases);
+        }
         self.overrides = self.overrides.merge(other);
         self
     }
+
+    /// Merge environment and region overrides, then apply the region's replica scaling
+    ///
+    /// Scaling is skipped when any of the overrides sets `replicaCount` explicitly.
+    pub(crate) fn merge_regional(mut self, overrides: Vec<ManifestOverrides>, scale: Option<f64>) -> Self {
+        let explicit = overrides.iter().any(|o| o.defaults.replica_count.is_some());
+        for o in overrides {
+            self = self.merge_overrides(o);
+        }
+        if let Some(factor) = scale.filter(|_| !explicit) {
+            let count = &mut self.overrides.defaults.replica_count;
+            *count = count.map(|c| ((f64::from(c) * factor).floor() as u32).max(1));
+        }
+        self
+    }
 }
 
-async fn read_template_file(svc: &str, tmpl: &str) -> Result<String> {
-    use std::path::Path;
-    use tokio::fs;
-    // try to read file from ./services/{svc}/{tmpl} into `tpl` sting
-    let pth = Path::new(".").join("services").join(svc).join(tmpl);
-    let gpth = Path::new(".").join("templates").join(tmpl);
-    let found_pth = if pth.exists() {
-        debug!("Reading template in {}", pth.display());
-        pth
+/// Locate a template in {root}/services/{svc}/{tmpl} or {root}/templates/{tmpl}
+pub(crate) fn find_template_file_in(root: &Path, svc: &str, tmpl: &str) -> Result<PathBuf> {
+    let pth = root.join("services").join(svc).join(tmpl);
+    let gpth = root.join("templates").join(tmpl);
+    if pth.exists() {
+        debug!("Found template in {}", pth.display());
+        Ok(pth)
+    } else if gpth.exists() {
+        debug!("Found template in {}", gpth.display());
+        Ok(gpth)
     } else {
-        if !gpth.exists() {
-            bail!(
-                "Template {} does not exist in neither {} nor {}",
-                tmpl,
-                pth.display(),
-                gpth.display()
-            );
-        }
-        debug!("Reading template in {}", gpth.display());
-        gpth
-    };
-    // read the template - should work now
+        bail!("Template {} not found in services/{} or templates/", tmpl, svc)
+    }
+}
+
+lazy_static! {
+    /// Template contents by path, along with the mtime they were read at
+    ///
+    /// Shared by the whole process so templates in `templates/` are only read once
+    /// when completing several services.
+    static ref TEMPLATE_CACHE: Mutex<HashMap<PathBuf, (SystemTime, String)>> = Mutex::new(HashMap::new());
+}
+
+/// Contents of a template from an earlier read, if the file is unchanged since
+fn cached_template(pth: &Path, mtime: SystemTime) -> Option<String> {
+    let cache = TEMPLATE_CACHE.lock().unwrap();
+    cache
+        .get(pth)
+        .filter(|(t, _)| *t == mtime)
+        .map(|(_, data)| data.clone())
+}
+
+/// Read a template, reusing an earlier read of it unless the file has since changed
+async fn read_template_file(root: &Path, svc: &str, tmpl: &str) -> Result<String> {
+    use tokio::fs;
+    let found_pth = find_template_file_in(root, svc, tmpl)?;
+    let mtime = fs::metadata(&found_pth).await?.modified()?;
+    if let Some(data) = cached_template(&found_pth, mtime) {
+        debug!("Using cached template {}", found_pth.display());
+        return Ok(data);
+    }
+    debug!("Reading template in {}", found_pth.display());
     let data = fs::read_to_string(&found_pth).await?;
+    TEMPLATE_CACHE
+        .lock()
+        .unwrap()
+        .insert(found_pth, (mtime, data.clone()));
     Ok(data)
 }
 
@@ -459,12 +808,463 @@ impl ManifestDefaults {
     }
 }
 
+/// Ensure containers that run in declaration order have distinct names
+///
+/// Names are what region overrides with `mergeByKey` merge on, so a duplicate
+/// leaves it ambiguous which entry an override applies to.
+fn unique_container_names(containers: Vec<Container>) -> Result<Vec<Container>> {
+    for (i, c) in containers.iter().enumerate() {
+        if containers[..i].iter().any(|prev| prev.name == c.name) {
+            bail!("Container name {} is used more than once", c.name);
+        }
+    }
+    Ok(containers)
+}
+
+/// Apply the security baseline to the security context of a service
+///
+/// Services can opt out of baseline options, but this is logged.
+fn build_security_context(
+    service: &str,
+    own: Option<SecurityContext>,
+    baseline: Option<SecurityContext>,
+) -> Option<SecurityContext> {
+    match (own, baseline) {
+        (Some(own), Some(baseline)) => {
+            let (sc, overridden) = own.with_baseline(&baseline);
+            if let Some(msg) = baseline_override_warning(service, &overridden) {
+                warn!("{}", msg);
+            }
+            Some(sc)
+        }
+        (own, baseline) => own.or(baseline),
+    }
+}
+
+fn baseline_override_warning(service: &str, overridden: &[&str]) -> Option<String> {
+    if overridden.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} overrides the security context baseline for {}",
+        service,
+        overridden.join(", ")
+    ))
+}
+
+/// Ensure a probe targets a port declared on the main container
+///
+/// Ports are referenced by name or number. `httpPort` declares the `http` port,
+/// and a distinct `health.port` declares `health-http` (as named by the charts).
+fn verify_probe_port(
+    probe: &Probe,
+    http_port: Option<u32>,
+    health_port: Option<u32>,
+    ports: &[Port],
+) -> Result<()> {
+    let target = match (probe.target_port(), probe.grpc_port()) {
+        (Some(t), _) => t.to_string(),
+        (None, Some(p)) => p.to_string(),
+        (None, None) => return Ok(()), // exec probes
+    };
+    let mut declared: Vec<(&str, u32)> = ports.iter().map(|p| (p.name.as_str(), p.port)).collect();
+    if let Some(hp) = http_port {
+        declared.push(("http", hp));
+        if let Some(p) = health_port.filter(|p| *p != hp) {
+            declared.push(("health-http", p));
+        }
+    }
+    let found = match target.parse::<u32>() {
+        Ok(n) => declared.iter().any(|(_, p)| *p == n),
+        Err(_) => declared.iter().any(|(name, _)| *name == target.as_str()),
+    };
+    if !found {
+        let names = declared
+            .iter()
+            .map(|(n, p)| format!("{} ({})", n, p))
+            .collect::<Vec<_>>();
+        bail!(
+            "probe targets port {} which is not declared (declared ports: {})",
+            target,
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Ensure an `externalPort` is a port the main container listens on
+///
+/// External services have no containers, so anything goes there.
+fn verify_external_port(
+    external_port: Option<u32>,
+    http_port: Option<u32>,
+    ports: &[Port],
+    external: bool,
+) -> Result<()> {
+    match external_port {
+        Some(ep) if !external && http_port != Some(ep) && !ports.iter().any(|p| p.port == ep) => {
+            bail!("externalPort {} is not the httpPort or a declared port", ep)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Ensure every volume mount names a volume the pod declares
+///
+/// Besides `volumes` and `persistentVolumes`, the charts declare one volume per
+/// config map, named `{service}-config{-name}-volume`.
+fn verify_volume_mounts(
+    service: &str,
+    mounts: &[VolumeMount],
+    volumes: &[Volume],
+    persistent_volumes: &[PersistentVolume],
+    configs: &[ConfigMap],
+) -> Result<()> {
+    let config_volumes = configs
+        .iter()
+        .map(|c| match &c.name {
+            Some(n) => format!("{}-config-{}-volume", service, n),
+            None => format!("{}-config-volume", service),
+        })
+        .collect::<Vec<_>>();
+    for m in mounts {
+        let declared = volumes.iter().any(|v| v.name == m.name)
+            || persistent_volumes.iter().any(|pv| pv.name == m.name)
+            || config_volumes.contains(&m.name);
+        if !declared {
+            bail!("volume mount {} does not reference a declared volume", m.name);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a string is an IPv4 or IPv6 CIDR range like `10.0.0.0/8`
+fn is_cidr(range: &str) -> bool {
+    use std::net::IpAddr;
+    let mut parts = range.splitn(2, '/');
+    let addr = parts.next().and_then(|a| a.parse::<IpAddr>().ok());
+    let prefix = parts.next().and_then(|p| p.parse::<u8>().ok());
+    match (addr, prefix) {
+        (Some(IpAddr::V4(_)), Some(p)) => p <= 32,
+        (Some(IpAddr::V6(_)), Some(p)) => p <= 128,
+        _ => false,
+    }
+}
+
+/// Resolve `!region` negations in a `regions` list
+///
+/// Negations exclude a region from the listed ones, or from all known regions
+/// when only negations are given. Configs loaded for a single region only know that one.
+fn resolve_regions(declared: &[String], known: &[String]) -> Result<Vec<String>> {
+    let (negated, listed): (Vec<&String>, Vec<&String>) = declared.iter().partition(|r| r.starts_with('!'));
+    let excluded = negated.iter().map(|r| &r[1..]).collect::<Vec<_>>();
+    if excluded.iter().any(|r| r.is_empty()) {
+        bail!("Negated regions need a region name after the !");
+    }
+    let base = if listed.is_empty() && !negated.is_empty() {
+        known.iter().collect()
+    } else {
+        listed
+    };
+    Ok(base
+        .into_iter()
+        .filter(|r| !excluded.contains(&r.as_str()))
+        .cloned()
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use merge::Merge;
     use std::collections::BTreeMap;
 
-    use super::ManifestDefaults;
+    use super::{
+        baseline_override_warning, build_security_context, cached_template, is_cidr, read_template_file,
+        resolve_regions, unique_container_names, verify_external_port, verify_probe_port,
+        verify_volume_mounts, ManifestDefaults, ManifestOverrides, ManifestSource, OneOrMany,
+    };
+    use crate::{container::ContainerBuildParams, util::Build};
+    use shipcat_definitions::{
+        structs::{Port, Probe, SecurityContext},
+        Result,
+    };
+
+    #[tokio::test]
+    async fn shared_templates_are_read_once() -> Result<()> {
+        use std::fs;
+        let root = std::env::temp_dir().join("shipcat-shared-template");
+        fs::create_dir_all(root.join("templates"))?;
+        fs::create_dir_all(root.join("services").join("a"))?;
+        fs::create_dir_all(root.join("services").join("b"))?;
+        let shared = root.join("templates").join("shared.ini.j2");
+        fs::write(&shared, "x = {{ x }}")?;
+        let mtime = fs::metadata(&shared)?.modified()?;
+
+        assert_eq!(
+            read_template_file(&root, "a", "shared.ini.j2").await?,
+            "x = {{ x }}"
+        );
+        // a second service using the same template is served from the cache
+        assert_eq!(cached_template(&shared, mtime), Some("x = {{ x }}".into()));
+        assert_eq!(
+            read_template_file(&root, "b", "shared.ini.j2").await?,
+            "x = {{ x }}"
+        );
+        // but only for as long as the file is unchanged
+        assert_eq!(cached_template(&shared, std::time::UNIX_EPOCH), None);
+        Ok(())
+    }
+
+    #[test]
+    fn cidr_ranges() {
+        assert!(is_cidr("0.0.0.0/0"));
+        assert!(is_cidr("10.1.0.0/16"));
+        assert!(is_cidr("2001:db8::/32"));
+        assert!(!is_cidr("10.1.0.0"));
+        assert!(!is_cidr("10.1.0.0/33"));
+        assert!(!is_cidr("10.1.0/16"));
+    }
+
+    #[test]
+    fn security_context_baseline() {
+        let sc = |s: &str| Some(serde_yaml::from_str::<SecurityContext>(s).unwrap());
+        let baseline = sc("runAsNonRoot: true\nallowPrivilegeEscalation: false");
+
+        let inherited = build_security_context("fake-ask", None, baseline.clone()).unwrap();
+        assert_eq!(inherited.runAsNonRoot, Some(true));
+        let own = build_security_context("fake-ask", sc("runAsUser: 1000"), baseline.clone()).unwrap();
+        assert_eq!(own.runAsUser, Some(1000));
+        assert_eq!(own.allowPrivilegeEscalation, Some(false));
+
+        let optout = build_security_context("fake-ask", sc("runAsNonRoot: false"), baseline).unwrap();
+        assert_eq!(optout.runAsNonRoot, Some(false));
+        assert_eq!(optout.allowPrivilegeEscalation, Some(false));
+        assert_eq!(
+            baseline_override_warning("fake-ask", &["runAsNonRoot"]).unwrap(),
+            "fake-ask overrides the security context baseline for runAsNonRoot"
+        );
+        assert!(baseline_override_warning("fake-ask", &[]).is_none());
+    }
+
+    #[test]
+    fn network_policy_is_opt_in() -> Result<()> {
+        let source = |s: &str| ManifestSource {
+            overrides: serde_yaml::from_str::<ManifestOverrides>(s).unwrap(),
+            ..Default::default()
+        };
+        let ranges = vec!["10.1.0.0/16".to_string()];
+        let unset = ManifestSource::default();
+        assert!(unset.build_network_policy("fake-ask", &ranges)?.is_none());
+
+        let kong = "networkPolicy:\n  allowSourceRanges: true\n  from:\n  - namespaceSelector: {matchLabels: {name: kong}}\n";
+        let np = source(kong).build_network_policy("fake-ask", &ranges)?.unwrap();
+        assert_eq!(np.cidrs, ranges);
+        assert_eq!(np.podSelector["app"], "fake-ask");
+        let selector = np.from[0].namespace_selector.clone().unwrap();
+        assert_eq!(selector.match_labels.unwrap()["name"], "kong");
+
+        let pods = "networkPolicy:\n  from:\n  - podSelector: {matchLabels: {app: fake-storage}}\n";
+        let peers = source(pods).build_network_policy("fake-ask", &ranges)?.unwrap();
+        assert!(peers.cidrs.is_empty());
+
+        let no_ranges = source("networkPolicy:\n  allowSourceRanges: true\n");
+        assert!(no_ranges.build_network_policy("fake-ask", &[]).is_err());
+        let no_peers = source("networkPolicy:\n  from:\n  - {}\n");
+        assert!(no_peers.build_network_policy("fake-ask", &ranges).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn init_container_order_and_uniqueness() -> Result<()> {
+        let overrides = |s: &str| serde_yaml::from_str::<ManifestOverrides>(s).unwrap();
+        let base = || ManifestSource {
+            overrides: overrides(
+                "initContainers:\n- name: migrate\n  image: app\n- name: wait-db\n  image: busybox\n",
+            ),
+            ..Default::default()
+        };
+        let params = ContainerBuildParams {
+            main_envs: Default::default(),
+            preserve_env_order: false,
+            main_resources: None,
+        };
+        let names = |src: ManifestSource| -> Result<Vec<String>> {
+            let built = src.overrides.init_containers.unwrap_or_default().build(&params)?;
+            let unique = unique_container_names(built)?;
+            Ok(unique.into_iter().map(|c| c.name).collect())
+        };
+
+        let merged = base().merge_overrides(overrides(
+            "listMerge: mergeByKey\ninitContainers:\n- name: wait-db\n  image: postgres\n- name: seed\n  image: app\n",
+        ));
+        assert_eq!(names(merged)?, vec!["migrate", "wait-db", "seed"]);
+
+        let duplicated = base().merge_overrides(overrides(
+            "initContainers:\n- name: seed\n  image: app\n- name: seed\n  image: app\n",
+        ));
+        let err = names(duplicated).unwrap_err();
+        assert_eq!(err.to_string(), "Container name seed is used more than once");
+        Ok(())
+    }
+
+    #[test]
+    fn negated_regions() {
+        let strs = |xs: &[&str]| xs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let known = strs(&["dev-uk", "staging-uk", "prod-uk"]);
+        let resolve = |xs: &[&str]| resolve_regions(&strs(xs), &known).unwrap();
+        assert_eq!(resolve(&["dev-uk", "prod-uk"]), strs(&["dev-uk", "prod-uk"]));
+        assert_eq!(resolve(&["!prod-uk"]), strs(&["dev-uk", "staging-uk"]));
+        assert_eq!(resolve(&["!prod-uk", "!dev-uk"]), strs(&["staging-uk"]));
+        // mixed entries exclude from the listed ones only
+        assert_eq!(resolve(&["dev-uk", "prod-uk", "!prod-uk"]), strs(&["dev-uk"]));
+        assert!(resolve(&[]).is_empty());
+        assert!(resolve_regions(&strs(&["!"]), &known).is_err());
+    }
+
+    #[test]
+    fn probe_ports_must_be_declared() {
+        let probe = |port: &str| -> Probe {
+            serde_yaml::from_str(&format!("httpGet:\n  path: /health\n  port: {}\n", port)).unwrap()
+        };
+        let ports = vec![Port {
+            name: "admin".into(),
+            port: 9000,
+            ..Default::default()
+        }];
+        // named and numeric references to declared ports
+        assert!(verify_probe_port(&probe("http"), Some(8080), None, &ports).is_ok());
+        assert!(verify_probe_port(&probe("admin"), Some(8080), None, &ports).is_ok());
+        assert!(verify_probe_port(&probe("9000"), None, None, &ports).is_ok());
+        assert!(verify_probe_port(&probe("health-http"), Some(8080), Some(8081), &ports).is_ok());
+
+        let err = verify_probe_port(&probe("metrics"), Some(8080), None, &ports).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "probe targets port metrics which is not declared (declared ports: admin (9000), http (8080))"
+        );
+        assert!(verify_probe_port(&probe("http"), None, None, &ports).is_err());
+        assert!(verify_probe_port(&probe("8081"), Some(8080), None, &ports).is_err());
+    }
+
+    #[test]
+    fn external_port_must_be_declared() {
+        let ports = vec![Port {
+            name: "admin".into(),
+            port: 9000,
+            ..Default::default()
+        }];
+        assert!(verify_external_port(None, None, &[], false).is_ok());
+        assert!(verify_external_port(Some(8080), Some(8080), &ports, false).is_ok());
+        assert!(verify_external_port(Some(9000), Some(8080), &ports, false).is_ok());
+
+        let err = verify_external_port(Some(443), Some(8080), &ports, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "externalPort 443 is not the httpPort or a declared port"
+        );
+        // external services have no container ports
+        assert!(verify_external_port(Some(443), None, &[], true).is_ok());
+    }
+
+    #[test]
+    fn volume_mounts_must_be_declared() {
+        let overrides: ManifestOverrides = serde_yaml::from_str(
+            "volumes:\n- name: certs\n  secret:\n    secretName: fake-ask-certs\n    items: []\n\
+             persistentVolumes:\n- name: data\n  mountPath: /data\n  size: 1Gi\n\
+             configs:\n  mount: /config/\n  files:\n  - name: env.j2\n    dest: .env\n\
+             volumeMounts:\n- name: certs\n  mountPath: /certs\n- name: data\n  mountPath: /data\n\
+             - name: fake-ask-config-volume\n  mountPath: /config\n",
+        )
+        .unwrap();
+        let volumes = overrides.volumes.unwrap();
+        let pvs = overrides.persistent_volumes.unwrap();
+        let configs = match overrides.configs.unwrap() {
+            OneOrMany::One(c) => vec![c],
+            OneOrMany::Many(cs) => cs,
+        };
+        let mut mounts = overrides.volume_mounts.unwrap();
+        assert!(verify_volume_mounts("fake-ask", &mounts, &volumes, &pvs, &configs).is_ok());
+
+        mounts[1].name = "missing".into();
+        let err = verify_volume_mounts("fake-ask", &mounts, &volumes, &pvs, &configs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "volume mount missing does not reference a declared volume"
+        );
+        // config volumes belong to the service
+        assert!(verify_volume_mounts("fake-storage", &mounts[2..], &volumes, &pvs, &configs).is_err());
+    }
+
+    #[test]
+    fn grpc_liveness_probe() {
+        let overrides: ManifestOverrides = serde_yaml::from_str(
+            "ports:\n- name: grpc\n  port: 50051\nlivenessProbe:\n  grpc:\n    port: 50051\n    service: fake-ask\n",
+        )
+        .unwrap();
+        let ports = overrides.ports.unwrap().build(&()).unwrap();
+        let probe = overrides.liveness_probe.unwrap();
+        assert!(probe.verify().is_ok());
+        assert!(verify_probe_port(&probe, None, None, &ports).is_ok());
+        let json = serde_json::to_value(&probe).unwrap();
+        assert_eq!(json["grpc"]["service"], "fake-ask");
+        let undeclared: Probe = serde_yaml::from_str("grpc:\n  port: 9090\n").unwrap();
+        assert!(verify_probe_port(&undeclared, None, None, &ports).is_err());
+
+        let both: Probe = serde_yaml::from_str("httpGet:\n  path: /health\ngrpc:\n  port: 50051\n").unwrap();
+        let err = both.verify().unwrap_err();
+        assert_eq!(err.to_string(), "Probe cannot define both 'httpGet' and 'grpc'");
+        let noport: Probe = serde_yaml::from_str("grpc:\n  port: 0\n").unwrap();
+        assert!(noport.verify().is_err());
+    }
+
+    #[test]
+    fn replica_scale_factor() {
+        let replicas = |n| ManifestOverrides {
+            defaults: ManifestDefaults {
+                replica_count: Some(n),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let base = || ManifestSource {
+            overrides: replicas(2),
+            ..Default::default()
+        };
+        let scaled = base().merge_regional(vec![], Some(3.0));
+        assert_eq!(scaled.overrides.defaults.replica_count, Some(6));
+        let floored = base().merge_regional(vec![], Some(0.25));
+        assert_eq!(floored.overrides.defaults.replica_count, Some(1));
+        // an explicit regional count wins
+        let explicit = base().merge_regional(vec![replicas(4)], Some(3.0));
+        assert_eq!(explicit.overrides.defaults.replica_count, Some(4));
+    }
+
+    #[test]
+    fn feature_flags_override_region_defaults() {
+        let flags = |fs: &[(&str, bool)]| ManifestDefaults {
+            features: fs.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            ..Default::default()
+        };
+        let regional = flags(&[("new-cache", true), ("tracing", true)]);
+        let service = ManifestSource {
+            overrides: ManifestOverrides {
+                defaults: flags(&[("new-cache", false)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let region_file = ManifestOverrides {
+            defaults: flags(&[("beta-api", true)]),
+            ..Default::default()
+        };
+        let merged = regional
+            .merge_source(service)
+            .merge_regional(vec![region_file], None);
+        let features = merged.overrides.defaults.features;
+        assert_eq!(features["new-cache"], false);
+        assert_eq!(features["tracing"], true);
+        assert_eq!(features["beta-api"], true);
+    }
 
     #[test]
     fn merge() {
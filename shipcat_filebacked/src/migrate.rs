@@ -0,0 +1,88 @@
+use serde_yaml::Value;
+use std::path::Path;
+
+use shipcat_definitions::Result;
+
+/// Schema version of manifests understood by this shipcat
+///
+/// Manifests without a `schemaVersion` are assumed to be current.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Top level fields renamed in a schema version, as `(version, old, new)`
+const RENAMES: &[(u32, &str, &str)] = &[
+    (2, "replicas", "replicaCount"),
+    (2, "annotations", "podAnnotations"),
+];
+
+/// Schema version declared in a raw `manifest.yml`
+pub(crate) fn schema_version(path: &Path, value: &Value) -> Result<u32> {
+    let declared = match value.get("schemaVersion") {
+        None => return Ok(SCHEMA_VERSION),
+        Some(v) => v,
+    };
+    match declared.as_u64() {
+        Some(v) if v >= 1 && v <= u64::from(SCHEMA_VERSION) => Ok(v as u32),
+        Some(v) if v > u64::from(SCHEMA_VERSION) => bail!(
+            "schemaVersion {} in {} is newer than the supported {} - please upgrade shipcat",
+            v,
+            path.display(),
+            SCHEMA_VERSION
+        ),
+        _ => bail!("Invalid schemaVersion {:?} in {}", declared, path.display()),
+    }
+}
+
+/// Upgrade a raw manifest or override file from an older schema version in place
+///
+/// Every deprecated form found is warned about, so it can be fixed at the source.
+pub(crate) fn migrate(path: &Path, value: &mut Value, from: u32) -> Result<()> {
+    let mapping = match value.as_mapping_mut() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    for (version, old, new) in RENAMES.iter().filter(|(v, ..)| *v > from) {
+        let old_key = Value::from(*old);
+        if let Some(v) = mapping.remove(&old_key) {
+            warn!(
+                "`{}` in {} is deprecated since schemaVersion {}, use `{}`",
+                old,
+                path.display(),
+                version,
+                new
+            );
+            let new_key = Value::from(*new);
+            if mapping.contains_key(&new_key) {
+                bail!("Both `{}` and `{}` are set in {}", old, new, path.display());
+            }
+            mapping.insert(new_key, v);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, schema_version, SCHEMA_VERSION};
+    use serde_yaml::Value;
+    use std::path::Path;
+
+    #[test]
+    fn schema_versions() {
+        let pth = Path::new("manifest.yml");
+        let parse = |s: &str| serde_yaml::from_str::<Value>(s).unwrap();
+        assert_eq!(schema_version(pth, &parse("name: a")).unwrap(), SCHEMA_VERSION);
+        assert_eq!(schema_version(pth, &parse("schemaVersion: 1")).unwrap(), 1);
+        let err = schema_version(pth, &parse("schemaVersion: 99")).unwrap_err();
+        assert!(err.to_string().contains("newer than the supported"));
+        assert!(schema_version(pth, &parse("schemaVersion: v1")).is_err());
+
+        let mut v = parse("replicas: 3");
+        migrate(pth, &mut v, SCHEMA_VERSION).unwrap();
+        assert_eq!(v, parse("replicas: 3"));
+        migrate(pth, &mut v, 1).unwrap();
+        assert_eq!(v, parse("replicaCount: 3"));
+
+        let mut both = parse("replicas: 3\nreplicaCount: 2");
+        assert!(migrate(pth, &mut both, 1).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use shipcat_definitions::{Config, Manifest, Region, Result};
+
+use crate::manifest::ManifestSource;
+
+impl ManifestSource {
+    /// Build all enabled manifests in a region
+    ///
+    /// Errors are collected per service before cross manifest validation runs.
+    pub async fn load_region(conf: &Config, reg: &Region) -> Result<Vec<Manifest>> {
+        let mut manifests = vec![];
+        let mut errs = vec![];
+        for svc in Self::available(conf, reg).await? {
+            match Self::load_manifest(&svc.base.name, conf, reg).await {
+                Ok(mf) => manifests.push(mf),
+                Err(e) => errs.push(format!("{}: {}", svc.base.name, e)),
+            }
+        }
+        if !errs.is_empty() {
+            bail!(
+                "Failed to build {} manifests in {}:\n{}",
+                errs.len(),
+                reg.name,
+                errs.join("\n")
+            );
+        }
+        verify_kong_hosts(&manifests)?;
+        verify_no_cycles(&manifests)?;
+        Ok(manifests)
+    }
+}
+
+/// Ensure no two services claim the same kong host
+fn verify_kong_hosts(mfs: &[Manifest]) -> Result<()> {
+    let mut owners: BTreeMap<&str, &str> = BTreeMap::new();
+    for mf in mfs {
+        for host in mf.kongApis.iter().flat_map(|k| &k.hosts) {
+            match owners.get(host.as_str()) {
+                Some(owner) if *owner != mf.name => {
+                    bail!("Kong host {} is used by both {} and {}", host, owner, mf.name)
+                }
+                _ => owners.insert(host, &mf.name),
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Ensure the dependency graph between services in the region is acyclic
+fn verify_no_cycles(mfs: &[Manifest]) -> Result<()> {
+    let deps: BTreeMap<&str, Vec<&str>> = mfs
+        .iter()
+        .map(|mf| {
+            (
+                mf.name.as_str(),
+                mf.dependencies.iter().map(|d| d.name.as_str()).collect(),
+            )
+        })
+        .collect();
+    let mut done = BTreeSet::new();
+    for name in deps.keys() {
+        let mut path = vec![];
+        if let Some(cycle) = find_cycle(*name, &deps, &mut path, &mut done) {
+            bail!("Dependency cycle detected: {}", cycle.join(" -> "));
+        }
+    }
+    Ok(())
+}
+
+fn find_cycle<'a>(
+    name: &'a str,
+    deps: &BTreeMap<&'a str, Vec<&'a str>>,
+    path: &mut Vec<&'a str>,
+    done: &mut BTreeSet<&'a str>,
+) -> Option<Vec<&'a str>> {
+    if let Some(idx) = path.iter().position(|p| *p == name) {
+        let mut cycle = path[idx..].to_vec();
+        cycle.push(name);
+        return Some(cycle);
+    }
+    if done.contains(name) {
+        return None;
+    }
+    path.push(name);
+    for dep in deps.get(name).into_iter().flatten() {
+        if let Some(cycle) = find_cycle(*dep, deps, path, done) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(name);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_kong_hosts, verify_no_cycles};
+    use crate::manifest::ManifestSource;
+    use shipcat_definitions::{structs::Dependency, Config, Manifest};
+    use std::{env, fs, path::Path};
+
+    fn with_deps(name: &str, deps: &[&str]) -> Manifest {
+        let mut mf = Manifest::test(name);
+        mf.dependencies = deps
+            .iter()
+            .map(|d| Dependency {
+                name: d.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        mf
+    }
+
+    #[test]
+    fn region_dependency_cycles() {
+        let mfs = vec![
+            with_deps("a", &["b"]),
+            with_deps("b", &["c"]),
+            with_deps("c", &[]),
+        ];
+        assert!(verify_no_cycles(&mfs).is_ok());
+        let mfs = vec![with_deps("a", &["b"]), with_deps("b", &["a"])];
+        let err = verify_no_cycles(&mfs).unwrap_err();
+        assert_eq!(err.to_string(), "Dependency cycle detected: a -> b -> a");
+    }
+
+    #[test]
+    fn region_kong_host_collision() {
+        let mut mfs = vec![Manifest::test("a"), Manifest::test("b")];
+        assert!(verify_kong_hosts(&mfs).is_ok());
+        for mf in &mut mfs {
+            let mut kong = shipcat_definitions::structs::Kong::default();
+            kong.hosts = vec!["shared.example.com".into()];
+            mf.kongApis = vec![kong];
+        }
+        let err = verify_kong_hosts(&mfs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Kong host shared.example.com is used by both a and b"
+        );
+    }
+
+    #[tokio::test]
+    async fn load_region_skips_disabled() {
+        let pwd = env::current_dir().unwrap();
+        let pth = fs::canonicalize(Path::new(&pwd).join("..").join("tests")).unwrap();
+        env::set_current_dir(pth).unwrap();
+
+        let conf = Config::read().await.unwrap();
+        let region = conf.get_region("dev-uk").unwrap();
+        let mfs = ManifestSource::load_region(&conf, &region).await.unwrap();
+        let names: Vec<_> = mfs.into_iter().map(|mf| mf.name).collect();
+        assert_eq!(names, vec!["fake-ask".to_string(), "fake-storage".to_string()]);
+    }
+}
@@ -0,0 +1,576 @@
+//! JSON Schema export for `manifest.yml`
+//!
+//! The schema is discovered by probing the serde `Deserialize` impls of the source
+//! structs with a recording deserializer, so renames, `deny_unknown_fields`,
+//! optionality and enum variants all come from the serde attributes themselves.
+//! Only the top level field lists are needed up front, as serde cannot list through `flatten`.
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde_json::{json, Map, Value};
+use std::{cell::RefCell, fmt};
+
+use super::manifest::{
+    ManifestDefaults, ManifestOverrides, ManifestSource, DEFAULT_FIELDS, OVERRIDE_FIELDS, SOURCE_FIELDS,
+};
+
+/// Nesting depth beyond which objects are left unconstrained
+const MAX_DEPTH: usize = 10;
+
+/// Key passed to structs to detect `deny_unknown_fields`
+const UNKNOWN_KEY: &str = "__shipcat_schema_probe";
+
+/// JSON Schema for `manifest.yml`
+pub fn manifest_schema() -> Value {
+    let mut properties = Map::new();
+    for f in SOURCE_FIELDS {
+        properties.insert(f.to_string(), field_schema::<ManifestSource>(&mut vec![*f]));
+    }
+    for f in OVERRIDE_FIELDS {
+        properties.insert(f.to_string(), field_schema::<ManifestOverrides>(&mut vec![*f]));
+    }
+    for f in DEFAULT_FIELDS {
+        properties.insert(f.to_string(), field_schema::<ManifestDefaults>(&mut vec![*f]));
+    }
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "shipcat manifest",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// Schema of the value found by following `path` from the root of `T`
+///
+/// Path entries are struct field names, or "" to step into map values.
+fn field_schema<T: DeserializeOwned>(path: &mut Vec<&'static str>) -> Value {
+    let trace = run::<T>(path, Mode::Shape, Trace::default());
+    let maps = trace.wrappers.iter().filter(|w| **w == Wrapper::Map).count();
+    let mut schema = match trace.leaf {
+        Some(Shape::Struct(_)) if path.len() >= MAX_DEPTH => json!({ "type": "object" }),
+        Some(Shape::Struct(fields)) => {
+            let depth = path.len();
+            path.extend(std::iter::repeat("").take(maps));
+            let schema = struct_schema::<T>(path, fields);
+            path.truncate(depth);
+            schema
+        }
+        Some(Shape::Enum(variants)) => enum_schema::<T>(path, variants),
+        Some(shape) => shape.schema(),
+        None => json!({}),
+    };
+    for w in trace.wrappers.iter().rev() {
+        schema = match w {
+            Wrapper::Optional => json!({ "anyOf": [schema, { "type": "null" }] }),
+            Wrapper::Array => json!({ "type": "array", "items": schema }),
+            Wrapper::Map => json!({ "type": "object", "additionalProperties": schema }),
+        };
+    }
+    schema
+}
+
+fn struct_schema<T: DeserializeOwned>(
+    path: &mut Vec<&'static str>,
+    fields: &'static [&'static str],
+) -> Value {
+    let mut properties = Map::new();
+    for f in fields {
+        path.push(*f);
+        properties.insert(f.to_string(), field_schema::<T>(path));
+        path.pop();
+    }
+    let mut schema = json!({ "type": "object", "properties": properties });
+
+    let mut required = vec![];
+    loop {
+        let known = Trace {
+            required: required.clone(),
+            ..Trace::default()
+        };
+        match run::<T>(path, Mode::Required, known).missing {
+            Some(f) if !required.contains(&f) => required.push(f),
+            _ => break,
+        }
+    }
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    if run::<T>(path, Mode::Unknown, Trace::default()).denies_unknown {
+        schema["additionalProperties"] = json!(false);
+    }
+    schema
+}
+
+/// Unit variants as strings, data variants as single key objects
+fn enum_schema<T: DeserializeOwned>(path: &[&'static str], variants: &'static [&'static str]) -> Value {
+    let mut unit = vec![];
+    let mut properties = Map::new();
+    for (i, v) in variants.iter().enumerate() {
+        if run::<T>(path, Mode::Variant(i), Trace::default()).data_variant {
+            properties.insert(v.to_string(), json!({}));
+        } else {
+            unit.push(*v);
+        }
+    }
+    if properties.is_empty() {
+        return json!({ "enum": unit });
+    }
+    json!({
+        "anyOf": [
+            { "enum": unit },
+            {
+                "type": "object",
+                "properties": properties,
+                "minProperties": 1,
+                "maxProperties": 1,
+                "additionalProperties": false,
+            },
+        ]
+    })
+}
+
+fn run<T: DeserializeOwned>(path: &[&'static str], mode: Mode, trace: Trace) -> Trace {
+    let trace = RefCell::new(trace);
+    let _ = T::deserialize(Probe {
+        trace: &trace,
+        path,
+        mode,
+    });
+    trace.into_inner()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// Record the shape at the end of the path
+    Shape,
+    /// Produce the smallest value the visitor accepts
+    Fill,
+    /// Find the first required field not in `Trace::required`
+    Required,
+    /// Check whether the struct rejects unknown fields
+    Unknown,
+    /// Check whether the nth enum variant carries data
+    Variant(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Wrapper {
+    Optional,
+    Array,
+    Map,
+}
+
+enum Shape {
+    Any,
+    Null,
+    Bool,
+    Integer,
+    Number,
+    String,
+    Enum(&'static [&'static str]),
+    Struct(&'static [&'static str]),
+}
+
+impl Shape {
+    fn schema(&self) -> Value {
+        match self {
+            Shape::Any => json!({}),
+            Shape::Null => json!({ "type": "null" }),
+            Shape::Bool => json!({ "type": "boolean" }),
+            Shape::Integer => json!({ "type": "integer" }),
+            Shape::Number => json!({ "type": "number" }),
+            Shape::String => json!({ "type": "string" }),
+            Shape::Enum(variants) => json!({ "enum": variants }),
+            Shape::Struct(_) => json!({ "type": "object" }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Trace {
+    wrappers: Vec<Wrapper>,
+    leaf: Option<Shape>,
+    required: Vec<&'static str>,
+    missing: Option<&'static str>,
+    denies_unknown: bool,
+    data_variant: bool,
+}
+
+#[derive(Debug)]
+enum ProbeError {
+    /// Probing finished (deserialization is never meant to complete)
+    Done,
+    Missing(&'static str),
+    Unknown,
+    Other(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Done => write!(f, "probe done"),
+            ProbeError::Missing(field) => write!(f, "missing field `{}`", field),
+            ProbeError::Unknown => write!(f, "unknown field"),
+            ProbeError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ProbeError::Other(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ProbeError::Missing(field)
+    }
+
+    fn unknown_field(_field: &str, _expected: &'static [&'static str]) -> Self {
+        ProbeError::Unknown
+    }
+}
+
+type ProbeResult<T> = std::result::Result<T, ProbeError>;
+
+/// Recording deserializer
+#[derive(Clone, Copy)]
+struct Probe<'a> {
+    trace: &'a RefCell<Trace>,
+    path: &'a [&'static str],
+    mode: Mode,
+}
+
+impl<'a> Probe<'a> {
+    fn at_target(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    fn fill(self) -> Self {
+        Probe {
+            mode: Mode::Fill,
+            path: &[],
+            ..self
+        }
+    }
+
+    fn wrap(&self, wrapper: Wrapper) {
+        if self.at_target() && self.mode == Mode::Shape {
+            self.trace.borrow_mut().wrappers.push(wrapper);
+        }
+    }
+
+    fn leaf<T>(self, shape: Shape) -> ProbeResult<T> {
+        if self.at_target() && self.mode == Mode::Shape {
+            self.trace.borrow_mut().leaf = Some(shape);
+        }
+        Err(ProbeError::Done)
+    }
+
+    /// Step into the next path entry as a map key
+    fn descend<'de, V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        let child = Probe {
+            path: &self.path[1..],
+            ..self
+        };
+        visitor.visit_map(Entries::new(vec![(self.path[0], child)]))
+    }
+}
+
+macro_rules! probe_leaf {
+    ($($method:ident => $shape:ident, $visit:ident($fill:expr);)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+            match self.mode {
+                Mode::Fill => visitor.$visit($fill),
+                _ => self.leaf(Shape::$shape),
+            }
+        }
+    )*};
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Probe<'a> {
+    type Error = ProbeError;
+
+    probe_leaf! {
+        deserialize_bool => Bool, visit_bool(false);
+        deserialize_i8 => Integer, visit_i64(0);
+        deserialize_i16 => Integer, visit_i64(0);
+        deserialize_i32 => Integer, visit_i64(0);
+        deserialize_i64 => Integer, visit_i64(0);
+        deserialize_u8 => Integer, visit_u64(0);
+        deserialize_u16 => Integer, visit_u64(0);
+        deserialize_u32 => Integer, visit_u64(0);
+        deserialize_u64 => Integer, visit_u64(0);
+        deserialize_f32 => Number, visit_f64(0.0);
+        deserialize_f64 => Number, visit_f64(0.0);
+        deserialize_char => String, visit_char('a');
+        deserialize_str => String, visit_str("");
+        deserialize_string => String, visit_str("");
+        deserialize_bytes => String, visit_bytes(&[]);
+        deserialize_byte_buf => String, visit_bytes(&[]);
+        deserialize_identifier => String, visit_str("");
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        match self.mode {
+            Mode::Fill => visitor.visit_unit(),
+            _ => self.leaf(Shape::Any),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        match self.mode {
+            Mode::Fill => visitor.visit_unit(),
+            _ => self.leaf(Shape::Null),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> ProbeResult<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> ProbeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        match self.mode {
+            Mode::Fill => visitor.visit_none(),
+            _ => {
+                self.wrap(Wrapper::Optional);
+                visitor.visit_some(self)
+            }
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        match self.mode {
+            Mode::Fill => visitor.visit_seq(Elements(None)),
+            _ => {
+                self.wrap(Wrapper::Array);
+                visitor.visit_seq(Elements(Some(self)))
+            }
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> ProbeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> ProbeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    // Plain maps, but also structs containing a `flatten` field
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> ProbeResult<V::Value> {
+        if self.mode == Mode::Fill {
+            visitor.visit_map(Entries::new(vec![]))
+        } else if !self.at_target() {
+            self.descend(visitor)
+        } else {
+            self.wrap(Wrapper::Map);
+            visitor.visit_map(Entries::new(vec![("", self)]))
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> ProbeResult<V::Value> {
+        if self.mode == Mode::Fill {
+            return visitor.visit_map(Entries::new(vec![]));
+        }
+        if !self.at_target() {
+            return self.descend(visitor);
+        }
+        match self.mode {
+            Mode::Required => {
+                let known = self.trace.borrow().required.clone();
+                let entries = known.into_iter().map(|f| (f, self.fill())).collect();
+                if let Err(ProbeError::Missing(f)) = visitor.visit_map(Entries::new(entries)) {
+                    self.trace.borrow_mut().missing = Some(f);
+                }
+                Err(ProbeError::Done)
+            }
+            Mode::Unknown => {
+                let entries = vec![(UNKNOWN_KEY, self.fill())];
+                if let Err(ProbeError::Unknown) = visitor.visit_map(Entries::new(entries)) {
+                    self.trace.borrow_mut().denies_unknown = true;
+                }
+                Err(ProbeError::Done)
+            }
+            _ => self.leaf(Shape::Struct(fields)),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> ProbeResult<V::Value> {
+        match self.mode {
+            Mode::Fill if !variants.is_empty() => visitor.visit_enum(Variant {
+                name: variants[0],
+                trace: self.trace,
+            }),
+            Mode::Variant(i) if self.at_target() => visitor.visit_enum(Variant {
+                name: variants[i],
+                trace: self.trace,
+            }),
+            _ => self.leaf(Shape::Enum(variants)),
+        }
+    }
+}
+
+/// Map access yielding fixed keys, with values deserialized by probes
+struct Entries<'a> {
+    entries: std::vec::IntoIter<(&'static str, Probe<'a>)>,
+    value: Option<Probe<'a>>,
+}
+
+impl<'a> Entries<'a> {
+    fn new(entries: Vec<(&'static str, Probe<'a>)>) -> Self {
+        Entries {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = ProbeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> ProbeResult<Option<K::Value>> {
+        match self.entries.next() {
+            Some((key, probe)) => {
+                self.value = Some(probe);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> ProbeResult<S::Value> {
+        let probe = self.value.take().ok_or(ProbeError::Done)?;
+        seed.deserialize(probe)
+    }
+}
+
+/// Sequence access yielding at most one probed element
+struct Elements<'a>(Option<Probe<'a>>);
+
+impl<'de, 'a> SeqAccess<'de> for Elements<'a> {
+    type Error = ProbeError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> ProbeResult<Option<S::Value>> {
+        match self.0.take() {
+            Some(probe) => seed.deserialize(probe).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Enum access selecting one variant and recording whether it carries data
+struct Variant<'a> {
+    name: &'static str,
+    trace: &'a RefCell<Trace>,
+}
+
+impl<'a> Variant<'a> {
+    fn data<T>(self) -> ProbeResult<T> {
+        self.trace.borrow_mut().data_variant = true;
+        Err(ProbeError::Done)
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = ProbeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> ProbeResult<(S::Value, Self)> {
+        let value = seed.deserialize(self.name.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = ProbeError;
+
+    fn unit_variant(self) -> ProbeResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, _seed: S) -> ProbeResult<S::Value> {
+        self.data()
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> ProbeResult<V::Value> {
+        self.data()
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> ProbeResult<V::Value> {
+        self.data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::manifest_schema;
+    use serde_json::json;
+
+    #[test]
+    fn manifest_schema_follows_serde() {
+        let schema = manifest_schema();
+        assert_eq!(schema["additionalProperties"], json!(false));
+        let props = &schema["properties"];
+
+        // camelCase renames, and optional fields are nullable
+        let replicas = &props["replicaCount"]["anyOf"];
+        assert_eq!(replicas[0], json!({ "type": "integer" }));
+        assert_eq!(replicas[1], json!({ "type": "null" }));
+        assert_eq!(props["regions"]["items"], json!({ "type": "string" }));
+
+        // nested structs keep deny_unknown_fields and required fields
+        let data_handling = &props["dataHandling"]["anyOf"][0];
+        assert_eq!(data_handling["additionalProperties"], json!(false));
+        let store = &data_handling["properties"]["stores"]["items"];
+        assert_eq!(store["required"], json!(["backend"]));
+        let cipher = &store["properties"]["cipher"]["anyOf"][0];
+        assert_eq!(cipher, &json!({ "type": "string" }));
+
+        // enum variants are enumerated
+        let classification = &store["properties"]["informationClassification"]["anyOf"][0]["enum"];
+        assert!(classification
+            .as_array()
+            .unwrap()
+            .contains(&json!("strictlyConfidential")));
+    }
+}
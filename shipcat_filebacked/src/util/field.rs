@@ -0,0 +1,13 @@
+use shipcat_definitions::{Result, ValidationError};
+
+/// Attribute build errors to a field as they propagate
+pub trait Field<T> {
+    /// Nest any error under `name` (dotted paths are allowed)
+    fn field(self, name: &str) -> Result<T>;
+}
+
+impl<T> Field<T> for Result<T> {
+    fn field(self, name: &str) -> Result<T> {
+        self.map_err(|e| ValidationError::from_error(e).within(name).into())
+    }
+}
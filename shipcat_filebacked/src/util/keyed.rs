@@ -0,0 +1,110 @@
+use shipcat_definitions::structs::{tolerations::Tolerations, HostAlias, Volume, VolumeMount};
+
+/// How list fields in an override file combine with the lists before it
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ListMerge {
+    /// Lists replace earlier lists entirely
+    Replace,
+    /// Entries replace or merge into earlier entries with the same key, new entries are appended
+    MergeByKey,
+}
+
+impl Default for ListMerge {
+    fn default() -> Self {
+        ListMerge::Replace
+    }
+}
+
+/// A list entry that can be matched against entries in earlier files
+pub trait Keyed {
+    /// Identifier used to match entries (unkeyed entries are always appended)
+    fn key(&self) -> Option<String>;
+
+    /// Combine with a later entry of the same key (replaces by default)
+    fn merge_entry(self, other: Self) -> Self
+    where
+        Self: Sized,
+    {
+        other
+    }
+}
+
+/// Merge two optional lists by key, appending entries with new keys
+pub fn merge_by_key<T: Keyed>(base: Option<Vec<T>>, other: Option<Vec<T>>) -> Option<Vec<T>> {
+    let (mut merged, other) = match (base, other) {
+        (Some(base), Some(other)) => (base, other),
+        (base, None) => return base,
+        (None, other) => return other,
+    };
+    for entry in other {
+        let existing = entry
+            .key()
+            .and_then(|k| merged.iter().position(|m| m.key().as_ref() == Some(&k)));
+        match existing {
+            Some(i) => {
+                let prev = merged.remove(i);
+                merged.insert(i, prev.merge_entry(entry));
+            }
+            None => merged.push(entry),
+        }
+    }
+    Some(merged)
+}
+
+impl Keyed for Volume {
+    fn key(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+}
+
+impl Keyed for VolumeMount {
+    fn key(&self) -> Option<String> {
+        Some(self.mountPath.clone())
+    }
+}
+
+impl Keyed for Tolerations {
+    fn key(&self) -> Option<String> {
+        self.key.clone()
+    }
+}
+
+impl Keyed for HostAlias {
+    fn key(&self) -> Option<String> {
+        Some(self.ip.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_by_key, Keyed};
+
+    #[derive(Debug, PartialEq)]
+    struct Entry(Option<&'static str>, u32);
+
+    impl Keyed for Entry {
+        fn key(&self) -> Option<String> {
+            self.0.map(String::from)
+        }
+
+        fn merge_entry(self, other: Self) -> Self {
+            Entry(self.0, self.1 + other.1)
+        }
+    }
+
+    #[test]
+    fn merges_matching_keys_and_appends() {
+        let base = vec![Entry(Some("a"), 1), Entry(Some("b"), 2)];
+        let other = vec![Entry(Some("b"), 10), Entry(None, 3), Entry(Some("c"), 4)];
+        let merged = merge_by_key(Some(base), Some(other)).unwrap();
+        assert_eq!(merged, vec![
+            Entry(Some("a"), 1),
+            Entry(Some("b"), 12),
+            Entry(None, 3),
+            Entry(Some("c"), 4),
+        ]);
+        assert_eq!(merge_by_key(None, Some(vec![Entry(None, 1)])).unwrap().len(), 1);
+        assert_eq!(merge_by_key::<Entry>(Some(vec![]), None), Some(vec![]));
+    }
+}
@@ -1,9 +1,15 @@
 mod build;
 mod enabled;
+mod field;
+mod keyed;
 mod relaxedstring;
 mod require;
+mod suggest;
 
 pub use build::Build;
 pub use enabled::{Enabled, EnabledMap};
+pub use field::Field;
+pub use keyed::{merge_by_key, Keyed, ListMerge};
 pub use relaxedstring::RelaxedString;
 pub use require::Require;
+pub use suggest::closest;
@@ -0,0 +1,41 @@
+/// Edit distance between two strings
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Closest candidate to an input within a reasonable edit distance
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let threshold = (input.len() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|c| (levenshtein(input, c), c))
+        .filter(|(d, _)| *d <= threshold)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, levenshtein};
+
+    #[test]
+    fn distances() {
+        assert_eq!(levenshtein("replicaCnt", "replicaCount"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(
+            closest("replicaCnt", vec!["chart", "replicaCount"]),
+            Some("replicaCount")
+        );
+        assert_eq!(closest("zzz", vec!["chart", "replicaCount"]), None);
+    }
+}